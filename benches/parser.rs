@@ -0,0 +1,43 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Parser throughput benchmarks
+//!
+//! Run with `cargo bench`. The size of the document in kilobytes can be given as argument, e.g.
+//! `cargo bench -- 10`.
+
+/// Default size of the synthetic document in kilobytes
+const SIZE_KB: usize = 50;
+
+fn main() {
+    // cargo bench passes --bench. Take the first number as size.
+    let size_kb = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(SIZE_KB);
+    println!("document size: {} KB", size_kb);
+    for measurement in sesd::bench::run(size_kb, 2, 9) {
+        println!("{}", measurement);
+    }
+}
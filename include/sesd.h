@@ -0,0 +1,111 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+/*
+ * C interface of sesd
+ *
+ * Build the library with
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type staticlib
+ *
+ * Ownership: An editor is created by sesd_editor_new and must be released by
+ * sesd_editor_free. All other pointers are borrowed for the duration of the call. Output buffers
+ * are owned by the caller. No function unwinds into the caller; internal errors are reported as
+ * SESD_PANIC.
+ *
+ * Positions and lengths in the buffer are counted in characters (Unicode scalar values).
+ */
+
+#ifndef SESD_H
+#define SESD_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes */
+#define SESD_OK 0
+#define SESD_NULL (-1)
+#define SESD_INVALID_UTF8 (-2)
+#define SESD_OUT_OF_RANGE (-3)
+#define SESD_PANIC (-4)
+
+/* Opaque editor handle */
+typedef struct SesdEditor SesdEditor;
+
+/* State of the editor */
+typedef struct SesdStatus {
+    /* Number of characters in the buffer */
+    size_t len;
+    /* 1 if the whole buffer has been accepted, 0 otherwise */
+    int32_t accepted;
+} SesdStatus;
+
+/*
+ * Create an editor for the grammar given as NUL-terminated UTF-8 text, one non-terminal per line:
+ *
+ *     <list> ::= <item> | <item> ", " <list>
+ *     <item> ::= [a-z]
+ *
+ * Lines starting with # are comments. The left hand side of the first rule is the start symbol.
+ * Return NULL if grammar_bnf is NULL or the grammar is invalid.
+ */
+SesdEditor *sesd_editor_new(const char *grammar_bnf);
+
+/* Release an editor. NULL is ignored. */
+void sesd_editor_free(SesdEditor *ed);
+
+/*
+ * Delete del_count characters at pos, then insert insert_len bytes of UTF-8 text there.
+ * insert_utf8 may be NULL if insert_len is 0. On error, the buffer is unchanged.
+ */
+int32_t sesd_editor_edit(SesdEditor *ed, size_t pos, size_t del_count, const uint8_t *insert_utf8,
+                         size_t insert_len);
+
+/* Write the state of the editor to out. */
+int32_t sesd_editor_status(const SesdEditor *ed, SesdStatus *out);
+
+/*
+ * Write the names of the symbols predicted at pos to out_buf, separated by newlines. At most
+ * out_cap bytes are written; the text is not NUL-terminated. Return the length of the whole text,
+ * 0 on errors.
+ */
+size_t sesd_editor_predictions(const SesdEditor *ed, size_t pos, uint8_t *out_buf, size_t out_cap);
+
+/*
+ * Write start and end (exclusive) of the completed nodes of a symbol to out_pairs. At most cap
+ * nodes, i.e. 2 * cap entries, are written. Return the number of nodes, 0 if the symbol is
+ * unknown or on errors.
+ */
+size_t sesd_editor_spans(const SesdEditor *ed, const char *symbol_name, size_t *out_pairs,
+                         size_t cap);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SESD_H */
@@ -0,0 +1,529 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Grammars of `char` from ABNF text
+//!
+//! [Grammar::from_abnf](../struct.Grammar.html#method.from_abnf) reads grammars in the Augmented
+//! BNF of [RFC 5234](https://tools.ietf.org/html/rfc5234), e.g. the grammars published with
+//! internet standards:
+//!
+//! ```text
+//! ; Comments start with a semicolon
+//! keyval = key ws "=" ws val
+//! key    = 1*( ALPHA / DIGIT / "-" / "_" )
+//! ws     = *WSP
+//! val    = %s"true" / [ "-" ] 1*3DIGIT
+//! val   =/ %x30.78 1*HEXDIG   ; hexadecimal
+//! ```
+//!
+//! Supported are alternatives `/`, groups `( )`, options `[ ]`, repetitions `n*m`, `*`, `n`,
+//! incremental alternatives `=/`, strings (case-insensitive as `"..."` or `%i"..."`,
+//! case-sensitive as `%s"..."` from RFC 7405) and numeric values `%x`, `%d`, `%b` with ranges
+//! (`%x20-7E`) and concatenations (`%x0D.0A`). A rule is continued on the following lines that
+//! begin with white space. Prose values `<...>` cannot be parsed and are reported as errors.
+//!
+//! Rule names are case-insensitive. The non-terminals are named like the first occurrence of
+//! their rule name. The core rules of RFC 5234 (e.g. `ALPHA`, `DIGIT`, `WSP`) are added if they
+//! are used but not defined. The left hand side of the first rule is the start symbol.
+//!
+//! Options, repetitions and groups with alternatives become helper non-terminals. They are named
+//! after the rule they appear in, followed by `~opt`, `~rep` or `~group` and a number, e.g.
+//! `key~rep1`. A case-insensitive letter becomes a non-terminal like `%i"a"`.
+
+use std::collections::{HashMap, HashSet};
+
+use super::char::CharMatcher;
+use super::grammar::{Error, Grammar, Symbol};
+
+/// Core rules of RFC 5234, appendix B.1
+const CORE_RULES: &str = "ALPHA = %x41-5A / %x61-7A
+BIT = \"0\" / \"1\"
+CHAR = %x01-7F
+CR = %x0D
+CRLF = CR LF
+CTL = %x00-1F / %x7F
+DIGIT = %x30-39
+DQUOTE = %x22
+HEXDIG = DIGIT / \"A\" / \"B\" / \"C\" / \"D\" / \"E\" / \"F\"
+HTAB = %x09
+LF = %x0A
+LWSP = *(WSP / CRLF WSP)
+OCTET = %x00-FF
+SP = %x20
+VCHAR = %x21-7E
+WSP = SP / HTAB";
+
+/// Right hand side of a rule
+type Sequence = Vec<Symbol<CharMatcher>>;
+
+/// Characters of one rule and the position of the next one
+struct Input {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Input {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    /// Skip the character if it is `c`.
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Syntax error at the current position
+    fn error(&self, what: &str) -> Error {
+        let text: String = self.chars.iter().collect();
+        Error::Syntax(format!("{} at column {} of {}", what, self.pos + 1, text))
+    }
+}
+
+/// Check if the character may appear in a rule name
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+/// Remove the comment of a line. Semicolons in strings don't start a comment.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Grammar under construction
+struct Reader {
+    grammar: Grammar<char, CharMatcher>,
+    /// Spelling of the rule names by their lowercase form
+    names: HashMap<String, String>,
+    /// Lowercase names of the defined rules
+    defined: HashSet<String>,
+    /// Number of helper non-terminals
+    helpers: usize,
+    /// Lowercase letters whose case-insensitive non-terminal has been defined
+    letters: HashSet<char>,
+}
+
+impl Reader {
+    /// Name of the non-terminal of a rule name
+    fn name(&mut self, name: String) -> String {
+        self.names
+            .entry(name.to_ascii_lowercase())
+            .or_insert(name)
+            .clone()
+    }
+
+    /// Name of a new helper non-terminal in the rule `lhs`
+    fn helper_name(&mut self, lhs: &str, kind: &str) -> String {
+        self.helpers += 1;
+        format!("{}~{}{}", lhs, kind, self.helpers)
+    }
+
+    /// Add a helper non-terminal with the given alternatives.
+    fn helper(
+        &mut self,
+        lhs: &str,
+        kind: &str,
+        alternatives: Vec<Sequence>,
+    ) -> Symbol<CharMatcher> {
+        let name = self.helper_name(lhs, kind);
+        for rhs in alternatives {
+            self.grammar.add_rule(name.clone(), rhs);
+        }
+        Symbol::NonTerminal(name)
+    }
+
+    /// Parse a rule and add it to the grammar. Return the name of its left hand side.
+    fn rule(&mut self, text: &str) -> Result<String, Error> {
+        let mut input = Input::new(text);
+        let name = input.take_while(is_name_char);
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return Err(input.error("expected rule name"));
+        }
+        let lhs = self.name(name);
+        input.skip_space();
+        if !input.eat('=') {
+            return Err(input.error("expected ="));
+        }
+        input.eat('/');
+        let alternatives = self.alternation(&lhs, &mut input)?;
+        if input.peek().is_some() {
+            return Err(input.error("unexpected character"));
+        }
+        self.defined.insert(lhs.to_ascii_lowercase());
+        for rhs in alternatives {
+            self.grammar.add_rule(lhs.clone(), rhs);
+        }
+        Ok(lhs)
+    }
+
+    /// Parse alternatives separated by `/`.
+    fn alternation(&mut self, lhs: &str, input: &mut Input) -> Result<Vec<Sequence>, Error> {
+        let mut alternatives = vec![self.concatenation(lhs, input)?];
+        while input.eat('/') {
+            alternatives.push(self.concatenation(lhs, input)?);
+        }
+        Ok(alternatives)
+    }
+
+    /// Parse repetitions separated by white space.
+    fn concatenation(&mut self, lhs: &str, input: &mut Input) -> Result<Sequence, Error> {
+        let mut sequence = Vec::new();
+        let mut repetitions = 0;
+        loop {
+            input.skip_space();
+            match input.peek() {
+                None | Some('/') | Some(')') | Some(']') => break,
+                _ => sequence.extend(self.repetition(lhs, input)?),
+            }
+            repetitions += 1;
+        }
+        if repetitions == 0 {
+            return Err(input.error("expected element"));
+        }
+        Ok(sequence)
+    }
+
+    /// Parse an element with an optional repeat count.
+    fn repetition(&mut self, lhs: &str, input: &mut Input) -> Result<Sequence, Error> {
+        let number = |input: &mut Input| {
+            input
+                .take_while(|c| c.is_ascii_digit())
+                .parse::<usize>()
+                .ok()
+        };
+        let min = number(input);
+        let (min, max) = if input.eat('*') {
+            (min.unwrap_or(0), number(input))
+        } else if let Some(min) = min {
+            (min, Some(min))
+        } else {
+            return self.element(lhs, input);
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(input.error("maximum below minimum"));
+        }
+        let element = self.element(lhs, input)?;
+        let single = if element.len() == 1 {
+            element[0].clone()
+        } else {
+            self.helper(lhs, "group", vec![element])
+        };
+
+        let mut sequence = vec![single.clone(); min];
+        match max {
+            None => {
+                // Left recursion keeps the chart small
+                let name = self.helper_name(lhs, "rep");
+                let repeated = Symbol::NonTerminal(name.clone());
+                self.grammar
+                    .add_rule(name.clone(), vec![repeated.clone(), single]);
+                self.grammar.add_rule(name, Vec::new());
+                sequence.push(repeated);
+            }
+            Some(max) if max > min => {
+                // Nested options avoid ambiguous positions of the missing elements.
+                let mut optional = self.helper(lhs, "opt", vec![vec![single.clone()], vec![]]);
+                for _ in min + 1..max {
+                    optional =
+                        self.helper(lhs, "opt", vec![vec![single.clone(), optional], vec![]]);
+                }
+                sequence.push(optional);
+            }
+            Some(_) => {}
+        }
+        Ok(sequence)
+    }
+
+    /// Parse a rule name, group, option, string or numeric value.
+    fn element(&mut self, lhs: &str, input: &mut Input) -> Result<Sequence, Error> {
+        match input.peek() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                let name = input.take_while(is_name_char);
+                Ok(vec![Symbol::NonTerminal(self.name(name))])
+            }
+            Some('(') => {
+                input.pos += 1;
+                let mut alternatives = self.alternation(lhs, input)?;
+                if !input.eat(')') {
+                    return Err(input.error("expected )"));
+                }
+                if alternatives.len() == 1 {
+                    Ok(alternatives.pop().unwrap_or_default())
+                } else {
+                    Ok(vec![self.helper(lhs, "group", alternatives)])
+                }
+            }
+            Some('[') => {
+                input.pos += 1;
+                let mut alternatives = self.alternation(lhs, input)?;
+                if !input.eat(']') {
+                    return Err(input.error("expected ]"));
+                }
+                alternatives.push(Vec::new());
+                Ok(vec![self.helper(lhs, "opt", alternatives)])
+            }
+            Some('"') => self.string(input, true),
+            Some('%') => {
+                input.pos += 1;
+                match input.peek().map(|c| c.to_ascii_lowercase()) {
+                    Some('s') | Some('i') => {
+                        let insensitive = input.peek().map(|c| c.to_ascii_lowercase()) == Some('i');
+                        input.pos += 1;
+                        self.string(input, insensitive)
+                    }
+                    Some('x') => self.number(input, 16),
+                    Some('d') => self.number(input, 10),
+                    Some('b') => self.number(input, 2),
+                    _ => Err(input.error("unknown value type")),
+                }
+            }
+            Some('<') => Err(input.error("prose values are not supported")),
+            _ => Err(input.error("expected element")),
+        }
+    }
+
+    /// Parse a quoted string.
+    fn string(&mut self, input: &mut Input, insensitive: bool) -> Result<Sequence, Error> {
+        if !input.eat('"') {
+            return Err(input.error("expected \""));
+        }
+        let text = input.take_while(|c| c != '"');
+        if !input.eat('"') {
+            return Err(input.error("unterminated string"));
+        }
+        Ok(text
+            .chars()
+            .map(|c| {
+                if insensitive && c.is_ascii_alphabetic() {
+                    self.letter(c)
+                } else {
+                    Symbol::Terminal(CharMatcher::Exact(c))
+                }
+            })
+            .collect())
+    }
+
+    /// Non-terminal that matches a letter in both cases
+    fn letter(&mut self, c: char) -> Symbol<CharMatcher> {
+        let lower = c.to_ascii_lowercase();
+        let name = format!("%i\"{}\"", lower);
+        if self.letters.insert(lower) {
+            for c in [lower, lower.to_ascii_uppercase()].iter() {
+                self.grammar
+                    .add_rule(name.clone(), vec![Symbol::Terminal(CharMatcher::Exact(*c))]);
+            }
+        }
+        Symbol::NonTerminal(name)
+    }
+
+    /// Parse a numeric value after its type.
+    fn number(&mut self, input: &mut Input, radix: u32) -> Result<Sequence, Error> {
+        input.pos += 1;
+        let value = |input: &mut Input| {
+            let digits = input.take_while(|c| c.is_digit(radix));
+            u32::from_str_radix(&digits, radix)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| input.error("invalid character value"))
+        };
+        let first = value(input)?;
+        if input.eat('-') {
+            let last = value(input)?;
+            return Ok(vec![Symbol::Terminal(CharMatcher::Range(first, last))]);
+        }
+        let mut sequence = vec![Symbol::Terminal(CharMatcher::Exact(first))];
+        while input.eat('.') {
+            sequence.push(Symbol::Terminal(CharMatcher::Exact(value(input)?)));
+        }
+        Ok(sequence)
+    }
+}
+
+impl Grammar<char, CharMatcher> {
+    /// Read the rules from ABNF text, see the [module documentation](abnf/index.html) for the
+    /// supported syntax.
+    ///
+    /// Errors in the syntax are reported as [Error::Syntax](enum.Error.html#variant.Syntax),
+    /// undefined rules when the grammar is compiled.
+    ///
+    /// ```
+    /// use sesd::{Grammar, Parser, Verdict};
+    ///
+    /// let grammar = Grammar::from_abnf("number = [\"-\"] 1*DIGIT").unwrap();
+    /// let mut parser = Parser::new(grammar.compile().unwrap());
+    /// assert_eq!(parser.update_slice(0, &['-', '4', '2']), Verdict::Accept);
+    /// ```
+    pub fn from_abnf(text: &str) -> Result<Self, Error> {
+        let mut reader = Reader {
+            grammar: Grammar::new(),
+            names: HashMap::new(),
+            defined: HashSet::new(),
+            helpers: 0,
+            letters: HashSet::new(),
+        };
+
+        // Join the continuation lines
+        let mut rules: Vec<String> = Vec::new();
+        for line in text.lines() {
+            let line = strip_comment(line).trim_end();
+            if line.trim_start().is_empty() {
+                continue;
+            }
+            match rules.last_mut() {
+                Some(rule) if line.starts_with(char::is_whitespace) => {
+                    rule.push(' ');
+                    rule.push_str(line.trim_start());
+                }
+                _ => rules.push(line.to_string()),
+            }
+        }
+
+        let mut start = None;
+        for rule in rules.iter() {
+            let lhs = reader.rule(rule)?;
+            start.get_or_insert(lhs);
+        }
+
+        // Core rules can use other core rules
+        let mut added = true;
+        while added {
+            added = false;
+            for rule in CORE_RULES.lines() {
+                let name = rule[..rule.find(' ').unwrap_or(0)].to_ascii_lowercase();
+                if reader.names.contains_key(&name) && !reader.defined.contains(&name) {
+                    reader.rule(rule)?;
+                    added = true;
+                }
+            }
+        }
+
+        let mut grammar = reader.grammar;
+        grammar.set_start(start.ok_or_else(|| Error::Syntax("no rules".to_string()))?);
+        Ok(grammar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, Verdict};
+
+    #[test]
+    fn from_abnf() {
+        let grammar = Grammar::from_abnf(
+            "; key/value pairs
+keyval = key ws \"=\" ws val
+key = 1*( ALPHA / DIGIT / \"-\" / \"_\" )
+ws = *WSP
+val = %s\"true\" / [ \"-\" ] 1*3digit ; up to three digits
+val =/ %x30.78
+       1*HEXDIG
+val =/ \"yes\" / 2\"ab\"",
+        )
+        .expect("grammar should parse");
+        let mut parser = Parser::new(grammar.compile().expect("grammar should compile"));
+        for (text, verdict) in [
+            ("a_b = 12", Verdict::Accept),
+            ("k=-123", Verdict::Accept),
+            ("k=1234", Verdict::Reject),
+            ("k\t= 0xfF", Verdict::Accept),
+            ("k = true", Verdict::Accept),
+            ("k = TRUE", Verdict::Reject),
+            ("k = YeS", Verdict::Accept),
+            ("k = abAB", Verdict::Accept),
+            ("k = ab", Verdict::More),
+        ]
+        .iter()
+        {
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(parser.update_slice(0, &tokens), *verdict, "{}", text);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for text in [
+            "",
+            "= b",
+            "a b",
+            "a = <prose>",
+            "a = 3*2b",
+            "a = \"x",
+            "a = (b",
+            "a = [b",
+            "a = %q20",
+            "a = %xD800",
+            "a = b /",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(Grammar::from_abnf(text), Err(Error::Syntax(_))),
+                "{}",
+                text
+            );
+        }
+        let grammar = Grammar::from_abnf("a = b").expect("syntax should be valid");
+        assert_eq!(
+            grammar
+                .compile()
+                .err()
+                .as_ref()
+                .and_then(Error::no_rule_symbol),
+            Some("b")
+        );
+    }
+}
@@ -0,0 +1,437 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Semantic actions
+//!
+//! A [RuleActions](struct.RuleActions.html) table maps rules to functions that compute a value
+//! from the values of the symbols on the right hand side, e.g. to build an AST of user-defined
+//! types. [Parser::evaluate](../struct.Parser.html#method.evaluate) calls the actions in the order
+//! the rules have been completed, i.e. children before parents.
+//!
+//! A [SubtreeMemo](struct.SubtreeMemo.html) keeps the values of non-terminals across reparses.
+//! Subtrees whose symbol and tokens are unchanged, e.g. a table that has been cut and pasted
+//! elsewhere, are not evaluated again, see
+//! [Parser::evaluate_memoized](../struct.Parser.html#method.evaluate_memoized).
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use super::sppf::{DerivationNode, NodeLabel};
+use super::{CompiledGrammar, Matcher, SymbolId};
+
+/// Value of a symbol on the right hand side of a rule
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Arg<T, V> {
+    /// Token that matched a terminal
+    Token(T),
+    /// Token that has been skipped by the error recovery
+    Error(T),
+    /// Value computed for a non-terminal
+    Value(V),
+}
+
+impl<T, V> Arg<T, V> {
+    /// Return the value if this is a non-terminal
+    pub fn into_value(self) -> Option<V> {
+        match self {
+            Arg::Value(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Return the token if this is a terminal
+    pub fn token(&self) -> Option<&T> {
+        match self {
+            Arg::Token(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+/// Function that computes the value of a rule from the values of its right hand side
+pub type Action<T, V> = Rc<dyn Fn(Vec<Arg<T, V>>) -> V>;
+
+/// Values of evaluated subtrees by symbol and hash of the tokens they cover.
+///
+/// Entries that haven't been used by the last `retention` evaluations are dropped. A retention of
+/// at least two keeps the values of a subtree that has been cut and pasted in two separate edits.
+pub struct SubtreeMemo<V> {
+    /// Value and generation of the last use by key
+    values: HashMap<(SymbolId, u64), (V, usize)>,
+    /// Number of evaluations so far
+    generation: usize,
+    /// Number of evaluations an unused entry is kept
+    retention: usize,
+    /// Number of values reused by the last evaluation
+    hits: usize,
+}
+
+impl<V> SubtreeMemo<V> {
+    /// Create an empty memo that keeps unused entries for `retention` evaluations.
+    pub fn new(retention: usize) -> Self {
+        Self {
+            values: HashMap::new(),
+            generation: 0,
+            retention: std::cmp::max(retention, 1),
+            hits: 0,
+        }
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Check if no values are stored
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Number of subtrees the last evaluation didn't need to evaluate
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Drop all values, e.g. when the actions have changed.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Start an evaluation
+    fn begin(&mut self) {
+        self.generation += 1;
+        self.hits = 0;
+    }
+
+    /// Drop the entries that are too old after an evaluation
+    fn end(&mut self) {
+        let (generation, retention) = (self.generation, self.retention);
+        self.values
+            .retain(|_, (_, used)| *used + retention > generation);
+    }
+}
+
+impl<V> Default for SubtreeMemo<V> {
+    fn default() -> Self {
+        Self::new(2)
+    }
+}
+
+/// Key of a subtree in a [SubtreeMemo](struct.SubtreeMemo.html)
+fn memo_key<T: Hash>(symbol: SymbolId, tokens: &[T]) -> (SymbolId, u64) {
+    let mut hasher = DefaultHasher::new();
+    tokens.hash(&mut hasher);
+    (symbol, hasher.finish())
+}
+
+/// Actions by rule index.
+///
+/// Rules without an action pass the value of their first non-terminal on.
+pub struct RuleActions<T, V> {
+    actions: Vec<Option<Action<T, V>>>,
+}
+
+impl<T, V> Default for RuleActions<T, V> {
+    fn default() -> Self {
+        Self {
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl<T, V> RuleActions<T, V>
+where
+    T: Clone,
+{
+    /// Create an empty table
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the action of the rule with the given index.
+    pub fn rule<F>(mut self, rule: usize, action: F) -> Self
+    where
+        F: Fn(Vec<Arg<T, V>>) -> V + 'static,
+    {
+        self.set(rule, Rc::new(action));
+        self
+    }
+
+    /// Set the action of all rules of a non-terminal.
+    ///
+    /// Panics if the grammar has no non-terminal of that name.
+    pub fn symbol<M, F>(mut self, grammar: &CompiledGrammar<T, M>, name: &str, action: F) -> Self
+    where
+        M: Matcher<T> + Clone,
+        F: Fn(Vec<Arg<T, V>>) -> V + 'static,
+    {
+        let id = grammar.nt_id(name);
+        assert!(
+            (id as usize) < grammar.nt_count(),
+            "unknown non-terminal {}",
+            name
+        );
+        let action: Action<T, V> = Rc::new(action);
+        for rule in grammar.rules_for_lhs(id).to_vec() {
+            self.set(rule, action.clone());
+        }
+        self
+    }
+
+    /// Store an action
+    fn set(&mut self, rule: usize, action: Action<T, V>) {
+        if self.actions.len() <= rule {
+            self.actions.resize_with(rule + 1, || None);
+        }
+        self.actions[rule] = Some(action);
+    }
+
+    /// Compute the value of a derivation from the [forest](../sppf/index.html).
+    ///
+    /// `tokens` are the tokens the derivation has been parsed from. Return `None` if a rule without
+    /// action has no value to pass on.
+    pub fn evaluate(&self, derivation: &[DerivationNode], tokens: &[T]) -> Option<V> {
+        // Arguments by node, in reverse order. As the nodes are in pre-order, all children have
+        // been visited when the parent is reached backwards.
+        let mut args: Vec<Vec<Arg<T, V>>> = (0..derivation.len()).map(|_| Vec::new()).collect();
+        let mut result = None;
+        for (index, node) in derivation.iter().enumerate().rev() {
+            let arg = match node.label {
+                NodeLabel::Token => Arg::Token(tokens.get(node.start)?.clone()),
+                NodeLabel::Error => Arg::Error(tokens.get(node.start)?.clone()),
+                _ => {
+                    let mut rhs = std::mem::take(&mut args[index]);
+                    rhs.reverse();
+                    let action = node
+                        .rule
+                        .and_then(|r| self.actions.get(r))
+                        .and_then(|a| a.as_ref());
+                    Arg::Value(match action {
+                        Some(action) => action(rhs),
+                        None => rhs.into_iter().find_map(Arg::into_value)?,
+                    })
+                }
+            };
+            match node.parent {
+                Some(parent) => args[parent].push(arg),
+                None => result = arg.into_value(),
+            }
+        }
+        result
+    }
+
+    /// Like [evaluate](#method.evaluate), but take the values of subtrees from the memo if their
+    /// symbol and tokens have been evaluated before. The values of the other subtrees are added to
+    /// the memo.
+    ///
+    /// The actions must not depend on anything but their arguments.
+    pub fn evaluate_memoized(
+        &self,
+        derivation: &[DerivationNode],
+        tokens: &[T],
+        memo: &mut SubtreeMemo<V>,
+    ) -> Option<V>
+    where
+        T: Hash,
+        V: Clone,
+    {
+        memo.begin();
+        // Key of each non-terminal node and whether its value is known. The descendants of known
+        // nodes are skipped. Parents come first in pre-order.
+        let mut keys = vec![None; derivation.len()];
+        let mut known = vec![false; derivation.len()];
+        let mut skipped = vec![false; derivation.len()];
+        for (index, node) in derivation.iter().enumerate() {
+            if node
+                .parent
+                .is_some_and(|parent| skipped[parent] || known[parent])
+            {
+                skipped[index] = true;
+                continue;
+            }
+            if let NodeLabel::Symbol(symbol) = node.label {
+                let key = memo_key(symbol, tokens.get(node.start..node.end)?);
+                known[index] = memo.values.contains_key(&key);
+                keys[index] = Some(key);
+            }
+        }
+
+        let mut args: Vec<Vec<Arg<T, V>>> = (0..derivation.len()).map(|_| Vec::new()).collect();
+        let mut result = None;
+        for (index, node) in derivation.iter().enumerate().rev() {
+            if skipped[index] {
+                continue;
+            }
+            let generation = memo.generation;
+            let cached = keys[index]
+                .filter(|_| known[index])
+                .and_then(|key| memo.values.get_mut(&key))
+                .map(|(value, used)| {
+                    *used = generation;
+                    value.clone()
+                });
+            let arg = match (cached, node.label) {
+                (Some(value), _) => {
+                    memo.hits += 1;
+                    Arg::Value(value)
+                }
+                (None, NodeLabel::Token) => Arg::Token(tokens.get(node.start)?.clone()),
+                (None, NodeLabel::Error) => Arg::Error(tokens.get(node.start)?.clone()),
+                (None, _) => {
+                    let mut rhs = std::mem::take(&mut args[index]);
+                    rhs.reverse();
+                    let action = node
+                        .rule
+                        .and_then(|r| self.actions.get(r))
+                        .and_then(|a| a.as_ref());
+                    let value = match action {
+                        Some(action) => action(rhs),
+                        None => rhs.into_iter().find_map(Arg::into_value)?,
+                    };
+                    if let Some(key) = keys[index] {
+                        memo.values.insert(key, (value.clone(), generation));
+                    }
+                    Arg::Value(value)
+                }
+            };
+            match node.parent {
+                Some(parent) => args[parent].push(arg),
+                None => result = arg.into_value(),
+            }
+        }
+        memo.end();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::char::CharMatcher;
+    use crate::{Grammar, Parser, Rule};
+
+    #[derive(Debug, PartialEq)]
+    enum Ast {
+        Num(u32),
+        Add(Box<Ast>, Box<Ast>),
+    }
+
+    #[test]
+    fn build_ast() {
+        use CharMatcher::*;
+        // Sum = Sum '+' Num | Num, Num = digit
+        let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+        grammar.set_start("Sum".to_string());
+        grammar.add(Rule::new("Sum").nt("Sum").t(Exact('+')).nt("Num"));
+        grammar.add(Rule::new("Sum").nt("Num"));
+        grammar.add(Rule::new("Num").t(Range('0', '9')));
+        let grammar = grammar.compile().unwrap();
+
+        let actions = RuleActions::new()
+            .rule(1, |args: Vec<Arg<char, Ast>>| {
+                let mut args = args.into_iter();
+                let lhs = args.next().and_then(Arg::into_value).unwrap();
+                let rhs = args.nth(1).and_then(Arg::into_value).unwrap();
+                Ast::Add(Box::new(lhs), Box::new(rhs))
+            })
+            .symbol(&grammar, "Num", |args| {
+                Ast::Num(args[0].token().unwrap().to_digit(10).unwrap())
+            });
+
+        let mut parser = Parser::new(grammar);
+        let tokens: Vec<char> = "1+2+3".chars().collect();
+        assert_eq!(parser.evaluate(&actions, &tokens), None);
+        for (i, c) in tokens.iter().enumerate() {
+            parser.update(i, *c);
+        }
+        // Rule 2 has no action and passes the value of Num on.
+        assert_eq!(
+            parser.evaluate(&actions, &tokens),
+            Some(Ast::Add(
+                Box::new(Ast::Add(Box::new(Ast::Num(1)), Box::new(Ast::Num(2)))),
+                Box::new(Ast::Num(3))
+            ))
+        );
+    }
+
+    #[test]
+    fn memoized() {
+        use std::cell::Cell;
+        use CharMatcher::*;
+        let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+        grammar.set_start("Sum".to_string());
+        grammar.add(Rule::new("Sum").nt("Sum").t(Exact('+')).nt("Num"));
+        grammar.add(Rule::new("Sum").nt("Num"));
+        grammar.add(Rule::new("Num").t(Range('0', '9')));
+        let grammar = grammar.compile().unwrap();
+
+        let calls = Rc::new(Cell::new(0));
+        let (add_calls, num_calls) = (calls.clone(), calls.clone());
+        let actions = RuleActions::new()
+            .rule(1, move |args: Vec<Arg<char, u32>>| {
+                add_calls.set(add_calls.get() + 1);
+                args[0].clone().into_value().unwrap() + args[2].clone().into_value().unwrap()
+            })
+            .symbol(&grammar, "Num", move |args| {
+                num_calls.set(num_calls.get() + 1);
+                args[0].token().unwrap().to_digit(10).unwrap()
+            });
+
+        let mut parser = Parser::new(grammar);
+        let mut memo = SubtreeMemo::default();
+        let tokens: Vec<char> = "1+2+3".chars().collect();
+        parser.update_slice(0, &tokens);
+        assert_eq!(
+            parser.evaluate_memoized(&actions, &tokens, &mut memo),
+            Some(6)
+        );
+        assert_eq!((calls.get(), memo.hits()), (5, 0));
+
+        // Only the changed number and the sum containing it are evaluated again
+        calls.set(0);
+        let tokens: Vec<char> = "1+2+4".chars().collect();
+        parser.update_slice(4, &tokens[4..]);
+        assert_eq!(
+            parser.evaluate_memoized(&actions, &tokens, &mut memo),
+            Some(7)
+        );
+        assert_eq!((calls.get(), memo.hits()), (2, 1));
+
+        // Entries that haven't been used for two evaluations are dropped
+        let tokens = vec!['5'];
+        parser.update_slice(0, &tokens);
+        assert_eq!(
+            parser.evaluate_memoized(&actions, &tokens, &mut memo),
+            Some(5)
+        );
+        assert_eq!(
+            parser.evaluate_memoized(&actions, &tokens, &mut memo),
+            Some(5)
+        );
+        assert_eq!(memo.len(), 2);
+    }
+}
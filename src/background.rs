@@ -0,0 +1,235 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+//! Parsing on a worker thread
+//!
+//! A [BackgroundParser](struct.BackgroundParser.html) owns a second parser on a worker thread.
+//! Each call to [parse](struct.BackgroundParser.html#method.parse) sends a snapshot of the buffer
+//! and cancels the job before it. When a job finishes, the callback receives the chart as a
+//! [ParserState](../struct.ParserState.html), which the foreground parser restores with
+//! [restore_state](../struct.Parser.html#method.restore_state). Both parsers need the same grammar.
+//!
+//! Cancelled jobs don't call the callback. The worker checks for cancellation once per token and
+//! keeps the part it has parsed, so the next job continues from there.
+
+use std::sync::mpsc::{channel, Sender};
+use std::thread::JoinHandle;
+
+use super::util::CancelToken;
+use super::{CompiledGrammar, Matcher, Parser, ParserState};
+
+/// Chart of a finished job
+#[derive(Debug)]
+pub struct ParseResult {
+    /// Number returned by [parse](struct.BackgroundParser.html#method.parse) for this job
+    pub job: u64,
+    /// Parse of the snapshot
+    pub state: ParserState,
+    /// Number of tokens in the snapshot
+    pub len: usize,
+    /// True if the start symbol has been completed at the end of the snapshot without any error
+    /// recovery, see
+    /// [Parser::accepted_at_without_errors](../struct.Parser.html#method.accepted_at_without_errors)
+    pub accepted: bool,
+}
+
+/// Snapshot of the buffer to parse
+struct Job<T> {
+    id: u64,
+    tokens: Vec<T>,
+    changed: usize,
+    cancel: CancelToken,
+}
+
+/// Parser that works on a separate thread.
+///
+/// Dropping it cancels the running job and waits for the worker to finish.
+pub struct BackgroundParser<T> {
+    jobs: Option<Sender<Job<T>>>,
+    worker: Option<JoinHandle<()>>,
+    last_job: u64,
+    cancel: CancelToken,
+}
+
+impl<T> BackgroundParser<T>
+where
+    T: Clone + Send + 'static,
+{
+    /// Start the worker thread with its own parser for `grammar`.
+    ///
+    /// `on_parsed` is called on the worker thread for each finished job.
+    pub fn new<M, F>(grammar: CompiledGrammar<T, M>, mut on_parsed: F) -> Self
+    where
+        M: Matcher<T> + Clone + std::hash::Hash + Send + 'static,
+        F: FnMut(ParseResult) + Send + 'static,
+    {
+        let (jobs, received) = channel::<Job<T>>();
+        let worker = std::thread::spawn(move || {
+            let mut parser = Parser::new(grammar);
+            while let Ok(mut job) = received.recv() {
+                // Only the latest snapshot is of interest. The skipped ones changed the tokens,
+                // too.
+                while let Ok(next) = received.try_recv() {
+                    let changed = std::cmp::min(job.changed, next.changed);
+                    job = next;
+                    job.changed = changed;
+                }
+                parser.buffer_changed(job.changed);
+                let mut finished = true;
+                while parser.valid_entries() < job.tokens.len() {
+                    if job.cancel.is_cancelled() {
+                        finished = false;
+                        break;
+                    }
+                    let i = parser.valid_entries();
+                    parser.update(i, job.tokens[i].clone());
+                }
+                if finished {
+                    on_parsed(ParseResult {
+                        job: job.id,
+                        state: parser.save_state(),
+                        len: job.tokens.len(),
+                        accepted: parser.accepted_at_without_errors(job.tokens.len()),
+                    });
+                }
+            }
+        });
+        Self {
+            jobs: Some(jobs),
+            worker: Some(worker),
+            last_job: 0,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Parse a snapshot of the buffer, which has changed at `changed` since the last snapshot.
+    ///
+    /// The previous job is cancelled. Return the number of the new job, which increases with each
+    /// call. If the buffer changes again before the result has been restored, call
+    /// [buffer_changed](../struct.Parser.html#method.buffer_changed) of the foreground parser
+    /// afterwards.
+    pub fn parse(&mut self, tokens: Vec<T>, changed: usize) -> u64 {
+        self.cancel.cancel();
+        self.cancel = CancelToken::new();
+        self.last_job += 1;
+        if let Some(jobs) = self.jobs.as_ref() {
+            // The worker only stops if the sender has been dropped
+            let _ = jobs.send(Job {
+                id: self.last_job,
+                tokens,
+                changed,
+                cancel: self.cancel.clone(),
+            });
+        }
+        self.last_job
+    }
+
+    /// Cancel the running job.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+impl<T> Drop for BackgroundParser<T> {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::char::CharMatcher;
+    use crate::grammar::tests::define_grammar;
+    use std::sync::mpsc::Receiver;
+
+    fn start() -> (BackgroundParser<char>, Receiver<ParseResult>) {
+        let (results, received) = channel();
+        let background = BackgroundParser::new(define_grammar().compile().unwrap(), move |r| {
+            let _ = results.send(r);
+        });
+        (background, received)
+    }
+
+    fn parse(parser: &mut Parser<char, CharMatcher>, text: &str) {
+        parser.buffer_changed(0);
+        for (i, c) in text.chars().enumerate() {
+            parser.update(i, c);
+        }
+    }
+
+    #[test]
+    fn restore_result() {
+        let (mut background, results) = start();
+        let text = "john called mary ";
+        let job = background.parse(text.chars().collect(), 0);
+        let result = results.recv().expect("job should finish");
+        assert_eq!(
+            (result.job, result.len, result.accepted),
+            (job, text.len(), true)
+        );
+
+        let mut foreground = Parser::new(define_grammar().compile().unwrap());
+        foreground.restore_state(result.state).unwrap();
+        let mut expected = Parser::new(define_grammar().compile().unwrap());
+        parse(&mut expected, text);
+        assert_eq!(foreground.save_state(), expected.save_state());
+
+        // Recovered errors are not accepted
+        let job = background.parse("john cxlled mary ".chars().collect(), 6);
+        let result = results.recv().expect("job should finish");
+        assert_eq!((result.job, result.accepted), (job, false));
+    }
+
+    #[test]
+    fn latest_snapshot_wins() {
+        let (mut background, results) = start();
+        let mut text = String::new();
+        let mut last = 0;
+        for word in ["john ", "called ", "mary ", "from ", "john "].iter() {
+            let changed = text.len();
+            text.push_str(word);
+            last = background.parse(text.chars().collect(), changed);
+        }
+        // Earlier jobs may finish or be cancelled, the last one always finishes
+        let result = loop {
+            let result = results.recv().expect("last job should finish");
+            if result.job == last {
+                break result;
+            }
+        };
+        let mut expected = Parser::new(define_grammar().compile().unwrap());
+        parse(&mut expected, &text);
+        assert_eq!(result.state, expected.save_state());
+        assert!(result.accepted);
+
+        // Dropping stops the worker
+        drop(background);
+        assert!(results.recv().is_err());
+    }
+}
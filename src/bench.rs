@@ -0,0 +1,483 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Throughput benchmarks for the parser
+//!
+//! The benchmarks use a small TOML-like grammar and deterministic synthetic documents. They are
+//! run by `cargo bench`, which calls [run](fn.run.html). Each benchmark body is a plain function
+//! that returns the amount of work it did, so another harness (e.g. criterion) can call them as
+//! well.
+//!
+//! The grammar comes in two shapes with the same language: One uses character classes, the other
+//! spells out every character as a separate rule. This shows how the parser scales with the number
+//! of rules and terminals.
+
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use super::char::CharMatcher;
+use super::{CompiledGrammar, Grammar, Parser, Rule, SynchronousEditor};
+
+/// Number of cursor positions for the prediction benchmark
+pub const PREDICTION_POSITIONS: usize = 100;
+
+/// Number of threads for the parallel scan benchmark
+#[cfg(feature = "parallel")]
+pub const SCAN_THREADS: usize = 4;
+
+/// Shape of the benchmark grammar
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum GrammarShape {
+    /// Character classes are single terminals
+    Classes,
+    /// Character classes are alternatives of exact characters
+    Exact,
+}
+
+/// Deterministic pseudo random numbers
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next() % n
+    }
+}
+
+/// Printable ASCII characters except the given ones
+fn printable_except(except: &[char]) -> Vec<char> {
+    (' '..='~').filter(|c| !except.contains(c)).collect()
+}
+
+/// Add the rules for a character class.
+fn add_class(
+    grammar: &mut Grammar<char, CharMatcher>,
+    shape: GrammarShape,
+    lhs: &str,
+    class: CharMatcher,
+    chars: Vec<char>,
+) {
+    match shape {
+        GrammarShape::Classes => grammar.add(Rule::new(lhs).t(class)),
+        GrammarShape::Exact => {
+            for c in chars {
+                grammar.add(Rule::new(lhs).t(CharMatcher::Exact(c)));
+            }
+        }
+    }
+}
+
+/// Build the benchmark grammar.
+///
+/// The language is a subset of TOML: Tables, key-value pairs with strings, integers, booleans and
+/// arrays, and comments.
+pub fn toml_grammar(shape: GrammarShape) -> CompiledGrammar<char, CharMatcher> {
+    use CharMatcher::*;
+
+    let mut g = Grammar::new();
+    g.add(Rule::new("toml").nt("lines"));
+    g.add(Rule::new("lines"));
+    g.add(Rule::new("lines").nt("lines").nt("line"));
+    g.add(
+        Rule::new("line")
+            .nt("ws")
+            .nt("expression")
+            .nt("ws")
+            .nt("comment")
+            .t(Exact('\n')),
+    );
+    g.add(Rule::new("expression"));
+    g.add(Rule::new("expression").nt("table"));
+    g.add(Rule::new("expression").nt("keyval"));
+    g.add(
+        Rule::new("table")
+            .t(Exact('['))
+            .nt("ws")
+            .nt("key")
+            .nt("ws")
+            .t(Exact(']')),
+    );
+    g.add(
+        Rule::new("keyval")
+            .nt("key")
+            .nt("ws")
+            .t(Exact('='))
+            .nt("ws")
+            .nt("val"),
+    );
+    g.add(Rule::new("key").nt("keychar"));
+    g.add(Rule::new("key").nt("key").nt("keychar"));
+    g.add(Rule::new("keychar").nt("alpha"));
+    g.add(Rule::new("keychar").nt("digit"));
+    g.add(Rule::new("keychar").t(Exact('_')));
+    g.add(Rule::new("keychar").t(Exact('-')));
+    g.add(Rule::new("val").nt("string"));
+    g.add(Rule::new("val").nt("integer"));
+    g.add(Rule::new("val").nt("boolean"));
+    g.add(Rule::new("val").nt("array"));
+    g.add(
+        Rule::new("string")
+            .t(Exact('"'))
+            .nt("strchars")
+            .t(Exact('"')),
+    );
+    g.add(Rule::new("strchars"));
+    g.add(Rule::new("strchars").nt("strchars").nt("strchar"));
+    g.add(Rule::new("integer").nt("digit"));
+    g.add(Rule::new("integer").nt("integer").nt("digit"));
+    g.add(Rule::new("boolean").ts("true".chars().map(Exact)));
+    g.add(Rule::new("boolean").ts("false".chars().map(Exact)));
+    g.add(
+        Rule::new("array")
+            .t(Exact('['))
+            .nt("ws")
+            .nt("values")
+            .nt("ws")
+            .t(Exact(']')),
+    );
+    g.add(Rule::new("values"));
+    g.add(Rule::new("values").nt("value_list"));
+    g.add(Rule::new("value_list").nt("val"));
+    g.add(
+        Rule::new("value_list")
+            .nt("value_list")
+            .nt("ws")
+            .t(Exact(','))
+            .nt("ws")
+            .nt("val"),
+    );
+    g.add(Rule::new("ws"));
+    g.add(Rule::new("ws").nt("ws").nt("wschar"));
+    g.add(Rule::new("wschar").t(Exact(' ')));
+    g.add(Rule::new("wschar").t(Exact('\t')));
+    g.add(Rule::new("comment"));
+    g.add(Rule::new("comment").t(Exact('#')).nt("comment_chars"));
+    g.add(Rule::new("comment_chars"));
+    g.add(
+        Rule::new("comment_chars")
+            .nt("comment_chars")
+            .nt("comment_char"),
+    );
+
+    add_class(
+        &mut g,
+        shape,
+        "alpha",
+        Range('a', 'z'),
+        ('a'..='z').collect(),
+    );
+    add_class(
+        &mut g,
+        shape,
+        "alpha",
+        Range('A', 'Z'),
+        ('A'..='Z').collect(),
+    );
+    add_class(
+        &mut g,
+        shape,
+        "digit",
+        Range('0', '9'),
+        ('0'..='9').collect(),
+    );
+    add_class(
+        &mut g,
+        shape,
+        "strchar",
+        NoneOf(vec!['"', '\\', '\n']),
+        printable_except(&['"', '\\']),
+    );
+    add_class(
+        &mut g,
+        shape,
+        "comment_char",
+        NoneOf(vec!['\n']),
+        printable_except(&[]),
+    );
+
+    g.set_start("toml".to_string());
+    g.compile()
+        .expect("compiling the benchmark grammar should not fail")
+}
+
+/// Words for keys, tables and strings
+const WORDS: [&str; 12] = [
+    "package", "name", "version", "edition", "features", "default", "path", "optional", "serde",
+    "log", "build", "release",
+];
+
+/// Generate a synthetic document of at least `size_kb` kilobytes.
+///
+/// The document is accepted by [toml_grammar](fn.toml_grammar.html) and only contains printable
+/// ASCII characters and newlines. The same size always gives the same document.
+pub fn generate_toml(size_kb: usize) -> String {
+    let mut rng = Lcg(0x5e5d);
+    let mut doc = String::new();
+    let mut section = 0;
+    while doc.len() < size_kb * 1024 {
+        let word = WORDS[rng.below(WORDS.len())];
+        match rng.below(16) {
+            0 => {
+                section += 1;
+                doc.push_str(&format!("\n[{}-{}]\n", word, section));
+            }
+            1 => doc.push_str(&format!("# {} of section {}\n", word, section)),
+            2..=6 => {
+                let other = WORDS[rng.below(WORDS.len())];
+                doc.push_str(&format!("{} = \"{} {}\"\n", word, other, rng.below(1000)));
+            }
+            7..=9 => doc.push_str(&format!("{}_{} = {}\n", word, section, rng.next())),
+            10 | 11 => doc.push_str(&format!("{} = {}\n", word, rng.below(2) == 0)),
+            _ => {
+                let items: Vec<String> = (0..1 + rng.below(5))
+                    .map(|_| rng.below(100).to_string())
+                    .collect();
+                doc.push_str(&format!("{} = [{}]  # list\n", word, items.join(", ")));
+            }
+        }
+    }
+    doc
+}
+
+/// Create an editor for the benchmark grammar that contains the given document.
+pub fn editor(shape: GrammarShape, doc: &str) -> SynchronousEditor<char, CharMatcher> {
+    let mut editor = SynchronousEditor::new(toml_grammar(shape));
+    editor.enter_iter(doc.chars());
+    editor
+}
+
+/// Parse the whole document from scratch. Returns the number of tokens.
+pub fn cold_parse(editor: &mut SynchronousEditor<char, CharMatcher>, doc: &str) -> u64 {
+    editor.clear();
+    editor.enter_iter(doc.chars());
+    editor.len() as u64
+}
+
+/// Insert and delete a character in the middle of the buffer. Returns the number of edits.
+pub fn edit_in_the_middle(editor: &mut SynchronousEditor<char, CharMatcher>) -> u64 {
+    let middle = editor.len() / 2;
+    editor.set_cursor(middle);
+    editor.enter('#');
+    editor.set_cursor(middle);
+    editor.delete(1);
+    2
+}
+
+/// Query the predictions at pseudo random cursor positions. Returns the number of queries.
+pub fn predictions(editor: &mut SynchronousEditor<char, CharMatcher>) -> u64 {
+    let mut rng = Lcg(0x9e37);
+    for _ in 0..PREDICTION_POSITIONS {
+        editor.set_cursor(rng.below(editor.len() + 1));
+        black_box(editor.predictions_at_cursor());
+    }
+    PREDICTION_POSITIONS as u64
+}
+
+/// Parse the whole document from scratch without an editor. Returns the number of tokens.
+pub fn scan(parser: &mut Parser<char, CharMatcher>, tokens: &[char]) -> u64 {
+    parser.buffer_changed(0);
+    parser.update_slice(0, tokens);
+    tokens.len() as u64
+}
+
+/// Traverse the whole parse tree. Returns the number of nodes.
+pub fn cst_traversal(editor: &SynchronousEditor<char, CharMatcher>) -> u64 {
+    editor.cst_iter().map(black_box).count() as u64
+}
+
+/// Timing samples of a benchmark
+pub struct Measurement {
+    /// Name of the benchmark
+    pub name: String,
+    /// Unit of the work done per iteration
+    pub unit: &'static str,
+    /// Work done per iteration
+    pub work: u64,
+    /// Duration of each iteration
+    pub samples: Vec<Duration>,
+}
+
+impl Measurement {
+    /// Median duration of an iteration
+    pub fn median(&self) -> Duration {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        sorted.get(sorted.len() / 2).copied().unwrap_or_default()
+    }
+
+    /// Work done per second, based on the median
+    pub fn rate(&self) -> f64 {
+        let secs = self.median().as_secs_f64();
+        if secs == 0.0 {
+            0.0
+        } else {
+            self.work as f64 / secs
+        }
+    }
+}
+
+impl fmt::Display for Measurement {
+    /// Print one line per benchmark: name, median, rate.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "bench {:<28} median {:>12} ns/iter {:>14.0} {}/s",
+            self.name,
+            self.median().as_nanos(),
+            self.rate(),
+            self.unit
+        )
+    }
+}
+
+/// Time a benchmark body.
+///
+/// Runs the body `warmup` times without measuring it, then `samples` times with measuring. The
+/// body returns the work it did.
+pub fn measure<F>(
+    name: &str,
+    unit: &'static str,
+    warmup: usize,
+    samples: usize,
+    mut body: F,
+) -> Measurement
+where
+    F: FnMut() -> u64,
+{
+    for _ in 0..warmup {
+        black_box(body());
+    }
+    let mut work = 0;
+    let samples = (0..samples)
+        .map(|_| {
+            let start = Instant::now();
+            work = black_box(body());
+            start.elapsed()
+        })
+        .collect();
+    Measurement {
+        name: name.to_string(),
+        unit,
+        work,
+        samples,
+    }
+}
+
+/// Run all benchmarks on a document of `size_kb` kilobytes.
+pub fn run(size_kb: usize, warmup: usize, samples: usize) -> Vec<Measurement> {
+    let doc = generate_toml(size_kb);
+    let mut results = Vec::new();
+
+    for (shape, name) in [
+        (GrammarShape::Classes, "cold_parse/classes"),
+        (GrammarShape::Exact, "cold_parse/exact"),
+    ] {
+        let mut editor = SynchronousEditor::new(toml_grammar(shape));
+        results.push(measure(name, "tokens", warmup, samples, || {
+            cold_parse(&mut editor, &doc)
+        }));
+    }
+
+    let mut editor = editor(GrammarShape::Classes, &doc);
+    results.push(measure(
+        "edit_in_the_middle",
+        "edits",
+        warmup,
+        samples,
+        || edit_in_the_middle(&mut editor),
+    ));
+    results.push(measure("predictions", "queries", warmup, samples, || {
+        predictions(&mut editor)
+    }));
+    results.push(measure("cst_traversal", "nodes", warmup, samples, || {
+        cst_traversal(&editor)
+    }));
+
+    // Every column is handed to the thread pool, which shows the overhead for columns of the
+    // usual width.
+    #[cfg(feature = "parallel")]
+    {
+        let tokens: Vec<char> = doc.chars().collect();
+        for (threads, name) in [(1, "scan/sequential"), (SCAN_THREADS, "scan/parallel")] {
+            let mut parser = Parser::new(toml_grammar(GrammarShape::Exact));
+            parser.set_parallel_scan(threads, 0);
+            results.push(measure(name, "tokens", warmup, samples, || {
+                scan(&mut parser, &tokens)
+            }));
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_document() {
+        let doc = generate_toml(2);
+        assert!(doc.len() >= 2048);
+        assert_eq!(doc, generate_toml(2));
+        assert!(doc.chars().all(|c| c == '\n' || (' '..='~').contains(&c)));
+    }
+
+    #[test]
+    fn smoke() {
+        let doc = generate_toml(1);
+        for shape in [GrammarShape::Classes, GrammarShape::Exact] {
+            let mut editor = editor(shape, &doc);
+            assert!(editor.is_accepted(), "{:?}", shape);
+
+            assert_eq!(cold_parse(&mut editor, &doc), doc.chars().count() as u64);
+            assert!(editor.is_accepted());
+
+            assert_eq!(edit_in_the_middle(&mut editor), 2);
+            assert_eq!(editor.as_string(), doc);
+            assert!(editor.is_accepted());
+
+            assert_eq!(predictions(&mut editor), PREDICTION_POSITIONS as u64);
+            assert!(cst_traversal(&editor) > doc.len() as u64);
+        }
+    }
+
+    #[test]
+    fn report_format() {
+        let m = measure("tiny", "tokens", 1, 3, || 1000);
+        assert_eq!(m.samples.len(), 3);
+        assert_eq!(m.work, 1000);
+        let line = m.to_string();
+        assert!(line.starts_with("bench tiny "), "{}", line);
+        assert!(line.ends_with(" tokens/s"), "{}", line);
+    }
+}
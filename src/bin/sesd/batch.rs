@@ -0,0 +1,227 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Coalescing of inputs
+//!
+//! Holding down a key floods the editor with inputs. Instead of rebuilding the document after each
+//! of them, all immediately available inputs are processed first and the screen is updated once
+//! for the whole batch.
+
+use pancurses::{Input, Window};
+
+use super::AppCmd;
+
+/// Maximal number of inputs processed before the screen is updated
+const MAX_BATCH_LEN: usize = 256;
+
+/// Source of user inputs
+pub trait InputSource {
+    /// Return the next input if one is available without waiting.
+    fn poll_input(&mut self) -> Option<Input>;
+}
+
+impl InputSource for &Window {
+    fn poll_input(&mut self) -> Option<Input> {
+        self.timeout(0);
+        let input = self.getch();
+        self.timeout(super::idle::INPUT_TIMEOUT_MS);
+        input
+    }
+}
+
+/// Update phases to run after a batch of inputs
+#[derive(Debug, Default, PartialEq)]
+pub struct Phases {
+    /// Quit the app, skip all other phases
+    pub quit: bool,
+
+    /// Rebuild the document
+    pub document: bool,
+
+    /// Update predictions and scroll position
+    pub cursor: bool,
+
+    /// Redisplay unconditionally
+    pub display: bool,
+}
+
+impl Phases {
+    /// Compute the minimal set of phases to run for a batch of commands.
+    pub fn of(cmds: &[AppCmd]) -> Self {
+        let mut phases = Phases::default();
+        for cmd in cmds {
+            match cmd {
+                AppCmd::Nothing => {}
+                AppCmd::Quit => {
+                    return Phases {
+                        quit: true,
+                        ..Phases::default()
+                    }
+                }
+                AppCmd::Document => {
+                    phases.document = true;
+                    phases.cursor = true;
+                    phases.display = true;
+                }
+                AppCmd::Cursor => phases.cursor = true,
+                AppCmd::Display => phases.display = true,
+            }
+        }
+        phases
+    }
+
+    /// Check if anything needs to be done
+    pub fn any(&self) -> bool {
+        self.quit || self.document || self.cursor || self.display
+    }
+}
+
+/// Process the first input and, if it changed the document, all immediately available inputs.
+///
+/// Processing stops at a quit command. Return the commands of all processed inputs.
+pub fn drain<S: InputSource>(
+    source: &mut S,
+    first: Input,
+    mut handle: impl FnMut(Input) -> AppCmd,
+) -> Vec<AppCmd> {
+    let mut cmds = vec![handle(first)];
+    if cmds[0] == AppCmd::Document {
+        while cmds.len() < MAX_BATCH_LEN {
+            match source.poll_input() {
+                Some(input) => {
+                    let cmd = handle(input);
+                    let quit = cmd == AppCmd::Quit;
+                    cmds.push(cmd);
+                    if quit {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+    cmds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{cargo_toml, Editor};
+    use super::*;
+    use std::collections::VecDeque;
+
+    impl InputSource for VecDeque<Input> {
+        fn poll_input(&mut self) -> Option<Input> {
+            self.pop_front()
+        }
+    }
+
+    #[test]
+    fn mixed_batch() {
+        use AppCmd::*;
+        assert_eq!(Phases::of(&[Nothing]), Phases::default());
+        assert_eq!(
+            Phases::of(&[Cursor, Display]),
+            Phases {
+                cursor: true,
+                display: true,
+                ..Phases::default()
+            }
+        );
+        // Characters, arrows, and F2 (which only redisplays)
+        let phases = Phases::of(&[Document, Document, Cursor, Document, Display]);
+        assert_eq!(
+            phases,
+            Phases {
+                document: true,
+                cursor: true,
+                display: true,
+                ..Phases::default()
+            }
+        );
+        assert!(phases.any());
+        assert_eq!(
+            Phases::of(&[Document, Quit, Cursor]),
+            Phases {
+                quit: true,
+                ..Phases::default()
+            }
+        );
+    }
+
+    /// Handle inputs like the app does for the keys used in the tests
+    fn handle(editor: &mut Editor, input: Input) -> AppCmd {
+        match input {
+            Input::Character(c) => {
+                editor.enter(c);
+                AppCmd::Document
+            }
+            Input::KeyLeft => {
+                editor.move_backward(1);
+                AppCmd::Cursor
+            }
+            Input::KeyF2 => AppCmd::Display,
+            Input::KeyF10 => AppCmd::Quit,
+            _ => AppCmd::Nothing,
+        }
+    }
+
+    #[test]
+    fn drain_loop() {
+        let mut editor = Editor::new(cargo_toml::grammar());
+        let mut source: VecDeque<Input> = "ab"
+            .chars()
+            .map(Input::Character)
+            .chain(vec![Input::KeyLeft, Input::KeyF2, Input::Character('c')])
+            .collect();
+        let cmds = drain(&mut source, Input::Character('x'), |i| {
+            handle(&mut editor, i)
+        });
+        assert_eq!(cmds.len(), 6);
+        assert!(source.is_empty());
+        assert_eq!(editor.span_string(0, 4), "xacb");
+        assert!(Phases::of(&cmds).document);
+    }
+
+    #[test]
+    fn drain_stops() {
+        let mut editor = Editor::new(cargo_toml::grammar());
+
+        // Cursor movements are not batched
+        let mut source: VecDeque<Input> = vec![Input::Character('a')].into_iter().collect();
+        let cmds = drain(&mut source, Input::KeyLeft, |i| handle(&mut editor, i));
+        assert_eq!(cmds, vec![AppCmd::Cursor]);
+        assert_eq!(source.len(), 1);
+
+        // Quit is processed immediately, later inputs stay in the queue
+        let mut source: VecDeque<Input> = vec![Input::KeyF10, Input::Character('b')]
+            .into_iter()
+            .collect();
+        let cmds = drain(&mut source, Input::Character('a'), |i| {
+            handle(&mut editor, i)
+        });
+        assert_eq!(cmds, vec![AppCmd::Document, AppCmd::Quit]);
+        assert_eq!(source.len(), 1);
+        assert!(Phases::of(&cmds).quit);
+    }
+}
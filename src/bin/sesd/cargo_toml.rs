@@ -27,9 +27,20 @@
 //! This is based on https://github.com/toml-lang/toml/blob/master/toml.abnf, which is
 //! MIT licensed.
 
-use sesd::{char::CharMatcher, CompiledGrammar, Grammar, Rule, Symbol, ERROR_ID};
+use std::rc::Rc;
 
-use super::look_and_feel::{LookAndFeel, Style, StyleMatcher};
+use sesd::{
+    char::CharMatcher,
+    prediction::{Prediction, PredictionContext, PredictionEngine, PredictionProvider},
+    snippet::Snippet,
+    style_sheet::StyleSheet,
+    CompiledGrammar, Grammar, Rule, Symbol, SymbolId, ERROR_ID,
+};
+
+use super::look_and_feel::{LookAndFeel, Style, StyleMatcher, SubLanguage};
+
+/// Longest string that is checked for a version requirement
+const MAX_SEMVER_LEN: usize = 64;
 
 /// Build the grammar for TOML files
 pub fn grammar() -> CompiledGrammar<char, CharMatcher> {
@@ -67,6 +78,11 @@ impl SB {
         self
     }
 
+    fn section(mut self) -> Self {
+        self.s.section_break_before = true;
+        self
+    }
+
     fn cp(mut self, c: pancurses::ColorPair) -> Self {
         self.s.attr.set_color_pair(c);
         self
@@ -77,9 +93,9 @@ impl SB {
 pub fn look_and_feel(grammar: &CompiledGrammar<char, CharMatcher>) -> LookAndFeel {
     let mut sheet = LookAndFeel::new(Style::none());
 
-    // Table headers, underlined
+    // Table headers, underlined, separated by a rule
     sheet.add_style(
-        StyleMatcher::new(SB::new().u().s)
+        StyleMatcher::new(SB::new().u().section().s)
             .exact(grammar.nt_id("toml"))
             .star(grammar.nt_id("expressions"))
             .exact(grammar.nt_id("expression"))
@@ -139,6 +155,14 @@ pub fn look_and_feel(grammar: &CompiledGrammar<char, CharMatcher>) -> LookAndFee
             .exact(grammar.nt_id("inline-table")),
     );
 
+    // Version requirements inside strings
+    sheet.add_sub_language(SubLanguage::new(
+        grammar.nt_id("string"),
+        semver_grammar(),
+        semver_style_sheet(),
+        MAX_SEMVER_LEN,
+    ));
+
     // Any error, white on red
     sheet.add_style(
         StyleMatcher::new(SB::new().cp(pancurses::ColorPair(0o71)).i().s).skip_to(ERROR_ID),
@@ -167,12 +191,125 @@ pub fn look_and_feel(grammar: &CompiledGrammar<char, CharMatcher>) -> LookAndFee
         ],
     );
 
+    // Snippets
+    sheet.add_snippet("keyval", "name = \"$1\"");
+    sheet.add_snippet("keyval", "version = \"$1.$2.$3\"");
+    sheet.add_snippet("keyval", "$1 = { version = \"$2\", features = [$3] }");
+
     sheet
 }
 
-/// Internal function to support testing
+/// Suggests today's date (UTC) where a date-time value can be entered
+struct TodayProvider {
+    date_time: SymbolId,
+}
+
+impl PredictionProvider<char, CharMatcher> for TodayProvider {
+    fn predict(&self, ctx: &PredictionContext<char, CharMatcher>) -> Vec<Prediction> {
+        if !ctx.predicted().contains(&self.date_time) {
+            return Vec::new();
+        }
+        let days = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() / 86400)
+            .unwrap_or(0);
+        vec![Snippet::plain(&date_string(days))]
+    }
+}
+
+/// Format the date in ISO 8601 that is the given number of days after 1970-01-01.
+fn date_string(days: u64) -> String {
+    // Count from 0000-03-01 to have the leap day at the end of the year.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Build the sources of predictions for Cargo.toml files. The style sheet comes first.
+pub fn prediction_engine(
+    grammar: &CompiledGrammar<char, CharMatcher>,
+    look_and_feel: Rc<LookAndFeel>,
+) -> PredictionEngine<char, CharMatcher> {
+    let mut engine = PredictionEngine::new();
+    engine.add(look_and_feel);
+    engine.add(TodayProvider {
+        date_time: grammar.nt_id("date-time"),
+    });
+    engine
+}
+
+/// Symbols of the nodes that can be swapped with their siblings: Array values and key-value pairs.
+/// Table headers separate the key-value pairs of different tables.
+pub fn sibling_symbols(grammar: &CompiledGrammar<char, CharMatcher>) -> Vec<SymbolId> {
+    grammar.nt_ids(&["val", "keyval", "table"])
+}
+
+/// Build the grammar for quoted version requirements, e.g. `"^1.0.3"`
+fn semver_grammar() -> CompiledGrammar<char, CharMatcher> {
+    let mut grammar = Grammar::<char, CharMatcher>::new();
+
+    use CharMatcher::*;
+
+    grammar.set_start("semver".to_string());
+    grammar.add(Rule::new("semver").t(Exact('"')).nt("req").t(Exact('"')));
+    grammar.add(Rule::new("req").nt("version"));
+    grammar.add(Rule::new("req").nt("op").nt("version"));
+    for op in ["^", "~", "=", "<", ">", "<=", ">="].iter() {
+        grammar.add(Rule::new("op").ts(op.chars().map(Exact)));
+    }
+    grammar.add(Rule::new("version").nt("number"));
+    grammar.add(Rule::new("version").nt("number").t(Exact('.')).nt("number"));
+    grammar.add(
+        Rule::new("version")
+            .nt("number")
+            .t(Exact('.'))
+            .nt("number")
+            .t(Exact('.'))
+            .nt("number"),
+    );
+    grammar.add(Rule::new("number").t(Range('0', '9')));
+    grammar.add(Rule::new("number").t(Range('0', '9')).nt("number"));
+
+    grammar
+        .compile()
+        .expect("compiling built-in grammar should not fail")
+}
+
+/// Build the style sheet for version requirements
+fn semver_style_sheet() -> StyleSheet<Style> {
+    let grammar = semver_grammar();
+    let mut sheet = StyleSheet::new();
+
+    // Operators, bold magenta on black
+    sheet.add(
+        StyleMatcher::new(SB::new().cp(pancurses::ColorPair(0o50)).b().s)
+            .skip_to(grammar.nt_id("op")),
+    );
+
+    // Version numbers, yellow on black
+    sheet.add(
+        StyleMatcher::new(SB::new().cp(pancurses::ColorPair(0o30)).s)
+            .skip_to(grammar.nt_id("number")),
+    );
+
+    sheet
+}
+
+/// Rules of the TOML grammar without a start symbol
 ///
-/// No start symbol is set, thus sub-rules can be tested.
+/// Use `Parser::with_start` to parse a sub-rule.
 fn grammar_nostart() -> Grammar<char, CharMatcher> {
     let mut grammar = Grammar::<char, CharMatcher>::new();
 
@@ -1309,17 +1446,116 @@ fn grammar_nostart() -> Grammar<char, CharMatcher> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use sesd::{Parser, Verdict};
+    use sesd::{Parser, SynchronousEditor, Verdict};
+
+    /// Classify the text and return the text of each span with its attributes
+    fn styled_spans(text: &str) -> Vec<(String, Option<pancurses::Attributes>)> {
+        let look_and_feel = look_and_feel(&grammar());
+        let mut editor = SynchronousEditor::new(grammar());
+        editor.enter_iter(text.chars());
+        look_and_feel
+            .classify(editor.parser(), editor.tokens())
+            .iter()
+            .map(|span| {
+                (
+                    editor.span_string(span.start, span.end),
+                    span.style.map(|s| s.attr),
+                )
+            })
+            .collect()
+    }
 
     #[test]
-    fn table() {
-        let mut grammar = grammar_nostart();
+    fn semver_in_string() {
+        let spans = styled_spans("[dependencies]\nserde = \"^1.0.3\"\n");
+        let string_attr = Some(SB::new().cp(pancurses::ColorPair(0o50)).s.attr);
+        let op_attr = Some(SB::new().cp(pancurses::ColorPair(0o50)).b().s.attr);
+        let number_attr = Some(SB::new().cp(pancurses::ColorPair(0o30)).s.attr);
+
+        let start = spans
+            .iter()
+            .position(|(t, _)| t == "\"")
+            .expect("opening quote should have its own span");
+        assert_eq!(
+            &spans[start..start + 8],
+            &[
+                ("\"".to_string(), string_attr),
+                ("^".to_string(), op_attr),
+                ("1".to_string(), number_attr),
+                (".".to_string(), string_attr),
+                ("0".to_string(), number_attr),
+                (".".to_string(), string_attr),
+                ("3".to_string(), number_attr),
+                ("\"".to_string(), string_attr),
+            ]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date_string(0), "1970-01-01");
+        assert_eq!(date_string(11_016), "2000-02-29");
+        assert_eq!(date_string(20_742), "2026-10-16");
+    }
+
+    #[test]
+    fn today_after_style_sheet() {
+        let grammar = grammar();
+        let engine = prediction_engine(&grammar, Rc::new(look_and_feel(&grammar)));
+        let mut editor = SynchronousEditor::new(grammar);
+        editor.enter_iter("[package]\npublished = ".chars());
+        let predictions = engine.predict(&editor);
+        let today = predictions
+            .last()
+            .expect("date should be predicted")
+            .clone();
+        assert_eq!(today.text.len(), 10);
+        assert!(today.text.starts_with("20"));
 
-        grammar.set_start("table".to_string());
+        // No date at the start of a line
+        editor.enter_iter("2020-01-01\n".chars());
+        assert!(!engine.predict(&editor).contains(&today));
+    }
+
+    #[test]
+    fn swap_siblings() {
+        let grammar = grammar();
+        let symbols = sibling_symbols(&grammar);
+        let mut editor = SynchronousEditor::new(grammar);
+        editor.enter_iter("[features]\ndefault = [1, 22, 333]\nstd = []\n".chars());
+
+        // Cursor inside 22
+        editor.set_cursor(26);
+        assert!(editor.swap_with_prev_sibling(&symbols));
+        assert_eq!(
+            editor.as_string(),
+            "[features]\ndefault = [22, 1, 333]\nstd = []\n"
+        );
+        assert_eq!(editor.cursor(), 23);
+        assert!(editor.is_accepted());
 
-        let compiled_grammar = grammar.compile().expect("compilation should have worked");
+        // Cursor in the key of the second pair
+        editor.set_cursor(35);
+        assert!(editor.swap_with_prev_sibling(&symbols));
+        assert_eq!(
+            editor.as_string(),
+            "[features]\nstd = []\ndefault = [22, 1, 333]\n"
+        );
+        assert_eq!(editor.cursor(), 12);
+        assert!(!editor.swap_with_prev_sibling(&symbols));
+    }
+
+    #[test]
+    fn non_semver_string() {
+        let spans = styled_spans("[package]\nname = \"sesd\"\n");
+        let string_attr = Some(SB::new().cp(pancurses::ColorPair(0o50)).s.attr);
+        assert!(spans.contains(&("\"sesd\"".to_string(), string_attr)));
+    }
 
-        let mut parser = Parser::<char, CharMatcher>::new(compiled_grammar);
+    #[test]
+    fn table() {
+        let mut parser =
+            Parser::with_start(grammar(), "table").expect("table should be a non-terminal");
         let mut position = 0;
         for (i, c) in "[key.rest".chars().enumerate() {
             let res = parser.update(i, c);
@@ -1327,7 +1563,33 @@ pub mod tests {
             position = i;
         }
         let res = parser.update(position + 1, ']');
-        parser.print_chart();
         assert_eq!(res, Verdict::Accept);
     }
+
+    #[test]
+    fn export_every_rule_once() {
+        use sesd::export::{grammar_to_writer, GrammarExportFormat};
+
+        let grammar = grammar();
+        let mut out = Vec::new();
+        grammar_to_writer(&grammar, &mut out, GrammarExportFormat::Json)
+            .expect("writing to memory should work");
+        let json = String::from_utf8(out).expect("export should be UTF-8");
+
+        let symbol_count = grammar.nt_count() + grammar.terminals().len();
+        let mut seen = vec![0; grammar.rule_count()];
+        for line in json.lines().filter(|l| l.contains("\"index\"")) {
+            let numbers: Vec<usize> = line
+                .split(|c: char| !c.is_ascii_digit())
+                .filter(|n| !n.is_empty())
+                .map(|n| n.parse().expect("numbers should parse"))
+                .collect();
+            let (index, lhs, rhs) = (numbers[0], numbers[1], &numbers[2..]);
+            seen[index] += 1;
+            assert!(lhs < grammar.nt_count());
+            assert!(rhs.iter().all(|s| *s < symbol_count));
+            assert_eq!(lhs, grammar.lhs(index) as usize);
+        }
+        assert!(seen.iter().all(|n| *n == 1));
+    }
 }
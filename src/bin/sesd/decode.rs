@@ -0,0 +1,205 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Lossy UTF-8 decoding of input files
+//!
+//! Invalid byte sequences are replaced by U+FFFD. The character index of each replacement is
+//! recorded, so the caller can tell the user which parts of the file did not survive decoding.
+
+use std::io::Read;
+
+/// Replacement character for invalid byte sequences
+pub const REPLACEMENT: char = '\u{FFFD}';
+
+/// Size of the chunks read from a file
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Incremental UTF-8 decoder.
+///
+/// Bytes can be pushed in arbitrary chunks. Sequences that straddle a chunk boundary are kept
+/// until the next chunk arrives. The result is identical to `String::from_utf8_lossy` on the
+/// concatenated input.
+pub struct Utf8Decoder {
+    /// Decoded text so far
+    text: String,
+
+    /// Number of characters in `text`
+    chars: usize,
+
+    /// Character indices of the replacement characters that were inserted for invalid sequences
+    errors: Vec<usize>,
+
+    /// Bytes of an incomplete sequence at the end of the last chunk
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            chars: 0,
+            errors: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.chars += s.chars().count();
+    }
+
+    fn push_replacement(&mut self) {
+        self.errors.push(self.chars);
+        self.text.push(REPLACEMENT);
+        self.chars += 1;
+    }
+
+    /// Decode the next chunk of bytes.
+    pub fn push(&mut self, bytes: &[u8]) {
+        let mut input = std::mem::take(&mut self.pending);
+        input.extend_from_slice(bytes);
+
+        let mut rest = &input[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    self.push_str(s);
+                    return;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    self.push_str(
+                        std::str::from_utf8(valid).expect("prefix should have been valid"),
+                    );
+                    match e.error_len() {
+                        Some(len) => {
+                            self.push_replacement();
+                            rest = &after[len..];
+                        }
+                        None => {
+                            // Incomplete sequence at the end. Wait for more bytes.
+                            self.pending = after.to_vec();
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finish decoding. Return the text and the character indices of the replacements.
+    ///
+    /// An incomplete sequence at the end of the input counts as one invalid sequence.
+    pub fn finish(mut self) -> (String, Vec<usize>) {
+        if !self.pending.is_empty() {
+            self.push_replacement();
+        }
+        (self.text, self.errors)
+    }
+}
+
+/// Decode a byte slice, replacing invalid sequences.
+///
+/// Return the text and the character indices of the replacement characters.
+#[cfg(test)]
+pub fn decode_lossy(bytes: &[u8]) -> (String, Vec<usize>) {
+    let mut decoder = Utf8Decoder::new();
+    decoder.push(bytes);
+    decoder.finish()
+}
+
+/// Read and decode everything from a reader in chunks.
+pub fn read_lossy<R: Read>(reader: &mut R) -> std::io::Result<(String, Vec<usize>)> {
+    let mut decoder = Utf8Decoder::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        decoder.push(&chunk[..n]);
+    }
+    Ok(decoder.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode by pushing chunks of the given size
+    fn decode_chunked(bytes: &[u8], size: usize) -> (String, Vec<usize>) {
+        let mut decoder = Utf8Decoder::new();
+        for c in bytes.chunks(size) {
+            decoder.push(c);
+        }
+        decoder.finish()
+    }
+
+    #[test]
+    fn valid() {
+        let (text, errors) = decode_lossy("a → b".as_bytes());
+        assert_eq!(text, "a → b");
+        assert!(errors.is_empty());
+
+        // Replacement characters in the input are not errors
+        let (text, errors) = decode_lossy("a\u{FFFD}b".as_bytes());
+        assert_eq!(text, "a\u{FFFD}b");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn invalid_start_middle_end() {
+        let (text, errors) = decode_lossy(b"\xffab");
+        assert_eq!(text, "\u{FFFD}ab");
+        assert_eq!(errors, vec![0]);
+
+        let (text, errors) = decode_lossy(b"a\xc3\xa4\xffb");
+        assert_eq!(text, "aä\u{FFFD}b");
+        assert_eq!(errors, vec![2]);
+
+        // Truncated multi-byte sequence at the end
+        let (text, errors) = decode_lossy(b"ab\xe2\x86");
+        assert_eq!(text, "ab\u{FFFD}");
+        assert_eq!(errors, vec![2]);
+    }
+
+    #[test]
+    fn chunk_boundaries() {
+        let input = b"x\xe2\x86\x92y\xff\xfez\xe2\x86";
+        let expected = String::from_utf8_lossy(input).into_owned();
+        for size in 1..=input.len() {
+            let (text, errors) = decode_chunked(input, size);
+            assert_eq!(text, expected, "chunk size {}", size);
+            assert_eq!(errors, vec![3, 4, 6], "chunk size {}", size);
+        }
+    }
+
+    #[test]
+    fn reader() {
+        let mut input: &[u8] = b"key = \"\xff\"\n";
+        let (text, errors) = read_lossy(&mut input).expect("reading from slice should work");
+        assert_eq!(text, "key = \"\u{FFFD}\"\n");
+        assert_eq!(errors, vec![7]);
+    }
+}
@@ -0,0 +1,245 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Deferred work while the user is idle
+//!
+//! The main loop waits for input with a short timeout. If no input arrives, it runs one slice of
+//! the work queued by the previous inputs, e.g. computing the predictions for the cursor position.
+//! A burst of cursor moves thus computes the predictions only once after the burst has settled.
+
+use std::time::{Duration, Instant};
+
+/// Time to wait for input before running deferred work
+pub const INPUT_TIMEOUT_MS: i32 = 30;
+
+/// Maximal time spent on deferred work before checking for input again
+pub const SLICE_BUDGET: Duration = Duration::from_millis(10);
+
+/// Deferred work of the app
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Work {
+    /// Compute the predictions for the current cursor position
+    RefreshPredictions,
+}
+
+/// Source of the current time
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// Wall clock time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Queued work item
+struct Item<W> {
+    priority: u8,
+    work: W,
+}
+
+/// Queue of work items, run in slices of bounded duration.
+///
+/// Items with higher priority run first, items of the same priority in the order they were
+/// queued. Equal items are only queued once.
+pub struct WorkQueue<W> {
+    items: Vec<Item<W>>,
+}
+
+impl<W: PartialEq> WorkQueue<W> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    /// Queue a work item. If an equal item is already queued, keep it and raise its priority.
+    pub fn push(&mut self, work: W, priority: u8) {
+        match self.items.iter_mut().find(|i| i.work == work) {
+            Some(item) => item.priority = item.priority.max(priority),
+            None => self.items.push(Item { priority, work }),
+        }
+    }
+
+    /// Number of queued items
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Check if there is nothing to do
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Remove the first item of the highest priority
+    fn pop(&mut self) -> Option<Item<W>> {
+        let index = self
+            .items
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, i)| i.priority)
+            .map(|(index, _)| index)?;
+        Some(self.items.remove(index))
+    }
+
+    /// Run queued items until the queue is empty or the budget is used up. At least one item is
+    /// run.
+    ///
+    /// `run` returns the work that remains to be done, which is queued again with the same
+    /// priority. Return the number of items run.
+    pub fn run_slice<C: Clock>(
+        &mut self,
+        clock: &C,
+        budget: Duration,
+        mut run: impl FnMut(W) -> Option<W>,
+    ) -> usize {
+        let start = clock.now();
+        let mut count = 0;
+        while count == 0 || clock.now().duration_since(start) < budget {
+            let item = match self.pop() {
+                Some(item) => item,
+                None => break,
+            };
+            count += 1;
+            if let Some(rest) = run(item.work) {
+                self.push(rest, item.priority);
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{cargo_toml, App, Editor};
+    use super::*;
+
+    use std::cell::Cell;
+
+    /// Clock that only moves when told to
+    struct FakeClock(Cell<Instant>);
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self(Cell::new(Instant::now()))
+        }
+
+        fn advance(&self, ms: u64) {
+            self.0.set(self.0.get() + Duration::from_millis(ms));
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn priorities() {
+        let clock = FakeClock::new();
+        let mut queue = WorkQueue::new();
+        queue.push('a', 0);
+        queue.push('b', 1);
+        queue.push('c', 0);
+        queue.push('a', 0);
+        assert_eq!(queue.len(), 3);
+
+        let mut order = Vec::new();
+        let count = queue.run_slice(&clock, SLICE_BUDGET, |w| {
+            order.push(w);
+            None
+        });
+        assert_eq!(count, 3);
+        assert_eq!(order, vec!['b', 'a', 'c']);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn bounded_slices() {
+        let clock = FakeClock::new();
+        let mut queue = WorkQueue::new();
+        queue.push(('a', 3), 0);
+        queue.push(('b', 10), 1);
+
+        // Each step takes 4ms and counts down. Work that is not done is queued again.
+        let mut step = |(name, n): (char, i32)| {
+            clock.advance(4);
+            if n > 1 {
+                Some((name, n - 1))
+            } else {
+                None
+            }
+        };
+        assert_eq!(queue.run_slice(&clock, SLICE_BUDGET, &mut step), 3);
+        assert_eq!(queue.len(), 2);
+
+        // A zero budget still makes progress
+        assert_eq!(
+            queue.run_slice(&clock, Duration::from_millis(0), &mut step),
+            1
+        );
+
+        let mut slices = 0;
+        while !queue.is_empty() {
+            queue.run_slice(&clock, SLICE_BUDGET, &mut step);
+            slices += 1;
+        }
+        assert_eq!(slices, 3);
+    }
+
+    #[test]
+    fn burst_of_cursor_moves() {
+        let clock = FakeClock::new();
+        let grammar = cargo_toml::grammar();
+        let look_and_feel = cargo_toml::look_and_feel(&grammar);
+        let mut app = App::new(Editor::new(grammar), look_and_feel, "Cargo.toml".into());
+        app.editor
+            .enter_iter("[package]\nname = \"sesd\"\n".chars());
+        app.editor.set_cursor(0);
+        let _ = app.update_prediction();
+        let before = app.predictions.clone();
+
+        // Inputs arrive faster than the timeout, thus no idle work is done in between.
+        for _ in 0..10 {
+            app.editor.move_forward(1);
+            app.cursor_moved();
+            clock.advance(5);
+        }
+        assert!(app.predictions_stale);
+        assert_eq!(app.predictions, before);
+        assert_eq!(app.work.len(), 1);
+
+        // The burst has settled
+        clock.advance(INPUT_TIMEOUT_MS as u64);
+        assert_eq!(app.run_idle(&clock), 1);
+        assert!(!app.predictions_stale);
+        assert_eq!(app.run_idle(&clock), 0);
+    }
+}
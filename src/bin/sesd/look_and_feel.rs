@@ -28,8 +28,11 @@ use std::collections::HashMap;
 
 use pancurses::Attributes;
 
+use sesd::char::CharMatcher;
+use sesd::prediction::{Prediction, PredictionContext, PredictionProvider};
+use sesd::snippet::Snippet;
 use sesd::style_sheet::StyleSheet;
-use sesd::SymbolId;
+use sesd::{Parser, SymbolId};
 
 /// Style of a syntactic element.
 #[derive(Debug)]
@@ -40,6 +43,8 @@ pub struct Style {
     pub line_break_before: bool,
     /// Shall the renderer break the line after the element
     pub line_break_after: bool,
+    /// Shall the renderer draw a horizontal rule before the element
+    pub section_break_before: bool,
 }
 
 /// Look and Feel of a language
@@ -52,12 +57,17 @@ pub struct LookAndFeel {
 
     /// List of predictions for a given symbol
     predictions: HashMap<SymbolId, Vec<String>>,
+
+    /// List of snippets for a given symbol name
+    snippets: HashMap<String, Vec<Snippet>>,
 }
 
 /// Re-export the style matcher for brevity
 pub type StyleMatcher = sesd::style_sheet::StyleMatcher<Style>;
-/// Re-export the style look up result for brevity
-pub type LookedUp<'a> = sesd::style_sheet::LookedUp<'a, Style>;
+/// Re-export the embedded language for brevity
+pub type SubLanguage = sesd::style_sheet::SubLanguage<Style>;
+/// Re-export the styled span for brevity
+pub type StyledSpan<'a> = sesd::style_sheet::StyledSpan<'a, Style>;
 
 impl Style {
     pub fn none() -> Self {
@@ -65,6 +75,7 @@ impl Style {
             attr: Attributes::new(),
             line_break_before: false,
             line_break_after: false,
+            section_break_before: false,
         }
     }
 }
@@ -76,6 +87,7 @@ impl LookAndFeel {
             default,
             style_sheet: StyleSheet::new(),
             predictions: HashMap::new(),
+            snippets: HashMap::new(),
         }
     }
 
@@ -84,9 +96,14 @@ impl LookAndFeel {
         self.style_sheet.add(m);
     }
 
-    /// Lookup a path in the style sheet.
-    pub fn lookup(&self, path: &[SymbolId]) -> LookedUp {
-        self.style_sheet.lookup(path)
+    /// Add an embedded language
+    pub fn add_sub_language(&mut self, sub: SubLanguage) {
+        self.style_sheet.add_sub_language(sub);
+    }
+
+    /// Split the parsed buffer into styled spans.
+    pub fn classify(&self, parser: &Parser<char, CharMatcher>, tokens: &[char]) -> Vec<StyledSpan> {
+        self.style_sheet.classify(parser, tokens)
     }
 
     /// Add a prediction to the look and feel
@@ -104,4 +121,32 @@ impl LookAndFeel {
             .map(|s| s.clone())
             .collect()
     }
+
+    /// Add a snippet for the symbol. Tab stops are marked with `$1`, `$2`, ...
+    pub fn add_snippet(&mut self, name: &str, template: &str) {
+        self.snippets
+            .entry(name.to_string())
+            .or_insert_with(Vec::new)
+            .push(Snippet::parse(template));
+    }
+
+    /// Find the snippets for this symbol name
+    pub fn snippets(&self, name: &str) -> Vec<Snippet> {
+        self.snippets.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// The predictions and snippets of the predicted symbols
+impl PredictionProvider<char, CharMatcher> for LookAndFeel {
+    fn predict(&self, ctx: &PredictionContext<char, CharMatcher>) -> Vec<Prediction> {
+        ctx.predicted()
+            .iter()
+            .flat_map(|sym| {
+                self.predictions(*sym)
+                    .into_iter()
+                    .map(|p| Snippet::plain(&p))
+                    .chain(self.snippets(ctx.grammar().nt_name(*sym)))
+            })
+            .collect()
+    }
 }
@@ -29,7 +29,7 @@ extern crate itertools;
 
 use libc;
 use std::fs::OpenOptions;
-use std::io::{Read, Write};
+use std::io::Write;
 
 #[cfg(target_family = "unix")]
 use std::os::unix::fs::OpenOptionsExt;
@@ -38,15 +38,30 @@ use std::os::unix::fs::OpenOptionsExt;
 use std::os::windows::fs::OpenOptionsExt;
 
 use std::path::PathBuf;
+use std::rc::Rc;
 
 use pancurses::{endwin, initscr, noecho, Input, Window};
 use structopt::StructOpt;
 
-use sesd::{char::CharMatcher, CstIterItem, SymbolId, SynchronousEditor};
+use sesd::{
+    char::CharMatcher,
+    prediction::{Prediction, PredictionEngine},
+    SymbolId, SynchronousEditor,
+};
 
+mod batch;
 mod cargo_toml;
+mod decode;
+#[cfg(test)]
+#[path = "../../golden.rs"]
+mod golden;
+mod idle;
 mod look_and_feel;
-use look_and_feel::{LookAndFeel, LookedUp, Style};
+mod pane;
+mod prompt;
+mod screenshot;
+mod tutorial;
+use look_and_feel::{LookAndFeel, Style};
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "sesd", about = "Syntax directed text editor")]
@@ -54,6 +69,22 @@ struct CommandLine {
     /// Input file
     #[structopt(parse(from_os_str))]
     input: PathBuf,
+
+    /// External formatter, reads the buffer from stdin and writes the result to stdout
+    #[structopt(long)]
+    formatter: Option<String>,
+
+    /// Start with an empty buffer and guide through the creation of a Cargo.toml file
+    #[structopt(long)]
+    tutorial: bool,
+
+    /// Serve the line protocol of the remote module on stdin/stdout instead of editing the input file
+    #[structopt(long)]
+    serve: bool,
+
+    /// Render the input file at the given width to stdout with style codes instead of editing it
+    #[structopt(long)]
+    screenshot: Option<usize>,
 }
 
 type Editor = SynchronousEditor<char, CharMatcher>;
@@ -64,6 +95,9 @@ struct SynElement {
     text: String,
     /// Buffer position where the element starts
     start: usize,
+    /// Horizontal rule across the window. It does not belong to the buffer and never spans a
+    /// position.
+    rule: bool,
 }
 
 /// All state of the edit app
@@ -72,7 +106,10 @@ struct App {
     editor: Editor,
 
     /// Language-specific look and feel
-    look_and_feel: LookAndFeel,
+    look_and_feel: Rc<LookAndFeel>,
+
+    /// Sources of predictions
+    prediction_engine: PredictionEngine<char, CharMatcher>,
 
     /// Cache for rendering syntax items
     ///
@@ -89,19 +126,61 @@ struct App {
     cursor_col: usize,
 
     /// Predictions
-    predictions: Vec<String>,
+    predictions: Vec<Prediction>,
 
     /// Selected prediction
     selected_predition: Option<usize>,
 
+    /// The predictions were computed for a different cursor position or buffer
+    predictions_stale: bool,
+
+    /// Work to do when the user is idle
+    work: idle::WorkQueue<idle::Work>,
+
+    /// Show the predictions inline instead of in the pane
+    inline_predictions: bool,
+
+    /// Height of the window in lines
+    win_height: usize,
+
     /// Last error message
     error: String,
 
     /// Name of file being edited
     filename: PathBuf,
+
+    /// Character indices where invalid byte sequences have been replaced while loading
+    decode_errors: Vec<usize>,
+
+    /// Command line of the external formatter
+    formatter: Option<String>,
+
+    /// Tutorial, if active
+    tutorial: Option<tutorial::Tutorial>,
+
+    /// Prompt that takes over the error line, if active
+    prompt: Option<PendingPrompt>,
+
+    /// Symbols of nodes that can be swapped with their siblings
+    sibling_symbols: Vec<SymbolId>,
+}
+
+/// Feature that waits for the input of a prompt
+#[derive(Debug, PartialEq)]
+enum PendingAction {
+    /// Save the buffer under a new name
+    SaveAs,
+}
+
+/// Active prompt and what to do with its input
+struct PendingPrompt {
+    prompt: prompt::Prompt,
+    action: PendingAction,
+    /// Error message to restore if the prompt is cancelled
+    previous_error: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum AppCmd {
     /// Nothing to do.
     Nothing,
@@ -122,11 +201,66 @@ enum AppCmd {
 const PREDICTION_SHOW_RAD: usize = 2;
 const MAX_PREDICTIONS_SHOWN: usize = 2 * PREDICTION_SHOW_RAD + 1;
 
+/// Render the elements of a document line by passing each piece of text and its attributes to
+/// `draw`.
+fn render_line<F>(line: &[SynElement], mut draw: F)
+where
+    F: FnMut(&str, pancurses::Attributes),
+{
+    for elem in line.iter() {
+        // Flag replaced byte sequences by inverting them.
+        for (i, part) in elem.text.split(decode::REPLACEMENT).enumerate() {
+            if i != 0 {
+                let mut attr = elem.attr;
+                attr.set_reverse(true);
+                draw(&decode::REPLACEMENT.to_string(), attr);
+            }
+            draw(part, elem.attr);
+        }
+    }
+}
+
 impl App {
+    /// Create the app for the editor with default settings
+    fn new(editor: Editor, look_and_feel: LookAndFeel, filename: PathBuf) -> Self {
+        let look_and_feel = Rc::new(look_and_feel);
+        let prediction_engine =
+            cargo_toml::prediction_engine(editor.grammar(), look_and_feel.clone());
+        let sibling_symbols = cargo_toml::sibling_symbols(editor.grammar());
+        Self {
+            editor,
+            error: String::new(),
+            document: Vec::new(),
+            look_and_feel,
+            prediction_engine,
+            cursor_doc_line: 0,
+            cursor_win_line: 0,
+            cursor_col: 0,
+            predictions: Vec::new(),
+            selected_predition: None,
+            predictions_stale: false,
+            work: idle::WorkQueue::new(),
+            inline_predictions: false,
+            win_height: 0,
+            filename,
+            decode_errors: Vec::new(),
+            formatter: None,
+            tutorial: None,
+            prompt: None,
+            sibling_symbols,
+        }
+    }
+
+    /// Window title
+    fn title(&self) -> String {
+        format!("{} -- sesd", self.filename.to_string_lossy())
+    }
+
     /// Load the input file into the editor if it exists.
     ///
-    /// Internal helper method that returns the error message
-    fn load_input_internal(&mut self, cmd_line: &CommandLine) -> std::io::Result<()> {
+    /// Internal helper method that returns the error message or the number of replaced invalid
+    /// byte sequences. If there are any, the cursor is placed on the first one.
+    fn load_input_internal(&mut self, cmd_line: &CommandLine) -> std::io::Result<usize> {
         // Delete everything in case this is used for reverting all changes
         self.editor.clear();
 
@@ -140,13 +274,17 @@ impl App {
 
         let mut file = file.open(&cmd_line.input)?;
 
-        let mut temp = String::new();
-        let _ = file.read_to_string(&mut temp)?;
+        let (temp, decode_errors) = decode::read_lossy(&mut file)?;
+        self.decode_errors = decode_errors;
 
         self.editor.enter_iter(temp.chars());
-        self.editor.move_start();
+        // Replacement characters that were already in the file don't count.
+        match self.decode_errors.first() {
+            Some(&index) => self.editor.set_cursor(index),
+            None => self.editor.move_start(),
+        }
 
-        Ok(())
+        Ok(self.decode_errors.len())
     }
 
     /// Set error message on Err, clear it on Ok
@@ -162,8 +300,23 @@ impl App {
 
     /// Load the input file into the editor if it exists. Sets error message
     fn load_input(&mut self, cmd_line: &CommandLine) {
-        let res = self.load_input_internal(cmd_line);
-        self.set_error(res);
+        match self.load_input_internal(cmd_line) {
+            Ok(0) => self.error.clear(),
+            Ok(n) => {
+                self.error = format!(
+                    "Opened with {} invalid byte sequences replaced, cursor at the first.",
+                    n
+                );
+            }
+            res => self.set_error(res),
+        }
+    }
+
+    /// Check if the buffer still contains characters that replaced invalid byte sequences.
+    ///
+    /// Saving such a buffer would not reproduce the original file.
+    fn has_decode_errors(&self) -> bool {
+        !self.decode_errors.is_empty() && self.editor.as_string().contains(decode::REPLACEMENT)
     }
 
     /// Overwrite the given file with the current buffer content
@@ -182,11 +335,122 @@ impl App {
         Ok(())
     }
 
+    /// Write the buffer content to a new or existing file
+    fn save_file_as(&self, path: &std::path::Path) -> Result<(), String> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+        file.write_all(self.editor.as_string().as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Set the error line after saving
+    fn report_save(&mut self, res: Result<(), String>) {
+        self.error = match res {
+            Ok(_) if self.has_decode_errors() => format!(
+                "Saved »{}«, but invalid byte sequences from loading have been replaced.",
+                self.filename.to_string_lossy()
+            ),
+            Ok(_) => format!("Successfully saved »{}«.", self.filename.to_string_lossy()),
+            Err(msg) => format!(
+                "Error saving file »{}«: {}",
+                self.filename.to_string_lossy(),
+                msg
+            ),
+        };
+    }
+
+    /// Let the prompt take over the error line
+    fn open_prompt(&mut self, prompt: prompt::Prompt, action: PendingAction) {
+        self.prompt = Some(PendingPrompt {
+            prompt,
+            action,
+            previous_error: std::mem::take(&mut self.error),
+        });
+    }
+
+    /// Pass the input to the active prompt. Run the pending action if the input is accepted.
+    fn handle_prompt_input(&mut self, mut pending: PendingPrompt, ch: Input) -> AppCmd {
+        match pending.prompt.handle_input(ch) {
+            prompt::PromptResult::Editing => {
+                self.prompt = Some(pending);
+                AppCmd::Display
+            }
+            prompt::PromptResult::Cancelled => {
+                self.error = pending.previous_error;
+                AppCmd::Display
+            }
+            prompt::PromptResult::Accepted(text) => match pending.action {
+                PendingAction::SaveAs => {
+                    if text.is_empty() {
+                        self.error = "No file name given.".to_string();
+                        return AppCmd::Display;
+                    }
+                    let path = PathBuf::from(text);
+                    match self.save_file_as(&path) {
+                        Ok(_) => {
+                            self.filename = path;
+                            self.report_save(Ok(()));
+                        }
+                        Err(msg) => {
+                            self.error =
+                                format!("Error saving file »{}«: {}", path.to_string_lossy(), msg)
+                        }
+                    }
+                    AppCmd::Display
+                }
+            },
+        }
+    }
+
+    /// Run the external formatter on the buffer and replace the buffer by its output
+    fn format_buffer(&mut self) -> Result<sesd::CursorMapReport, String> {
+        let formatter = self
+            .formatter
+            .as_ref()
+            .ok_or_else(|| "No formatter configured.".to_string())?;
+        let mut args = formatter.split_whitespace();
+        let program = args
+            .next()
+            .ok_or_else(|| "Formatter command is empty.".to_string())?;
+
+        let mut child = std::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+
+        // Feed stdin from a separate thread, so that a formatter producing output early does not
+        // block on a full pipe.
+        let mut stdin = child.stdin.take().expect("stdin should have been piped");
+        let input = self.editor.as_string();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let output = child.wait_with_output().map_err(|e| e.to_string())?;
+        writer
+            .join()
+            .map_err(|_| "Writing to formatter failed.".to_string())?
+            .map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(format!("Formatter failed: {}", output.status));
+        }
+        let text = String::from_utf8(output.stdout).map_err(|e| e.to_string())?;
+        Ok(self.editor.apply_external_edit(text.chars().collect()))
+    }
+
     /// Process the input character
     ///
     /// Return true if a redraw is needed
     fn handle_input(&mut self, ch: Input) -> AppCmd {
         trace!("{:?}", ch);
+        if let Some(pending) = self.prompt.take() {
+            return self.handle_prompt_input(pending, ch);
+        }
         match ch {
             Input::KeyLeft => {
                 self.editor.move_backward(1);
@@ -264,6 +528,7 @@ impl App {
             }
 
             Input::KeyNPage => {
+                self.refresh_stale_predictions();
                 if let Some(selected) = &mut self.selected_predition {
                     if *selected + 1 < self.predictions.len() {
                         *selected += 1;
@@ -279,6 +544,7 @@ impl App {
             }
 
             Input::KeyPPage => {
+                self.refresh_stale_predictions();
                 if let Some(selected) = &mut self.selected_predition {
                     if *selected > 0 {
                         *selected -= 1;
@@ -292,28 +558,91 @@ impl App {
                 }
                 AppCmd::Nothing
             }
+            Input::KeyBTab | Input::KeySTab if self.editor.is_snippet_active() => {
+                self.editor.prev_snippet_stop();
+                AppCmd::Cursor
+            }
             Input::KeyBTab | Input::KeySTab => {
-                if let Some(selected) = self.selected_predition {
-                    self.editor.enter_iter(self.predictions[selected].chars());
+                self.refresh_stale_predictions();
+                // The inline prediction can be inserted without selecting it
+                let selected = match self.prediction_mode() {
+                    pane::PredictionMode::Inline if !self.predictions.is_empty() => {
+                        Some(self.selected_predition.unwrap_or(0))
+                    }
+                    _ => self.selected_predition,
+                };
+                if let Some(selected) = selected {
+                    self.editor.insert_snippet(&self.predictions[selected]);
                     return AppCmd::Document;
                 }
                 AppCmd::Nothing
             }
 
+            Input::KeyF4 => {
+                self.inline_predictions = !self.inline_predictions;
+                AppCmd::Display
+            }
+
             Input::KeyF2 => {
-                self.error = match self.save_file() {
-                    Ok(_) => format!("Successfully saved »{}«.", self.filename.to_string_lossy()),
-                    Err(msg) => format!(
-                        "Error saving file »{}«: {}",
-                        self.filename.to_string_lossy(),
-                        msg
-                    ),
-                };
+                let res = self.save_file();
+                self.report_save(res);
+                AppCmd::Display
+            }
+
+            Input::KeyF3 => {
+                let prompt =
+                    prompt::Prompt::new("Save as: ").with_text(&self.filename.to_string_lossy());
+                self.open_prompt(prompt, PendingAction::SaveAs);
                 AppCmd::Display
             }
 
+            Input::KeyF5 => match self.format_buffer() {
+                Ok(report) => {
+                    self.error = if report.approximate {
+                        "Formatted. Cursor position is approximate.".to_string()
+                    } else {
+                        "Formatted.".to_string()
+                    };
+                    if report.changed {
+                        AppCmd::Document
+                    } else {
+                        AppCmd::Display
+                    }
+                }
+                Err(msg) => {
+                    self.error = format!("Error formatting: {}", msg);
+                    AppCmd::Display
+                }
+            },
+
+            Input::KeyF1 => {
+                if let Some(hint) = self.tutorial.as_ref().and_then(|t| t.hint()) {
+                    self.editor.enter_iter(hint.chars());
+                    return AppCmd::Document;
+                }
+                AppCmd::Nothing
+            }
+
+            Input::KeyF6 => {
+                if self.editor.swap_with_prev_sibling(&self.sibling_symbols) {
+                    return AppCmd::Document;
+                }
+                AppCmd::Nothing
+            }
+
+            Input::KeyF7 => {
+                if self.editor.swap_with_next_sibling(&self.sibling_symbols) {
+                    return AppCmd::Document;
+                }
+                AppCmd::Nothing
+            }
+
             Input::KeyF10 => AppCmd::Quit,
 
+            Input::Character('\t') if self.editor.is_snippet_active() => {
+                self.editor.next_snippet_stop();
+                AppCmd::Cursor
+            }
             Input::Character(c) => {
                 self.editor.enter(c);
                 AppCmd::Document
@@ -340,7 +669,9 @@ impl App {
         let mut res = None;
 
         let mut text = editor.span_string(start, end);
-        if style.line_break_before {
+        let section_break = style.section_break_before
+            && Self::section_break(document, line_nr, line_len, width, start);
+        if style.line_break_before && !section_break {
             *line_nr += 1;
             document.push(Vec::new());
             *line_len = 0;
@@ -370,6 +701,7 @@ impl App {
                     attr: style.attr,
                     text: l.to_string(),
                     start,
+                    rule: false,
                 };
                 if se.spans(cursor_index) {
                     res = Some((*line_nr, cursor_index - se.start));
@@ -386,6 +718,7 @@ impl App {
                 attr: style.attr,
                 text: String::from("¶"),
                 start: start + offs - 1,
+                rule: false,
             };
             if nl.spans(cursor_index) {
                 res = Some((*line_nr, cursor_index - nl.start));
@@ -403,6 +736,7 @@ impl App {
                     attr: style.attr,
                     text: l.to_string(),
                     start: start + offs,
+                    rule: false,
                 };
                 if se.spans(cursor_index) {
                     res = Some((*line_nr, cursor_index - se.start));
@@ -419,6 +753,39 @@ impl App {
         res
     }
 
+    /// Place a horizontal rule on a line of its own before the element starting at `start`.
+    ///
+    /// Nothing is placed at the top of the document or directly after another rule. Return true
+    /// if a rule has been placed.
+    fn section_break(
+        document: &mut Vec<Vec<SynElement>>,
+        line_nr: &mut usize,
+        line_len: &mut usize,
+        width: usize,
+        start: usize,
+    ) -> bool {
+        match document[..=*line_nr].iter().rev().find(|l| !l.is_empty()) {
+            Some(line) if !line.iter().any(|se| se.rule) => {}
+            _ => return false,
+        }
+        if !document[*line_nr].is_empty() {
+            *line_nr += 1;
+            document.push(Vec::new());
+        }
+        let mut attr = pancurses::Attributes::new();
+        attr.set_dim(true);
+        document[*line_nr].push(SynElement {
+            attr,
+            text: "─".repeat(width),
+            start,
+            rule: true,
+        });
+        *line_nr += 1;
+        document.push(Vec::new());
+        *line_len = 0;
+        true
+    }
+
     /// Compute the cached cursor position on screen from the cursor position in the editor.
     ///
     /// Return true if a full redisplay is required. Return false if only the cursor needs to move.
@@ -437,12 +804,18 @@ impl App {
             }
         }
 
+        // The prediction pane might have grown and covered the cursor
+        let display_height = self.display_height(win);
+        if self.cursor_win_line >= display_height {
+            self.cursor_win_line = display_height - 1;
+            return true;
+        }
+
         // If the cursor only moved horizontally, just move it
         if old_doc_line == self.cursor_doc_line {
             return false;
         }
 
-        let display_height = self.display_height(win);
         // If the document cursor moved forward, check if the win cursor can also be moved forward
         if old_doc_line < self.cursor_doc_line {
             let lines = self.cursor_doc_line - old_doc_line;
@@ -479,29 +852,28 @@ impl App {
             trace!("update_document CST");
             for cst_node in self.editor.cst_iter() {
                 match cst_node {
-                    sesd::CstIterItem::Parsed(item) => {
-                        if item.end - item.start > 0 {
+                    sesd::CstIterItem::Parsed(item) if item.end - item.start > 0 => {
+                        trace!(
+                            "{}, {}-{}",
+                            self.editor
+                                .grammar()
+                                .dotted_rule_to_string(&item.dotted_rule)
+                                .unwrap(),
+                            item.start,
+                            item.end
+                        );
+                        for n in item.path_iter() {
+                            let dr = self.editor.parser().dotted_rule(n);
                             trace!(
-                                "{}, {}-{}",
-                                self.editor
-                                    .grammar()
-                                    .dotted_rule_to_string(&item.dotted_rule)
-                                    .unwrap(),
-                                item.start,
-                                item.end
+                                "   {}",
+                                self.editor.grammar().dotted_rule_to_string(&dr).unwrap()
                             );
-                            for n in item.path_iter() {
-                                let dr = self.editor.parser().dotted_rule(n);
-                                trace!(
-                                    "   {}",
-                                    self.editor.grammar().dotted_rule_to_string(&dr).unwrap()
-                                );
-                            }
                         }
                     }
                     sesd::CstIterItem::Unparsed(start) => {
                         trace!("Unparsed: {} - {}", start, self.editor.len());
                     }
+                    _ => {}
                 }
             }
         }
@@ -509,130 +881,38 @@ impl App {
         // Compute the cursor position on the fly.
         let cursor_index = self.editor.cursor();
 
-        // Traverse the parse tree. If there are items that have no style in the style sheet, draw
-        // them and mark until which index the input has been drawn already. Skip all entries that
-        // begin before the current end. This prevents multiple occurrances of the same text.
+        // Render the spans of the classified parse tree. Spans without a style are rendered with
+        // the default style.
         let mut line_nr = 0;
         let mut line_len = 0;
-        let mut rendered_until = 0;
         trace!("update_document render");
-        for cst_node in self.editor.cst_iter() {
-            match cst_node {
-                CstIterItem::Parsed(cst_node) => {
-                    trace!(
-                        "{}: {}, {}-{}",
-                        rendered_until,
-                        self.editor
-                            .grammar()
-                            .dotted_rule_to_string(&cst_node.dotted_rule)
-                            .unwrap(),
-                        cst_node.start,
-                        cst_node.end
-                    );
-
-                    // If a rule contains a terminal in the middle, and no style has been defined,
-                    // it is possible that rendered_until is larger than cst_node.start. Thus, the
-                    // buffer needs to be rendered from rendered_until to cst_node.end.
-                    if cst_node.end != cst_node.start && cst_node.end > rendered_until {
-                        if line_nr == self.document.len() {
-                            self.document.push(Vec::new());
-                        }
-
-                        // Convert the path to a list of SymbolIds
-                        let mut path: Vec<SymbolId> = cst_node
-                            .path
-                            .0
-                            .iter()
-                            .map(|n| {
-                                let dr = self.editor.parser().dotted_rule(&n);
-                                self.editor.grammar().lhs(dr.rule as usize)
-                            })
-                            .collect();
-                        path.push(
-                            self.editor
-                                .grammar()
-                                .lhs(cst_node.dotted_rule.rule as usize),
-                        );
-
-                        // Log the lookup path as readable
-                        if log_enabled!(log::Level::Trace) {
-                            trace!("lookup: {:?}", path);
-                            for p in path.iter() {
-                                trace!("  {:?}", self.editor.grammar().nt_name(*p));
-                            }
-                        }
-
-                        let looked_up = self.look_and_feel.lookup(&path);
-                        trace!("{:?}", looked_up);
-                        match looked_up {
-                            LookedUp::Parent => {
-                                // Do nothing now. Render later.
-                            }
-                            LookedUp::Found(style) => {
-                                // Found an exact match. Render with style.
-                                if let Some((row, col)) = Self::render_node(
-                                    &self.editor,
-                                    &mut self.document,
-                                    &mut line_nr,
-                                    &mut line_len,
-                                    width,
-                                    rendered_until,
-                                    cst_node.end,
-                                    cursor_index,
-                                    style,
-                                ) {
-                                    trace!("Cursor to ({},{})", row, col);
-                                    self.cursor_doc_line = row;
-                                    self.cursor_col = col;
-                                }
-                                rendered_until = cst_node.end;
-                            }
-                            LookedUp::Nothing => {
-                                // Found nothing. Render with default style.
-                                if let Some((row, col)) = Self::render_node(
-                                    &self.editor,
-                                    &mut self.document,
-                                    &mut line_nr,
-                                    &mut line_len,
-                                    width,
-                                    rendered_until,
-                                    cst_node.end,
-                                    cursor_index,
-                                    &self.look_and_feel.default,
-                                ) {
-                                    trace!("Cursor to ({},{})", row, col);
-                                    self.cursor_doc_line = row;
-                                    self.cursor_col = col;
-                                }
-                                rendered_until = cst_node.end;
-                            }
-                        }
-                    }
-                }
-                CstIterItem::Unparsed(_unparsed) => {
-                    if line_nr == self.document.len() {
-                        self.document.push(Vec::new());
-                    }
-                    // Render the unparsed part with defualt syle
-                    if let Some((row, col)) = Self::render_node(
-                        &self.editor,
-                        &mut self.document,
-                        &mut line_nr,
-                        &mut line_len,
-                        width,
-                        rendered_until,
-                        self.editor.len(),
-                        cursor_index,
-                        &self.look_and_feel.default,
-                    ) {
-                        trace!("Cursor to ({},{})", row, col);
-                        self.cursor_doc_line = row;
-                        self.cursor_col = col;
-                    }
-                    rendered_until = self.editor.len();
-                }
+        for span in self
+            .look_and_feel
+            .classify(self.editor.parser(), self.editor.tokens())
+        {
+            trace!("{}-{}: {:?}", span.start, span.end, span.style);
+            if line_nr == self.document.len() {
+                self.document.push(Vec::new());
+            }
+            if let Some((row, col)) = Self::render_node(
+                &self.editor,
+                &mut self.document,
+                &mut line_nr,
+                &mut line_len,
+                width,
+                span.start,
+                span.end,
+                cursor_index,
+                span.style.unwrap_or(&self.look_and_feel.default),
+            ) {
+                trace!("Cursor to ({},{})", row, col);
+                self.cursor_doc_line = row;
+                self.cursor_col = col;
             }
         }
+        if line_nr == self.document.len() {
+            self.document.push(Vec::new());
+        }
     }
 
     /// Compute the list of predictions at the cursor position
@@ -640,12 +920,7 @@ impl App {
     /// Return true, if a complete redisplay is required. Return false, if only the cursor position
     /// needs to be changed.
     fn update_prediction(&mut self) -> bool {
-        let symbols = self.editor.predictions_at_cursor();
-        // Get possible prediction strings from style sheet
-        let predictions = symbols
-            .iter()
-            .flat_map(|sym| self.look_and_feel.predictions(*sym))
-            .collect();
+        let predictions = self.prediction_engine.predict(&self.editor);
 
         let res = self.predictions != predictions;
         if res {
@@ -655,17 +930,74 @@ impl App {
         res
     }
 
+    /// Defer the prediction update after the cursor moved or the buffer changed.
+    ///
+    /// Return true if predictions are shown, i.e. the staleness needs to be displayed.
+    fn cursor_moved(&mut self) -> bool {
+        self.predictions_stale = true;
+        self.work.push(idle::Work::RefreshPredictions, 1);
+        !self.predictions.is_empty()
+    }
+
+    /// Compute the predictions now if they are stale
+    fn refresh_stale_predictions(&mut self) {
+        if self.predictions_stale {
+            self.predictions_stale = false;
+            let _ = self.update_prediction();
+        }
+    }
+
+    /// Run one slice of deferred work. Return the number of work items run.
+    fn run_idle<C: idle::Clock>(&mut self, clock: &C) -> usize {
+        if self.work.is_empty() {
+            return 0;
+        }
+        let mut work = std::mem::replace(&mut self.work, idle::WorkQueue::new());
+        let count = work.run_slice(clock, idle::SLICE_BUDGET, |w| match w {
+            idle::Work::RefreshPredictions => {
+                self.refresh_stale_predictions();
+                None
+            }
+        });
+        self.work = work;
+        count
+    }
+
+    /// Check the progress of the tutorial, if active
+    fn update_tutorial(&mut self) {
+        if let Some(tutorial) = &mut self.tutorial {
+            tutorial.update(&self.editor);
+        }
+    }
+
+    /// Current display mode of the predictions
+    fn prediction_mode(&self) -> pane::PredictionMode {
+        pane::prediction_mode(self.inline_predictions, self.win_height)
+    }
+
+    /// Prediction to show after the cursor, if any
+    fn ghost_text(&self) -> Option<&str> {
+        if self.predictions_stale {
+            return None;
+        }
+        pane::ghost_text(
+            self.prediction_mode(),
+            &self.predictions,
+            self.selected_predition,
+        )
+    }
+
+    /// Window line of the error message, below the document and the prediction pane
+    fn error_line(&self, win: &Window) -> usize {
+        self.display_height(win) + pane::pane_height(self.prediction_mode(), self.predictions.len())
+    }
+
     fn display_height(&self, win: &Window) -> usize {
         let win_height = win.get_max_y() as usize;
 
-        // If there are predictions, show some and a separator
-        if self.predictions.is_empty() {
-            // Leave one line for the error message
-            win_height - 1
-        } else {
-            // Leave one line for the error message, one for the separator and some for the predictions
-            win_height - 2 - MAX_PREDICTIONS_SHOWN
-        }
+        // Leave one line for the error message and some for the prediction pane
+        let reserved = 1 + pane::pane_height(self.prediction_mode(), self.predictions.len());
+        std::cmp::max(win_height, reserved + 1) - reserved
     }
 
     /// Display the current state of the app to the window
@@ -674,13 +1006,22 @@ impl App {
         let start_doc_line = self.cursor_doc_line - self.cursor_win_line;
         win.clear();
         let display_height = self.display_height(win);
+        let ghost = self.ghost_text();
         for win_line in 0..display_height {
-            if win_line + start_doc_line < self.document.len() {
+            let doc_line = start_doc_line + win_line;
+            if doc_line < self.document.len() {
                 win.mv(win_line as i32, 0);
 
-                for elem in self.document[start_doc_line + win_line].iter() {
-                    win.attrset(elem.attr);
-                    win.addstr(&elem.text);
+                let draw = |part: &str, attr| {
+                    win.attrset(attr);
+                    win.addstr(part);
+                };
+                match ghost {
+                    Some(ghost) if doc_line == self.cursor_doc_line => render_line(
+                        &pane::compose_ghost(&self.document[doc_line], self.editor.cursor(), ghost),
+                        draw,
+                    ),
+                    _ => render_line(&self.document[doc_line], draw),
                 }
             } else {
                 break;
@@ -688,15 +1029,14 @@ impl App {
         }
 
         // Show predictions
-        let mut error_line = display_height;
-        if !self.predictions.is_empty() {
+        let error_line = self.error_line(win);
+        if error_line != display_height {
             // Draw a separator with instructions
             win.mv(display_height as i32, 0);
             win.attron(pancurses::A_REVERSE);
-            win.addstr( "Suggested input: (Press Page Up / Page Down to select. Press Shift-Tab to insert.)");
+            win.addstr(pane::pane_title(self.predictions_stale));
             win.hline(' ', win.get_max_x());
             win.attroff(pancurses::A_REVERSE);
-            error_line += MAX_PREDICTIONS_SHOWN;
 
             // If no prediction is selected, draw the first few.
             let (start, end, highlight) = if let Some(selected) = self.selected_predition {
@@ -723,19 +1063,32 @@ impl App {
                 if is_selection {
                     win.attron(pancurses::A_UNDERLINE);
                 }
-                win.addstr(&self.predictions[i]);
+                win.addstr(&self.predictions[i].text);
                 if is_selection {
                     win.attroff(pancurses::A_UNDERLINE);
                 }
             }
         }
 
+        // Show the prompt or the tutorial task if there is no error
+        let error = match (&self.prompt, &self.tutorial) {
+            (Some(pending), _) => pending.prompt.line(),
+            (None, Some(tutorial)) if self.error.is_empty() => tutorial.text(),
+            _ => self.error.clone(),
+        };
         win.attron(pancurses::A_REVERSE);
-        win.mvaddnstr(error_line as i32, 0, &self.error, win.get_max_x());
+        win.mvaddnstr(error_line as i32, 0, &error, win.get_max_x());
         win.attroff(pancurses::A_REVERSE);
     }
 
     fn move_cursor(&self, win: &Window) {
+        if let Some(pending) = &self.prompt {
+            win.mv(
+                self.error_line(win) as i32,
+                pending.prompt.cursor_col() as i32,
+            );
+            return;
+        }
         trace!("Cursor to ({},{})", self.cursor_win_line, self.cursor_col);
         win.mv(self.cursor_win_line as i32, self.cursor_col as i32);
     }
@@ -768,27 +1121,44 @@ fn main() {
         libc::signal(libc::SIGINT, libc::SIG_IGN)
     };
 
-    let mut app = App {
-        editor: Editor::new(grammar),
-        error: String::new(),
-        document: Vec::new(),
-        look_and_feel,
-        cursor_doc_line: 0,
-        cursor_win_line: 0,
-        cursor_col: 0,
-        predictions: Vec::new(),
-        selected_predition: None,
-        filename: cmd_line.input.clone(),
-    };
+    let mut app = App::new(Editor::new(grammar), look_and_feel, cmd_line.input.clone());
+    app.formatter = cmd_line.formatter.clone();
 
-    // Load the file in the buffer if it exists
-    app.load_input(&cmd_line);
+    // The other process owns the text, start from scratch.
+    if cmd_line.serve {
+        let stdin = std::io::stdin();
+        if let Err(e) = sesd::remote::serve(&mut app.editor, stdin.lock(), std::io::stdout()) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+
+    // Load the file in the buffer if it exists. The tutorial starts from scratch.
+    if cmd_line.tutorial {
+        app.tutorial = Some(tutorial::Tutorial::new());
+        app.update_tutorial();
+    } else {
+        app.load_input(&cmd_line);
+    }
+
+    // Render without curses
+    if let Some(width) = cmd_line.screenshot {
+        app.update_document(width);
+        let mut text = String::new();
+        let _ = screenshot::Screenshot::of(&app.document).write(&mut text);
+        print!("{}", text);
+        if !app.error.is_empty() {
+            eprintln!("{}", app.error);
+        }
+        return;
+    }
 
     let win = initscr();
     noecho();
     win.keypad(true);
 
-    pancurses::set_title(&format!("{} -- sesd", cmd_line.input.to_string_lossy()));
+    let mut title = app.title();
+    pancurses::set_title(&title);
     pancurses::start_color();
     trace!("has_colors: {:?}", pancurses::has_colors());
     trace!("COLORS: {}", pancurses::COLORS());
@@ -803,45 +1173,61 @@ fn main() {
         }
     }
 
+    app.win_height = win.get_max_y() as usize;
     app.update_document(win.get_max_x() as usize);
     let _ = app.update_prediction();
     app.display(&win);
     app.move_cursor(&win);
     win.refresh();
 
+    // Wait for input only briefly to run deferred work in between
+    win.timeout(idle::INPUT_TIMEOUT_MS);
+    let mut source = &win;
     loop {
-        if let Some(input) = win.getch() {
-            app.error = String::new();
-            let app_cmd = app.handle_input(input);
-            trace!("{:?}", app_cmd);
-            match app_cmd {
-                AppCmd::Nothing => {
-                    // Don't do anything
-                }
-                AppCmd::Quit => break,
-                AppCmd::Display => {
-                    app.display(&win);
-                    app.move_cursor(&win);
-                    win.refresh();
-                }
-                AppCmd::Cursor => {
-                    let pred_redisplay = app.update_prediction();
-                    let scroll_redisplay = app.update_cursor(&win);
-                    if pred_redisplay || scroll_redisplay {
-                        app.display(&win);
-                    }
-                    app.move_cursor(&win);
-                    win.refresh();
-                }
-                AppCmd::Document => {
-                    app.update_document(win.get_max_x() as usize);
-                    let _ = app.update_prediction();
-                    let _ = app.update_cursor(&win);
+        let input = match win.getch() {
+            Some(input) => input,
+            None => {
+                // No input arrived in time, catch up on deferred work
+                if app.run_idle(&idle::SystemClock) != 0 {
+                    app.update_cursor(&win);
                     app.display(&win);
                     app.move_cursor(&win);
                     win.refresh();
                 }
+                continue;
             }
+        };
+        app.win_height = win.get_max_y() as usize;
+        // Process all inputs that are immediately available, but render only once.
+        let app_cmds = batch::drain(&mut source, input, |input| {
+            app.error = String::new();
+            app.handle_input(input)
+        });
+        trace!("{:?}", app_cmds);
+        let phases = batch::Phases::of(&app_cmds);
+        if phases.quit {
+            break;
+        }
+        let mut redisplay = phases.display;
+        if phases.document {
+            app.update_tutorial();
+            app.update_document(win.get_max_x() as usize);
+        }
+        if phases.cursor {
+            let stale_redisplay = app.cursor_moved();
+            let scroll_redisplay = app.update_cursor(&win);
+            redisplay = redisplay || stale_redisplay || scroll_redisplay;
+        }
+        if redisplay {
+            app.display(&win);
+        }
+        if app.title() != title {
+            title = app.title();
+            pancurses::set_title(&title);
+        }
+        if phases.any() {
+            app.move_cursor(&win);
+            win.refresh();
         }
     }
 
@@ -850,6 +1236,6 @@ fn main() {
 
 impl SynElement {
     fn spans(&self, index: usize) -> bool {
-        self.start <= index && (index < (self.start + self.text.chars().count()))
+        !self.rule && self.start <= index && (index < (self.start + self.text.chars().count()))
     }
 }
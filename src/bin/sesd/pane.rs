@@ -0,0 +1,244 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Layout of the predictions
+//!
+//! Predictions are either listed in a pane below the document or the best one is shown as ghost
+//! text after the cursor. The pane takes only as many lines as there are predictions to show.
+
+use pancurses::Attributes;
+use sesd::prediction::Prediction;
+
+use super::{SynElement, MAX_PREDICTIONS_SHOWN};
+
+/// Windows with fewer lines show the predictions inline
+pub const SHORT_WINDOW_HEIGHT: usize = 2 * (MAX_PREDICTIONS_SHOWN + 1) + 1;
+
+/// Display of the predictions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PredictionMode {
+    /// List below the document
+    Pane,
+    /// Best prediction as ghost text after the cursor
+    Inline,
+}
+
+/// Select the display mode. Short windows always show the predictions inline.
+pub fn prediction_mode(inline: bool, win_height: usize) -> PredictionMode {
+    if inline || win_height < SHORT_WINDOW_HEIGHT {
+        PredictionMode::Inline
+    } else {
+        PredictionMode::Pane
+    }
+}
+
+/// Number of lines the predictions take below the document, including the separator.
+pub fn pane_height(mode: PredictionMode, predictions: usize) -> usize {
+    match mode {
+        PredictionMode::Pane if predictions != 0 => {
+            std::cmp::min(predictions, MAX_PREDICTIONS_SHOWN) + 1
+        }
+        _ => 0,
+    }
+}
+
+/// Separator line above the prediction pane. Stale predictions are marked with an ellipsis.
+pub fn pane_title(stale: bool) -> &'static str {
+    if stale {
+        "Suggested input: (Press Page Up / Page Down to select. Press Shift-Tab to insert.) …"
+    } else {
+        "Suggested input: (Press Page Up / Page Down to select. Press Shift-Tab to insert.)"
+    }
+}
+
+/// Text of the prediction to show inline, i.e. the selected one or the best one.
+pub fn ghost_text(
+    mode: PredictionMode,
+    predictions: &[Prediction],
+    selected: Option<usize>,
+) -> Option<&str> {
+    match mode {
+        PredictionMode::Inline => predictions
+            .get(selected.unwrap_or(0))
+            .map(|p| p.text.as_str()),
+        PredictionMode::Pane => None,
+    }
+}
+
+/// Insert the ghost text into a copy of the line at the cursor position.
+///
+/// The elements around the cursor are split, all others are copied unchanged. The ghost text
+/// element starts at the cursor too, but the result is only meant to be drawn. Use the original
+/// line to map buffer positions. Only the first line of the ghost text is shown.
+pub fn compose_ghost(line: &[SynElement], cursor_index: usize, ghost: &str) -> Vec<SynElement> {
+    let mut ghost_attr = Attributes::new();
+    ghost_attr.set_dim(true);
+    let ghost = SynElement {
+        attr: ghost_attr,
+        text: ghost.lines().next().unwrap_or("").to_string(),
+        start: cursor_index,
+        rule: false,
+    };
+
+    let mut composed = Vec::with_capacity(line.len() + 2);
+    let mut ghost = Some(ghost);
+    for se in line.iter() {
+        if se.spans(cursor_index) {
+            let split = cursor_index - se.start;
+            let head: String = se.text.chars().take(split).collect();
+            if !head.is_empty() {
+                composed.push(SynElement {
+                    attr: se.attr,
+                    text: head,
+                    start: se.start,
+                    rule: se.rule,
+                });
+            }
+            composed.extend(ghost.take());
+            composed.push(SynElement {
+                attr: se.attr,
+                text: se.text.chars().skip(split).collect(),
+                start: cursor_index,
+                rule: se.rule,
+            });
+        } else {
+            composed.push(SynElement {
+                attr: se.attr,
+                text: se.text.clone(),
+                start: se.start,
+                rule: se.rule,
+            });
+        }
+    }
+    // Cursor is behind the last element
+    composed.extend(ghost.take());
+    composed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(text: &str, start: usize) -> SynElement {
+        SynElement {
+            attr: Attributes::new(),
+            text: text.to_string(),
+            start,
+            rule: false,
+        }
+    }
+
+    fn texts(line: &[SynElement]) -> Vec<(&str, usize, bool)> {
+        line.iter()
+            .map(|se| (se.text.as_str(), se.start, se.attr.is_dim()))
+            .collect()
+    }
+
+    #[test]
+    fn adaptive_height() {
+        let pane = prediction_mode(false, 40);
+        assert_eq!(pane, PredictionMode::Pane);
+        assert_eq!(pane_height(pane, 0), 0);
+        assert_eq!(pane_height(pane, 1), 2);
+        assert_eq!(pane_height(pane, 3), 4);
+        assert_eq!(pane_height(pane, 100), MAX_PREDICTIONS_SHOWN + 1);
+
+        let inline = prediction_mode(true, 40);
+        assert_eq!(inline, PredictionMode::Inline);
+        assert_eq!(pane_height(inline, 0), 0);
+        assert_eq!(pane_height(inline, 1), 0);
+        assert_eq!(pane_height(inline, 100), 0);
+    }
+
+    #[test]
+    fn ghost_selection() {
+        use sesd::snippet::Snippet;
+        let inline = PredictionMode::Inline;
+        assert_eq!(ghost_text(inline, &[], None), None);
+
+        let one = vec![Snippet::plain("[package]")];
+        assert_eq!(ghost_text(inline, &one, None), Some("[package]"));
+        assert_eq!(ghost_text(PredictionMode::Pane, &one, None), None);
+
+        let many = vec![
+            Snippet::plain("[package]"),
+            Snippet::plain("[lib]"),
+            Snippet::plain("[[bin]]"),
+        ];
+        assert_eq!(ghost_text(inline, &many, None), Some("[package]"));
+        assert_eq!(ghost_text(inline, &many, Some(2)), Some("[[bin]]"));
+    }
+
+    #[test]
+    fn short_window() {
+        assert_eq!(
+            prediction_mode(false, SHORT_WINDOW_HEIGHT),
+            PredictionMode::Pane
+        );
+        assert_eq!(
+            prediction_mode(false, SHORT_WINDOW_HEIGHT - 1),
+            PredictionMode::Inline
+        );
+        assert_eq!(pane_height(prediction_mode(false, 5), 3), 0);
+    }
+
+    #[test]
+    fn ghost_inside_element() {
+        let line = vec![element("name", 10), element(" = ", 14), element("¶", 17)];
+        let composed = compose_ghost(&line, 12, "ver\nignored");
+        assert_eq!(
+            texts(&composed),
+            vec![
+                ("na", 10, false),
+                ("ver", 12, true),
+                ("me", 12, false),
+                (" = ", 14, false),
+                ("¶", 17, false),
+            ]
+        );
+        // The original line still maps the positions.
+        assert!(line[1].spans(14));
+    }
+
+    #[test]
+    fn ghost_at_element_start_and_end() {
+        let line = vec![element("[package]", 0), element("¶", 9)];
+        assert_eq!(
+            texts(&compose_ghost(&line, 9, "\"x\"")),
+            vec![("[package]", 0, false), ("\"x\"", 9, true), ("¶", 9, false)]
+        );
+        assert_eq!(
+            texts(&compose_ghost(&line, 10, "[lib]")),
+            vec![
+                ("[package]", 0, false),
+                ("¶", 9, false),
+                ("[lib]", 10, true)
+            ]
+        );
+        assert_eq!(
+            texts(&compose_ghost(&[], 0, "[lib]")),
+            vec![("[lib]", 0, true)]
+        );
+    }
+}
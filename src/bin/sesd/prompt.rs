@@ -0,0 +1,226 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Single-line input on the error line
+//!
+//! Features that need a short string from the user (e.g. a file name) open a prompt. While the
+//! prompt is active, it receives all input. It ends when the user accepts or cancels it.
+
+use pancurses::Input;
+
+/// Line editor for short strings
+pub struct Prompt {
+    /// Text in front of the input
+    label: String,
+    /// Input so far
+    text: Vec<char>,
+    /// Index into text
+    cursor: usize,
+}
+
+/// Outcome of an input to the prompt
+#[derive(Debug, PartialEq)]
+pub enum PromptResult {
+    /// The prompt is still active
+    Editing,
+    /// The user pressed Enter
+    Accepted(String),
+    /// The user pressed Escape
+    Cancelled,
+}
+
+impl Prompt {
+    /// Create an empty prompt with a label
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            text: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    /// Start with the given input. The cursor is placed after it.
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+        self
+    }
+
+    /// Process one input
+    pub fn handle_input(&mut self, input: Input) -> PromptResult {
+        match input {
+            Input::Character('\n') | Input::Character('\r') | Input::KeyEnter => {
+                return PromptResult::Accepted(self.text.iter().collect());
+            }
+            Input::Character('\x1b') => return PromptResult::Cancelled,
+            Input::KeyBackspace | Input::Character('\x7f') | Input::Character('\x08') => {
+                if self.cursor != 0 {
+                    self.cursor -= 1;
+                    self.text.remove(self.cursor);
+                }
+            }
+            Input::KeyDC => {
+                if self.cursor < self.text.len() {
+                    self.text.remove(self.cursor);
+                }
+            }
+            Input::KeyLeft => self.cursor = self.cursor.saturating_sub(1),
+            Input::KeyRight => self.cursor = std::cmp::min(self.cursor + 1, self.text.len()),
+            Input::KeyHome => self.cursor = 0,
+            Input::KeyEnd => self.cursor = self.text.len(),
+            Input::Character(c) if !c.is_control() => {
+                self.text.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+        PromptResult::Editing
+    }
+
+    /// Label and input to display
+    pub fn line(&self) -> String {
+        let mut line = self.label.clone();
+        line.extend(self.text.iter());
+        line
+    }
+
+    /// Screen column of the cursor
+    pub fn cursor_col(&self) -> usize {
+        self.label.chars().count() + self.cursor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{cargo_toml, App, Editor};
+    use super::*;
+
+    /// Feed the characters of a string to the prompt
+    fn type_str(prompt: &mut Prompt, s: &str) {
+        for c in s.chars() {
+            assert_eq!(
+                prompt.handle_input(Input::Character(c)),
+                PromptResult::Editing
+            );
+        }
+    }
+
+    #[test]
+    fn line_editing() {
+        let mut prompt = Prompt::new("Save as: ");
+        type_str(&mut prompt, "ab.toml");
+        assert_eq!(prompt.line(), "Save as: ab.toml");
+        assert_eq!(prompt.cursor_col(), 16);
+
+        for input in [Input::KeyHome, Input::KeyRight, Input::KeyDC] {
+            assert_eq!(prompt.handle_input(input), PromptResult::Editing);
+        }
+        type_str(&mut prompt, "x");
+        assert_eq!(prompt.line(), "Save as: ax.toml");
+
+        for input in [Input::KeyEnd, Input::KeyLeft, Input::KeyBackspace] {
+            prompt.handle_input(input);
+        }
+        assert_eq!(prompt.line(), "Save as: ax.tol");
+        assert_eq!(prompt.cursor_col(), 14);
+
+        // Moving beyond the ends is ignored
+        prompt.handle_input(Input::KeyHome);
+        prompt.handle_input(Input::KeyBackspace);
+        prompt.handle_input(Input::KeyLeft);
+        assert_eq!(prompt.cursor_col(), 9);
+        prompt.handle_input(Input::KeyEnd);
+        prompt.handle_input(Input::KeyRight);
+        prompt.handle_input(Input::KeyDC);
+        assert_eq!(prompt.cursor_col(), 15);
+
+        assert_eq!(
+            prompt.handle_input(Input::Character('\n')),
+            PromptResult::Accepted("ax.tol".to_string())
+        );
+    }
+
+    #[test]
+    fn cancel() {
+        let mut prompt = Prompt::new("> ").with_text("old");
+        type_str(&mut prompt, "new");
+        assert_eq!(prompt.line(), "> oldnew");
+        assert_eq!(
+            prompt.handle_input(Input::Character('\x1b')),
+            PromptResult::Cancelled
+        );
+    }
+
+    fn app() -> App {
+        let grammar = cargo_toml::grammar();
+        let look_and_feel = cargo_toml::look_and_feel(&grammar);
+        let mut app = App::new(Editor::new(grammar), look_and_feel, "Cargo.toml".into());
+        app.editor.enter_iter("[package]\n".chars());
+        app
+    }
+
+    #[test]
+    fn cancel_restores_error() {
+        let mut app = app();
+        app.error = "previous".to_string();
+        app.handle_input(Input::KeyF3);
+        assert!(app.prompt.is_some());
+        app.handle_input(Input::Character('x'));
+        app.handle_input(Input::Character('\x1b'));
+        assert!(app.prompt.is_none());
+        assert_eq!(app.error, "previous");
+        assert_eq!(app.filename, std::path::PathBuf::from("Cargo.toml"));
+    }
+
+    #[test]
+    fn save_as() {
+        let dir = std::env::temp_dir().join(format!("sesd-save-as-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("temp dir should be writable");
+        let path = dir.join("new.toml");
+        let _ = std::fs::remove_file(&path);
+
+        let mut app = app();
+        app.handle_input(Input::KeyF3);
+        // The prompt starts with the current file name
+        for _ in 0.."Cargo.toml".len() {
+            app.handle_input(Input::KeyBackspace);
+        }
+        for c in path.to_string_lossy().chars() {
+            app.handle_input(Input::Character(c));
+        }
+        app.handle_input(Input::Character('\n'));
+
+        assert!(app.prompt.is_none());
+        assert_eq!(app.filename, path);
+        assert_eq!(app.title(), format!("{} -- sesd", path.to_string_lossy()));
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("file should have been saved"),
+            "[package]\n"
+        );
+        // The buffer is still unchanged
+        assert_eq!(app.editor.as_string(), "[package]\n");
+
+        std::fs::remove_dir_all(&dir).expect("temp dir should be removable");
+    }
+}
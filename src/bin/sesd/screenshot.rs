@@ -0,0 +1,250 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Headless rendering of the document
+//!
+//! Renders the laid-out document into rows of characters and rows of style codes instead of a
+//! curses window. Each cell of a style row holds the code of the style of the character in the
+//! same cell of the text row. The codes are assigned in order of appearance and explained in the
+//! legend. Unstyled cells are marked with `.`.
+
+use pancurses::Attributes;
+
+use super::SynElement;
+
+/// Characters used as style codes
+const STYLE_CODES: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+/// Rendered document
+#[derive(Debug, Default)]
+pub struct Screenshot {
+    /// Text of each line
+    pub text: Vec<String>,
+    /// Style code of each character of a line
+    pub styles: Vec<String>,
+    /// Attributes for each style code
+    legend: Vec<Attributes>,
+}
+
+impl Screenshot {
+    /// Render all lines of a document.
+    pub fn of(document: &[Vec<SynElement>]) -> Self {
+        let mut screenshot = Self::default();
+        for line in document {
+            let mut text = String::new();
+            let mut styles = String::new();
+            super::render_line(line, |part, attr| {
+                let code = screenshot.code(attr);
+                text.push_str(part);
+                styles.extend(part.chars().map(|_| code));
+            });
+            screenshot.text.push(text);
+            screenshot.styles.push(styles);
+        }
+        screenshot
+    }
+
+    /// Find or assign the style code of the attributes
+    fn code(&mut self, attr: Attributes) -> char {
+        if attr == Attributes::new() {
+            return '.';
+        }
+        let index = match self.legend.iter().position(|a| *a == attr) {
+            Some(index) => index,
+            None => {
+                self.legend.push(attr);
+                self.legend.len() - 1
+            }
+        };
+        STYLE_CODES.chars().nth(index).unwrap_or('?')
+    }
+
+    /// Write the legend, then each text row followed by its style row.
+    pub fn write(&self, w: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        for (attr, code) in self.legend.iter().zip(STYLE_CODES.chars()) {
+            write!(w, "{}: pair {:#o}", code, attr.color_pair().0)?;
+            for (set, name) in [
+                (attr.is_bold(), "bold"),
+                (attr.is_italic(), "italic"),
+                (attr.is_underline(), "underline"),
+                (attr.is_reverse(), "reverse"),
+                (attr.is_dim(), "dim"),
+            ]
+            .iter()
+            {
+                if *set {
+                    write!(w, ", {}", name)?;
+                }
+            }
+            writeln!(w)?;
+        }
+        for (text, styles) in self.text.iter().zip(self.styles.iter()) {
+            writeln!(w, "|{}", text)?;
+            writeln!(w, "|{}", styles)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::golden::check_golden;
+    use super::super::look_and_feel::{LookAndFeel, Style, StyleMatcher};
+    use super::super::{cargo_toml, App, Editor};
+    use super::*;
+
+    fn screenshot(width: usize) -> String {
+        let grammar = cargo_toml::grammar();
+        let look_and_feel = cargo_toml::look_and_feel(&grammar);
+        let mut app = App::new(Editor::new(grammar), look_and_feel, "Cargo.toml".into());
+        app.editor
+            .enter_iter(include_str!("../../../test_files/screenshot.toml").chars());
+        app.update_document(width);
+
+        let mut text = String::new();
+        Screenshot::of(&app.document)
+            .write(&mut text)
+            .expect("writing to a string should work");
+        text
+    }
+
+    #[test]
+    fn wide() {
+        check_golden("screenshot_wide.txt", &screenshot(80));
+    }
+
+    #[test]
+    fn narrow() {
+        check_golden("screenshot_narrow.txt", &screenshot(24));
+    }
+
+    /// Lay out the text with only the table headers styled.
+    fn table_app(text: &str, section_break: bool) -> App {
+        let grammar = cargo_toml::grammar();
+        let mut style = Style::none();
+        style.section_break_before = section_break;
+        let mut look_and_feel = LookAndFeel::new(Style::none());
+        look_and_feel.add_style(
+            StyleMatcher::new(style)
+                .exact(grammar.nt_id("toml"))
+                .star(grammar.nt_id("expressions"))
+                .exact(grammar.nt_id("expression"))
+                .exact(grammar.nt_id("table")),
+        );
+        let mut app = App::new(Editor::new(grammar), look_and_feel, "Cargo.toml".into());
+        app.editor.enter_iter(text.chars());
+        app.update_document(20);
+        app
+    }
+
+    /// Text of each line, rules as `---`
+    fn lines(app: &App) -> Vec<String> {
+        app.document
+            .iter()
+            .map(|line| {
+                if line.iter().any(|se| se.rule) {
+                    "---".to_string()
+                } else {
+                    line.iter().map(|se| se.text.as_str()).collect()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn section_breaks() {
+        let app = table_app("# deps\n[a]\nx = 1\n[b]\n", true);
+        assert_eq!(
+            lines(&app),
+            vec!["# deps¶", "---", "[a]¶", "x = 1¶", "---", "[b]¶", ""]
+        );
+        assert!(app.document[1][0].attr.is_dim());
+        assert_eq!(app.document[1][0].text.chars().count(), 20);
+
+        // No rule at the top of the document
+        let app = table_app("[a]\n[b]\n", true);
+        assert_eq!(lines(&app), vec!["[a]¶", "---", "[b]¶", ""]);
+    }
+
+    #[test]
+    fn section_breaks_collapse() {
+        let mut document = vec![vec![SynElement {
+            attr: Attributes::new(),
+            text: "x".to_string(),
+            start: 0,
+            rule: false,
+        }]];
+        let mut line_nr = 0;
+        let mut line_len = 1;
+        assert!(App::section_break(
+            &mut document,
+            &mut line_nr,
+            &mut line_len,
+            5,
+            1
+        ));
+        assert!(!App::section_break(
+            &mut document,
+            &mut line_nr,
+            &mut line_len,
+            5,
+            1
+        ));
+        assert_eq!(document.len(), 3);
+        assert_eq!(line_nr, 2);
+        assert_eq!(line_len, 0);
+        assert!(document[1][0].rule);
+        assert!(document[2].is_empty());
+    }
+
+    #[test]
+    fn section_breaks_keep_cursor() {
+        let text = "# deps\n[a]\nx = 1\n[b]\n";
+        let mut with_rules = table_app(text, true);
+        let mut without_rules = table_app(text, false);
+        for i in 0..=text.len() {
+            with_rules.editor.set_cursor(i);
+            with_rules.update_document(20);
+            without_rules.editor.set_cursor(i);
+            without_rules.update_document(20);
+
+            let line = with_rules.cursor_doc_line;
+            let rules_above = lines(&with_rules)[..line]
+                .iter()
+                .filter(|l| *l == "---")
+                .count();
+            assert_eq!(
+                line - rules_above,
+                without_rules.cursor_doc_line,
+                "at {}",
+                i
+            );
+            assert_eq!(with_rules.cursor_col, without_rules.cursor_col, "at {}", i);
+            assert_eq!(
+                lines(&with_rules)[line],
+                lines(&without_rules)[without_rules.cursor_doc_line]
+            );
+        }
+    }
+}
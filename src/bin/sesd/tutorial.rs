@@ -0,0 +1,243 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Interactive tutorial for Cargo.toml files
+//!
+//! The tutorial is a sequence of tasks. Each task has a goal that is checked against the parse
+//! tree of the buffer. As soon as the goal of the current task is reached, the tutorial advances
+//! to the next task.
+
+use sesd::SymbolId;
+
+use super::Editor;
+
+/// Goal of a task, checked against the parse tree
+enum Goal {
+    /// A standard table with the given name exists
+    Table(&'static str),
+
+    /// A key-value pair with the given key and a string value exists
+    KeyWithString(&'static str),
+}
+
+/// One step of the tutorial
+struct Task {
+    /// Instruction shown to the user
+    description: &'static str,
+
+    /// Text that solves the task
+    hint: &'static str,
+
+    /// Condition to advance to the next task
+    goal: Goal,
+}
+
+/// Progress through the tasks of the tutorial
+pub struct Tutorial {
+    /// All tasks in order
+    tasks: Vec<Task>,
+
+    /// Index of the current task. If equal to the number of tasks, the tutorial is complete.
+    current: usize,
+}
+
+/// Symbol IDs needed to check the goals
+struct Symbols {
+    std_table: SymbolId,
+    keyval: SymbolId,
+    key: SymbolId,
+    string: SymbolId,
+}
+
+/// Find the spans of all completed nodes of the given symbol.
+fn completed_nodes(editor: &Editor, sym: SymbolId) -> Vec<(usize, usize)> {
+    let grammar = editor.grammar();
+    let mut nodes: Vec<(usize, usize)> = editor
+        .cst_iter()
+        .filter_map(|item| {
+            item.as_parsed()
+                .filter(|node| grammar.dotted_is_completed(&node.dotted_rule) && node.lhs == sym)
+                .map(|node| (node.start, node.end))
+        })
+        .collect();
+    nodes.dedup();
+    nodes
+}
+
+/// Check if any of the key nodes inside the span has the given text.
+fn has_key(editor: &Editor, keys: &[(usize, usize)], start: usize, end: usize, name: &str) -> bool {
+    keys.iter()
+        .any(|(s, e)| start <= *s && *e <= end && editor.span_string(*s, *e).trim() == name)
+}
+
+impl Goal {
+    /// Check if the goal has been reached
+    fn reached(&self, editor: &Editor, symbols: &Symbols) -> bool {
+        let keys = completed_nodes(editor, symbols.key);
+        match self {
+            Goal::Table(name) => completed_nodes(editor, symbols.std_table)
+                .iter()
+                .any(|(s, e)| has_key(editor, &keys, *s, *e, name)),
+            Goal::KeyWithString(name) => {
+                let strings = completed_nodes(editor, symbols.string);
+                completed_nodes(editor, symbols.keyval)
+                    .iter()
+                    .any(|(s, e)| {
+                        has_key(editor, &keys, *s, *e, name)
+                            && strings.iter().any(|(ss, se)| s <= ss && se <= e)
+                    })
+            }
+        }
+    }
+}
+
+impl Tutorial {
+    /// Create the tutorial for Cargo.toml files
+    pub fn new() -> Self {
+        Self {
+            tasks: vec![
+                Task {
+                    description: "Create a [package] table.",
+                    hint: "[package]\n",
+                    goal: Goal::Table("package"),
+                },
+                Task {
+                    description: "Add a name key with a string value, e.g. name = \"hello\".",
+                    hint: "name = \"hello\"\n",
+                    goal: Goal::KeyWithString("name"),
+                },
+                Task {
+                    description: "Add a version key with a string value, e.g. version = \"0.1.0\".",
+                    hint: "version = \"0.1.0\"\n",
+                    goal: Goal::KeyWithString("version"),
+                },
+                Task {
+                    description: "Create a [dependencies] table.",
+                    hint: "[dependencies]\n",
+                    goal: Goal::Table("dependencies"),
+                },
+            ],
+            current: 0,
+        }
+    }
+
+    /// Advance through all tasks whose goals have been reached.
+    ///
+    /// Return true if the tutorial advanced.
+    pub fn update(&mut self, editor: &Editor) -> bool {
+        let grammar = editor.grammar();
+        let symbols = Symbols {
+            std_table: grammar.nt_id("std-table"),
+            keyval: grammar.nt_id("keyval"),
+            key: grammar.nt_id("key"),
+            string: grammar.nt_id("string"),
+        };
+        let old = self.current;
+        while self.current < self.tasks.len()
+            && self.tasks[self.current].goal.reached(editor, &symbols)
+        {
+            self.current += 1;
+        }
+        old != self.current
+    }
+
+    /// Text that solves the current task
+    pub fn hint(&self) -> Option<&'static str> {
+        self.tasks.get(self.current).map(|t| t.hint)
+    }
+
+    /// Instruction for the current task
+    pub fn text(&self) -> String {
+        match self.tasks.get(self.current) {
+            Some(task) => format!(
+                "Task {}/{}: {} (Press F1 for a hint.)",
+                self.current + 1,
+                self.tasks.len(),
+                task.description
+            ),
+            None => "Tutorial complete. Press F10 to quit.".to_string(),
+        }
+    }
+
+    /// Check if all tasks have been solved
+    #[cfg(test)]
+    pub fn is_complete(&self) -> bool {
+        self.current == self.tasks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cargo_toml;
+    use super::*;
+
+    fn editor() -> Editor {
+        Editor::new(cargo_toml::grammar())
+    }
+
+    #[test]
+    fn progression() {
+        let mut editor = editor();
+        let mut tutorial = Tutorial::new();
+        assert!(!tutorial.update(&editor));
+        assert_eq!(tutorial.current, 0);
+
+        editor.enter_iter("[package]\n".chars());
+        assert!(tutorial.update(&editor));
+        assert_eq!(tutorial.current, 1);
+
+        // Wrong key does not count
+        editor.enter_iter("names = \"x\"\n".chars());
+        assert!(!tutorial.update(&editor));
+
+        editor.enter_iter("name = \"sesd\"\n".chars());
+        assert!(tutorial.update(&editor));
+        assert_eq!(tutorial.current, 2);
+    }
+
+    #[test]
+    fn similar_table_name() {
+        let mut editor = editor();
+        let mut tutorial = Tutorial::new();
+        editor.enter_iter("[packages]\n".chars());
+        assert!(!tutorial.update(&editor));
+        assert_eq!(tutorial.current, 0);
+    }
+
+    #[test]
+    fn hints_solve_tasks() {
+        let mut editor = editor();
+        let mut tutorial = Tutorial::new();
+        while let Some(hint) = tutorial.hint() {
+            editor.enter_iter(hint.chars());
+            assert!(
+                tutorial.update(&editor),
+                "hint {:?} should solve task",
+                hint
+            );
+        }
+        assert!(tutorial.is_complete());
+        assert_eq!(tutorial.text(), "Tutorial complete. Press F10 to quit.");
+    }
+}
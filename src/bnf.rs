@@ -0,0 +1,201 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Grammars of `char` from BNF text
+//!
+//! [Grammar::parse_bnf](../struct.Grammar.html#method.parse_bnf) reads the rules of a grammar from
+//! text, e.g. to ship the grammar as a file instead of building it in code:
+//!
+//! ```text
+//! # Comments start with a hash
+//! <list> ::= <item> | <item> ", " <list>
+//! <item> ::= [a-z] | "\"" <digits> "\""
+//! <digits> ::=
+//!            | [0-9] <digits>
+//! ```
+//!
+//! Non-terminals are enclosed in angle brackets, strings in double quotes. `\n`, `\t`, `\"` and
+//! `\\` are the escapes in strings. `[a-z]` matches a range of characters. Alternatives are
+//! separated by ` | `. A line that begins with `|` continues the rules of the previous line. The
+//! left hand side of the first rule is the start symbol.
+//!
+//! The [EBNF export](../export/enum.GrammarExportFormat.html#variant.Ebnf) writes this syntax.
+
+use super::char::CharMatcher;
+use super::grammar::{Error, Grammar, Rule};
+
+/// Parse the right hand side of a rule. `line` is only used for error messages.
+fn parse_rhs(lhs: &str, rhs: &str, line: &str) -> Result<Rule<CharMatcher>, Error> {
+    let syntax = |what: &str| Error::Syntax(format!("{} in {}", what, line));
+    let mut rule = Rule::new(lhs);
+    let mut chars = rhs.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {}
+            '<' => {
+                let name: String = chars.by_ref().take_while(|c| *c != '>').collect();
+                rule = rule.nt(&name);
+            }
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        let escaped = match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(c) => c,
+                            None => return Err(syntax("unterminated string")),
+                        };
+                        rule = rule.t(CharMatcher::Exact(escaped));
+                    }
+                    Some(c) => rule = rule.t(CharMatcher::Exact(c)),
+                    None => return Err(syntax("unterminated string")),
+                }
+            },
+            '[' => {
+                let range: Vec<char> = chars.by_ref().take_while(|c| *c != ']').collect();
+                match range[..] {
+                    [from, '-', to] => rule = rule.t(CharMatcher::Range(from, to)),
+                    _ => return Err(syntax("invalid range")),
+                }
+            }
+            _ => return Err(syntax(&format!("unexpected {}", c))),
+        }
+    }
+    Ok(rule)
+}
+
+impl Grammar<char, CharMatcher> {
+    /// Read the rules from BNF text, see the [module documentation](bnf/index.html) for the
+    /// syntax.
+    ///
+    /// The start symbol is the left hand side of the first rule. Errors in the syntax are reported
+    /// as [Error::Syntax](enum.Error.html#variant.Syntax), undefined non-terminals when the
+    /// grammar is compiled.
+    ///
+    /// ```
+    /// use sesd::{Grammar, Parser, Verdict};
+    ///
+    /// let grammar = Grammar::parse_bnf("<S> ::= \"a\" <S> | \"b\"").unwrap();
+    /// let mut parser = Parser::new(grammar.compile().unwrap());
+    /// assert_eq!(parser.update_slice(0, &['a', 'a', 'b']), Verdict::Accept);
+    /// ```
+    pub fn parse_bnf(text: &str) -> Result<Self, Error> {
+        let mut grammar = Grammar::new();
+        let mut start = None;
+        let mut lhs: Option<String> = None;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rhs = if let Some(rhs) = line.strip_prefix('|') {
+                // Continue the previous rule
+                if lhs.is_none() {
+                    return Err(Error::Syntax(format!("no rule to continue in {}", line)));
+                }
+                rhs
+            } else {
+                let (left, rhs) = line
+                    .split_once("::=")
+                    .ok_or_else(|| Error::Syntax(format!("missing ::= in {}", line)))?;
+                let left = left.trim();
+                let name = left
+                    .strip_prefix('<')
+                    .and_then(|l| l.strip_suffix('>'))
+                    .ok_or_else(|| Error::Syntax(format!("invalid left hand side {}", left)))?;
+                start.get_or_insert_with(|| name.to_string());
+                lhs = Some(name.to_string());
+                rhs
+            };
+            let lhs = lhs.as_deref().unwrap_or_default();
+            for alternative in format!(" {} ", rhs).split(" | ") {
+                grammar.add(parse_rhs(lhs, alternative, line)?);
+            }
+        }
+        grammar.set_start(start.ok_or_else(|| Error::Syntax("no rules".to_string()))?);
+        Ok(grammar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, Verdict};
+
+    #[test]
+    fn parse_bnf() {
+        let grammar = Grammar::parse_bnf(
+            "# Quoted lists
+            <list> ::= <item> | <item> \", \" <list>
+            <item> ::= [a-z]
+                     | \"\\\"\" <digits> \"\\\"\"
+            <digits> ::=
+                     | [0-9] <digits>
+            ",
+        )
+        .expect("grammar should parse");
+        let mut parser = Parser::new(grammar.compile().expect("grammar should compile"));
+        for (text, verdict) in [
+            ("a, \"12\", \"\"", Verdict::Accept),
+            ("a, ", Verdict::More),
+            ("a; b", Verdict::Reject),
+        ]
+        .iter()
+        {
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(parser.update_slice(0, &tokens), *verdict, "{}", text);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for text in [
+            "",
+            "<S> = \"a\"",
+            "S ::= \"a\"",
+            "| \"a\"",
+            "<S> ::= \"a",
+            "<S> ::= [a-]",
+            "<S> ::= a",
+        ]
+        .iter()
+        {
+            assert!(
+                matches!(Grammar::parse_bnf(text), Err(Error::Syntax(_))),
+                "{}",
+                text
+            );
+        }
+        let grammar = Grammar::parse_bnf("<S> ::= <T>").expect("syntax should be valid");
+        assert_eq!(
+            grammar
+                .compile()
+                .err()
+                .as_ref()
+                .and_then(Error::no_rule_symbol),
+            Some("T")
+        );
+    }
+}
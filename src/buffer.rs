@@ -202,10 +202,6 @@ impl<T> Buffer<T> {
     pub fn len(&self) -> usize {
         self.tokens.len()
     }
-
-    pub fn token_from_iter<'a>(&'a self, start: usize) -> impl Iterator<Item = (usize, &'a T)> {
-        self.tokens.iter().enumerate().skip(start)
-    }
 }
 
 impl Buffer<char> {
@@ -26,10 +26,15 @@
 //!
 //! Provides exact and range matches.
 
-use super::grammar::Matcher;
+use std::io::Write;
+
+use super::codegen::MatcherSource;
+use super::export::Describe;
+use super::grammar::{CompiledGrammar, Matcher, MatcherDisplay, Synthesize, TerminalDispatch};
 
 /// Matches single characters or ranges
 #[derive(Clone, PartialEq, PartialOrd, Eq, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CharMatcher {
     /// Match a single char
     Exact(char),
@@ -41,6 +46,13 @@ pub enum CharMatcher {
     NoneOf(Vec<char>),
 }
 
+/// Match the character exactly, e.g. for literals in [grammar!](../macro.grammar.html)
+impl From<char> for CharMatcher {
+    fn from(c: char) -> Self {
+        CharMatcher::Exact(c)
+    }
+}
+
 impl Matcher<char> for CharMatcher {
     fn matches(&self, t: char) -> bool {
         match self {
@@ -58,6 +70,147 @@ impl Matcher<char> for CharMatcher {
     }
 }
 
+/// Produce the character of an exact match or the lower limit of a range. There is no obvious
+/// choice for `NoneOf`.
+impl Synthesize<char> for CharMatcher {
+    fn synthesize(&self) -> Option<char> {
+        match self {
+            CharMatcher::Exact(c) => Some(*c),
+            CharMatcher::Range(from, _) => Some(*from),
+            CharMatcher::NoneOf(_) => None,
+        }
+    }
+}
+
+/// Write the matcher as Rust character literals, i.e. `'a'`, `'a'..'z'` or `!['a', 'b']`.
+impl MatcherDisplay for CharMatcher {
+    fn write_matcher(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+        match self {
+            CharMatcher::Exact(c) => write!(writer, "{:?}", c),
+            CharMatcher::Range(from, to) => write!(writer, "{:?}..{:?}", from, to),
+            CharMatcher::NoneOf(cs) => {
+                write!(writer, "![")?;
+                for (i, c) in cs.iter().enumerate() {
+                    if i != 0 {
+                        write!(writer, ", ")?;
+                    }
+                    write!(writer, "{:?}", c)?;
+                }
+                write!(writer, "]")
+            }
+        }
+    }
+}
+
+/// Write a character inside a BNF string.
+fn escape_into(s: &mut String, c: char) {
+    match c {
+        '\n' => s.push_str("\\n"),
+        '\t' => s.push_str("\\t"),
+        '"' => s.push_str("\\\""),
+        '\\' => s.push_str("\\\\"),
+        c => s.push(c),
+    }
+}
+
+/// Describe the matcher in the syntax of the [bnf](../bnf/index.html) module, i.e. `"a"` or
+/// `[a-z]`. `NoneOf` is written as `[^...]`.
+impl Describe for CharMatcher {
+    fn describe(&self) -> String {
+        let mut s = String::new();
+        match self {
+            CharMatcher::Exact(c) => {
+                s.push('"');
+                escape_into(&mut s, *c);
+                s.push('"');
+            }
+            CharMatcher::Range(from, to) => {
+                s.push('[');
+                s.push(*from);
+                s.push('-');
+                s.push(*to);
+                s.push(']');
+            }
+            CharMatcher::NoneOf(cs) => {
+                s.push_str("[^");
+                for c in cs {
+                    escape_into(&mut s, *c);
+                }
+                s.push(']');
+            }
+        }
+        s
+    }
+}
+
+/// Write the matcher with its full path, e.g. `sesd::char::CharMatcher::Exact('a')`.
+impl MatcherSource for CharMatcher {
+    fn write_source(&self, w: &mut dyn Write) -> std::io::Result<()> {
+        write!(w, "sesd::char::CharMatcher::")?;
+        match self {
+            CharMatcher::Exact(c) => write!(w, "Exact({:?})", c),
+            CharMatcher::Range(from, to) => write!(w, "Range({:?}, {:?})", from, to),
+            CharMatcher::NoneOf(cs) => write!(w, "NoneOf(vec!{:?})", cs),
+        }
+    }
+}
+
+/// Interval map from characters to the terminals that match them.
+///
+/// The characters are split into intervals in which all terminals give the same result, so the
+/// terminals of a token are found by one binary search, see
+/// [Parser::set_terminal_dispatch](../struct.Parser.html#method.set_terminal_dispatch).
+#[derive(Clone, Debug)]
+pub struct CharDispatch {
+    /// First code point of each interval, ascending. The first interval starts at 0.
+    starts: Vec<u32>,
+    /// Matching terminals of each interval
+    matching: Vec<Vec<bool>>,
+}
+
+impl CharDispatch {
+    /// Build the map for the terminals of a grammar.
+    pub fn new(terminals: &[CharMatcher]) -> Self {
+        // Surrogates are no chars, so they are kept in an interval of their own.
+        let mut starts = vec![0, 0xD800, 0xE000];
+        for t in terminals {
+            match t {
+                CharMatcher::Exact(c) => starts.extend([*c as u32, *c as u32 + 1]),
+                CharMatcher::Range(from, to) => starts.extend([*from as u32, *to as u32 + 1]),
+                CharMatcher::NoneOf(cs) => {
+                    starts.extend(cs.iter().flat_map(|c| [*c as u32, *c as u32 + 1]))
+                }
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+        let matching = starts
+            .iter()
+            .map(|start| match char::from_u32(*start) {
+                Some(c) => terminals.iter().map(|t| t.matches(c)).collect(),
+                None => vec![false; terminals.len()],
+            })
+            .collect();
+        Self { starts, matching }
+    }
+
+    /// Build the map for the terminals of a grammar.
+    ///
+    /// Builder for set_terminal_dispatch.
+    pub fn build(
+        grammar: &CompiledGrammar<char, CharMatcher>,
+    ) -> Box<dyn TerminalDispatch<char> + Send> {
+        Box::new(Self::new(grammar.terminals()))
+    }
+}
+
+impl TerminalDispatch<char> for CharDispatch {
+    fn matching_terminals(&self, token: &char) -> &[bool] {
+        let interval = self.starts.partition_point(|start| *start <= *token as u32) - 1;
+        &self.matching[interval]
+    }
+}
+
 /// Check if the character before the buffer position is a newline.
 ///
 /// Predicate for skip_backward.
@@ -68,6 +221,39 @@ pub fn start_of_line(buffer: &Vec<char>, position: usize) -> bool {
     buffer[position - 1] == '\n'
 }
 
+/// Check if the character is a line break.
+///
+/// Predicate for set_submit_token.
+pub fn is_line_break(c: &char) -> bool {
+    *c == '\n' || *c == '\r'
+}
+
+/// Check if the character is a control character other than a tab.
+///
+/// Predicate for set_token_filter to reject control characters.
+pub fn is_not_control(c: &char) -> bool {
+    *c == '\t' || !c.is_control()
+}
+
+/// Treat tabs as spaces.
+///
+/// Normalizer for set_token_normalizer.
+pub fn fold_whitespace(c: &char) -> char {
+    if *c == '\t' {
+        ' '
+    } else {
+        *c
+    }
+}
+
+/// Match ASCII letters regardless of case. Terminals need to be lowercase.
+///
+/// Normalizer for set_token_normalizer. Unicode normalization (e.g. NFC) is not provided as it
+/// would require the Unicode tables.
+pub fn ascii_lowercase(c: &char) -> char {
+    c.to_ascii_lowercase()
+}
+
 /// Check if the character at the buffer position is a newline
 ///
 /// Predicate for skip_forward
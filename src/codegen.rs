@@ -0,0 +1,218 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Generate Rust source for compiled grammars
+//!
+//! Build scripts can write the tables of a grammar to a file and include it in the crate, so the
+//! grammar doesn't need to be built and compiled at every program start:
+//!
+//! ```ignore
+//! // build.rs
+//! let text = std::fs::read_to_string("toml.bnf").unwrap();
+//! let grammar = Grammar::parse_bnf(&text).and_then(Grammar::compile).unwrap();
+//! let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("toml.rs");
+//! let mut file = std::fs::File::create(path).unwrap();
+//! sesd::codegen::write_static_grammar(&grammar, &mut file).unwrap();
+//!
+//! // src/toml.rs
+//! include!(concat!(env!("OUT_DIR"), "/toml.rs"));
+//! ```
+//!
+//! The generated code defines the constants `NT_NAMES`, `NUMBER_OF_NTS`, `TERMINAL_COUNT`,
+//! `START` and `RULES`, verifies them with [check_tables](../fn.check_tables.html)
+//! at compile time, and defines the function `grammar()`, which builds the grammar with
+//! [CompiledGrammar::from_tables](../struct.CompiledGrammar.html#method.from_tables). Each
+//! generated file should be included in a module of its own.
+//!
+//! Removed rules are left out, so the rule indices may differ from the source grammar. Terminal
+//! names and rule annotations are not written.
+
+use std::io::{Result, Write};
+
+use super::grammar::{CompiledGrammar, Matcher, SymbolId};
+
+/// Write a matcher as Rust source
+pub trait MatcherSource {
+    /// Write an expression that evaluates to the matcher. Paths need to start at the crate root,
+    /// as the code is included in other crates.
+    fn write_source(&self, w: &mut dyn Write) -> Result<()>;
+}
+
+/// Write the tables of the grammar and a function to build it as Rust source.
+pub fn write_static_grammar<T, M>(grammar: &CompiledGrammar<T, M>, w: &mut dyn Write) -> Result<()>
+where
+    M: Matcher<T> + Clone + MatcherSource,
+{
+    let nt_count = grammar.nt_count();
+    writeln!(
+        w,
+        "// Generated by sesd::codegen::write_static_grammar. Do not edit."
+    )?;
+    writeln!(w)?;
+    writeln!(w, "/// Names of the non-terminals")?;
+    writeln!(w, "pub const NT_NAMES: [&str; {}] = [", nt_count)?;
+    for sym in 0..nt_count {
+        writeln!(w, "    {:?},", grammar.nt_name(sym as SymbolId))?;
+    }
+    writeln!(w, "];")?;
+    writeln!(w, "/// Number of non-terminals")?;
+    writeln!(w, "pub const NUMBER_OF_NTS: usize = {};", nt_count)?;
+    writeln!(w, "/// Number of terminals")?;
+    writeln!(
+        w,
+        "pub const TERMINAL_COUNT: usize = {};",
+        grammar.terminals().len()
+    )?;
+    writeln!(w, "/// Start symbol")?;
+    writeln!(w, "pub const START: sesd::SymbolId = {};", grammar.start())?;
+    writeln!(w, "/// Rules as lhs and rhs")?;
+    writeln!(
+        w,
+        "pub const RULES: &[(sesd::SymbolId, &[sesd::SymbolId])] = &["
+    )?;
+    for i in (0..grammar.rule_count()).filter(|i| !grammar.is_removed(*i)) {
+        write!(w, "    ({}, &[", grammar.lhs(i))?;
+        for (j, sym) in grammar.rhs(i).iter().enumerate() {
+            if j != 0 {
+                write!(w, ", ")?;
+            }
+            write!(w, "{}", sym)?;
+        }
+        writeln!(w, "]),")?;
+    }
+    writeln!(w, "];")?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "const _: () = sesd::check_tables(NT_NAMES.len(), NUMBER_OF_NTS, TERMINAL_COUNT, RULES);"
+    )?;
+    writeln!(w)?;
+    writeln!(w, "/// Build the grammar from the tables")?;
+    writeln!(
+        w,
+        "pub fn grammar() -> sesd::CompiledGrammar<{}, {}> {{",
+        std::any::type_name::<T>(),
+        std::any::type_name::<M>()
+    )?;
+    writeln!(w, "    let terminals = vec![")?;
+    for t in grammar.terminals() {
+        write!(w, "        ")?;
+        t.write_source(w)?;
+        writeln!(w, ",")?;
+    }
+    writeln!(w, "    ];")?;
+    writeln!(
+        w,
+        "    sesd::CompiledGrammar::from_tables(&NT_NAMES, terminals, RULES, START)"
+    )?;
+    writeln!(w, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::char::CharMatcher;
+    use crate::grammar::tests::define_grammar;
+    use crate::{Grammar, Rule};
+
+    #[test]
+    fn static_grammar() {
+        use CharMatcher::*;
+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").t(Range('a', 'z')).nt("S"));
+        grammar.add(Rule::new("S"));
+        grammar.add(Rule::new("S").t(NoneOf(vec!['"', '\\'])));
+        let grammar = grammar.compile().expect("compilation should have worked");
+        let mut source = Vec::new();
+        write_static_grammar(&grammar, &mut source).expect("writing should have worked");
+        assert_eq!(
+            String::from_utf8(source).expect("source should be UTF-8"),
+            r#"// Generated by sesd::codegen::write_static_grammar. Do not edit.
+
+/// Names of the non-terminals
+pub const NT_NAMES: [&str; 2] = [
+    "~~~ERROR~~~",
+    "S",
+];
+/// Number of non-terminals
+pub const NUMBER_OF_NTS: usize = 2;
+/// Number of terminals
+pub const TERMINAL_COUNT: usize = 2;
+/// Start symbol
+pub const START: sesd::SymbolId = 1;
+/// Rules as lhs and rhs
+pub const RULES: &[(sesd::SymbolId, &[sesd::SymbolId])] = &[
+    (0, &[]),
+    (1, &[2, 1]),
+    (1, &[]),
+    (1, &[3]),
+];
+
+const _: () = sesd::check_tables(NT_NAMES.len(), NUMBER_OF_NTS, TERMINAL_COUNT, RULES);
+
+/// Build the grammar from the tables
+pub fn grammar() -> sesd::CompiledGrammar<char, sesd::char::CharMatcher> {
+    let terminals = vec![
+        sesd::char::CharMatcher::Range('a', 'z'),
+        sesd::char::CharMatcher::NoneOf(vec!['"', '\\']),
+    ];
+    sesd::CompiledGrammar::from_tables(&NT_NAMES, terminals, RULES, START)
+}
+"#
+        );
+    }
+
+    /// A grammar built from its tables is the same as the original one
+    #[test]
+    fn from_tables() {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let nt_names: Vec<&str> = (0..grammar.nt_count())
+            .map(|sym| grammar.nt_name(sym as SymbolId))
+            .collect();
+        let rules: Vec<(SymbolId, &[SymbolId])> = (0..grammar.rule_count())
+            .map(|i| (grammar.lhs(i), grammar.rhs(i)))
+            .collect();
+        let copy = CompiledGrammar::<char, CharMatcher>::from_tables(
+            &nt_names,
+            grammar.terminals().to_vec(),
+            &rules,
+            grammar.start(),
+        );
+        assert_eq!(copy.fingerprint(), grammar.fingerprint());
+        for sym in 0..grammar.nt_count() {
+            let sym = sym as SymbolId;
+            assert_eq!(copy.nt_id(grammar.nt_name(sym)), sym);
+            assert_eq!(copy.rules_for_lhs(sym), grammar.rules_for_lhs(sym));
+        }
+        assert_eq!(copy.nullable_symbols(), grammar.nullable_symbols());
+        for (i, t) in grammar.terminals().iter().enumerate() {
+            assert_eq!(copy.t_id(t), Some(i));
+        }
+    }
+}
@@ -0,0 +1,174 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Changes of the parse tree between two parses
+//!
+//! A [CstSnapshot](struct.CstSnapshot.html) records the completed nodes of a parse by symbol and
+//! span. Comparing the snapshots taken before and after an edit tells renderers and semantic
+//! layers which nodes have to be updated, instead of rebuilding everything.
+
+use crate::{Matcher, Parser, SymbolId};
+
+/// Completed node of the parse tree as symbol, start and end position
+pub type NodeSpan = (SymbolId, usize, usize);
+
+/// Completed nodes of a parse, sorted by start, end and symbol
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CstSnapshot {
+    nodes: Vec<NodeSpan>,
+}
+
+/// Difference between two snapshots, see [CstSnapshot::diff](struct.CstSnapshot.html#method.diff)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CstDiff {
+    /// Nodes that only exist in the newer parse
+    pub added: Vec<NodeSpan>,
+    /// Nodes that only exist in the older parse
+    pub removed: Vec<NodeSpan>,
+    /// Nodes that exist in both parses
+    pub unchanged: Vec<NodeSpan>,
+}
+
+impl CstSnapshot {
+    /// Record the completed nodes of the current parse, including error nodes.
+    ///
+    /// Nodes of all derivations are recorded unless the parser has a disambiguation strategy.
+    pub fn new<T, M>(parser: &Parser<T, M>) -> Self
+    where
+        T: Clone,
+        M: Matcher<T> + Clone,
+    {
+        let mut nodes: Vec<NodeSpan> = parser
+            .cst_iter()
+            .filter_map(|item| {
+                item.as_parsed()
+                    .filter(|n| parser.grammar().dotted_is_completed(&n.dotted_rule))
+                    .map(|n| (n.lhs, n.start, n.end))
+            })
+            .collect();
+        nodes.sort_by_key(|(symbol, start, end)| (*start, *end, *symbol));
+        nodes.dedup();
+        Self { nodes }
+    }
+
+    /// Recorded nodes
+    pub fn nodes(&self) -> &[NodeSpan] {
+        &self.nodes
+    }
+
+    /// Move the nodes behind an edit where `removed` tokens at `position` have been replaced by
+    /// `inserted` tokens.
+    ///
+    /// Call this on the old snapshot before comparing it with the parse of the edited buffer.
+    /// Nodes that overlap the edit keep their span.
+    pub fn shift(&mut self, position: usize, removed: usize, inserted: usize) {
+        let edit_end = position + removed;
+        for (_, start, end) in self.nodes.iter_mut() {
+            if *start >= edit_end {
+                *start = *start - removed + inserted;
+                *end = *end - removed + inserted;
+            }
+        }
+        self.nodes
+            .sort_by_key(|(symbol, start, end)| (*start, *end, *symbol));
+    }
+
+    /// Compare with a snapshot of a newer parse.
+    pub fn diff(&self, newer: &CstSnapshot) -> CstDiff {
+        let key = |(symbol, start, end): &NodeSpan| (*start, *end, *symbol);
+        let mut diff = CstDiff::default();
+        let mut old = self.nodes.iter().peekable();
+        let mut new = newer.nodes.iter().peekable();
+        loop {
+            match (old.peek(), new.peek()) {
+                (Some(o), Some(n)) if key(o) == key(n) => {
+                    diff.unchanged.push(**o);
+                    old.next();
+                    new.next();
+                }
+                (Some(o), Some(n)) if key(o) < key(n) => {
+                    diff.removed.push(**o);
+                    old.next();
+                }
+                (_, Some(n)) => {
+                    diff.added.push(**n);
+                    new.next();
+                }
+                (Some(o), None) => {
+                    diff.removed.push(**o);
+                    old.next();
+                }
+                (None, None) => break,
+            }
+        }
+        diff
+    }
+}
+
+impl CstDiff {
+    /// Check if the parse tree hasn't changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::char::CharMatcher;
+    use crate::{Grammar, Rule};
+
+    #[test]
+    fn edit_inside_list() {
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("List"));
+        grammar.add(Rule::new("List").nt("List").nt("Item"));
+        grammar.add(Rule::new("List").nt("Item"));
+        grammar.add(Rule::new("Item").t(Exact('a')));
+        grammar.add(Rule::new("Item").t(Exact('(')).nt("List").t(Exact(')')));
+        let mut parser = Parser::new(grammar.compile().expect("compilation should have worked"));
+        let s = parser.grammar().nt_id("S");
+        let item = parser.grammar().nt_id("Item");
+
+        let old: Vec<char> = "a(a)a".chars().collect();
+        parser.update_slice(0, &old);
+        let mut before = CstSnapshot::new(&parser);
+        assert!(before.diff(&CstSnapshot::new(&parser)).is_empty());
+
+        // Replace the inner 'a' by "aa"
+        parser.update_slice(2, &['a', 'a', ')', 'a']);
+        before.shift(2, 1, 2);
+        let diff = before.diff(&CstSnapshot::new(&parser));
+        assert!(diff.removed.contains(&(s, 0, 5)));
+        assert!(diff.added.contains(&(s, 0, 6)));
+        assert!(diff.added.contains(&(item, 3, 4)));
+        assert!(diff.unchanged.contains(&(item, 0, 1)));
+        assert!(diff.unchanged.contains(&(item, 2, 3)));
+        assert!(diff.unchanged.contains(&(item, 5, 6)));
+        assert!(!diff.unchanged.contains(&(item, 1, 4)));
+    }
+}
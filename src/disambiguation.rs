@@ -0,0 +1,95 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Strategies to select a single parse tree
+//!
+//! If the input has been parsed ambiguously, the CST iterator visits all derivations at once. A
+//! strategy registered with
+//! [Parser::set_disambiguation](../struct.Parser.html#method.set_disambiguation) selects one
+//! derivation wherever there is a choice, so that styling and structural editing see a single
+//! tree.
+//!
+//! The choice is made from the last symbol of a partially parsed rule backwards. The symbols
+//! before it are decided when the iterator gets there.
+
+/// Alternative derivation of the last symbol of a partially parsed rule or of the start symbol
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alternative {
+    /// Index of the rule that derived the symbol
+    pub rule: usize,
+    /// Start position of the symbol
+    pub start: usize,
+    /// End position of the symbol
+    pub end: usize,
+}
+
+/// Strategy to select one of several derivations
+pub trait Disambiguation {
+    /// Return the index of the alternative to keep. There are at least two alternatives.
+    fn choose(&self, alternatives: &[Alternative]) -> usize;
+}
+
+/// Prefer the rule with the highest priority. The index is the rule index, missing rules have
+/// priority 0. Ties go to the first alternative.
+#[derive(Clone, Debug, Default)]
+pub struct RulePriority(pub Vec<i32>);
+
+impl Disambiguation for RulePriority {
+    fn choose(&self, alternatives: &[Alternative]) -> usize {
+        first_min_by_key(alternatives, |a| {
+            -self.0.get(a.rule).copied().unwrap_or_default()
+        })
+    }
+}
+
+/// Prefer the derivation where the last symbol covers the most tokens, e.g. to attach an `else`
+/// to the innermost `if`. Ties go to the first alternative.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LongestMatch;
+
+impl Disambiguation for LongestMatch {
+    fn choose(&self, alternatives: &[Alternative]) -> usize {
+        first_min_by_key(alternatives, |a| a.start)
+    }
+}
+
+/// Prefer the rule that has been added to the grammar first. Ties go to the first alternative.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FirstDeclared;
+
+impl Disambiguation for FirstDeclared {
+    fn choose(&self, alternatives: &[Alternative]) -> usize {
+        first_min_by_key(alternatives, |a| a.rule)
+    }
+}
+
+/// Index of the first alternative with the smallest key
+fn first_min_by_key<K: Ord>(
+    alternatives: &[Alternative],
+    key: impl Fn(&Alternative) -> K,
+) -> usize {
+    (0..alternatives.len())
+        .min_by_key(|i| key(&alternatives[*i]))
+        .unwrap_or(0)
+}
@@ -0,0 +1,410 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Export the structure of a grammar or a parse tree
+//!
+//! The exported grammar can be fed to railroad diagram generators or other visualization tools.
+//! Two formats are supported:
+//!
+//! * [Json](enum.GrammarExportFormat.html#variant.Json) lists the non-terminals with their IDs,
+//!   names and whether they can derive the empty string, the terminals with their descriptions
+//!   and the rules as lhs ID and rhs IDs. Fields are always written in the same order.
+//! * [Ebnf](enum.GrammarExportFormat.html#variant.Ebnf) reconstructs readable productions. The
+//!   alternatives of a non-terminal are grouped on one line and separated by ` | `. The start
+//!   symbol comes first, the other non-terminals follow in the order of their first rule.
+//!
+//! For grammars of `char`, the EBNF output uses the syntax of the [bnf](../bnf/index.html) module
+//! and can be loaded again. This normalizes the grammar: Strings are written as one string per character,
+//! alternatives of a non-terminal on separate lines are joined, and comments are lost. Matchers
+//! without a BNF syntax (e.g. `NoneOf`) are written as `[^...]`, which cannot be loaded.
+//!
+//! The error pseudo-rule and removed rules are not part of the EBNF output.
+//!
+//! The parse tree can be written as [Json](enum.CstExportFormat.html#variant.Json) or as
+//! [S-expressions](enum.CstExportFormat.html#variant.SExpr), e.g. for golden tests. Only completed
+//! nodes are written, with their symbol names and spans. If the tokens are given, the text of the
+//! leaves is added.
+
+use std::io::{Result, Write};
+
+use super::grammar::{CompiledGrammar, Matcher, SymbolId, ERROR_ID};
+use super::{CstCursor, Parser};
+
+/// Output format of [grammar_to_writer](fn.grammar_to_writer.html)
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum GrammarExportFormat {
+    /// Structured output for tools
+    Json,
+    /// Readable productions
+    Ebnf,
+}
+
+/// Output format of [cst_to_writer](fn.cst_to_writer.html)
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum CstExportFormat {
+    /// Object with the start of the unparsed tokens and the root node. Nodes have the fields
+    /// `symbol`, `start`, `end` and either `children` or, if the tokens are given, `text`.
+    Json,
+    /// Lisp-style lists of symbol name, start, end and the children or the text
+    SExpr,
+}
+
+/// Human readable description of a terminal
+pub trait Describe {
+    fn describe(&self) -> String;
+}
+
+/// Write the structure of the grammar in the given format.
+pub fn grammar_to_writer<T, M>(
+    grammar: &CompiledGrammar<T, M>,
+    w: &mut dyn Write,
+    format: GrammarExportFormat,
+) -> Result<()>
+where
+    M: Matcher<T> + Clone + Describe,
+{
+    match format {
+        GrammarExportFormat::Json => write_json(grammar, w),
+        GrammarExportFormat::Ebnf => write_ebnf(grammar, w),
+    }
+}
+
+/// Write the parse tree of the parser in the given format.
+///
+/// `tokens` are the tokens the parser has been fed with. If given, the leaves contain their text.
+pub fn cst_to_writer<T, M>(
+    parser: &Parser<T, M>,
+    tokens: Option<&[char]>,
+    w: &mut dyn Write,
+    format: CstExportFormat,
+) -> Result<()>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    let unparsed = parser
+        .cst_iter()
+        .last()
+        .and_then(|item| item.unparsed_start())
+        .unwrap_or_default();
+    if format == CstExportFormat::Json {
+        write!(w, "{{\"unparsed\": {}, \"root\": ", unparsed)?;
+    }
+    match parser.cst_cursor() {
+        Some(cursor) => write_cst(parser.grammar(), cursor, tokens, w, format)?,
+        None if format == CstExportFormat::Json => write!(w, "null")?,
+        None => write!(w, "()")?,
+    }
+    match format {
+        CstExportFormat::Json => writeln!(w, "}}"),
+        CstExportFormat::SExpr => writeln!(w),
+    }
+}
+
+/// Write the nodes below the cursor in pre-order.
+///
+/// The cursor is used instead of recursion as the tree can be as deep as the input is long.
+fn write_cst<T, M>(
+    grammar: &CompiledGrammar<T, M>,
+    mut cursor: CstCursor<T, M>,
+    tokens: Option<&[char]>,
+    w: &mut dyn Write,
+    format: CstExportFormat,
+) -> Result<()>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    let json = format == CstExportFormat::Json;
+    loop {
+        // Open the node
+        let (start, end) = cursor.span();
+        let name = grammar.nt_name(grammar.lhs(cursor.dotted_rule().rule as usize));
+        if json {
+            write!(w, "{{\"symbol\": ")?;
+            write_json_string(w, name)?;
+            write!(w, ", \"start\": {}, \"end\": {}", start, end)?;
+        } else {
+            write!(w, "(")?;
+            write_symbol(w, name)?;
+            write!(w, " {} {}", start, end)?;
+        }
+        if cursor.goto_first_child() {
+            if json {
+                write!(w, ", \"children\": [")?;
+            }
+            write_newline(w, cursor.depth())?;
+            continue;
+        }
+
+        // Close the leaf
+        let text: Option<String> =
+            tokens.map(|t| t[start.min(t.len())..end.min(t.len())].iter().collect());
+        match (json, text) {
+            (true, Some(text)) => {
+                write!(w, ", \"text\": ")?;
+                write_json_string(w, &text)?;
+                write!(w, "}}")?;
+            }
+            (true, None) => write!(w, ", \"children\": []}}")?,
+            (false, Some(text)) => {
+                write!(w, " ")?;
+                write_json_string(w, &text)?;
+                write!(w, ")")?;
+            }
+            (false, None) => write!(w, ")")?,
+        }
+
+        // Move on, closing the parents
+        loop {
+            if cursor.goto_next_sibling() {
+                if json {
+                    write!(w, ",")?;
+                }
+                write_newline(w, cursor.depth())?;
+                break;
+            }
+            if !cursor.goto_parent() {
+                return Ok(());
+            }
+            if json {
+                write!(w, "]}}")?;
+            } else {
+                write!(w, ")")?;
+            }
+        }
+    }
+}
+
+/// Start a new line, indented by the depth of the next node
+fn write_newline(w: &mut dyn Write, depth: usize) -> Result<()> {
+    write!(w, "\n{:indent$}", "", indent = 2 * depth)
+}
+
+/// Write a symbol name in an S-expression, quoted if necessary
+fn write_symbol(w: &mut dyn Write, name: &str) -> Result<()> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && !"()\";".contains(c))
+    {
+        write!(w, "{}", name)
+    } else {
+        write_json_string(w, name)
+    }
+}
+
+/// Write a string in JSON syntax
+fn write_json_string(w: &mut dyn Write, s: &str) -> Result<()> {
+    write!(w, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            '\t' => write!(w, "\\t")?,
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
+
+fn write_json<T, M>(grammar: &CompiledGrammar<T, M>, w: &mut dyn Write) -> Result<()>
+where
+    M: Matcher<T> + Clone + Describe,
+{
+    let nullable = grammar.nullable_symbols();
+    writeln!(w, "{{")?;
+    writeln!(w, "  \"start\": {},", grammar.start())?;
+
+    writeln!(w, "  \"symbols\": [")?;
+    for (id, nullable) in nullable.iter().enumerate() {
+        write!(w, "    {{\"id\": {}, \"name\": ", id)?;
+        write_json_string(w, grammar.nt_name(id as SymbolId))?;
+        let sep = if id + 1 < grammar.nt_count() { "," } else { "" };
+        writeln!(w, ", \"nullable\": {}}}{}", nullable, sep)?;
+    }
+    writeln!(w, "  ],")?;
+
+    writeln!(w, "  \"terminals\": [")?;
+    let terminals = grammar.terminals();
+    for (i, t) in terminals.iter().enumerate() {
+        write!(
+            w,
+            "    {{\"id\": {}, \"describe\": ",
+            grammar.nt_count() + i
+        )?;
+        write_json_string(w, &t.describe())?;
+        let sep = if i + 1 < terminals.len() { "," } else { "" };
+        writeln!(w, "}}{}", sep)?;
+    }
+    writeln!(w, "  ],")?;
+
+    writeln!(w, "  \"rules\": [")?;
+    let rules: Vec<usize> = (0..grammar.rule_count())
+        .filter(|i| !grammar.is_removed(*i))
+        .collect();
+    for (n, i) in rules.iter().enumerate() {
+        let rhs: Vec<String> = grammar.rhs(*i).iter().map(|s| s.to_string()).collect();
+        let sep = if n + 1 < rules.len() { "," } else { "" };
+        writeln!(
+            w,
+            "    {{\"index\": {}, \"lhs\": {}, \"rhs\": [{}]}}{}",
+            i,
+            grammar.lhs(*i),
+            rhs.join(", "),
+            sep
+        )?;
+    }
+    writeln!(w, "  ]")?;
+    writeln!(w, "}}")
+}
+
+fn write_ebnf<T, M>(grammar: &CompiledGrammar<T, M>, w: &mut dyn Write) -> Result<()>
+where
+    M: Matcher<T> + Clone + Describe,
+{
+    let rules: Vec<usize> = (0..grammar.rule_count())
+        .filter(|i| !grammar.is_removed(*i) && grammar.lhs(*i) != ERROR_ID)
+        .collect();
+
+    // Start symbol first, then in the order of the first rule.
+    let mut order = vec![grammar.start()];
+    for i in rules.iter() {
+        if !order.contains(&grammar.lhs(*i)) {
+            order.push(grammar.lhs(*i));
+        }
+    }
+
+    let nt_count = grammar.nt_count();
+    for lhs in order {
+        let alternatives: Vec<String> = grammar
+            .rules_for_lhs(lhs)
+            .iter()
+            .map(|i| {
+                grammar
+                    .rhs(*i)
+                    .iter()
+                    .map(|s| {
+                        if (*s as usize) < nt_count {
+                            format!("<{}>", grammar.nt_name(*s))
+                        } else {
+                            grammar.terminals()[*s as usize - nt_count].describe()
+                        }
+                    })
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            })
+            .collect();
+        writeln!(
+            w,
+            "<{}> ::= {}",
+            grammar.nt_name(lhs),
+            alternatives.join(" | ")
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::char::CharMatcher;
+    use crate::golden::check_golden;
+    use crate::grammar::tests::define_grammar;
+
+    fn export<T, M>(grammar: &CompiledGrammar<T, M>, format: GrammarExportFormat) -> String
+    where
+        M: Matcher<T> + Clone + Describe,
+    {
+        let mut out = Vec::new();
+        grammar_to_writer(grammar, &mut out, format).expect("writing to memory should work");
+        String::from_utf8(out).expect("export should be UTF-8")
+    }
+
+    #[test]
+    fn sentence_json() {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        check_golden(
+            "sentence_grammar.json",
+            &export(&grammar, GrammarExportFormat::Json),
+        );
+    }
+
+    #[test]
+    fn sentence_ebnf() {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        check_golden(
+            "sentence_grammar.ebnf",
+            &export(&grammar, GrammarExportFormat::Ebnf),
+        );
+    }
+
+    fn export_cst(with_text: bool, format: CstExportFormat) -> String {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let mut parser = Parser::<char, CharMatcher>::new(grammar);
+        let input: Vec<char> = "john called mary ".chars().collect();
+        for (i, c) in input.iter().enumerate() {
+            parser.update(i, *c);
+        }
+        let mut out = Vec::new();
+        cst_to_writer(
+            &parser,
+            if with_text { Some(&input) } else { None },
+            &mut out,
+            format,
+        )
+        .expect("writing to memory should work");
+        String::from_utf8(out).expect("export should be UTF-8")
+    }
+
+    #[test]
+    fn sentence_cst_json() {
+        check_golden(
+            "sentence_cst.json",
+            &export_cst(true, CstExportFormat::Json),
+        );
+        let structure = export_cst(false, CstExportFormat::Json);
+        assert!(structure.starts_with("{\"unparsed\": 17, \"root\": {\"symbol\": \"S\""));
+        assert!(!structure.contains("\"text\""));
+    }
+
+    #[test]
+    fn sentence_cst_sexpr() {
+        check_golden(
+            "sentence_cst.sexpr",
+            &export_cst(true, CstExportFormat::SExpr),
+        );
+    }
+}
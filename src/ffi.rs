@@ -0,0 +1,457 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! C interface
+//!
+//! Enabled by the feature `ffi`. The functions wrap an editor for `char` tokens. Its grammar is
+//! given as text in a small BNF dialect, see [sesd_editor_new](fn.sesd_editor_new.html). The
+//! declarations for C are in `include/sesd.h`. To build a library for C, run
+//!
+//! ```sh
+//! cargo rustc --lib --release --features ffi --crate-type staticlib
+//! ```
+//!
+//! No function unwinds into the caller. Panics are caught and reported as
+//! [SESD_PANIC](constant.SESD_PANIC.html).
+//!
+//! # Ownership
+//!
+//! * An editor is created by `sesd_editor_new` and must be released by `sesd_editor_free`.
+//! * All other pointers are borrowed for the duration of the call. The output buffers are owned
+//!   by the caller.
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use super::char::CharMatcher;
+use super::{CompiledGrammar, CstIterItem, Grammar, SymbolId, SynchronousEditor};
+
+/// Success
+pub const SESD_OK: i32 = 0;
+/// A required pointer was null
+pub const SESD_NULL: i32 = -1;
+/// The inserted text is not UTF-8
+pub const SESD_INVALID_UTF8: i32 = -2;
+/// The position or the deleted range is outside of the buffer
+pub const SESD_OUT_OF_RANGE: i32 = -3;
+/// An internal error occurred
+pub const SESD_PANIC: i32 = -4;
+
+/// Editor handle for C
+pub struct SesdEditor {
+    editor: SynchronousEditor<char, CharMatcher>,
+}
+
+/// State of the editor as reported by [sesd_editor_status](fn.sesd_editor_status.html)
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct SesdStatus {
+    /// Number of characters in the buffer
+    pub len: usize,
+    /// 1 if the whole buffer has been accepted, 0 otherwise
+    pub accepted: i32,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Panic inside the next guarded call
+    static INJECT_PANIC: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+/// Run `f`. Return `on_panic` if it panics.
+fn guard<R>(on_panic: R, f: impl FnOnce() -> R) -> R {
+    catch_unwind(AssertUnwindSafe(|| {
+        #[cfg(test)]
+        {
+            if INJECT_PANIC.with(|p| p.replace(false)) {
+                panic!("injected panic");
+            }
+        }
+        f()
+    }))
+    .unwrap_or(on_panic)
+}
+
+/// Build a grammar from BNF text. See [sesd_editor_new](fn.sesd_editor_new.html).
+fn load_bnf(text: &str) -> Result<CompiledGrammar<char, CharMatcher>, String> {
+    Grammar::parse_bnf(text)
+        .and_then(Grammar::compile)
+        .map_err(|e| e.to_string())
+}
+
+/// Create an editor for the grammar given as NUL-terminated UTF-8 text.
+///
+/// Each line holds the rules of one non-terminal, e.g.
+///
+/// ```text
+/// # Comments start with a hash
+/// <list> ::= <item> | <item> ", " <list>
+/// <item> ::= [a-z] | "\"" <digits> "\""
+/// <digits> ::= | [0-9] <digits>
+/// ```
+///
+/// The syntax is described in the [bnf](../bnf/index.html) module.
+///
+/// Return null if `grammar_bnf` is null or the grammar is invalid. Release the editor with
+/// [sesd_editor_free](fn.sesd_editor_free.html).
+///
+/// # Safety
+///
+/// `grammar_bnf` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sesd_editor_new(grammar_bnf: *const c_char) -> *mut SesdEditor {
+    if grammar_bnf.is_null() {
+        return std::ptr::null_mut();
+    }
+    let text = CStr::from_ptr(grammar_bnf);
+    guard(std::ptr::null_mut(), || {
+        match text.to_str().map_err(|e| e.to_string()).and_then(load_bnf) {
+            Ok(grammar) => Box::into_raw(Box::new(SesdEditor {
+                editor: SynchronousEditor::new(grammar),
+            })),
+            Err(_) => std::ptr::null_mut(),
+        }
+    })
+}
+
+/// Release an editor. Null is ignored.
+///
+/// # Safety
+///
+/// `ed` must be null or have been returned by [sesd_editor_new](fn.sesd_editor_new.html) and not
+/// been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn sesd_editor_free(ed: *mut SesdEditor) {
+    if !ed.is_null() {
+        let ed = Box::from_raw(ed);
+        guard((), move || drop(ed));
+    }
+}
+
+/// Delete `del_count` characters at `pos`, then insert `insert_len` bytes of UTF-8 text there.
+///
+/// Return [SESD_OK](constant.SESD_OK.html) or one of the error codes. On error, the buffer is
+/// unchanged. `insert_utf8` may be null if `insert_len` is 0.
+///
+/// # Safety
+///
+/// `ed` must be null or a valid editor. `insert_utf8` must point to `insert_len` readable bytes
+/// unless `insert_len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn sesd_editor_edit(
+    ed: *mut SesdEditor,
+    pos: usize,
+    del_count: usize,
+    insert_utf8: *const u8,
+    insert_len: usize,
+) -> i32 {
+    if ed.is_null() || (insert_utf8.is_null() && insert_len != 0) {
+        return SESD_NULL;
+    }
+    let ed = &mut *ed;
+    let bytes = if insert_len == 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(insert_utf8, insert_len)
+    };
+    guard(SESD_PANIC, || {
+        let text = match std::str::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(_) => return SESD_INVALID_UTF8,
+        };
+        match pos.checked_add(del_count) {
+            Some(end) if end <= ed.editor.len() => {
+                ed.editor.replace(pos, end, text.chars());
+                SESD_OK
+            }
+            _ => SESD_OUT_OF_RANGE,
+        }
+    })
+}
+
+/// Write the state of the editor to `out`.
+///
+/// Return [SESD_OK](constant.SESD_OK.html) or one of the error codes.
+///
+/// # Safety
+///
+/// `ed` must be null or a valid editor. `out` must be null or point to a writable `SesdStatus`.
+#[no_mangle]
+pub unsafe extern "C" fn sesd_editor_status(ed: *const SesdEditor, out: *mut SesdStatus) -> i32 {
+    if ed.is_null() || out.is_null() {
+        return SESD_NULL;
+    }
+    let ed = &*ed;
+    match guard(None, || {
+        Some(SesdStatus {
+            len: ed.editor.len(),
+            accepted: ed.editor.is_accepted() as i32,
+        })
+    }) {
+        Some(status) => {
+            *out = status;
+            SESD_OK
+        }
+        None => SESD_PANIC,
+    }
+}
+
+/// Copy as many bytes as fit into `out` and return the length of the whole text.
+unsafe fn copy_out<T: Copy>(data: &[T], out: *mut T, cap: usize) -> usize {
+    if !out.is_null() {
+        let n = std::cmp::min(data.len(), cap);
+        std::ptr::copy_nonoverlapping(data.as_ptr(), out, n);
+    }
+    data.len()
+}
+
+/// Write the names of the symbols predicted at `pos` to `out_buf`, separated by newlines.
+///
+/// At most `out_cap` bytes are written. The text is not NUL-terminated. Return the length of the
+/// whole text in bytes. If it is larger than `out_cap`, call again with a larger buffer. Return 0
+/// on errors.
+///
+/// # Safety
+///
+/// `ed` must be null or a valid editor. `out_buf` must be null or point to `out_cap` writable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sesd_editor_predictions(
+    ed: *const SesdEditor,
+    pos: usize,
+    out_buf: *mut u8,
+    out_cap: usize,
+) -> usize {
+    if ed.is_null() {
+        return 0;
+    }
+    let ed = &*ed;
+    let text = guard(String::new(), || {
+        if pos > ed.editor.len() {
+            return String::new();
+        }
+        let grammar = ed.editor.grammar();
+        ed.editor
+            .parser()
+            .predictions(pos)
+            .iter()
+            .map(|sym| grammar.nt_name(*sym))
+            .collect::<Vec<_>>()
+            .join("\n")
+    });
+    copy_out(text.as_bytes(), out_buf, out_cap)
+}
+
+/// Write start and end of the completed nodes of a symbol to `out_pairs`.
+///
+/// Each node takes two entries: start and end (exclusive) in characters. At most `cap` nodes are
+/// written. Return the number of nodes. If it is larger than `cap`, call again with a larger
+/// buffer. Return 0 if the symbol is unknown or on errors.
+///
+/// # Safety
+///
+/// `ed` must be null or a valid editor. `symbol_name` must be null or point to a NUL-terminated
+/// string. `out_pairs` must be null or point to `2 * cap` writable entries.
+#[no_mangle]
+pub unsafe extern "C" fn sesd_editor_spans(
+    ed: *const SesdEditor,
+    symbol_name: *const c_char,
+    out_pairs: *mut usize,
+    cap: usize,
+) -> usize {
+    if ed.is_null() || symbol_name.is_null() {
+        return 0;
+    }
+    let ed = &*ed;
+    let name = CStr::from_ptr(symbol_name);
+    let pairs = guard(Vec::new(), || {
+        let grammar = ed.editor.grammar();
+        let sym = match name.to_str() {
+            Ok(name) => grammar.nt_id(name),
+            Err(_) => return Vec::new(),
+        };
+        if sym == SymbolId::MAX {
+            return Vec::new();
+        }
+        ed.editor
+            .cst_iter()
+            .filter_map(|item| match item {
+                CstIterItem::Parsed(node)
+                    if grammar.dotted_is_completed(&node.dotted_rule) && node.lhs == sym =>
+                {
+                    Some([node.start, node.end])
+                }
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    });
+    copy_out(&pairs, out_pairs, 2 * cap) / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAMMAR: &str = "# Sentences\n\
+        <S> ::= <NP> <VP>\n\
+        <NP> ::= \"john \" | \"mary \" | <NP> <PP>\n\
+        <VP> ::= \"called \" <NP> | <VP> <PP>\n\
+        <PP> ::= \"from \" <NP>\n\0";
+
+    /// Create an editor for the test grammar
+    fn editor() -> *mut SesdEditor {
+        let ed = unsafe { sesd_editor_new(GRAMMAR.as_ptr() as *const c_char) };
+        assert!(!ed.is_null());
+        ed
+    }
+
+    fn edit(ed: *mut SesdEditor, pos: usize, del_count: usize, text: &[u8]) -> i32 {
+        unsafe { sesd_editor_edit(ed, pos, del_count, text.as_ptr(), text.len()) }
+    }
+
+    fn status(ed: *const SesdEditor) -> SesdStatus {
+        let mut status = SesdStatus::default();
+        assert_eq!(unsafe { sesd_editor_status(ed, &mut status) }, SESD_OK);
+        status
+    }
+
+    #[test]
+    fn happy_path() {
+        let ed = editor();
+        assert_eq!(edit(ed, 0, 0, b"john called mary "), SESD_OK);
+        assert_eq!(
+            status(ed),
+            SesdStatus {
+                len: 17,
+                accepted: 1
+            }
+        );
+
+        // Replace mary by john
+        assert_eq!(edit(ed, 12, 4, b"john"), SESD_OK);
+
+        let mut pairs = [0usize; 4];
+        let n = unsafe {
+            sesd_editor_spans(ed, "NP\0".as_ptr() as *const c_char, pairs.as_mut_ptr(), 2)
+        };
+        assert_eq!(n, 2);
+        assert_eq!(pairs, [0, 5, 12, 17]);
+
+        // Too small buffers report the full size
+        let n = unsafe {
+            sesd_editor_spans(ed, "NP\0".as_ptr() as *const c_char, pairs.as_mut_ptr(), 1)
+        };
+        assert_eq!(n, 2);
+
+        let mut buf = [0u8; 64];
+        let len = unsafe { sesd_editor_predictions(ed, 17, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(&buf[..len], b"PP");
+        let len = unsafe { sesd_editor_predictions(ed, 17, std::ptr::null_mut(), 0) };
+        assert_eq!(len, 2);
+
+        unsafe { sesd_editor_free(ed) };
+    }
+
+    #[test]
+    fn null_inputs() {
+        unsafe {
+            assert!(sesd_editor_new(std::ptr::null()).is_null());
+            assert!(sesd_editor_new("<S> ::= \"a\0".as_ptr() as *const c_char).is_null());
+            assert!(sesd_editor_new("<S> ::= <T>\0".as_ptr() as *const c_char).is_null());
+            sesd_editor_free(std::ptr::null_mut());
+
+            assert_eq!(
+                sesd_editor_edit(std::ptr::null_mut(), 0, 0, std::ptr::null(), 0),
+                SESD_NULL
+            );
+            assert_eq!(
+                sesd_editor_status(std::ptr::null(), std::ptr::null_mut()),
+                SESD_NULL
+            );
+            assert_eq!(
+                sesd_editor_predictions(std::ptr::null(), 0, std::ptr::null_mut(), 0),
+                0
+            );
+
+            let ed = editor();
+            assert_eq!(sesd_editor_edit(ed, 0, 0, std::ptr::null(), 1), SESD_NULL);
+            assert_eq!(sesd_editor_edit(ed, 0, 0, std::ptr::null(), 0), SESD_OK);
+            assert_eq!(sesd_editor_status(ed, std::ptr::null_mut()), SESD_NULL);
+            assert_eq!(
+                sesd_editor_spans(ed, std::ptr::null(), std::ptr::null_mut(), 0),
+                0
+            );
+            sesd_editor_free(ed);
+        }
+    }
+
+    #[test]
+    fn invalid_edits() {
+        let ed = editor();
+        assert_eq!(edit(ed, 0, 0, b"john "), SESD_OK);
+        assert_eq!(edit(ed, 0, 0, b"\xc3\x28"), SESD_INVALID_UTF8);
+        assert_eq!(edit(ed, 6, 0, b"x"), SESD_OUT_OF_RANGE);
+        assert_eq!(edit(ed, 4, usize::MAX, b""), SESD_OUT_OF_RANGE);
+        assert_eq!(status(ed).len, 5);
+
+        // Multi-byte characters count as one
+        assert_eq!(edit(ed, 5, 0, "änne".as_bytes()), SESD_OK);
+        assert_eq!(status(ed).len, 9);
+        unsafe { sesd_editor_free(ed) };
+    }
+
+    #[test]
+    fn panic_is_caught() {
+        let ed = editor();
+        INJECT_PANIC.with(|p| p.set(true));
+        assert_eq!(edit(ed, 0, 0, b"john "), SESD_PANIC);
+
+        // The editor is still usable
+        assert_eq!(edit(ed, 0, 0, b"john "), SESD_OK);
+        INJECT_PANIC.with(|p| p.set(true));
+        let mut out = SesdStatus::default();
+        assert_eq!(unsafe { sesd_editor_status(ed, &mut out) }, SESD_PANIC);
+        assert_eq!(status(ed).len, 5);
+        unsafe { sesd_editor_free(ed) };
+    }
+
+    #[test]
+    fn ebnf_round_trip() {
+        use crate::export::{grammar_to_writer, GrammarExportFormat};
+
+        let ebnf = |grammar: &CompiledGrammar<char, CharMatcher>| {
+            let mut out = Vec::new();
+            grammar_to_writer(grammar, &mut out, GrammarExportFormat::Ebnf)
+                .expect("writing to memory should work");
+            String::from_utf8(out).expect("export should be UTF-8")
+        };
+        let text = GRAMMAR.trim_end_matches('\0');
+        let exported = ebnf(&load_bnf(text).expect("grammar should load"));
+        assert!(exported.starts_with("<S> ::= <NP> <VP>\n"));
+        let reloaded = load_bnf(&exported).expect("export should load");
+        assert_eq!(ebnf(&reloaded), exported);
+    }
+}
@@ -23,10 +23,17 @@
 */
 
 //! Grammar builder for Parser
-
+//!
+//! The width of [SymbolId](type.SymbolId.html) is selected by cargo features. By default
+//! (`symbols-u16`), symbol ids are 16 bit wide, which limits a grammar to 65535 symbols and rules.
+//! The feature `symbols-u32` selects 32 bit ids for larger grammars, e.g. generated ones. Since
+//! symbol ids are also used for the dotted rules and the state indices of the parse chart and the
+//! CST edges, this roughly doubles the memory used by the parser.
+
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::io::Write;
 use std::marker::PhantomData;
 
@@ -34,9 +41,17 @@ use itertools::Itertools;
 
 /// List of errors when processing grammars
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    /// Too many entries to compile
-    TooLarge(String),
+    /// Too many entries in a table to compile. The rule index (in the order of addition) and its
+    /// left hand side are given if a single rule is too long.
+    TooLarge {
+        table: Table,
+        rule: Option<usize>,
+        symbol: Option<String>,
+        count: usize,
+        sizes: Box<GrammarSizes>,
+    },
     /// Non-terminal used in a rule without a rule for it
     NoRule(String),
     /// No start symbol set
@@ -45,11 +60,230 @@ pub enum Error {
     EmptySymbol,
     /// Empty right hand side of a rule
     EmptyRhs,
+    /// Rule index out of range or rule already removed
+    NoSuchRule(usize),
+    /// Non-terminal name already in use
+    DuplicateSymbol(String),
+    /// Invalid grammar text, see [Grammar::parse_bnf](struct.Grammar.html#method.parse_bnf)
+    Syntax(String),
 }
 
 /// Type alias for Results with Errors
 type Result<T> = std::result::Result<T, Error>;
 
+/// Tables of a grammar that are limited by the range of [SymbolId](type.SymbolId.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Table {
+    /// Right hand side of a rule
+    Rhs,
+    /// Non-terminals
+    NonTerminals,
+    /// Terminals and non-terminals together
+    Symbols,
+    /// Rules
+    Rules,
+}
+
+/// Sizes of a grammar, e.g. to find out what to simplify when it is
+/// [too large](enum.Error.html#variant.TooLarge)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GrammarSizes {
+    /// Number of non-terminals, including undefined ones
+    pub non_terminals: usize,
+    /// Number of different terminals
+    pub terminals: usize,
+    /// Number of rules
+    pub rules: usize,
+    /// Non-terminals with the most symbols on the right hand sides of their rules and the number
+    /// of symbols, largest first. At most five are listed.
+    pub largest: Vec<(String, usize)>,
+}
+
+impl GrammarSizes {
+    /// Number of non-terminals listed in `largest`
+    const LARGEST: usize = 5;
+
+    /// Collect the sizes from the rules, given as lhs names and rhs lengths.
+    fn new<'a>(
+        non_terminals: usize,
+        terminals: usize,
+        rules: impl Iterator<Item = (&'a str, usize)>,
+    ) -> Self {
+        let mut symbols: HashMap<&str, usize> = HashMap::new();
+        let mut rule_count = 0;
+        for (lhs, len) in rules {
+            *symbols.entry(lhs).or_default() += len;
+            rule_count += 1;
+        }
+        let largest = symbols
+            .into_iter()
+            .sorted_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)))
+            .take(Self::LARGEST)
+            .map(|(nt, count)| (nt.to_string(), count))
+            .collect();
+        Self {
+            non_terminals,
+            terminals,
+            rules: rule_count,
+            largest,
+        }
+    }
+}
+
+impl std::fmt::Display for GrammarSizes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} non-terminals, {} terminals and {} rules",
+            self.non_terminals, self.terminals, self.rules
+        )?;
+        if !self.largest.is_empty() {
+            write!(
+                f,
+                ", largest non-terminals: {}",
+                self.largest
+                    .iter()
+                    .map(|(nt, count)| format!("{} ({} symbols)", nt, count))
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Error {
+    /// Check if the grammar had too many symbols or rules
+    pub fn is_too_large(&self) -> bool {
+        matches!(self, Error::TooLarge { .. })
+    }
+
+    /// Return the non-terminal that was used without a rule for it
+    pub fn no_rule_symbol(&self) -> Option<&str> {
+        match self {
+            Error::NoRule(nt) => Some(nt),
+            _ => None,
+        }
+    }
+
+    /// Return the non-terminal the error is about, if any
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            Error::NoRule(nt) | Error::DuplicateSymbol(nt) => Some(nt),
+            Error::TooLarge { symbol, .. } => symbol.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Return the index of the rule the error is about, if any
+    pub fn rule(&self) -> Option<usize> {
+        match self {
+            Error::NoSuchRule(rule) => Some(*rule),
+            Error::TooLarge { rule, .. } => *rule,
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Table::Rhs => "symbols on the right hand side",
+            Table::NonTerminals => "non-terminals",
+            Table::Symbols => "terminals and non-terminals",
+            Table::Rules => "rules",
+        })
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::TooLarge {
+                table,
+                rule,
+                symbol,
+                count,
+                sizes,
+            } => {
+                write!(
+                    f,
+                    "too many {}: {} of at most {}",
+                    table, count, MAX_SYMBOL_ID
+                )?;
+                if let Some(rule) = rule {
+                    write!(f, " in rule {}", rule)?;
+                }
+                if let Some(symbol) = symbol {
+                    write!(f, " of {}", symbol)?;
+                }
+                write!(f, "; the grammar has {}", sizes)?;
+                if cfg!(feature = "symbols-u32") {
+                    write!(f, "; split or simplify the largest non-terminals")
+                } else {
+                    write!(
+                        f,
+                        "; split or simplify the largest non-terminals or enable the feature symbols-u32"
+                    )
+                }
+            }
+            Error::NoRule(nt) => write!(f, "no rule for non-terminal {}", nt),
+            Error::EmptyStart => write!(f, "no start symbol"),
+            Error::EmptySymbol => write!(f, "empty non-terminal name"),
+            Error::EmptyRhs => write!(f, "empty right hand side"),
+            Error::NoSuchRule(rule) => write!(f, "no rule with index {}", rule),
+            Error::DuplicateSymbol(nt) => write!(f, "non-terminal {} already exists", nt),
+            Error::Syntax(message) => write!(f, "syntax error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Problems found by [CompiledGrammar::validate](struct.CompiledGrammar.html#method.validate).
+///
+/// Unlike errors, they do not prevent parsing, but they usually are mistakes in the grammar.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// Non-terminal and index of a rule that derives the non-terminal after nullable symbols,
+    /// e.g. `A → B A 'a'` with a nullable `B`
+    HiddenLeftRecursion(String, usize),
+    /// Non-terminal that cannot be derived from the start symbol
+    Unreachable(String),
+    /// Non-terminal that never derives a string of terminals
+    Unproductive(String),
+    /// Non-terminal, index of a rule and index of the earlier rule it duplicates
+    DuplicateRule(String, usize, usize),
+    /// Non-terminal and two terminals that begin different alternatives of it, where the first
+    /// one also matches the tokens of the second one
+    ShadowedTerminal(String, String, String),
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::HiddenLeftRecursion(nt, rule) => write!(
+                f,
+                "rule {} of {} is left-recursive after nullable symbols",
+                rule, nt
+            ),
+            Warning::Unreachable(nt) => {
+                write!(f, "{} is not reachable from the start symbol", nt)
+            }
+            Warning::Unproductive(nt) => write!(f, "{} never derives a string of terminals", nt),
+            Warning::DuplicateRule(nt, rule, first) => {
+                write!(f, "rule {} of {} duplicates rule {}", rule, nt, first)
+            }
+            Warning::ShadowedTerminal(nt, terminal, shadowed) => write!(
+                f,
+                "{} shadows {} at the start of the alternatives of {}",
+                terminal, shadowed, nt
+            ),
+        }
+    }
+}
+
 /// Match token classes during parsing.
 ///
 /// Token classes (e.g. all digits 0-9) can be represented as rules with alternative terminal
@@ -63,11 +297,83 @@ pub trait Matcher<T> {
     fn matches(&self, t: T) -> bool;
 }
 
+/// Matchers that can produce a token they match, e.g. to suggest insertions, see
+/// [Parser::suggest_insertion](struct.Parser.html#method.suggest_insertion).
+pub trait Synthesize<T> {
+    /// Return a token that the matcher matches or None if there is no obvious choice.
+    fn synthesize(&self) -> Option<T>;
+}
+
+/// Index from tokens to the terminals of a grammar that match them, see
+/// [Parser::set_terminal_dispatch](struct.Parser.html#method.set_terminal_dispatch).
+pub trait TerminalDispatch<T> {
+    /// Return a flag for each terminal of the grammar, in the order of
+    /// [CompiledGrammar::terminals](struct.CompiledGrammar.html#method.terminals), that is true if
+    /// the terminal matches the token.
+    fn matching_terminals(&self, token: &T) -> &[bool];
+}
+
+/// Read access to the rules of a grammar, e.g. for tools that work on
+/// [CompiledGrammar](struct.CompiledGrammar.html) as well as on static tables.
+pub trait RuleTable {
+    /// Number of rules, including removed ones
+    fn rule_count(&self) -> usize;
+
+    /// Lhs symbol of the rule with index `i`
+    fn lhs(&self, i: usize) -> SymbolId;
+
+    /// Check if the rule with index `i` has been removed.
+    fn is_removed(&self, i: usize) -> bool;
+
+    /// Indices of the rules that have the given non-terminal as lhs, in ascending order. Removed
+    /// rules are not included.
+    ///
+    /// The default scans all rules. Implementations with a lookup table should override it.
+    fn rules_for_lhs(&self, sym: SymbolId) -> Box<dyn Iterator<Item = usize> + '_> {
+        Box::new(
+            (0..self.rule_count()).filter(move |i| !self.is_removed(*i) && self.lhs(*i) == sym),
+        )
+    }
+}
+
+/// Human readable form of a matcher in charts and diagnostics, e.g. `'a'..'z'`.
+///
+/// The default writes the debug output, so an empty `impl` is sufficient for simple matchers.
+pub trait MatcherDisplay: std::fmt::Debug {
+    fn write_matcher(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+        write!(writer, "{:?}", self)
+    }
+}
+
+/// Tokens that are their own matchers are written as debug output.
+macro_rules! matcher_display_as_debug {
+    ($($t:ty),*) => {
+        $(impl MatcherDisplay for $t {})*
+    };
+}
+
+matcher_display_as_debug!(
+    char,
+    bool,
+    u8,
+    u16,
+    u32,
+    u64,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    isize,
+    String,
+    &'static str
+);
+
 /// Grammar Symbols, terminals and non-terminals.
 ///
 /// The terminal symbols hold matcher instances to match against the input tokens of type `T`. The
 /// non-terminals hold their name.
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Symbol<M> {
     /// Terminals are of the same type as in the Buffer struct.
     Terminal(M),
@@ -81,6 +387,11 @@ pub struct Rule<M> {
     /// Name of a non-terminal symbol.
     lhs: String,
     rhs: Vec<Symbol<M>>,
+    /// Rules of the auxiliary non-terminals created by [opt](#method.opt), [star](#method.star),
+    /// [plus](#method.plus) and [alt](#method.alt)
+    helpers: Vec<Rule<M>>,
+    /// Key/value pairs set by [annotate](#method.annotate)
+    annotations: Vec<(String, String)>,
 }
 
 /// Grammar builder, textual representation of productions rules: S -> A B C
@@ -104,15 +415,160 @@ where
 }
 
 /// Symbol IDs are indices into the symbol table. As such, the can be fairly small integers to
-/// save space. 16 bit should be sufficient for all purposes. If not, file a feature request.
+/// save space. 16 bit should be sufficient for most purposes. If not, enable the feature
+/// `symbols-u32`.
+///
+/// The parser uses the same type to index the entries of a chart column. Ambiguous grammars or
+/// long inputs may need the larger type even if the grammar is small.
+#[cfg(not(feature = "symbols-u32"))]
 pub type SymbolId = u16;
 
+/// Symbol IDs are indices into the symbol table. The feature `symbols-u32` has been selected to
+/// support very large grammars.
+#[cfg(feature = "symbols-u32")]
+pub type SymbolId = u32;
+
 /// Number of symbol ids.
-const MAX_SYMBOL_ID: SymbolId = std::u16::MAX;
+const MAX_SYMBOL_ID: SymbolId = SymbolId::MAX;
 
 /// ID of the pseudo-non-terminal to represent parsing errors
 pub const ERROR_ID: SymbolId = 0;
 
+/// Check hand-written grammar tables during constant evaluation.
+///
+/// The tables follow the layout of [CompiledGrammar](struct.CompiledGrammar.html): Non-terminals
+/// are numbered from 0 to `nt_count - 1`, terminals follow directly after them. Use the macro
+/// [verify_tables](macro.verify_tables.html) to fail the build on inconsistent tables.
+///
+/// Panics if
+/// * the number of non-terminal names is not `nt_count`,
+/// * the lhs of a rule is not a non-terminal, or
+/// * a symbol on the rhs of a rule is neither a non-terminal nor a terminal.
+pub const fn check_tables(
+    nt_names_len: usize,
+    nt_count: usize,
+    terminal_count: usize,
+    rules: &[(SymbolId, &[SymbolId])],
+) {
+    match find_table_error(nt_names_len, nt_count, terminal_count, rules) {
+        Some(TableError::NameCount) => {
+            panic!("number of non-terminal names differs from non-terminal count")
+        }
+        Some(TableError::Lhs(i)) => lhs_out_of_range(i),
+        Some(TableError::Rhs(i)) => rhs_out_of_range(i),
+        None => {}
+    }
+}
+
+/// Violation of the conditions of [check_tables](fn.check_tables.html)
+#[derive(Debug, PartialEq)]
+enum TableError {
+    /// The number of non-terminal names differs from the non-terminal count
+    NameCount,
+    /// The lhs of the rule with the given index is not a non-terminal
+    Lhs(usize),
+    /// A symbol on the rhs of the rule with the given index is out of range
+    Rhs(usize),
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TableError::NameCount => {
+                write!(
+                    f,
+                    "number of non-terminal names differs from non-terminal count"
+                )
+            }
+            TableError::Lhs(i) => write!(f, "lhs of rule {} is not a non-terminal", i),
+            TableError::Rhs(i) => write!(f, "rhs of rule {} has an unknown symbol", i),
+        }
+    }
+}
+
+/// Find the first violation of the conditions of [check_tables](fn.check_tables.html).
+const fn find_table_error(
+    nt_names_len: usize,
+    nt_count: usize,
+    terminal_count: usize,
+    rules: &[(SymbolId, &[SymbolId])],
+) -> Option<TableError> {
+    if nt_names_len != nt_count {
+        return Some(TableError::NameCount);
+    }
+    let mut i = 0;
+    while i < rules.len() {
+        let (lhs, rhs) = rules[i];
+        if (lhs as usize) >= nt_count {
+            return Some(TableError::Lhs(i));
+        }
+        let mut j = 0;
+        while j < rhs.len() {
+            if (rhs[j] as usize) >= nt_count + terminal_count {
+                return Some(TableError::Rhs(i));
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Report the index of a rule with an invalid lhs.
+///
+/// Panic messages in constant evaluation cannot contain formatted numbers. Indexing an empty array
+/// reports the index in the error message instead.
+const fn lhs_out_of_range(rule: usize) {
+    let rules_with_invalid_lhs: [(); 0] = [];
+    rules_with_invalid_lhs[rule]
+}
+
+/// Report the index of a rule with an invalid rhs symbol.
+///
+/// See [lhs_out_of_range](fn.lhs_out_of_range.html).
+const fn rhs_out_of_range(rule: usize) {
+    let rules_with_invalid_rhs: [(); 0] = [];
+    rules_with_invalid_rhs[rule]
+}
+
+/// Verify hand-written grammar tables at compile time.
+///
+/// Takes the table of non-terminal names, the declared number of non-terminals, the table of
+/// terminals and the rules. The rules must be a slice of `(SymbolId, &[SymbolId])`. See [check_tables](fn.check_tables.html) for the conditions. An
+/// invalid rule fails the build with an index-out-of-bounds error that reports the index of the
+/// offending rule.
+///
+/// ```
+/// use sesd::{verify_tables, SymbolId};
+///
+/// const NT_NAMES: [&str; 2] = ["~~~ERROR~~~", "S"];
+/// const NUMBER_OF_NTS: usize = 2;
+/// const TERMINALS: [char; 1] = ['a'];
+/// const RULES: &[(SymbolId, &[SymbolId])] = &[(1, &[2, 1]), (1, &[2])];
+///
+/// verify_tables!(NT_NAMES, NUMBER_OF_NTS, TERMINALS, RULES);
+/// ```
+///
+/// A rule that references a symbol beyond the terminals does not compile:
+///
+/// ```compile_fail
+/// use sesd::{verify_tables, SymbolId};
+///
+/// const NT_NAMES: [&str; 2] = ["~~~ERROR~~~", "S"];
+/// const NUMBER_OF_NTS: usize = 2;
+/// const TERMINALS: [char; 1] = ['a'];
+/// const RULES: &[(SymbolId, &[SymbolId])] = &[(1, &[2, 1]), (1, &[3])];
+///
+/// verify_tables!(NT_NAMES, NUMBER_OF_NTS, TERMINALS, RULES);
+/// ```
+#[macro_export]
+macro_rules! verify_tables {
+    ($nt_names:expr, $nt_count:expr, $terminals:expr, $rules:expr) => {
+        const _: () = $crate::check_tables($nt_names.len(), $nt_count, $terminals.len(), $rules);
+    };
+}
+
 /// Checked and compacted representation of a grammar.
 ///
 /// Symbols (terminals and non-terminals) are identified by small integers. For debugging and
@@ -123,8 +579,25 @@ pub const ERROR_ID: SymbolId = 0;
 /// used for debugging and error messages. The terminals cannot be queried from the public API,
 /// thus all parameters of type `SymbolId` refer to non-terminal symbols.
 ///
-/// TODO: Make finding rules of NonTerminal more efficient. Sort rules by lhs. Either keep separate table of
-/// first fule index or store first rule index in rhs instead of symbol index.
+/// With the feature `serde`, a compiled grammar can be serialized, e.g. to cache it instead of
+/// compiling it at every start. The tables are checked when the grammar is deserialized, so that a
+/// stale or corrupt cache is reported as an error instead of crashing the parser later.
+///
+/// The rules of a non-terminal are found with [rules_for_lhs](#method.rules_for_lhs), which is
+/// backed by a table that is built once, also for grammars built
+/// [from_tables](#method.from_tables). Code that should also work on other rule tables can use
+/// the trait [RuleTable](trait.RuleTable.html) instead.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        bound(
+            serialize = "M: serde::Serialize",
+            deserialize = "M: serde::Deserialize<'de>"
+        ),
+        try_from = "GrammarTables<M>"
+    )
+)]
 pub struct CompiledGrammar<T, M>
 where
     M: Matcher<T>,
@@ -133,9 +606,19 @@ where
     /// hand sides come first.
     nonterminal_table: Vec<String>,
 
+    /// Symbol IDs of the non-terminals by name
+    nt_by_name: HashMap<String, SymbolId>,
+
     /// Values of expected terminals. Index is value from rhs of rule - nonterminal_table.len().
     terminal_table: Vec<M>,
 
+    /// Names of the terminals set by the user. Index is the same as for the terminal table.
+    terminal_names: Vec<Option<String>>,
+
+    /// Indices into the terminal table, sorted by matcher. Terminals added after compilation are
+    /// appended to the table, so it is not sorted itself.
+    terminal_order: Vec<usize>,
+
     /// Rules as indices into the symbol tables. If the ID is < nonterminal_table.len(), it's a
     /// non-terminal. Otherwise it's a terminal.
     ///
@@ -145,13 +628,100 @@ where
     /// Index of start symbol
     start: SymbolId,
 
-    /// Number of symbols that have empty right hand sides.
-    empty_rules: SymbolId,
+    /// Indices of the rules for each non-terminal. Index is the symbol ID of the lhs.
+    rules_by_lhs: Vec<Vec<usize>>,
+
+    /// Key/value pairs of each rule. Index is the same as for the rule table.
+    annotations: Vec<Vec<(String, String)>>,
 
     /// Marker to indicate the T is used indirectly by Matcher
     _marker: std::marker::PhantomData<T>,
 }
 
+/// Tables of a deserialized [CompiledGrammar](struct.CompiledGrammar.html) before they have been
+/// checked. The fields are those of the grammar.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GrammarTables<M> {
+    nonterminal_table: Vec<String>,
+    nt_by_name: HashMap<String, SymbolId>,
+    terminal_table: Vec<M>,
+    terminal_names: Vec<Option<String>>,
+    terminal_order: Vec<usize>,
+    rules: Vec<(SymbolId, Vec<SymbolId>)>,
+    start: SymbolId,
+    rules_by_lhs: Vec<Vec<usize>>,
+    annotations: Vec<Vec<(String, String)>>,
+}
+
+#[cfg(feature = "serde")]
+impl<T, M> std::convert::TryFrom<GrammarTables<M>> for CompiledGrammar<T, M>
+where
+    M: Matcher<T>,
+{
+    type Error = String;
+
+    /// Check the rules like [check_tables](fn.check_tables.html) does and the lookup tables
+    /// against the rules.
+    fn try_from(tables: GrammarTables<M>) -> std::result::Result<Self, String> {
+        let nt_count = tables.nonterminal_table.len();
+        let t_count = tables.terminal_table.len();
+
+        // Removed rules are checked as error pseudo-rules to keep the indices.
+        let mut rules: Vec<(SymbolId, &[SymbolId])> = Vec::new();
+        for (lhs, rhs) in tables.rules.iter() {
+            match *lhs {
+                MAX_SYMBOL_ID if rhs.is_empty() => rules.push((ERROR_ID, &[])),
+                lhs => rules.push((lhs, rhs)),
+            }
+        }
+        if let Some(e) = find_table_error(nt_count, nt_count, t_count, &rules) {
+            return Err(e.to_string());
+        }
+        if rules.first() != Some(&(ERROR_ID, &[])) {
+            return Err("the first rule is not the error pseudo-rule".to_string());
+        }
+        if (tables.start as usize) >= nt_count || tables.start == ERROR_ID {
+            return Err("start symbol is not a non-terminal".to_string());
+        }
+
+        let mut rules_by_lhs = vec![Vec::new(); nt_count];
+        for (i, (lhs, _)) in tables.rules.iter().enumerate() {
+            if *lhs != MAX_SYMBOL_ID {
+                rules_by_lhs[*lhs as usize].push(i);
+            }
+        }
+        let mut terminal_order = tables.terminal_order.clone();
+        terminal_order.sort_unstable();
+        if tables.rules_by_lhs != rules_by_lhs
+            || tables.nt_by_name.len() != nt_count
+            || tables
+                .nonterminal_table
+                .iter()
+                .enumerate()
+                .any(|(i, name)| tables.nt_by_name.get(name) != Some(&(i as SymbolId)))
+            || tables.terminal_names.len() != t_count
+            || terminal_order != (0..t_count).collect::<Vec<usize>>()
+            || tables.annotations.len() != tables.rules.len()
+        {
+            return Err("lookup tables do not match the rules".to_string());
+        }
+
+        Ok(Self {
+            nonterminal_table: tables.nonterminal_table,
+            nt_by_name: tables.nt_by_name,
+            terminal_table: tables.terminal_table,
+            terminal_names: tables.terminal_names,
+            terminal_order: tables.terminal_order,
+            rules: tables.rules,
+            start: tables.start,
+            rules_by_lhs: tables.rules_by_lhs,
+            annotations: tables.annotations,
+            _marker: PhantomData,
+        })
+    }
+}
+
 /// Decoded symbol right of the dot in a dotted rule.
 pub enum CompiledSymbol<M> {
     /// Dot was at the end of the rule. Return the LHS of the rule.
@@ -164,6 +734,7 @@ pub enum CompiledSymbol<M> {
 
 /// Dotted rule from Earley Algorithm.
 #[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DottedRule {
     /// Index into rule table
     pub rule: SymbolId,
@@ -183,6 +754,43 @@ where
     }
 }
 
+impl<T> Synthesize<T> for T
+where
+    T: PartialEq + Clone,
+{
+    /// Tokens that are their own matchers produce themselves.
+    fn synthesize(&self) -> Option<T> {
+        Some(self.clone())
+    }
+}
+
+/// FNV-1a hasher for fingerprints, as the hasher of the standard library may change
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Fixed width, so the value does not depend on the platform.
+    fn write_usize(&mut self, value: usize) {
+        self.write(&(value as u64).to_le_bytes());
+    }
+}
+
 /// Update the symbol table during grammar compilation.
 fn update_symbol(
     map: &mut HashMap<String, (bool, usize)>,
@@ -217,12 +825,29 @@ where
     ///
     /// Obsolete interface. Use [add](#method.add).
     pub fn add_rule(&mut self, lhs: String, rhs: Vec<Symbol<M>>) {
-        self.rules.push(Rule { lhs, rhs });
+        self.rules.push(Rule {
+            lhs,
+            rhs,
+            helpers: Vec::new(),
+            annotations: Vec::new(),
+        });
     }
 
     /// Add a rule.
-    pub fn add(&mut self, rule: Rule<M>) {
+    ///
+    /// The rules of its auxiliary non-terminals are added unless they already exist.
+    pub fn add(&mut self, mut rule: Rule<M>) {
+        let helpers = std::mem::take(&mut rule.helpers);
         self.rules.push(rule);
+        for helper in helpers {
+            if !self
+                .rules
+                .iter()
+                .any(|r| r.lhs == helper.lhs && r.rhs == helper.rhs)
+            {
+                self.rules.push(helper);
+            }
+        }
     }
 
     /// Set the start symbol. This can be overwritten and may contain an unknown symbol until just
@@ -231,6 +856,131 @@ where
         self.start = sym;
     }
 
+    /// Add the rules of another grammar, prefixing all its non-terminals.
+    ///
+    /// The non-terminals of `other` are renamed to `prefix` followed by their name, e.g. `version`
+    /// becomes `semver_version` for the prefix `semver_`. The rules of this grammar can use them
+    /// under the new names. The start symbol of `other` is ignored.
+    ///
+    /// Return an error without adding anything if a renamed non-terminal already has a rule in
+    /// this grammar.
+    pub fn extend_with(&mut self, prefix: &str, other: Grammar<T, M>) -> Result<()> {
+        let prefixed = |name: &str| format!("{}{}", prefix, name);
+        if let Some(rule) = other
+            .rules
+            .iter()
+            .find(|o| self.rules.iter().any(|r| r.lhs == prefixed(&o.lhs)))
+        {
+            return Err(Error::DuplicateSymbol(prefixed(&rule.lhs)));
+        }
+        for rule in other.rules {
+            self.rules.push(Rule {
+                lhs: prefixed(&rule.lhs),
+                rhs: rule
+                    .rhs
+                    .into_iter()
+                    .map(|s| match s {
+                        Symbol::NonTerminal(nt) => Symbol::NonTerminal(prefixed(&nt)),
+                        t => t,
+                    })
+                    .collect(),
+                helpers: Vec::new(),
+                annotations: rule.annotations,
+            });
+        }
+        Ok(())
+    }
+
+    /// Count the symbols and rules, e.g. to find the parts of the grammar to simplify.
+    pub fn sizes(&self) -> GrammarSizes {
+        let mut non_terminals = HashSet::new();
+        let mut terminals = HashSet::new();
+        for rule in self.rules.iter() {
+            non_terminals.insert(rule.lhs.as_str());
+            for s in rule.rhs.iter() {
+                match s {
+                    Symbol::Terminal(t) => {
+                        terminals.insert(t);
+                    }
+                    Symbol::NonTerminal(nt) => {
+                        non_terminals.insert(nt.as_str());
+                    }
+                }
+            }
+        }
+        GrammarSizes::new(
+            non_terminals.len(),
+            terminals.len(),
+            self.rules.iter().map(|r| (r.lhs.as_str(), r.rhs.len())),
+        )
+    }
+
+    /// Simplify the grammar and return the number of removed rules.
+    ///
+    /// * Rules of non-terminals that never derive a string of terminals are removed, as well as
+    ///   the rules that use them. Non-terminals without any rules are kept for
+    ///   [compile](#method.compile) to report them.
+    /// * Non-terminals other than the start symbol whose only rule is a single non-terminal are
+    ///   replaced by that non-terminal, e.g. `A → B` with `B → 'b'` becomes `B → 'b'`.
+    /// * Duplicate rules are removed.
+    ///
+    /// This changes the non-terminals that appear in the parse tree and drops the annotations of
+    /// the removed rules.
+    pub fn minimize(&mut self) -> usize {
+        let before = self.rules.len();
+
+        let defined: HashSet<String> = self.rules.iter().map(|r| r.lhs.clone()).collect();
+        let mut productive = HashSet::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in self.rules.iter() {
+                if !productive.contains(&rule.lhs)
+                    && rule.rhs.iter().all(|s| match s {
+                        Symbol::Terminal(_) => true,
+                        Symbol::NonTerminal(nt) => !defined.contains(nt) || productive.contains(nt),
+                    })
+                {
+                    productive.insert(rule.lhs.clone());
+                    changed = true;
+                }
+            }
+        }
+        self.rules.retain(|rule| {
+            productive.contains(&rule.lhs)
+                && rule.rhs.iter().all(|s| match s {
+                    Symbol::Terminal(_) => true,
+                    Symbol::NonTerminal(nt) => !defined.contains(nt) || productive.contains(nt),
+                })
+        });
+
+        while let Some((unit, target)) = self.rules.iter().find_map(|rule| match &rule.rhs[..] {
+            [Symbol::NonTerminal(target)]
+                if rule.lhs != self.start
+                    && *target != rule.lhs
+                    && self.rules.iter().filter(|r| r.lhs == rule.lhs).count() == 1 =>
+            {
+                Some((rule.lhs.clone(), target.clone()))
+            }
+            _ => None,
+        }) {
+            self.rules.retain(|rule| rule.lhs != unit);
+            for rule in self.rules.iter_mut() {
+                for s in rule.rhs.iter_mut() {
+                    if *s == Symbol::NonTerminal(unit.clone()) {
+                        *s = Symbol::NonTerminal(target.clone());
+                    }
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        self.rules
+            .retain(|rule| seen.insert((rule.lhs.clone(), rule.rhs.clone())));
+
+        before - self.rules.len()
+    }
+
     /// Compile the grammar for efficient use.
     ///
     /// If the given grammar is incorrect or inconsistent, return an error.
@@ -244,29 +994,21 @@ where
 
         let mut terminal_set = HashSet::new();
 
-        // Find empty rules first to give their lhs symbols low numbers
-        for r in self.rules.iter() {
+        for (i, r) in self.rules.iter().enumerate() {
             let lhs = &r.lhs;
             if lhs.is_empty() {
                 return Err(Error::EmptySymbol);
             }
             // The index into the rhs can grow to the full length (i.e. past the last entry).
             if r.rhs.len() >= (MAX_SYMBOL_ID as usize) {
-                return Err(Error::TooLarge(lhs.clone()));
-            }
-
-            if r.rhs.len() == 0 {
-                update_symbol(&mut symbol_set, lhs.clone(), true, &mut next_symbol_id);
+                return Err(Error::TooLarge {
+                    table: Table::Rhs,
+                    rule: Some(i),
+                    symbol: Some(lhs.clone()),
+                    count: r.rhs.len(),
+                    sizes: Box::new(self.sizes()),
+                });
             }
-        }
-
-        let empty_rules = next_symbol_id;
-        if empty_rules > (MAX_SYMBOL_ID as usize) {
-            return Err(Error::TooLarge("Empty Rules".to_string()));
-        }
-
-        for r in self.rules.iter() {
-            let lhs = &r.lhs;
             update_symbol(&mut symbol_set, lhs.clone(), true, &mut next_symbol_id);
             // TODO?: Reject if left recursive rule
             for s in r.rhs.iter() {
@@ -311,7 +1053,13 @@ where
             .map(|x| x.0.clone())
             .collect();
         if nonterminal_table.len() > (MAX_SYMBOL_ID as usize) {
-            return Err(Error::TooLarge("Terminals".to_string()));
+            return Err(Error::TooLarge {
+                table: Table::NonTerminals,
+                rule: None,
+                symbol: None,
+                count: nonterminal_table.len(),
+                sizes: Box::new(self.sizes()),
+            });
         }
         // Overwrite the error pseudo-non-terminal with a descriptive name
         nonterminal_table[0] = "~~~ERROR~~~".to_string();
@@ -323,9 +1071,13 @@ where
             .map(|x| (*x).clone())
             .collect();
         if terminal_table.len() + nonterminal_table.len() > (MAX_SYMBOL_ID as usize) {
-            return Err(Error::TooLarge(
-                "Terminals and NonTerminals together".to_string(),
-            ));
+            return Err(Error::TooLarge {
+                table: Table::Symbols,
+                rule: None,
+                symbol: None,
+                count: terminal_table.len() + nonterminal_table.len(),
+                sizes: Box::new(self.sizes()),
+            });
         }
 
         // Build the rules
@@ -366,12 +1118,29 @@ where
             .1;
         let start = start as SymbolId;
 
+        let mut rules_by_lhs = vec![Vec::new(); nonterminal_table.len()];
+        for (i, (lhs, _)) in rules.iter().enumerate() {
+            rules_by_lhs[*lhs as usize].push(i);
+        }
+
+        let annotations = std::iter::once(Vec::new())
+            .chain(self.rules.into_iter().map(|rule| rule.annotations))
+            .collect();
+
         Ok(CompiledGrammar {
-            nonterminal_table,
+            nt_by_name: nonterminal_table
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), i as SymbolId))
+                .collect(),
+            terminal_names: vec![None; terminal_table.len()],
+            terminal_order: (0..terminal_table.len()).collect(),
             terminal_table,
             rules,
+            rules_by_lhs,
+            annotations,
             start,
-            empty_rules: empty_rules as SymbolId,
+            nonterminal_table,
             _marker: PhantomData,
         })
     }
@@ -394,9 +1163,21 @@ impl<M> Rule<M> {
         Self {
             lhs: lhs.to_string(),
             rhs: Vec::new(),
+            helpers: Vec::new(),
+            annotations: Vec::new(),
         }
     }
 
+    /// Create a group of symbols for [opt](#method.opt), [star](#method.star),
+    /// [plus](#method.plus) and [alt](#method.alt).
+    ///
+    /// ```ignore
+    /// Rule::group().nt("key").t('=')
+    /// ```
+    pub fn group() -> Self {
+        Self::new("")
+    }
+
     /// Append a non-terminal to a rule.
     ///
     /// ```ignore
@@ -450,29 +1231,393 @@ impl<M> Rule<M> {
         }
         self
     }
+
+    /// Attach a key/value pair to the rule, e.g. a precedence or a hint for styling or folding.
+    ///
+    /// ```ignore
+    /// Rule::new("expr").nt("expr").t('*').nt("expr").annotate("precedence", "2")
+    /// ```
+    ///
+    /// The parser ignores annotations. They are available from
+    /// [CompiledGrammar::rule_annotations](struct.CompiledGrammar.html#method.rule_annotations).
+    pub fn annotate(mut self, key: &str, value: &str) -> Self {
+        self.annotations.push((key.to_string(), value.to_string()));
+        self
+    }
 }
 
-impl<T, M> CompiledGrammar<T, M>
-where
-    M: Matcher<T> + Clone,
-{
-    /// Number of rules in the grammqr
-    pub fn rule_count(&self) -> usize {
-        self.rules.len()
+/// Name of the auxiliary non-terminal of a group, e.g. `ws`, `Exact('=')` or `(key Exact('='))`
+fn group_name<M: std::fmt::Debug>(rhs: &[Symbol<M>]) -> String {
+    let mut names = rhs.iter().map(|s| match s {
+        Symbol::NonTerminal(nt) => nt.clone(),
+        Symbol::Terminal(t) => format!("{:?}", t),
+    });
+    if rhs.len() == 1 {
+        names.collect()
+    } else {
+        format!("({})", names.join(" "))
     }
+}
 
-    /// Check if rule with index `i` has the start symbol as lhs symbol.
-    pub fn is_start_rule(&self, i: usize) -> bool {
-        self.rules[i].0 == self.start
+/// Repetitions and alternatives.
+///
+/// Each one appends an auxiliary non-terminal, whose rules are added to the grammar together
+/// with the rule. It is named after the group and the operator, e.g. `ws*` or `(key Exact('='))?`,
+/// so that rules using the same construct share it.
+impl<M> Rule<M>
+where
+    M: Clone + std::fmt::Debug,
+{
+    /// Append a group that may be left out.
+    ///
+    /// ```ignore
+    /// Rule::new("left").opt(Rule::group().nt("sign")).nt("digits")
+    /// ```
+    ///
+    /// corresponds to
+    /// ```ignore
+    /// <left> ::= <sign>? <digits>
+    /// ```
+    ///
+    /// in [EBNF](https://en.wikipedia.org/wiki/Extended_Backus%E2%80%93Naur_form).
+    pub fn opt(self, group: Rule<M>) -> Self {
+        let alternatives = vec![group.rhs.clone(), Vec::new()];
+        self.auxiliary(group, "?", alternatives)
     }
 
-    /// Check if the given symbol is the start symbol.
-    pub fn is_start_symbol(&self, sym: SymbolId) -> bool {
-        self.start == sym
+    /// Append a group that is repeated zero or more times.
+    ///
+    /// ```ignore
+    /// Rule::new("left").star(Rule::group().nt("item"))
+    /// ```
+    ///
+    /// corresponds to
+    /// ```ignore
+    /// <left> ::= <item>*
+    /// ```
+    ///
+    /// in [EBNF](https://en.wikipedia.org/wiki/Extended_Backus%E2%80%93Naur_form).
+    pub fn star(self, group: Rule<M>) -> Self {
+        let alternatives = vec![Self::repeated(&group, "*"), Vec::new()];
+        self.auxiliary(group, "*", alternatives)
     }
 
-    /// Check if the rule with index `i` as the given symbol as lhs.
-    pub fn lhs_is(&self, i: usize, sym: SymbolId) -> bool {
+    /// Append a group that is repeated one or more times.
+    ///
+    /// ```ignore
+    /// Rule::new("left").plus(Rule::group().nt("item"))
+    /// ```
+    ///
+    /// corresponds to
+    /// ```ignore
+    /// <left> ::= <item>+
+    /// ```
+    ///
+    /// in [EBNF](https://en.wikipedia.org/wiki/Extended_Backus%E2%80%93Naur_form).
+    pub fn plus(self, group: Rule<M>) -> Self {
+        let alternatives = vec![Self::repeated(&group, "+"), group.rhs.clone()];
+        self.auxiliary(group, "+", alternatives)
+    }
+
+    /// Append a choice between groups.
+    ///
+    /// ```ignore
+    /// Rule::new("left").alt(vec![Rule::group().nt("a"), Rule::group().t('b')])
+    /// ```
+    ///
+    /// corresponds to
+    /// ```ignore
+    /// <left> ::= (<a> | "b")
+    /// ```
+    ///
+    /// in [EBNF](https://en.wikipedia.org/wiki/Extended_Backus%E2%80%93Naur_form).
+    pub fn alt(mut self, groups: Vec<Rule<M>>) -> Self {
+        let name = format!(
+            "({})",
+            groups.iter().map(|g| group_name(&g.rhs)).join(" | ")
+        );
+        for group in groups {
+            self.helpers.extend(group.helpers);
+            self.helpers.push(Rule {
+                lhs: name.clone(),
+                rhs: group.rhs,
+                helpers: Vec::new(),
+                annotations: Vec::new(),
+            });
+        }
+        self.rhs.push(Symbol::NonTerminal(name));
+        self
+    }
+
+    /// Right hand side of a left-recursive repetition of the group
+    fn repeated(group: &Rule<M>, operator: &str) -> Vec<Symbol<M>> {
+        let name = format!("{}{}", group_name(&group.rhs), operator);
+        std::iter::once(Symbol::NonTerminal(name))
+            .chain(group.rhs.iter().cloned())
+            .collect()
+    }
+
+    /// Append the auxiliary non-terminal of the group with the given rules.
+    fn auxiliary(
+        mut self,
+        group: Rule<M>,
+        operator: &str,
+        alternatives: Vec<Vec<Symbol<M>>>,
+    ) -> Self {
+        let name = format!("{}{}", group_name(&group.rhs), operator);
+        self.helpers.extend(group.helpers);
+        for rhs in alternatives {
+            self.helpers.push(Rule {
+                lhs: name.clone(),
+                rhs,
+                helpers: Vec::new(),
+                annotations: Vec::new(),
+            });
+        }
+        self.rhs.push(Symbol::NonTerminal(name));
+        self
+    }
+}
+
+/// Build a [Grammar](grammar/struct.Grammar.html) from EBNF-like rules.
+///
+/// ```ignore
+/// let grammar: Grammar<char, CharMatcher> = grammar! {
+///     key = simple_key | dotted_key;
+///     dotted_key = simple_key (dot_sep simple_key)+;
+///     ws = wschar*;
+///     wschar = ' ' | '\t';
+///     boolean = 't' 'r' 'u' 'e' | {Exact('f')} 'a' 'l' 's' 'e';
+///     digit = Range('0', '9');
+/// };
+/// ```
+///
+/// Each rule is a non-terminal followed by `=`, alternatives separated by `|` and a `;`. An
+/// alternative may be empty. Its items are non-terminals, terminals and groups in parentheses,
+/// each optionally followed by `?`, `*` or `+`. Terminals are literals, which are converted to
+/// matchers with `From`, [CharMatcher](char/enum.CharMatcher.html) variants, e.g.
+/// `Range('a', 'z')`, or matcher expressions in braces. Alternatives become separate rules,
+/// groups and repetitions become auxiliary non-terminals as described in
+/// [Rule::opt](grammar/struct.Rule.html#method.opt). The first rule defines the start symbol.
+///
+/// An alternative can start with annotations, e.g. `#[precedence = "2", fold = "block"]`, see
+/// [Rule::annotate](grammar/struct.Rule.html#method.annotate).
+///
+/// Every item is one recursion step of the macro, so large grammars may need to raise the
+/// `recursion_limit`.
+#[macro_export]
+macro_rules! grammar {
+    // Items of an alternative
+    (@seq $ctx:tt [$cur:expr]; #[$($key:ident = $value:expr),*] $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur$(.annotate(stringify!($key), $value))*]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; Exact($($m:tt)*) $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur]; {$crate::char::CharMatcher::Exact($($m)*)} $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; Range($($m:tt)*) $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur]; {$crate::char::CharMatcher::Range($($m)*)} $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; NoneOf($($m:tt)*) $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur]; {$crate::char::CharMatcher::NoneOf($($m)*)} $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; $token:literal $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur]; {::std::convert::From::from($token)} $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; $nt:ident ? $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.opt($crate::Rule::group().nt(stringify!($nt)))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; $nt:ident * $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.star($crate::Rule::group().nt(stringify!($nt)))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; $nt:ident + $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.plus($crate::Rule::group().nt(stringify!($nt)))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; $nt:ident $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.nt(stringify!($nt))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; {$t:expr} ? $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.opt($crate::Rule::group().t($t))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; {$t:expr} * $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.star($crate::Rule::group().t($t))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; {$t:expr} + $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.plus($crate::Rule::group().t($t))]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; {$t:expr} $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [$cur.t($t)]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; ($($group:tt)*) ? $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [
+            $crate::grammar!(@seq (group $cur, opt []) [$crate::Rule::group()]; $($group)*)
+        ]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; ($($group:tt)*) * $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [
+            $crate::grammar!(@seq (group $cur, star []) [$crate::Rule::group()]; $($group)*)
+        ]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; ($($group:tt)*) + $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [
+            $crate::grammar!(@seq (group $cur, plus []) [$crate::Rule::group()]; $($group)*)
+        ]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; ($($group:tt)*) $($rest:tt)*) => {
+        $crate::grammar!(@seq $ctx [
+            $crate::grammar!(@seq (group $cur, alt []) [$crate::Rule::group()]; $($group)*)
+        ]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; | $($rest:tt)*) => {
+        $crate::grammar!(@next $ctx [$cur]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr]; ; $($rest:tt)*) => {
+        $crate::grammar!(@end $ctx [$cur]; $($rest)*)
+    };
+    (@seq $ctx:tt [$cur:expr];) => {
+        $crate::grammar!(@end $ctx [$cur];)
+    };
+
+    // End of an alternative of a rule
+    (@next (rule $g:ident $lhs:ident) [$cur:expr]; $($rest:tt)*) => {
+        $g.add($cur);
+        $crate::grammar!(@seq (rule $g $lhs) [$crate::Rule::new(stringify!($lhs))]; $($rest)*)
+    };
+    (@end (rule $g:ident $lhs:ident) [$cur:expr]; $($rest:tt)*) => {
+        $g.add($cur);
+        $crate::grammar!(@rules $g; $($rest)*)
+    };
+
+    // End of an alternative of a group
+    (@next (group $outer:expr, $op:ident [$($alts:expr),*]) [$cur:expr]; $($rest:tt)*) => {
+        $crate::grammar!(@seq (group $outer, $op [$($alts,)* $cur]) [$crate::Rule::group()]; $($rest)*)
+    };
+    (@end (group $outer:expr, alt [$($alts:expr),*]) [$cur:expr];) => {
+        $outer.alt(vec![$($alts,)* $cur])
+    };
+    (@end (group $outer:expr, $op:ident []) [$cur:expr];) => {
+        $outer.$op($cur)
+    };
+    (@end (group $outer:expr, $op:ident [$($alts:expr),+]) [$cur:expr];) => {
+        $outer.$op($crate::Rule::group().alt(vec![$($alts,)* $cur]))
+    };
+
+    // Rules
+    (@rules $g:ident;) => {};
+    (@rules $g:ident; $lhs:ident = $($rest:tt)*) => {
+        $crate::grammar!(@seq (rule $g $lhs) [$crate::Rule::new(stringify!($lhs))]; $($rest)*);
+    };
+
+    ($start:ident = $($rest:tt)*) => {{
+        let mut grammar = $crate::Grammar::new();
+        grammar.set_start(stringify!($start).to_string());
+        $crate::grammar!(@rules grammar; $start = $($rest)*);
+        grammar
+    }};
+}
+
+impl<T, M> CompiledGrammar<T, M>
+where
+    M: Matcher<T> + Clone,
+{
+    /// Build a grammar from tables, e.g. generated by
+    /// [write_static_grammar](codegen/fn.write_static_grammar.html).
+    ///
+    /// The tables have the layout described in [check_tables](fn.check_tables.html), which
+    /// should be used to verify them. The first rule must be the error pseudo-rule
+    /// `(ERROR_ID, &[])`. Rules have no annotations.
+    pub fn from_tables(
+        nt_names: &[&str],
+        terminals: Vec<M>,
+        rules: &[(SymbolId, &[SymbolId])],
+        start: SymbolId,
+    ) -> Self
+    where
+        M: Ord,
+    {
+        let mut rules_by_lhs = vec![Vec::new(); nt_names.len()];
+        for (i, (lhs, _)) in rules.iter().enumerate() {
+            rules_by_lhs[*lhs as usize].push(i);
+        }
+        let mut terminal_order: Vec<usize> = (0..terminals.len()).collect();
+        terminal_order.sort_by(|a, b| terminals[*a].cmp(&terminals[*b]));
+        Self {
+            nonterminal_table: nt_names.iter().map(|name| name.to_string()).collect(),
+            nt_by_name: nt_names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), i as SymbolId))
+                .collect(),
+            terminal_names: vec![None; terminals.len()],
+            terminal_order,
+            terminal_table: terminals,
+            rules: rules
+                .iter()
+                .map(|(lhs, rhs)| (*lhs, rhs.to_vec()))
+                .collect(),
+            start,
+            rules_by_lhs,
+            annotations: vec![Vec::new(); rules.len()],
+            _marker: PhantomData,
+        }
+    }
+
+    /// Count the symbols and rules. Removed rules and the error pseudo-rule are not counted.
+    pub fn sizes(&self) -> GrammarSizes {
+        GrammarSizes::new(
+            self.nonterminal_table.len() - 1,
+            self.terminal_table.len(),
+            (1..self.rules.len())
+                .filter(|i| !self.is_removed(*i))
+                .map(|i| {
+                    let (lhs, rhs) = &self.rules[i];
+                    (self.nonterminal_table[*lhs as usize].as_str(), rhs.len())
+                }),
+        )
+    }
+
+    /// Number of rules in the grammqr
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Hash of the grammar.
+    ///
+    /// Covers the rules, the start symbol, the names of the non-terminals and the matchers of the
+    /// terminals. Use this to detect that a cached result (e.g. a chart or a tree) was produced by
+    /// a different version of the grammar. The value does not change between program runs, as
+    /// long as the `Hash` implementation of the matcher doesn't.
+    pub fn fingerprint(&self) -> u64
+    where
+        M: Hash,
+    {
+        let mut hasher = Fnv::default();
+        hasher.write_usize(self.nonterminal_table.len());
+        self.terminal_table.hash(&mut hasher);
+        hasher.write_usize(self.start as usize);
+        for (lhs, rhs) in self.rules.iter() {
+            hasher.write_usize(*lhs as usize);
+            hasher.write_usize(rhs.len());
+            rhs.iter().for_each(|s| hasher.write_usize(*s as usize));
+        }
+        for name in self.nonterminal_table.iter() {
+            hasher.write_usize(name.len());
+            hasher.write(name.as_bytes());
+        }
+        hasher.finish()
+    }
+
+    /// Check if rule with index `i` has the start symbol as lhs symbol.
+    pub fn is_start_rule(&self, i: usize) -> bool {
+        self.rules[i].0 == self.start
+    }
+
+    /// Check if the given symbol is the start symbol.
+    pub fn is_start_symbol(&self, sym: SymbolId) -> bool {
+        self.start == sym
+    }
+
+    /// Check if the rule with index `i` as the given symbol as lhs.
+    pub fn lhs_is(&self, i: usize, sym: SymbolId) -> bool {
         self.rules[i].0 == sym
     }
 
@@ -518,41 +1663,537 @@ where
     /// Convert the name of non-terminal to its SymbolId.
     ///
     /// Unknown names are returned as MAX_SYMBOL_ID.
-    ///
-    /// This function is slow and should not be used for mass queries.
     pub fn nt_id(&self, name: &str) -> SymbolId {
-        for i in 0..self.nonterminal_table.len() {
-            if name == self.nonterminal_table[i] {
-                return i as SymbolId;
-            }
-        }
-        MAX_SYMBOL_ID
+        self.nt_by_name.get(name).copied().unwrap_or(MAX_SYMBOL_ID)
+    }
+
+    /// Find a terminal by its matcher. Return its index into [terminals](#method.terminals).
+    pub fn t_id(&self, matcher: &M) -> Option<usize>
+    where
+        M: Ord,
+    {
+        self.terminal_order
+            .binary_search_by(|t| self.terminal_table[*t].cmp(matcher))
+            .ok()
+            .map(|i| self.terminal_order[i])
     }
 
     /// Convert a list of non-terminal names to SymbolIds.
     ///
     /// Unknown names are returned as MAX_SYMBOL_ID.
-    ///
-    /// This function is slow and should not be used for mass queries.
     pub fn nt_ids(&self, names: &[&str]) -> Vec<SymbolId> {
         names.iter().map(|n| self.nt_id(n)).collect()
     }
 
+    /// Key/value pairs of rule with index `i`, see [Rule::annotate](struct.Rule.html#method.annotate)
+    pub fn rule_annotations(&self, i: usize) -> &[(String, String)] {
+        &self.annotations[i]
+    }
+
+    /// Value of the first annotation of rule with index `i` with the given key
+    pub fn rule_annotation(&self, i: usize, key: &str) -> Option<&str> {
+        self.annotations[i]
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
     /// Get the lhs of rule with index `i`
     pub fn lhs(&self, i: usize) -> SymbolId {
         self.rules[i as usize].0
     }
 
-    /// Check if the non-terminal symbol has empty rules
-    pub fn nt_with_empty_rule(&self, sym: SymbolId) -> bool {
-        sym < self.empty_rules
+    /// Get the rhs of rule with index `i`. IDs below [nt_count](#method.nt_count) are
+    /// non-terminals, the others terminals.
+    pub fn rhs(&self, i: usize) -> &[SymbolId] {
+        &self.rules[i].1
+    }
+
+    /// Check if the rule with index `i` has been removed.
+    pub fn is_removed(&self, i: usize) -> bool {
+        self.rules[i].0 == MAX_SYMBOL_ID
+    }
+
+    /// Find the non-terminals that derive the empty string, directly or indirectly. Index is the
+    /// symbol ID.
+    pub fn nullable_symbols(&self) -> Vec<bool> {
+        let nt_count = self.nonterminal_table.len();
+        let mut nullable = vec![false; nt_count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (lhs, rhs) in self.rules.iter() {
+                if *lhs == MAX_SYMBOL_ID || nullable[*lhs as usize] {
+                    continue;
+                }
+                if rhs
+                    .iter()
+                    .all(|s| (*s as usize) < nt_count && nullable[*s as usize])
+                {
+                    nullable[*lhs as usize] = true;
+                    changed = true;
+                }
+            }
+        }
+        nullable
+    }
+
+    /// Find the terminals that can begin a derivation of each non-terminal. Index is the symbol ID.
+    /// The terminals are given as indices into [terminals](#method.terminals), sorted.
+    pub fn first_terminals(&self) -> Vec<Vec<usize>> {
+        let nt_count = self.nonterminal_table.len();
+        let nullable = self.nullable_symbols();
+        let mut first = vec![std::collections::BTreeSet::new(); nt_count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (lhs, rhs) in self.rules.iter() {
+                if *lhs == MAX_SYMBOL_ID {
+                    continue;
+                }
+                for s in rhs.iter().map(|s| *s as usize) {
+                    if s >= nt_count {
+                        changed |= first[*lhs as usize].insert(s - nt_count);
+                        break;
+                    }
+                    if s != *lhs as usize {
+                        let derived: Vec<usize> = first[s].iter().cloned().collect();
+                        for t in derived {
+                            changed |= first[*lhs as usize].insert(t);
+                        }
+                    }
+                    if !nullable[s] {
+                        break;
+                    }
+                }
+            }
+        }
+        first
+            .into_iter()
+            .map(|terminals| terminals.into_iter().collect())
+            .collect()
+    }
+
+    /// Find the non-terminals that can be derived from the start symbol. Index is the symbol ID.
+    ///
+    /// The error pseudo-non-terminal counts as reachable.
+    pub fn reachable_symbols(&self) -> Vec<bool> {
+        let nt_count = self.nonterminal_table.len();
+        let mut reachable = vec![false; nt_count];
+        reachable[ERROR_ID as usize] = true;
+        reachable[self.start as usize] = true;
+        let mut todo = vec![self.start];
+        while let Some(sym) = todo.pop() {
+            for rule in self.rules_by_lhs[sym as usize].iter() {
+                for s in self.rules[*rule].1.iter() {
+                    if (*s as usize) < nt_count && !reachable[*s as usize] {
+                        reachable[*s as usize] = true;
+                        todo.push(*s);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Find the non-terminals that derive at least one string of terminals, possibly the empty
+    /// one. Index is the symbol ID.
+    ///
+    /// Non-terminals that are not productive can never be completed, e.g. `A → A 'a'` without
+    /// another rule for `A`.
+    pub fn productive_symbols(&self) -> Vec<bool> {
+        let nt_count = self.nonterminal_table.len();
+        let mut productive = vec![false; nt_count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (lhs, rhs) in self.rules.iter() {
+                if *lhs == MAX_SYMBOL_ID || productive[*lhs as usize] {
+                    continue;
+                }
+                if rhs
+                    .iter()
+                    .all(|s| (*s as usize) >= nt_count || productive[*s as usize])
+                {
+                    productive[*lhs as usize] = true;
+                    changed = true;
+                }
+            }
+        }
+        productive
+    }
+
+    /// Number of non-terminals, including the error pseudo-non-terminal
+    pub fn nt_count(&self) -> usize {
+        self.nonterminal_table.len()
+    }
+
+    /// Matchers of the terminals. The symbol ID of a terminal is its index plus
+    /// [nt_count](#method.nt_count).
+    pub fn terminals(&self) -> &[M] {
+        &self.terminal_table
+    }
+
+    /// Name a terminal, given as index into [terminals](#method.terminals), for diagnostics, e.g.
+    /// `digit` instead of `'0'..'9'`. See [t_name](#method.t_name).
+    pub fn set_t_name(&mut self, term: usize, name: &str) {
+        self.terminal_names[term] = Some(name.to_string());
+    }
+
+    /// Get the start symbol
+    pub fn start(&self) -> SymbolId {
+        self.start
+    }
+
+    /// Select the non-terminal whose derivations are accepted, e.g. to parse a fragment of a
+    /// document.
+    pub fn set_start_by_name(&mut self, name: &str) -> Result<()> {
+        let id = self.nt_id(name);
+        if id == MAX_SYMBOL_ID || id == ERROR_ID || self.rules_by_lhs[id as usize].is_empty() {
+            return Err(Error::NoRule(name.to_string()));
+        }
+        self.start = id;
+        Ok(())
+    }
+
+    /// Indices of the rules that have the given non-terminal as lhs, in ascending order. Removed
+    /// rules are not included.
+    ///
+    /// The lookup is a table access, so there is no need to scan all rules.
+    pub fn rules_for_lhs(&self, sym: SymbolId) -> &[usize] {
+        &self.rules_by_lhs[sym as usize]
+    }
+}
+
+impl<T, M> RuleTable for CompiledGrammar<T, M>
+where
+    M: Matcher<T> + Clone,
+{
+    fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    fn lhs(&self, i: usize) -> SymbolId {
+        self.rules[i].0
+    }
+
+    fn is_removed(&self, i: usize) -> bool {
+        self.rules[i].0 == MAX_SYMBOL_ID
+    }
+
+    /// Iterate over the lookup table.
+    fn rules_for_lhs(&self, sym: SymbolId) -> Box<dyn Iterator<Item = usize> + '_> {
+        Box::new(self.rules_by_lhs[sym as usize].iter().copied())
     }
 }
 
 impl<T, M> CompiledGrammar<T, M>
 where
-    M: Matcher<T> + Clone + std::fmt::Debug,
+    M: Matcher<T> + Synthesize<T> + Clone + std::fmt::Debug,
 {
+    /// Check the grammar for likely mistakes, see [Warning](enum.Warning.html).
+    ///
+    /// Terminals shadow each other if one matches the token synthesized from the other one.
+    pub fn validate(&self) -> Vec<Warning> {
+        let nt_count = self.nonterminal_table.len();
+        let nullable = self.nullable_symbols();
+        let reachable = self.reachable_symbols();
+        let productive = self.productive_symbols();
+        let first = self.first_terminals();
+        let live_rules = || {
+            (1..self.rules.len()).filter(|i| !self.is_removed(*i) && self.rules[*i].0 != ERROR_ID)
+        };
+        let mut warnings = Vec::new();
+
+        // Non-terminals that begin a rule after nullable symbols, i.e. edges of left derivations.
+        // The flag is set if the non-terminal is preceded by at least one symbol.
+        let mut left = vec![Vec::new(); nt_count];
+        for i in live_rules() {
+            let (lhs, rhs) = &self.rules[i];
+            for (pos, s) in rhs.iter().map(|s| *s as usize).enumerate() {
+                if s >= nt_count {
+                    break;
+                }
+                left[*lhs as usize].push((s, pos != 0, i));
+                if !nullable[s] {
+                    break;
+                }
+            }
+        }
+        for lhs in 0..nt_count {
+            let mut reported = None;
+            for (s, hidden, rule) in left[lhs].iter() {
+                if !hidden || reported == Some(*rule) {
+                    continue;
+                }
+                let mut seen = vec![false; nt_count];
+                let mut todo = vec![*s];
+                while let Some(sym) = todo.pop() {
+                    if sym == lhs {
+                        warnings.push(Warning::HiddenLeftRecursion(
+                            self.nonterminal_table[lhs].clone(),
+                            *rule,
+                        ));
+                        reported = Some(*rule);
+                        break;
+                    }
+                    for (next, _, _) in left[sym].iter() {
+                        if !seen[*next] {
+                            seen[*next] = true;
+                            todo.push(*next);
+                        }
+                    }
+                }
+            }
+        }
+
+        for sym in 1..nt_count {
+            if !reachable[sym] {
+                warnings.push(Warning::Unreachable(self.nonterminal_table[sym].clone()));
+            }
+            if !productive[sym] {
+                warnings.push(Warning::Unproductive(self.nonterminal_table[sym].clone()));
+            }
+        }
+
+        let mut seen_rules = HashMap::new();
+        for i in live_rules() {
+            if let Some(first) = seen_rules.insert(&self.rules[i], i) {
+                warnings.push(Warning::DuplicateRule(
+                    self.nonterminal_table[self.rules[i].0 as usize].clone(),
+                    i,
+                    first,
+                ));
+                // Report further duplicates against the first one
+                seen_rules.insert(&self.rules[i], first);
+            }
+        }
+
+        // Terminals that can begin each rule
+        let rule_first = |i: usize| {
+            let mut terminals = Vec::new();
+            for s in self.rules[i].1.iter().map(|s| *s as usize) {
+                if s >= nt_count {
+                    terminals.push(s - nt_count);
+                    break;
+                }
+                terminals.extend(first[s].iter().cloned());
+                if !nullable[s] {
+                    break;
+                }
+            }
+            terminals
+        };
+        for (lhs, rules) in self.rules_by_lhs.iter().enumerate().skip(1) {
+            let mut shadowed = std::collections::BTreeSet::new();
+            let rules: Vec<Vec<usize>> = rules
+                .iter()
+                .filter(|i| !self.is_removed(**i))
+                .map(|i| rule_first(*i))
+                .collect();
+            for (i, a_terminals) in rules.iter().enumerate() {
+                for b_terminals in rules[i + 1..].iter() {
+                    for a in a_terminals.iter() {
+                        for b in b_terminals.iter().filter(|b| *b != a) {
+                            for (t, s) in [(*a, *b), (*b, *a)].iter() {
+                                let shadows = self.terminal_table[*s]
+                                    .synthesize()
+                                    .is_some_and(|token| self.terminal_table[*t].matches(token));
+                                if shadows {
+                                    shadowed.insert((*t, *s));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            for (t, s) in shadowed {
+                warnings.push(Warning::ShadowedTerminal(
+                    self.nonterminal_table[lhs].clone(),
+                    format!("{:?}", self.terminal_table[t]),
+                    format!("{:?}", self.terminal_table[s]),
+                ));
+            }
+        }
+
+        warnings
+    }
+}
+
+/// Mutation of a compiled grammar, e.g. for interactive grammar development.
+///
+/// Rule indices are stable: New rules are appended and removed rules are replaced by a tombstone,
+/// which has `MAX_SYMBOL_ID` as lhs and an empty rhs. Tombstones are never predicted and count in
+/// [rule_count](#method.rule_count). Use [is_removed](#method.is_removed) to skip them.
+///
+/// Non-terminal IDs are stable, too. New non-terminals are appended to the symbol table. As the
+/// terminal IDs follow the non-terminals, they change when non-terminals are added.
+///
+/// A parser that uses the grammar must reset its chart after any change. See
+/// [Parser::grammar_mut](struct.Parser.html#method.grammar_mut).
+impl<T, M> CompiledGrammar<T, M>
+where
+    M: Matcher<T> + Ord + Clone,
+{
+    /// Append a rule and return its index.
+    ///
+    /// An unknown lhs becomes a new non-terminal. Non-terminals on the rhs must already have a
+    /// rule or be the lhs. New terminals are added to the terminal table.
+    pub fn add_rule_dyn(&mut self, lhs: &str, rhs: Vec<Symbol<M>>) -> Result<usize> {
+        if lhs.is_empty() {
+            return Err(Error::EmptySymbol);
+        }
+        if rhs.len() >= (MAX_SYMBOL_ID as usize) {
+            return Err(Error::TooLarge {
+                table: Table::Rhs,
+                rule: Some(self.rules.len()),
+                symbol: Some(lhs.to_string()),
+                count: rhs.len(),
+                sizes: Box::new(self.sizes()),
+            });
+        }
+        if self.rules.len() >= (MAX_SYMBOL_ID as usize) {
+            return Err(Error::TooLarge {
+                table: Table::Rules,
+                rule: None,
+                symbol: None,
+                count: self.rules.len() + 1,
+                sizes: Box::new(self.sizes()),
+            });
+        }
+
+        // Check everything before changing anything.
+        let lhs_is_new = self.nt_id(lhs) == MAX_SYMBOL_ID;
+        let mut new_terminals = Vec::new();
+        for s in rhs.iter() {
+            match s {
+                Symbol::Terminal(t) => {
+                    if !self.terminal_table.contains(t) && !new_terminals.contains(&t) {
+                        new_terminals.push(t);
+                    }
+                }
+                Symbol::NonTerminal(nt) => {
+                    if nt.is_empty() {
+                        return Err(Error::EmptySymbol);
+                    }
+                    if nt != lhs && self.nt_id(nt) == MAX_SYMBOL_ID {
+                        return Err(Error::NoRule(nt.clone()));
+                    }
+                }
+            }
+        }
+        let new_nts = if lhs_is_new { 1 } else { 0 };
+        let count = self.nonterminal_table.len()
+            + new_nts
+            + self.terminal_table.len()
+            + new_terminals.len();
+        if count >= (MAX_SYMBOL_ID as usize) {
+            return Err(Error::TooLarge {
+                table: Table::Symbols,
+                rule: None,
+                symbol: None,
+                count,
+                sizes: Box::new(self.sizes()),
+            });
+        }
+
+        for t in new_terminals {
+            let i = self
+                .terminal_order
+                .partition_point(|x| self.terminal_table[*x] < *t);
+            self.terminal_order.insert(i, self.terminal_table.len());
+            self.terminal_table.push(t.clone());
+            self.terminal_names.push(None);
+        }
+        if lhs_is_new {
+            self.add_nonterminal(lhs);
+        }
+
+        let lhs_id = self.nt_id(lhs);
+        let rhs_id = rhs
+            .iter()
+            .map(|s| match s {
+                Symbol::Terminal(t) => {
+                    let t_id = self.t_id(t).expect("rhs terminal should be known");
+                    (t_id + self.nonterminal_table.len()) as SymbolId
+                }
+                Symbol::NonTerminal(nt) => self.nt_id(nt),
+            })
+            .collect::<Vec<SymbolId>>();
+        self.rules.push((lhs_id, rhs_id));
+        self.annotations.push(Vec::new());
+        self.rules_by_lhs[lhs_id as usize].push(self.rules.len() - 1);
+        Ok(self.rules.len() - 1)
+    }
+
+    /// Remove the rule with index `i`.
+    ///
+    /// The rule is replaced by a tombstone to keep the indices of the other rules. The error
+    /// pseudo-rule cannot be removed.
+    pub fn remove_rule_dyn(&mut self, i: usize) -> Result<()> {
+        if i == ERROR_ID as usize || i >= self.rules.len() || self.is_removed(i) {
+            return Err(Error::NoSuchRule(i));
+        }
+        let (lhs, _) = std::mem::replace(&mut self.rules[i], (MAX_SYMBOL_ID, Vec::new()));
+        self.rules_by_lhs[lhs as usize].retain(|r| *r != i);
+        self.annotations[i].clear();
+        Ok(())
+    }
+
+    /// Rename a non-terminal. Its ID does not change.
+    pub fn rename_nt(&mut self, old: &str, new: &str) -> Result<()> {
+        if new.is_empty() {
+            return Err(Error::EmptySymbol);
+        }
+        let id = self.nt_id(old);
+        if id == MAX_SYMBOL_ID || id == ERROR_ID {
+            return Err(Error::NoRule(old.to_string()));
+        }
+        if self.nt_id(new) != MAX_SYMBOL_ID {
+            return Err(Error::DuplicateSymbol(new.to_string()));
+        }
+        self.nt_by_name.remove(old);
+        self.nt_by_name.insert(new.to_string(), id);
+        self.nonterminal_table[id as usize] = new.to_string();
+        Ok(())
+    }
+
+    /// Append a non-terminal and move the terminal IDs in all rules up by one.
+    fn add_nonterminal(&mut self, name: &str) {
+        let terminal_base = self.nonterminal_table.len() as SymbolId;
+        for (_, rhs) in self.rules.iter_mut() {
+            for s in rhs.iter_mut() {
+                if *s >= terminal_base {
+                    *s += 1;
+                }
+            }
+        }
+        self.nt_by_name
+            .insert(name.to_string(), self.nonterminal_table.len() as SymbolId);
+        self.nonterminal_table.push(name.to_string());
+        self.rules_by_lhs.push(Vec::new());
+    }
+}
+
+impl<T, M> CompiledGrammar<T, M>
+where
+    M: Matcher<T> + Clone + MatcherDisplay,
+{
+    /// Return the name of a terminal, given as index into [terminals](#method.terminals).
+    ///
+    /// Unless a name has been set with [set_t_name](#method.set_t_name), it is written by the
+    /// matcher, e.g. `']'` or `'0'..'9'` for characters.
+    pub fn t_name(&self, term: usize) -> Cow<'_, str> {
+        match &self.terminal_names[term] {
+            Some(name) => Cow::Borrowed(name),
+            None => {
+                let mut text = Vec::new();
+                let _ = self.terminal_table[term].write_matcher(&mut text);
+                Cow::Owned(String::from_utf8_lossy(&text).into_owned())
+            }
+        }
+    }
+
     /// Write a reabale form of a dotted rule to the given Writer instance.
     ///
     /// Debug function. Creates unicode characters that might not display correctly on old
@@ -575,7 +2216,7 @@ where
                 write!(writer, "{} ", self.nonterminal_table[sym as usize])?;
             } else {
                 let t_ind = (sym as usize) - self.nonterminal_table.len();
-                write!(writer, "'{:?}' ", self.terminal_table[t_ind])?;
+                write!(writer, "{} ", self.t_name(t_ind))?;
             }
         }
         if dot_index == rule.1.len() {
@@ -598,6 +2239,7 @@ where
     ///
     /// Debug function. Creates unicode characters that might not display correctly on old
     /// terminals.
+    #[deprecated(note = "use write_dotted_rule")]
     pub fn print_dotted_rule(&self, dotted_rule: &DottedRule) -> std::io::Result<()> {
         self.write_dotted_rule(&mut std::io::stdout(), dotted_rule)
     }
@@ -609,7 +2251,13 @@ where
             debug!("  {:6}: {}", i, n);
         }
         for (i, n) in self.terminal_table.iter().enumerate() {
-            debug!("  {:6}: {:?}", i + self.nonterminal_table.len(), n);
+            let mut text = Vec::new();
+            let _ = n.write_matcher(&mut text);
+            debug!(
+                "  {:6}: {}",
+                i + self.nonterminal_table.len(),
+                String::from_utf8_lossy(&text)
+            );
         }
     }
 }
@@ -636,6 +2284,11 @@ impl DottedRule {
     pub fn is_first(&self) -> bool {
         self.dot == 0
     }
+
+    /// Return the number of symbols left of the dot.
+    pub fn dot(&self) -> usize {
+        self.dot as usize
+    }
 }
 
 impl<M> CompiledSymbol<M> {
@@ -831,4 +2484,746 @@ pub mod tests {
         assert!(pp_found);
         assert!(mary_found);
     }
+
+    #[test]
+    fn symbol_id_width() {
+        #[cfg(not(feature = "symbols-u32"))]
+        assert_eq!(std::mem::size_of::<SymbolId>(), 2);
+        #[cfg(feature = "symbols-u32")]
+        assert_eq!(std::mem::size_of::<SymbolId>(), 4);
+    }
+
+    /// Compile a grammar with more terminals than fit into 16 bit and parse with it.
+    #[cfg(feature = "symbols-u32")]
+    #[test]
+    fn large_grammar() {
+        use super::super::parser::{Parser, Verdict};
+        use CharMatcher::*;
+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").t(Exact('a')).t(Exact('b')));
+        grammar.add(Rule::new("S").nt("Large"));
+        grammar.add(
+            Rule::new("Large").ts((0x4E00..)
+                .filter_map(std::char::from_u32)
+                .take(70_000)
+                .map(Exact)),
+        );
+
+        let compiled_grammar = grammar.compile().expect("compilation should have worked");
+        assert!(compiled_grammar.terminal_table.len() >= 70_000);
+
+        let mut parser = Parser::<char, CharMatcher>::new(compiled_grammar);
+        assert_eq!(parser.update(0, 'a'), Verdict::More);
+        assert_eq!(parser.update(1, 'b'), Verdict::Accept);
+    }
+
+    #[test]
+    fn error_accessors() {
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("Missing"));
+        let err = grammar
+            .compile()
+            .err()
+            .expect("compilation should have failed");
+        assert_eq!(err.no_rule_symbol(), Some("Missing"));
+        assert!(!err.is_too_large());
+
+        assert_eq!(err.symbol(), Some("Missing"));
+        assert_eq!(err.to_string(), "no rule for non-terminal Missing");
+
+        let err = Error::TooLarge {
+            table: Table::Rhs,
+            rule: Some(3),
+            symbol: Some("S".to_string()),
+            count: 70000,
+            sizes: Box::new(define_grammar().sizes()),
+        };
+        assert!(err.is_too_large());
+        assert_eq!(err.no_rule_symbol(), None);
+        assert_eq!(err.symbol(), Some("S"));
+        assert_eq!(err.rule(), Some(3));
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!(
+                "too many symbols on the right hand side: 70000 of at most {} in rule 3 of S; \
+                 the grammar has ",
+                MAX_SYMBOL_ID
+            )),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        let sizes = define_grammar().sizes();
+        assert_eq!(
+            (sizes.non_terminals, sizes.terminals, sizes.rules),
+            (7, 15, 11)
+        );
+        assert_eq!(sizes.largest.len(), 5);
+        assert_eq!(sizes.largest[0], ("Noun".to_string(), 17));
+        let compiled = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        assert_eq!(compiled.sizes(), sizes);
+    }
+
+    #[cfg(not(feature = "symbols-u32"))]
+    #[test]
+    fn too_large() {
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").t(CharMatcher::Exact('s')));
+        grammar.add(Rule::new("Long").ts(std::iter::repeat_n(
+            CharMatcher::Exact('a'),
+            MAX_SYMBOL_ID as usize,
+        )));
+        match grammar.compile() {
+            Err(Error::TooLarge {
+                table: Table::Rhs,
+                rule: Some(1),
+                sizes,
+                ..
+            }) => {
+                assert_eq!(
+                    (sizes.non_terminals, sizes.terminals, sizes.rules),
+                    (2, 2, 2)
+                );
+                assert_eq!(
+                    sizes.largest[0],
+                    ("Long".to_string(), MAX_SYMBOL_ID as usize)
+                );
+            }
+            _ => panic!("compilation should have failed"),
+        }
+    }
+
+    const NT_NAMES: [&str; 3] = ["~~~ERROR~~~", "S", "A"];
+    const NUMBER_OF_NTS: usize = 3;
+    const TERMINALS: [char; 2] = ['a', 'b'];
+    const RULES: &[(SymbolId, &[SymbolId])] = &[(1, &[2, 4]), (2, &[3]), (2, &[])];
+    verify_tables!(NT_NAMES, NUMBER_OF_NTS, TERMINALS, RULES);
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use crate::{Parser, Verdict};
+
+        let mut grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let removed = grammar
+            .add_rule_dyn(
+                "Noun",
+                "jack "
+                    .chars()
+                    .map(CharMatcher::Exact)
+                    .map(Symbol::Terminal)
+                    .collect(),
+            )
+            .expect("rule should be added");
+        grammar
+            .remove_rule_dyn(removed)
+            .expect("rule should be removed");
+
+        let json = serde_json::to_value(&grammar).expect("grammar should serialize");
+        let copy: CompiledGrammar<char, CharMatcher> =
+            serde_json::from_value(json.clone()).expect("grammar should deserialize");
+        assert_eq!(copy.fingerprint(), grammar.fingerprint());
+        let mut parser = Parser::new(copy);
+        let tokens: Vec<char> = "john called mary ".chars().collect();
+        assert_eq!(parser.update_slice(0, &tokens), Verdict::Accept);
+
+        // Corrupt tables are rejected
+        let corrupt = |change: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            change(&mut json);
+            serde_json::from_value::<CompiledGrammar<char, CharMatcher>>(json)
+                .err()
+                .map(|e| e.to_string())
+        };
+        let unknown = serde_json::Value::from(MAX_SYMBOL_ID - 1);
+        assert_eq!(
+            corrupt(&|json| json["rules"][1][1][0] = unknown.clone()).as_deref(),
+            Some("rhs of rule 1 has an unknown symbol")
+        );
+        let start = serde_json::Value::from(grammar.nonterminal_table.len());
+        assert!(corrupt(&|json| json["start"] = start.clone()).is_some());
+        assert!(corrupt(&|json| json["rules_by_lhs"][1] = serde_json::json!([])).is_some());
+        assert!(
+            corrupt(&|json| json["terminal_order"][0] = json["terminal_order"][1].clone())
+                .is_some()
+        );
+    }
+
+    /// Tables that fail verification panic when checked at run time
+    #[test]
+    fn check_tables() {
+        super::check_tables(3, 3, 2, RULES);
+
+        let bad_rhs: &[(SymbolId, &[SymbolId])] = &[(1, &[2, 4]), (2, &[5])];
+        let res = std::panic::catch_unwind(|| super::check_tables(3, 3, 2, bad_rhs));
+        assert!(res.is_err());
+
+        let bad_lhs: &[(SymbolId, &[SymbolId])] = &[(3, &[4])];
+        let res = std::panic::catch_unwind(|| super::check_tables(3, 3, 2, bad_lhs));
+        assert!(res.is_err());
+
+        let res = std::panic::catch_unwind(|| super::check_tables(2, 3, 2, RULES));
+        assert!(res.is_err());
+    }
+
+    /// Rules of a compiled grammar that are found by the default lookup
+    struct ScannedRules<'a>(&'a CompiledGrammar<char, CharMatcher>);
+
+    impl RuleTable for ScannedRules<'_> {
+        fn rule_count(&self) -> usize {
+            self.0.rule_count()
+        }
+
+        fn lhs(&self, i: usize) -> SymbolId {
+            self.0.lhs(i)
+        }
+
+        fn is_removed(&self, i: usize) -> bool {
+            self.0.is_removed(i)
+        }
+    }
+
+    #[test]
+    fn rule_table() {
+        let rules = |table: &dyn RuleTable, sym| table.rules_for_lhs(sym).collect::<Vec<usize>>();
+        let mut grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let noun = grammar.nt_id("Noun");
+        let removed = grammar
+            .add_rule_dyn("Noun", Vec::new())
+            .expect("rule should be added");
+        let added = grammar
+            .add_rule_dyn("Noun", vec![Symbol::Terminal(CharMatcher::Exact('x'))])
+            .expect("rule should be added");
+        grammar
+            .remove_rule_dyn(removed)
+            .expect("rule should be removed");
+        assert_eq!(rules(&grammar, noun).last(), Some(&added));
+        assert!(!rules(&grammar, noun).contains(&removed));
+
+        // The lookup table gives the same result as the default
+        for sym in 0..grammar.nt_count() as SymbolId {
+            assert_eq!(rules(&grammar, sym), rules(&ScannedRules(&grammar), sym));
+            assert_eq!(rules(&grammar, sym), grammar.rules_for_lhs(sym));
+        }
+    }
+
+    #[test]
+    fn mutation() {
+        use CharMatcher::*;
+        let mut grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let noun = grammar.nt_id("Noun");
+        let terminals = grammar.terminal_table.len();
+
+        // Empty rules make the non-terminal nullable
+        assert!(!grammar.nullable_symbols()[noun as usize]);
+        let empty = grammar
+            .add_rule_dyn("Noun", Vec::new())
+            .expect("rule should be added");
+        assert!(grammar.nullable_symbols()[noun as usize]);
+        grammar
+            .remove_rule_dyn(empty)
+            .expect("rule should be removed");
+        assert!(!grammar.nullable_symbols()[noun as usize]);
+        assert!(grammar.remove_rule_dyn(empty).is_err());
+        assert!(grammar.remove_rule_dyn(0).is_err());
+
+        // New non-terminals move the terminals.
+        let rule = grammar
+            .add_rule_dyn(
+                "Adj",
+                vec![Symbol::Terminal(Exact('b')), Symbol::Terminal(Exact('a'))],
+            )
+            .expect("rule should be added");
+        let adj = grammar.nt_id("Adj");
+        assert_eq!(adj as usize, grammar.nonterminal_table.len() - 1);
+        assert_eq!(grammar.terminal_table.len(), terminals + 1);
+        let dotted = DottedRule {
+            rule: rule as SymbolId,
+            dot: 1,
+        };
+        match grammar.dotted_symbol(&dotted) {
+            CompiledSymbol::Terminal(m) => assert_eq!(m, Exact('a')),
+            _ => panic!("Expected terminal"),
+        }
+
+        assert!(grammar
+            .add_rule_dyn("Adj", vec![Symbol::NonTerminal("Adv".to_string())])
+            .is_err());
+
+        grammar
+            .rename_nt("Adj", "Adjective")
+            .expect("rename should work");
+        assert_eq!(grammar.nt_id("Adjective"), adj);
+        assert_eq!(grammar.nt_id("Adj"), MAX_SYMBOL_ID);
+        assert!(grammar.rename_nt("Adjective", "Noun").is_err());
+        assert!(grammar.rename_nt("Adj", "Other").is_err());
+    }
+
+    #[test]
+    fn t_id() {
+        use CharMatcher::Exact;
+
+        let mut grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        for (i, t) in grammar.terminals().iter().enumerate() {
+            assert_eq!(grammar.t_id(t), Some(i));
+        }
+        assert_eq!(grammar.t_id(&Exact('!')), None);
+
+        // New terminals are found after the existing ones
+        grammar
+            .add_rule_dyn("Noun", vec![Symbol::Terminal(Exact('!'))])
+            .expect("adding should have worked");
+        grammar
+            .add_rule_dyn("Noun", vec![Symbol::Terminal(Exact('?'))])
+            .expect("adding should have worked");
+        let count = grammar.terminals().len();
+        assert_eq!(grammar.t_id(&Exact('!')), Some(count - 2));
+        assert_eq!(grammar.t_id(&Exact('?')), Some(count - 1));
+    }
+
+    #[test]
+    fn t_name() {
+        use CharMatcher::{Exact, Range};
+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").t(Range('0', '9')).t(Exact(']')));
+        let mut grammar = grammar.compile().expect("compilation should have worked");
+        let digit = grammar
+            .t_id(&Range('0', '9'))
+            .expect("digits should be a terminal");
+        let bracket = grammar.t_id(&Exact(']')).expect("] should be a terminal");
+        assert_eq!(grammar.t_name(digit), "'0'..'9'");
+        assert_eq!(grammar.t_name(bracket), "']'");
+
+        grammar.set_t_name(digit, "digit");
+        assert_eq!(grammar.t_name(digit), "digit");
+        assert_eq!(
+            grammar
+                .dotted_rule_to_string(&DottedRule::new(1))
+                .expect("writing should have worked"),
+            "S → • digit ']' "
+        );
+    }
+
+    #[test]
+    fn fingerprint() {
+        use CharMatcher::Exact;
+
+        let build = |name: &str, t: char| {
+            let mut grammar = Grammar::<char, CharMatcher>::new();
+            grammar.set_start("S".to_string());
+            grammar.add(Rule::new("S").nt(name).t(Exact('x')));
+            grammar.add(Rule::new(name).t(Exact(t)));
+            grammar.compile().expect("compilation should have worked")
+        };
+        let grammar = build("A", 'a');
+        assert_eq!(grammar.fingerprint(), build("A", 'a').fingerprint());
+
+        // Other terminals change the fingerprint
+        assert_ne!(grammar.fingerprint(), build("A", 'b').fingerprint());
+
+        let renamed = build("B", 'a');
+        assert_ne!(grammar.fingerprint(), renamed.fingerprint());
+    }
+
+    #[test]
+    fn set_start_by_name() {
+        let mut grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        grammar
+            .set_start_by_name("NP")
+            .expect("NP should have rules");
+        assert_eq!(grammar.start(), grammar.nt_id("NP"));
+        assert!(grammar.is_start_rule(grammar.rules_for_lhs(grammar.nt_id("NP"))[0]));
+        assert_eq!(
+            grammar
+                .set_start_by_name("XP")
+                .unwrap_err()
+                .no_rule_symbol(),
+            Some("XP")
+        );
+        assert!(grammar.set_start_by_name("").is_err());
+        assert_eq!(grammar.start(), grammar.nt_id("NP"));
+    }
+
+    #[test]
+    fn rules_for_lhs() {
+        let mut grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        for nt in 0..grammar.nt_count() {
+            let nt = nt as SymbolId;
+            let scanned: Vec<usize> = (0..grammar.rule_count())
+                .filter(|i| grammar.lhs_is(*i, nt))
+                .collect();
+            assert_eq!(grammar.rules_for_lhs(nt), scanned.as_slice());
+        }
+
+        let noun = grammar.nt_id("Noun");
+        let count = grammar.rules_for_lhs(noun).len();
+        let empty = grammar
+            .add_rule_dyn("Noun", Vec::new())
+            .expect("rule should be added");
+        assert_eq!(grammar.rules_for_lhs(noun).last(), Some(&empty));
+        grammar
+            .remove_rule_dyn(empty)
+            .expect("rule should be removed");
+        assert_eq!(grammar.rules_for_lhs(noun).len(), count);
+
+        grammar
+            .add_rule_dyn("Adj", Vec::new())
+            .expect("rule should be added");
+        assert_eq!(grammar.rules_for_lhs(grammar.nt_id("Adj")).len(), 1);
+    }
+
+    #[test]
+    fn first_terminals() {
+        use CharMatcher::Exact;
+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("A").nt("B"));
+        grammar.add(Rule::new("A"));
+        grammar.add(Rule::new("A").nt("A").t(Exact('a')));
+        grammar.add(Rule::new("B").t(Exact('b')));
+        grammar.add(Rule::new("C").nt("B").t(Exact('c')));
+        let grammar = grammar.compile().expect("compilation should have worked");
+        let first = grammar.first_terminals();
+        for (name, expected) in [("S", "ab"), ("A", "a"), ("B", "b"), ("C", "b")].iter() {
+            let terminals: Vec<CharMatcher> = first[grammar.nt_id(name) as usize]
+                .iter()
+                .map(|t| grammar.terminals()[*t].clone())
+                .collect();
+            let expected: Vec<CharMatcher> = expected.chars().map(Exact).collect();
+            assert_eq!(terminals, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn nullable_symbols() {
+        use CharMatcher::Exact;
+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("A").nt("B"));
+        grammar.add(Rule::new("A"));
+        grammar.add(Rule::new("B").nt("A"));
+        grammar.add(Rule::new("B").t(Exact('b')));
+        grammar.add(Rule::new("C").t(Exact('c')));
+        let grammar = grammar.compile().expect("compilation should have worked");
+        let nullable = grammar.nullable_symbols();
+        for (name, expected) in [("S", true), ("A", true), ("B", true), ("C", false)].iter() {
+            assert_eq!(
+                nullable[grammar.nt_id(name) as usize],
+                *expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn repetitions() {
+        use crate::{Parser, Verdict};
+        use CharMatcher::*;
+
+        // List = '[' (Item (',' Item)*)? ']' with Item = ('a' | 'b')+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("List".to_string());
+        let item = || Rule::group().nt("Item");
+        grammar.add(
+            Rule::new("List")
+                .t(Exact('['))
+                .opt(item().star(Rule::group().t(Exact(',')).nt("Item")))
+                .t(Exact(']')),
+        );
+        grammar.add(Rule::new("Item").plus(Rule::group().alt(vec![
+            Rule::group().t(Exact('a')),
+            Rule::group().t(Exact('b')),
+        ])));
+        // Shared auxiliary rules are only added once
+        grammar.add(Rule::new("Items").star(item()).star(item()));
+        let grammar = grammar.compile().expect("compilation should have worked");
+        assert_eq!(grammar.rules_for_lhs(grammar.nt_id("Item*")).len(), 2);
+        assert!(grammar.nt_id("(Exact('a') | Exact('b'))+") != MAX_SYMBOL_ID);
+
+        let mut parser = Parser::new(grammar);
+        for (text, verdict) in [
+            ("[]", Verdict::Accept),
+            ("[ab]", Verdict::Accept),
+            ("[a,bba,b]", Verdict::Accept),
+            ("[a,", Verdict::More),
+            ("[,a]", Verdict::Reject),
+        ]
+        .iter()
+        {
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(parser.update_slice(0, &tokens), *verdict, "{}", text);
+        }
+    }
+
+    #[test]
+    fn grammar_macro() {
+        use crate::{Parser, Verdict};
+        use CharMatcher::*;
+
+        let grammar: Grammar<char, CharMatcher> = grammar! {
+            list = {Exact('[')} ws (item (ws {Exact(',')} ws item)*)? ws {Exact(']')};
+            item = word | {Range('0', '9')}+ | ;
+            word = {Range('a', 'z')} ({Range('a', 'z')} | {Range('0', '9')})*;
+            ws = ({Exact(' ')} | {Exact('\t')})*;
+        };
+        let grammar = grammar.compile().expect("compilation should have worked");
+        assert_eq!(grammar.nt_name(grammar.start()), "list");
+        assert_eq!(grammar.rules_for_lhs(grammar.nt_id("item")).len(), 3);
+
+        let mut parser = Parser::new(grammar);
+        for (text, verdict) in [
+            ("[]", Verdict::Accept),
+            ("[ a1 , 42,\tb ]", Verdict::Accept),
+            ("[,]", Verdict::Accept),
+            ("[a", Verdict::More),
+            ("[1a]", Verdict::Reject),
+        ]
+        .iter()
+        {
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(parser.update_slice(0, &tokens), *verdict, "{}", text);
+        }
+    }
+
+    #[test]
+    fn annotations() {
+        use CharMatcher::*;
+
+        let grammar: Grammar<char, CharMatcher> = grammar! {
+            expr = #[precedence = "1", assoc = "left"] expr {Exact('+')} expr
+                | #[precedence = "2"] expr {Exact('*')} expr
+                | {Range('0', '9')};
+        };
+        let mut grammar = grammar.compile().expect("compilation should have worked");
+        let rules = grammar.rules_for_lhs(grammar.nt_id("expr")).to_vec();
+        assert_eq!(
+            grammar.rule_annotations(rules[0]),
+            &[
+                ("precedence".to_string(), "1".to_string()),
+                ("assoc".to_string(), "left".to_string())
+            ]
+        );
+        assert_eq!(grammar.rule_annotation(rules[1], "precedence"), Some("2"));
+        assert_eq!(grammar.rule_annotation(rules[1], "assoc"), None);
+        assert!(grammar.rule_annotations(rules[2]).is_empty());
+        assert!(grammar.rule_annotations(ERROR_ID as usize).is_empty());
+
+        let added = grammar
+            .add_rule_dyn("expr", vec![Symbol::Terminal(Exact('x'))])
+            .expect("adding should have worked");
+        assert!(grammar.rule_annotations(added).is_empty());
+        grammar
+            .remove_rule_dyn(rules[0])
+            .expect("removing should have worked");
+        assert!(grammar.rule_annotations(rules[0]).is_empty());
+    }
+
+    #[test]
+    fn grammar_macro_literals() {
+        use crate::{Parser, Verdict};
+
+        let grammar: Grammar<char, CharMatcher> = grammar! {
+            value = boolean | name | string;
+            boolean = 't' 'r' 'u' 'e' | 'f' 'a' 'l' 's' 'e';
+            name = Range('a', 'z')+;
+            string = '"' NoneOf(vec!['"'])* Exact('"');
+        };
+        let grammar = grammar.compile().expect("compilation should have worked");
+        assert_eq!(
+            grammar
+                .terminals()
+                .iter()
+                .filter(|t| **t == CharMatcher::Exact('e'))
+                .count(),
+            1
+        );
+
+        let mut parser = Parser::new(grammar);
+        for (text, verdict) in [
+            ("true", Verdict::Accept),
+            ("false", Verdict::Accept),
+            ("\"a b\"", Verdict::Accept),
+            ("\"a", Verdict::More),
+            ("True", Verdict::Reject),
+        ]
+        .iter()
+        {
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(parser.update_slice(0, &tokens), *verdict, "{}", text);
+        }
+    }
+
+    #[test]
+    fn minimize() {
+        use crate::{Parser, Verdict};
+
+        let mut grammar: Grammar<char, CharMatcher> = grammar! {
+            s = a | b | c | s ',' a;
+            a = a1;
+            a1 = a2;
+            a2 = 'a' | 'x';
+            b = 'b' | 'b';
+            c = 'c' c;
+            unit = a;
+        };
+        assert_eq!(grammar.minimize(), 6);
+        let grammar = grammar.compile().expect("compilation should have worked");
+        for (name, rules) in [("s", 3), ("a2", 2), ("b", 1)].iter() {
+            assert_eq!(
+                grammar.rules_for_lhs(grammar.nt_id(name)).len(),
+                *rules,
+                "{}",
+                name
+            );
+        }
+        for name in ["a", "a1", "c", "unit"].iter() {
+            assert_eq!(grammar.nt_id(name), MAX_SYMBOL_ID, "{}", name);
+        }
+
+        let mut parser = Parser::new(grammar);
+        let tokens: Vec<char> = "a,x,a".chars().collect();
+        assert_eq!(parser.update_slice(0, &tokens), Verdict::Accept);
+    }
+
+    #[test]
+    fn extend_with() {
+        use crate::{Parser, Verdict};
+        use CharMatcher::*;
+
+        let semver: Grammar<char, CharMatcher> = grammar! {
+            version = number {Exact('.')} number {Exact('.')} number;
+            number = {Range('0', '9')}+;
+        };
+        let mut grammar: Grammar<char, CharMatcher> = grammar! {
+            dependency = name {Exact('=')} semver_version;
+            name = {Range('a', 'z')}+;
+        };
+        grammar
+            .extend_with("semver_", semver)
+            .expect("extending should have worked");
+
+        let number: Grammar<char, CharMatcher> = grammar! {
+            number = {Range('0', '9')};
+        };
+        assert!(matches!(
+            grammar.extend_with("semver_", number),
+            Err(Error::DuplicateSymbol(name)) if name == "semver_number"
+        ));
+
+        let grammar = grammar.compile().expect("compilation should have worked");
+        assert!(grammar.nt_id("number") == MAX_SYMBOL_ID);
+        let mut parser = Parser::new(grammar);
+        for (text, verdict) in [
+            ("serde=1.0.10", Verdict::Accept),
+            ("serde=1.0", Verdict::More),
+            ("serde=1.a", Verdict::Reject),
+        ]
+        .iter()
+        {
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(parser.update_slice(0, &tokens), *verdict, "{}", text);
+        }
+    }
+
+    #[test]
+    fn validate() {
+        use CharMatcher::*;
+
+        let grammar: Grammar<char, CharMatcher> = grammar! {
+            s = list | word | number | {Exact('-')} number;
+            list = ws list {Exact(',')} word | word;
+            ws = {Exact(' ')}*;
+            word = {Range('a', 'z')}+;
+            number = {Range('0', '9')}+ | {Exact('0')} {Exact('x')};
+            number = {Range('0', '9')}+;
+            unused = {Exact('u')};
+            endless = endless {Exact('e')};
+        };
+        let grammar = grammar.compile().expect("compilation should have worked");
+        let warnings = grammar.validate();
+        let rule = |lhs: &str, nth: usize| grammar.rules_for_lhs(grammar.nt_id(lhs))[nth];
+        for warning in [
+            Warning::HiddenLeftRecursion("list".to_string(), rule("list", 0)),
+            Warning::Unreachable("unused".to_string()),
+            Warning::Unreachable("endless".to_string()),
+            Warning::Unproductive("endless".to_string()),
+            Warning::DuplicateRule("number".to_string(), rule("number", 2), rule("number", 0)),
+            Warning::ShadowedTerminal(
+                "number".to_string(),
+                "Range('0', '9')".to_string(),
+                "Exact('0')".to_string(),
+            ),
+            Warning::ShadowedTerminal(
+                "number".to_string(),
+                "Exact('0')".to_string(),
+                "Range('0', '9')".to_string(),
+            ),
+        ]
+        .iter()
+        {
+            assert!(warnings.contains(warning), "{}", warning);
+        }
+        assert_eq!(warnings.len(), 7, "{:?}", warnings);
+        assert_eq!(
+            warnings[0].to_string(),
+            format!(
+                "rule {} of list is left-recursive after nullable symbols",
+                rule("list", 0)
+            )
+        );
+    }
+
+    #[test]
+    fn dead_symbols() {
+        use CharMatcher::Exact;
+
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("A"));
+        grammar.add(Rule::new("S").nt("L"));
+        grammar.add(Rule::new("A").t(Exact('a')));
+        grammar.add(Rule::new("L").nt("L").t(Exact('l')));
+        grammar.add(Rule::new("U").nt("A"));
+        let grammar = grammar.compile().expect("compilation should have worked");
+        let reachable = grammar.reachable_symbols();
+        let productive = grammar.productive_symbols();
+        for (name, r, p) in [
+            ("S", true, true),
+            ("A", true, true),
+            ("L", true, false),
+            ("U", false, true),
+        ]
+        .iter()
+        {
+            let id = grammar.nt_id(name) as usize;
+            assert_eq!((reachable[id], productive[id]), (*r, *p), "{}", name);
+        }
+        assert!(reachable[ERROR_ID as usize] && productive[ERROR_ID as usize]);
+    }
 }
@@ -0,0 +1,226 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Integration of external lexers
+//!
+//! By default, the editor parses characters. A [Lexer](trait.Lexer.html) splits the text into
+//! coarser tokens, e.g. words or numbers, and trivia like whitespace and comments. Only the
+//! tokens are entered into the editor. The trivia is kept in [Lexed](struct.Lexed.html) together
+//! with the source, so the text can be rendered and reconstructed exactly.
+
+use crate::span::SpanTable;
+
+/// Part of the input found by a lexer
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Lexeme<T> {
+    /// Token to be parsed
+    Token(T),
+    /// Text that the parser skips, e.g. whitespace or comments
+    Trivia,
+}
+
+/// Converter of raw text into tokens and trivia
+pub trait Lexer<T> {
+    /// Return the lexeme at the beginning of `input` and its length in bytes.
+    ///
+    /// `input` is never empty. Return None if no lexeme matches.
+    fn next_lexeme(&mut self, input: &str) -> Option<(Lexeme<T>, usize)>;
+}
+
+impl<T, F> Lexer<T> for F
+where
+    F: FnMut(&str) -> Option<(Lexeme<T>, usize)>,
+{
+    fn next_lexeme(&mut self, input: &str) -> Option<(Lexeme<T>, usize)> {
+        self(input)
+    }
+}
+
+/// Text split into tokens and trivia
+#[derive(Clone, Debug)]
+pub struct Lexed<T> {
+    /// The complete input
+    source: String,
+    /// Tokens without the trivia
+    tokens: Vec<T>,
+    /// Byte ranges of the tokens in the source
+    spans: SpanTable,
+}
+
+impl<T> Lexed<T> {
+    /// Split the input with the lexer.
+    ///
+    /// Return the byte offset of the remaining input if the lexer didn't find a lexeme.
+    pub fn new(lexer: &mut dyn Lexer<T>, input: &str) -> Result<Self, usize> {
+        let mut tokens = Vec::new();
+        let mut spans = SpanTable::new();
+        let mut offset = 0;
+        while offset < input.len() {
+            match lexer.next_lexeme(&input[offset..]) {
+                Some((lexeme, len)) if len > 0 && input.is_char_boundary(offset + len) => {
+                    if let Lexeme::Token(token) = lexeme {
+                        tokens.push(token);
+                        spans.push(offset..offset + len);
+                    }
+                    offset += len;
+                }
+                _ => return Err(offset),
+            }
+        }
+        Ok(Self {
+            source: input.to_string(),
+            tokens,
+            spans,
+        })
+    }
+
+    /// Tokens to be entered into the editor, without the trivia
+    pub fn tokens(&self) -> &[T] {
+        &self.tokens
+    }
+
+    /// Byte ranges of the tokens in the source
+    pub fn spans(&self) -> &SpanTable {
+        &self.spans
+    }
+
+    /// The complete input, including the trivia
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Source text of the token at the given index
+    pub fn token_text(&self, index: usize) -> Option<&str> {
+        self.spans.token(index).map(|range| &self.source[range])
+    }
+
+    /// Trivia between the token at the given index and its predecessor.
+    ///
+    /// The index after the last token returns the trailing trivia.
+    pub fn trivia_before(&self, index: usize) -> Option<&str> {
+        if index > self.tokens.len() {
+            return None;
+        }
+        let start = match index {
+            0 => 0,
+            _ => self.spans.token(index - 1)?.end,
+        };
+        let end = self
+            .spans
+            .token(index)
+            .map_or(self.source.len(), |range| range.start);
+        Some(&self.source[start..end])
+    }
+
+    /// Iterate through the source in order. Tokens are returned with their index, trivia
+    /// without.
+    ///
+    /// Concatenating the text of all pieces reconstructs the source.
+    pub fn pieces(&self) -> impl Iterator<Item = (Option<usize>, &str)> {
+        (0..=self.tokens.len()).flat_map(move |index| {
+            let trivia = self.trivia_before(index).filter(|t| !t.is_empty());
+            let token = self.token_text(index).map(|text| (Some(index), text));
+            trivia.map(|t| (None, t)).into_iter().chain(token)
+        })
+    }
+}
+
+/// Lexer that splits the text at whitespace into words
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WordLexer;
+
+impl Lexer<String> for WordLexer {
+    fn next_lexeme(&mut self, input: &str) -> Option<(Lexeme<String>, usize)> {
+        let is_space = input.starts_with(char::is_whitespace);
+        let len = input
+            .find(|c: char| c.is_whitespace() != is_space)
+            .unwrap_or(input.len());
+        if is_space {
+            Some((Lexeme::Trivia, len))
+        } else {
+            Some((Lexeme::Token(input[..len].to_string()), len))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{Grammar, Rule, SynchronousEditor};
+
+    #[test]
+    fn words_and_trivia() {
+        let source = "  let x =\t1 \n";
+        let lexed = Lexed::new(&mut WordLexer, source).expect("lexing should work");
+        assert_eq!(lexed.tokens(), ["let", "x", "=", "1"]);
+        assert_eq!(lexed.token_text(2), Some("="));
+        assert_eq!(lexed.trivia_before(0), Some("  "));
+        assert_eq!(lexed.trivia_before(3), Some("\t"));
+        assert_eq!(lexed.trivia_before(4), Some(" \n"));
+        assert_eq!(lexed.trivia_before(5), None);
+        assert_eq!(lexed.spans().token(1), Some(6..7));
+
+        let rebuilt: String = lexed.pieces().map(|(_, text)| text).collect();
+        assert_eq!(rebuilt, source);
+        let tokens: Vec<usize> = lexed.pieces().filter_map(|(index, _)| index).collect();
+        assert_eq!(tokens, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn lexer_error() {
+        let mut digits = |input: &str| match input.find(|c: char| !c.is_ascii_digit()) {
+            Some(0) => None,
+            len => Some((Lexeme::Token(()), len.unwrap_or(input.len()))),
+        };
+        assert!(Lexed::new(&mut digits, "123").is_ok());
+        assert_eq!(Lexed::new(&mut digits, "12a3").err(), Some(2));
+    }
+
+    #[test]
+    fn parse_words() {
+        let mut grammar = Grammar::<String, String>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("Let").t("1".to_string()));
+        grammar.add(Rule::new("Let").ts(["let", "x", "="].iter().map(|w| w.to_string())));
+        let mut editor =
+            SynchronousEditor::new(grammar.compile().expect("compilation should have worked"));
+
+        let lexed = Lexed::new(&mut WordLexer, "let  x = 1\n").expect("lexing should work");
+        editor.enter_iter(lexed.tokens().iter().cloned());
+        assert!(editor.is_accepted());
+
+        let let_id = editor.grammar().nt_id("Let");
+        let node = editor
+            .cst_iter()
+            .filter_map(|item| {
+                item.as_parsed()
+                    .map(|n| (n.lhs, n.byte_span(lexed.spans())))
+            })
+            .filter(|(lhs, _)| *lhs == let_id)
+            .last();
+        assert_eq!(node, Some((let_id, Some(0..8))));
+    }
+}
@@ -32,8 +32,9 @@
 //! requirements regarding the traits they need to implement, primarily `Clone` and `PartialEq`.
 //!
 //! The parser can rebuild its parsing chart on the fly, even partially. This allows it to be in
-//! interactive applications. At the moment, only the synchronous reparsing is implemented (i.e.
-//! reparsing after each change of the input buffer).
+//! interactive applications. The [editor](struct.SynchronousEditor.html) reparses synchronously
+//! after each change of the input buffer. To keep the user interface responsive, a
+//! [BackgroundParser](background/struct.BackgroundParser.html) parses on a worker thread instead.
 //!
 //! The parser generates a [Concrete Syntax Tree](https://en.wikipedia.org/wiki/Parse_tree)
 //! or parse tree on the fly too. The tree is ambiguous or a forest until the start symbol has been
@@ -77,30 +78,154 @@
 //! }
 //! let res = parser.update(position+1, 'n');
 //! assert_eq!(res, Verdict::Accept);
+//!
+//! // The start symbol spans the whole input
+//! let spans = parser.accepted_spans();
+//! assert_eq!((spans[0].start, spans[0].end), (0, 9));
+//!
+//! // Walk the parse tree. The root node comes last.
+//! let root = parser
+//!     .cst_iter()
+//!     .filter_map(|item| item.as_parsed().map(|node| (node.start, node.end)))
+//!     .last();
+//! assert_eq!(root, Some((0, 9)));
+//! ```
+//!
+//! # Debugging
+//!
+//! The library does not write to stdout or stderr, as the terminal might be owned by the
+//! application. The debug functions take a writer instead. A parser can also write the states of
+//! the chart to a sink whenever a token is rejected.
+//!
+//! ```
+//! # use sesd::{char::CharMatcher, Grammar, Parser, Rule};
+//! # let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+//! # grammar.set_start("S".to_string());
+//! # grammar.add(Rule::new("S").t(CharMatcher::Exact('a')));
+//! # let mut parser = Parser::new(grammar.compile().unwrap());
+//! parser.set_debug_sink(Some(Box::new(std::io::sink())));
+//! parser.set_debug_dump_on_reject(true);
+//! parser.update(0, 'b');
+//!
+//! let mut chart = Vec::new();
+//! parser.write_chart(&mut chart).unwrap();
+//! assert!(String::from_utf8(chart).unwrap().starts_with("chart[0]:"));
 //! ```
+//!
+//! # Features
+//!
+//! * `symbols-u16` (default): 16 bit [SymbolId](type.SymbolId.html)s.
+//! * `symbols-u32`: 32 bit [SymbolId](type.SymbolId.html)s for grammars with more than 65535
+//!   symbols or rules. Increases the memory used by the parser.
+//! * `ffi`: C interface in the module `ffi`.
+//! * `parallel`: Match tokens against wide chart columns on several threads, see
+//!   [Parser::set_parallel_scan](struct.Parser.html#method.set_parallel_scan).
+//!
+//! # Stability
+//!
+//! Enums that are expected to grow, e.g. [Error](enum.Error.html), [Verdict](enum.Verdict.html)
+//! and [CstIterItem](enum.CstIterItem.html), are marked `#[non_exhaustive]`. Adding variants to
+//! them, adding methods, and adding public fields to structs that cannot be constructed outside of
+//! the crate are considered additive changes. Removing or renaming items, changing signatures, and
+//! changing the meaning of existing variants are breaking changes.
+//!
+//! Prefer the accessors (e.g. [CstIterItem::as_parsed](enum.CstIterItem.html#method.as_parsed),
+//! [Error::is_too_large](enum.Error.html#method.is_too_large)) over exhaustive matches.
 
 #[macro_use]
 extern crate log;
 
+pub mod abnf;
+pub mod actions;
+pub mod background;
+pub mod bench;
+pub mod bnf;
 mod buffer;
 pub mod char;
+pub mod codegen;
+pub mod diff;
+pub mod disambiguation;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(test)]
+mod golden;
 mod grammar;
+pub mod lexer;
+pub mod observer;
 mod parser;
+pub mod prediction;
+pub mod remote;
+pub mod shrink;
+pub mod snippet;
+pub mod span;
+pub mod sppf;
 pub mod style_sheet;
+pub mod util;
 
 use buffer::Buffer;
 pub use grammar::{
-    CompiledGrammar, DottedRule, Error, Grammar, Matcher, Rule, Symbol, SymbolId, ERROR_ID,
+    check_tables, CompiledGrammar, DottedRule, Error, Grammar, GrammarSizes, Matcher,
+    MatcherDisplay, Rule, RuleTable, Symbol, SymbolId, Synthesize, Table, TerminalDispatch,
+    Warning, ERROR_ID,
+};
+pub use parser::{
+    AcceptedSpan, Ambiguity, AmbiguityReport, ChartItem, CstCursor, CstIter, CstIterItem,
+    CstIterItemNode, CstPath, DebugSink, DispatchBuilder, DotOptions, GrammarMut, ParseError,
+    Parser, ParserState, ParserStats, RecoveryPolicy, Repair, StateError, TokenNormalizer,
+    TokenSource, Verdict, MAX_DERIVATION_COUNT,
 };
-pub use parser::{CstIter, CstIterItem, CstIterItemNode, CstPath, Parser, Verdict};
+use snippet::{Snippet, SnippetStops};
+
+/// Predicate on single tokens
+type TokenPredicate<T> = Box<dyn Fn(&T) -> bool + Send>;
+
+/// Symbol, start and end of a node in the parse tree
+type NodeSpan = (SymbolId, usize, usize);
 
 /// Editor with synchronous parsing.
 ///
 /// Provides a buffer for tokens and a parser. Edit operation trigger a re-parse of the changed
 /// part of the buffer.
 ///
-/// The grammar is not meant to be changed on the fly, but there is no technical limitations
-/// against that. File a feature request if you need it.
+/// The grammar can be replaced with [set_grammar](#method.set_grammar), e.g. when the file type
+/// changes. The whole buffer is parsed again.
+///
+/// # Single-line mode
+///
+/// In single-line mode, the editor acts as an input field. Tokens matching the submit predicate are
+/// not entered into the buffer. Instead, they mark the input as submitted. Tokens rejected by the
+/// token filter are dropped in any mode.
+///
+/// ```
+/// use sesd::{char::CharMatcher, Grammar, Rule, SynchronousEditor};
+///
+/// use CharMatcher::*;
+/// let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+/// grammar.set_start("Command".to_string());
+/// grammar.add(Rule::new("Command").ts("quit".chars().map(Exact)));
+/// grammar.add(Rule::new("Command").ts("save".chars().map(Exact)));
+///
+/// let mut command_line = SynchronousEditor::new(grammar.compile().unwrap());
+/// command_line.set_single_line(true);
+/// command_line.set_submit_token(sesd::char::is_line_break);
+/// command_line.set_token_filter(sesd::char::is_not_control);
+///
+/// // Incomplete commands are not executed.
+/// command_line.enter_iter("sa\n".chars());
+/// assert!(command_line.take_submitted());
+/// assert!(!command_line.is_accepted());
+///
+/// command_line.enter_iter("ve\x07\n".chars());
+/// assert_eq!(command_line.as_string(), "save");
+/// assert!(command_line.take_submitted() && command_line.is_accepted());
+/// assert!(!command_line.take_submitted());
+///
+/// // Neither are misspelled ones, even if the parser recovered from the errors.
+/// command_line.clear();
+/// command_line.enter_iter("sxve\n".chars());
+/// assert!(command_line.take_submitted() && !command_line.is_accepted());
+/// ```
 pub struct SynchronousEditor<T, M>
 where
     M: Matcher<T>,
@@ -109,6 +234,16 @@ where
     buffer: Buffer<T>,
     /// Parser
     parser: Parser<T, M>,
+    /// Single-line mode is active
+    single_line: bool,
+    /// In single-line mode, tokens for which this predicate is true submit the input
+    submit_token: Option<TokenPredicate<T>>,
+    /// Tokens for which this predicate is false are not entered
+    token_filter: Option<TokenPredicate<T>>,
+    /// A submit token has been entered since the last call to take_submitted
+    submitted: std::cell::Cell<bool>,
+    /// Tab stops of the last inserted snippet
+    snippet_stops: SnippetStops,
 }
 
 impl<T, M> SynchronousEditor<T, M>
@@ -121,9 +256,116 @@ where
         Self {
             buffer: Buffer::new(),
             parser: Parser::new(grammar),
+            single_line: false,
+            submit_token: None,
+            token_filter: None,
+            submitted: std::cell::Cell::new(false),
+            snippet_stops: SnippetStops::default(),
         }
     }
 
+    /// Create a new editor with an empty buffer whose parser accepts the derivations of the
+    /// non-terminal `start`, see [Parser::with_start](struct.Parser.html#method.with_start).
+    pub fn with_start(mut grammar: CompiledGrammar<T, M>, start: &str) -> Result<Self, Error> {
+        grammar.set_start_by_name(start)?;
+        Ok(Self::new(grammar))
+    }
+
+    /// Switch single-line mode on or off.
+    pub fn set_single_line(&mut self, single_line: bool) {
+        self.single_line = single_line;
+    }
+
+    /// Set the predicate for tokens that submit the input in single-line mode.
+    pub fn set_submit_token<F>(&mut self, is_submit: F)
+    where
+        F: Fn(&T) -> bool + Send + 'static,
+    {
+        self.submit_token = Some(Box::new(is_submit));
+    }
+
+    /// Set the predicate for tokens to accept. Other tokens are dropped without changing the
+    /// buffer.
+    pub fn set_token_filter<F>(&mut self, accept: F)
+    where
+        F: Fn(&T) -> bool + Send + 'static,
+    {
+        self.token_filter = Some(Box::new(accept));
+    }
+
+    /// Normalize each token before the parser matches it. The buffer keeps the original tokens.
+    ///
+    /// Triggers a re-parse of the whole buffer.
+    pub fn set_token_normalizer<F>(&mut self, normalize: F)
+    where
+        F: Fn(&T) -> T + Send + 'static,
+    {
+        self.parser.set_token_normalizer(Box::new(normalize));
+        self.reparse(0);
+    }
+
+    /// Let the parser match the tokens as they are.
+    ///
+    /// Triggers a re-parse of the whole buffer.
+    pub fn clear_token_normalizer(&mut self) {
+        self.parser.clear_token_normalizer();
+        self.reparse(0);
+    }
+
+    /// Select how the parser continues after a token that doesn't match.
+    ///
+    /// Triggers a re-parse of the whole buffer.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.parser.set_recovery_policy(policy);
+        self.reparse(0);
+    }
+
+    /// Limit the number of chart entries of the parser, see
+    /// [Parser::set_chart_limit](struct.Parser.html#method.set_chart_limit).
+    ///
+    /// Continues parsing the buffer if the limit has been raised.
+    pub fn set_chart_limit(&mut self, limit: Option<usize>) {
+        self.parser.set_chart_limit(limit);
+        self.reparse(self.parser.valid_entries());
+    }
+
+    /// Limit the number of tokens in a row that the parser recovers from, see
+    /// [Parser::set_error_limit](struct.Parser.html#method.set_error_limit).
+    ///
+    /// Continues parsing the buffer if the limit has been raised.
+    pub fn set_error_limit(&mut self, limit: Option<usize>) {
+        self.parser.set_error_limit(limit);
+        self.reparse(self.parser.valid_entries());
+    }
+
+    /// Let the CST iterator follow a single derivation if the input is ambiguous, see
+    /// [disambiguation](disambiguation/index.html). The parse is not changed.
+    pub fn set_disambiguation(
+        &mut self,
+        strategy: Option<Box<dyn disambiguation::Disambiguation + Send>>,
+    ) {
+        self.parser.set_disambiguation(strategy);
+    }
+
+    /// Return whether the input has been submitted and reset the flag.
+    pub fn take_submitted(&self) -> bool {
+        self.submitted.replace(false)
+    }
+
+    /// Check if the whole buffer has been accepted by the parser without any errors.
+    pub fn is_accepted(&self) -> bool {
+        self.parser.accepted_at_without_errors(self.buffer.len())
+    }
+
+    /// Check if a token should go into the buffer. Set the submitted flag for submit tokens.
+    fn admit(&self, token: &T) -> bool {
+        if self.single_line && self.submit_token.as_ref().is_some_and(|f| f(token)) {
+            self.submitted.set(true);
+            return false;
+        }
+        self.token_filter.as_ref().is_none_or(|f| f(token))
+    }
+
     fn buffer_changed(&mut self) {
         self.parser.buffer_changed(self.buffer.cursor());
     }
@@ -131,6 +373,7 @@ where
     /// Remove all content from the token buffer.
     pub fn clear(&mut self) {
         self.buffer.clear();
+        self.snippet_stops.clear();
         self.buffer_changed();
     }
 
@@ -138,8 +381,13 @@ where
     ///
     /// Triggers a re-parse.
     pub fn enter(&mut self, token: T) {
+        if !self.admit(&token) {
+            return;
+        }
         let c = self.buffer.cursor();
         self.buffer.enter(token.clone());
+        self.snippet_stops.inserted(c, 1);
+        self.parser.buffer_replaced(c, 0, 1);
         self.reparse(c);
     }
 
@@ -147,8 +395,12 @@ where
     ///
     /// Triggers a re-parse.
     pub fn delete(&mut self, n: usize) {
+        let c = self.buffer.cursor();
+        let len = self.buffer.len();
         self.buffer.delete(n);
+        self.snippet_stops.deleted(c, c + n);
         let c = self.buffer.cursor();
+        self.parser.buffer_replaced(c, len - self.buffer.len(), 0);
         self.reparse(c);
     }
 
@@ -158,23 +410,39 @@ where
     fn reparse(&mut self, start: usize) {
         // Mark the buffer as changed at start, even if the rest has been deleted
         self.parser.buffer_changed(start);
-        for (i, t) in self.buffer.token_from_iter(start) {
-            self.parser.update(i, t.clone());
-        }
+        let valid_entries = self.parser.valid_entries();
+        self.parser.update_slice(
+            valid_entries,
+            self.buffer.span(valid_entries, self.buffer.len()),
+        );
     }
 
     /// Enter tokens as long as an iterator can provide them
     ///
     /// Triggers a re-parse at the end of the iterator.
     pub fn enter_iter<I>(&mut self, iter: I)
+    where
+        I: Iterator<Item = T>,
+    {
+        let c = self.buffer.cursor();
+        let inserted = self.enter_tokens(iter);
+        self.parser.buffer_replaced(c, 0, inserted);
+        self.reparse(c);
+    }
+
+    /// Enter the admitted tokens without reparsing. Return their number.
+    fn enter_tokens<I>(&mut self, iter: I) -> usize
     where
         I: Iterator<Item = T>,
     {
         let c = self.buffer.cursor();
         for t in iter {
-            self.buffer.enter(t);
+            if self.admit(&t) {
+                self.buffer.enter(t);
+            }
         }
-        self.reparse(c);
+        self.snippet_stops.inserted(c, self.buffer.cursor() - c);
+        self.buffer.cursor() - c
     }
 
     /// Move the cursor to the start of the buffer.
@@ -187,16 +455,56 @@ where
         self.parser.cst_iter()
     }
 
+    /// Create a new iterator that only traverses the nodes overlapping the buffer span from
+    /// `start` to `end`.
+    pub fn cst_iter_range(&self, start: usize, end: usize) -> CstIter<'_, T, M> {
+        self.parser.cst_iter_range(start, end)
+    }
+
+    /// Cursor at the root of the parse tree to navigate it structurally.
+    pub fn cst_cursor(&self) -> Option<CstCursor<'_, T, M>> {
+        self.parser.cst_cursor()
+    }
+
+    /// List the tokens in the buffer that didn't match, see
+    /// [Parser::errors](struct.Parser.html#method.errors).
+    pub fn errors(&self) -> Vec<ParseError<T, M>> {
+        self.parser.errors(self.tokens())
+    }
+
+    /// Innermost completed node of the parse tree that contains the token at `position`.
+    pub fn node_at(&self, position: usize) -> Option<CstIterItemNode> {
+        self.parser.node_at(position)
+    }
+
     /// Number of tokens in the buffer.
     pub fn len(&self) -> usize {
         self.buffer.len()
     }
 
+    /// Borrow all tokens in the buffer.
+    pub fn tokens(&self) -> &[T] {
+        self.buffer.span(0, self.buffer.len())
+    }
+
     /// Borrow the parser for reading.
     pub fn parser<'a>(&'a self) -> &Parser<T, M> {
         &self.parser
     }
 
+    /// Replace the grammar, re-parse the whole buffer and return the previous grammar.
+    pub fn set_grammar(&mut self, grammar: CompiledGrammar<T, M>) -> CompiledGrammar<T, M> {
+        let previous = self.parser.set_grammar(grammar);
+        self.reparse(0);
+        previous
+    }
+
+    /// Look up the terminals that match a token in an index, see
+    /// [Parser::set_terminal_dispatch](struct.Parser.html#method.set_terminal_dispatch)
+    pub fn set_terminal_dispatch(&mut self, build: Option<DispatchBuilder<T, M>>) {
+        self.parser.set_terminal_dispatch(build);
+    }
+
     /// Borrow the compiled grammar from inside the parser
     pub fn grammar<'a>(&'a self) -> &CompiledGrammar<T, M> {
         self.parser.grammar()
@@ -271,6 +579,84 @@ where
         self.parser.predictions(self.buffer.cursor())
     }
 
+    /// Non-terminals that end at the cursor position with their start positions, see
+    /// [Parser::completions_at](struct.Parser.html#method.completions_at)
+    pub fn completions_at_cursor(&self) -> Vec<(SymbolId, usize)> {
+        self.parser.completions_at(self.buffer.cursor())
+    }
+
+    /// Largest completed subtrees of the part of the buffer after the last accepted position, see
+    /// [Parser::tail_subtrees](struct.Parser.html#method.tail_subtrees)
+    pub fn tail_subtrees(&self) -> Vec<(SymbolId, usize, usize)> {
+        self.parser.tail_subtrees(self.tokens())
+    }
+
+    /// Tokens that could be inserted at the cursor position, see
+    /// [Parser::suggest_insertion](struct.Parser.html#method.suggest_insertion)
+    pub fn suggest_insertion_at_cursor(&self) -> Vec<T>
+    where
+        T: PartialEq,
+        M: Synthesize<T>,
+    {
+        self.parser.suggest_insertion(self.buffer.cursor())
+    }
+
+    /// Symbols of `stop` predicted at the cursor position, see
+    /// [Parser::expanded_predictions](struct.Parser.html#method.expanded_predictions)
+    pub fn expanded_predictions_at_cursor(&self, stop: &[SymbolId]) -> Vec<SymbolId> {
+        self.parser.expanded_predictions(self.buffer.cursor(), stop)
+    }
+
+    /// Terminals that could be typed at the cursor position, sorted and without duplicates
+    pub fn expected_terminals_at_cursor(&self) -> Vec<M>
+    where
+        M: Ord,
+    {
+        self.parser.expected_terminals(self.buffer.cursor())
+    }
+
+    /// Check if the tab stops of an inserted snippet can be navigated
+    pub fn is_snippet_active(&self) -> bool {
+        self.snippet_stops.is_active()
+    }
+
+    /// Move the cursor to the next tab stop of the snippet.
+    ///
+    /// Return false and leave snippet mode if the cursor was at the last stop or there is no
+    /// active snippet.
+    pub fn next_snippet_stop(&mut self) -> bool {
+        if self.snippet_stops.next() {
+            self.move_to_snippet_stop();
+            true
+        } else {
+            self.leave_snippet();
+            false
+        }
+    }
+
+    /// Move the cursor to the previous tab stop of the snippet.
+    ///
+    /// Return false if the cursor was at the first stop or there is no active snippet.
+    pub fn prev_snippet_stop(&mut self) -> bool {
+        if self.snippet_stops.prev() {
+            self.move_to_snippet_stop();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forget the tab stops of the inserted snippet.
+    pub fn leave_snippet(&mut self) {
+        self.snippet_stops.clear();
+    }
+
+    fn move_to_snippet_stop(&mut self) {
+        if let Some(stop) = self.snippet_stops.cursor() {
+            self.buffer.set_cursor(stop);
+        }
+    }
+
     /// Replace a section of the buffer by new tokens
     ///
     /// Place the cursor at the end of the inserted text and reparse from start.
@@ -279,8 +665,207 @@ where
         I: Iterator<Item = T>,
     {
         self.buffer.delete_range(start, end);
+        self.snippet_stops.deleted(start, end);
         self.buffer.set_cursor(start);
-        self.enter_iter(iter);
+        let inserted = self.enter_tokens(iter);
+        self.parser.buffer_replaced(start, end - start, inserted);
+        self.reparse(start);
+    }
+
+    /// Exchange the node at the cursor with its previous sibling.
+    ///
+    /// The node at the cursor is the innermost completed node whose symbol is in `symbols`. Its
+    /// siblings are the nodes of the same symbol that have the same closest ancestor with a
+    /// symbol in `symbols`. Nodes of the other symbols in `symbols` separate siblings, e.g. a
+    /// table header separates key-value pairs.
+    ///
+    /// Only the texts of the two nodes are exchanged. The text between them stays in place. The
+    /// cursor moves with the node. Return false and leave the buffer unchanged if there is no
+    /// sibling.
+    pub fn swap_with_prev_sibling(&mut self, symbols: &[SymbolId]) -> bool {
+        self.swap_with_sibling(symbols, false)
+    }
+
+    /// Exchange the node at the cursor with its next sibling.
+    ///
+    /// See [swap_with_prev_sibling](#method.swap_with_prev_sibling).
+    pub fn swap_with_next_sibling(&mut self, symbols: &[SymbolId]) -> bool {
+        self.swap_with_sibling(symbols, true)
+    }
+
+    fn swap_with_sibling(&mut self, symbols: &[SymbolId], next: bool) -> bool {
+        let (node, sibling) = match self.find_sibling(symbols, next) {
+            Some(pair) => pair,
+            None => return false,
+        };
+        let (first, second) = if next {
+            (node, sibling)
+        } else {
+            (sibling, node)
+        };
+
+        let mut tokens = self.buffer.span(second.1, second.2).to_vec();
+        tokens.extend_from_slice(self.buffer.span(first.2, second.1));
+        tokens.extend_from_slice(self.buffer.span(first.1, first.2));
+
+        // Keep the cursor at the same offset inside the moved node
+        let new_start = if next {
+            second.2 - (node.2 - node.1)
+        } else {
+            first.1
+        };
+        let cursor = new_start + self.buffer.cursor() - node.1;
+
+        self.replace(first.1, second.2, tokens.into_iter());
+        self.buffer.set_cursor(cursor);
+        true
+    }
+
+    /// Find the node at the cursor and its sibling.
+    fn find_sibling(&self, symbols: &[SymbolId], next: bool) -> Option<(NodeSpan, NodeSpan)> {
+        let grammar = self.parser.grammar();
+        let cursor = self.buffer.cursor();
+
+        // Collect the nodes with their closest ancestor. The CST is traversed children first,
+        // thus the first node containing the cursor is the innermost one.
+        let mut at_cursor = None;
+        let mut nodes = Vec::new();
+        for item in self.parser.cst_iter() {
+            if let CstIterItem::Parsed(node) = item {
+                if node.start == node.end || !grammar.dotted_is_completed(&node.dotted_rule) {
+                    continue;
+                }
+                let symbol = node.lhs;
+                if !symbols.contains(&symbol) {
+                    continue;
+                }
+                let ancestor = node
+                    .path
+                    .0
+                    .iter()
+                    .rev()
+                    .map(|n| {
+                        let (start, end) = self.parser.span(n);
+                        (
+                            grammar.lhs(self.parser.dotted_rule(n).rule as usize),
+                            start,
+                            end,
+                        )
+                    })
+                    .find(|n| symbols.contains(&n.0));
+                let entry = ((symbol, node.start, node.end), ancestor);
+                if at_cursor.is_none() && node.start <= cursor && cursor <= node.end {
+                    at_cursor = Some(entry);
+                }
+                nodes.push(entry);
+            }
+        }
+
+        let (node, ancestor) = at_cursor?;
+        let same_parent = nodes.iter().filter(|n| n.1 == ancestor).map(|n| n.0);
+        let sibling = if next {
+            same_parent.filter(|n| n.1 >= node.2).min_by_key(|n| n.1)
+        } else {
+            same_parent.filter(|n| n.2 <= node.1).max_by_key(|n| n.2)
+        }?;
+        if sibling.0 == node.0 {
+            Some((node, sibling))
+        } else {
+            None
+        }
+    }
+}
+
+/// Result of replacing the buffer content by [apply_external_edit](struct.SynchronousEditor.html#method.apply_external_edit).
+#[derive(Debug, PartialEq)]
+pub struct CursorMapReport {
+    /// Number of tokens at the start of the buffer that did not change.
+    pub prefix: usize,
+    /// Number of tokens at the end of the buffer that did not change.
+    pub suffix: usize,
+    /// Cursor position after the edit.
+    pub cursor: usize,
+    /// The cursor was inside the changed region. Its new position has been estimated.
+    pub approximate: bool,
+    /// The content has been changed.
+    pub changed: bool,
+}
+
+impl<T, M> SynchronousEditor<T, M>
+where
+    T: Clone + PartialEq,
+    M: Matcher<T> + Clone,
+{
+    /// Replace the whole buffer by the output of an external tool, e.g. a formatter.
+    ///
+    /// Only the part between the common prefix and the common suffix of the old and the new
+    /// content is replaced and reparsed. If the content did not change, nothing happens.
+    ///
+    /// The cursor keeps its place relative to the unchanged text. If it was inside the changed
+    /// region, it is moved proportionally inside the new region and the report is marked as
+    /// approximate.
+    pub fn apply_external_edit(&mut self, new_text_tokens: Vec<T>) -> CursorMapReport {
+        let old_len = self.buffer.len();
+        let new_len = new_text_tokens.len();
+        let cursor = self.buffer.cursor();
+
+        let (prefix, suffix) = {
+            let old = self.buffer.span(0, old_len);
+            let prefix = old
+                .iter()
+                .zip(new_text_tokens.iter())
+                .take_while(|(o, n)| o == n)
+                .count();
+            let max_suffix = std::cmp::min(old_len, new_len) - prefix;
+            let suffix = old
+                .iter()
+                .rev()
+                .zip(new_text_tokens.iter().rev())
+                .take(max_suffix)
+                .take_while(|(o, n)| o == n)
+                .count();
+            (prefix, suffix)
+        };
+
+        if prefix == old_len && prefix == new_len {
+            return CursorMapReport {
+                prefix,
+                suffix,
+                cursor,
+                approximate: false,
+                changed: false,
+            };
+        }
+
+        let old_end = old_len - suffix;
+        let new_end = new_len - suffix;
+        let (new_cursor, approximate) = if cursor <= prefix {
+            (cursor, false)
+        } else if cursor >= old_end {
+            (cursor + new_len - old_len, false)
+        } else {
+            let old_mid = old_end - prefix;
+            let new_mid = new_end - prefix;
+            (prefix + (cursor - prefix) * new_mid / old_mid, true)
+        };
+
+        self.replace(
+            prefix,
+            old_end,
+            new_text_tokens
+                .into_iter()
+                .skip(prefix)
+                .take(new_end - prefix),
+        );
+        self.buffer.set_cursor(new_cursor);
+
+        CursorMapReport {
+            prefix,
+            suffix,
+            cursor: new_cursor,
+            approximate,
+            changed: true,
+        }
     }
 }
 
@@ -300,3 +885,344 @@ where
         self.buffer.as_string()
     }
 }
+
+impl<M> SynchronousEditor<char, M>
+where
+    M: Matcher<char> + Clone,
+{
+    /// Insert the text of a snippet at the cursor and place the cursor at its first tab stop.
+    ///
+    /// The text is inserted as a whole and reparsed once. Like typed tokens, its characters pass
+    /// the token filter and, in single-line mode, the submit predicate. Its tab stops are
+    /// remembered and move with later edits until snippet mode is left. If the snippet has no tab
+    /// stops, the cursor is placed after the text.
+    pub fn insert_snippet(&mut self, snippet: &Snippet) {
+        let c = self.buffer.cursor();
+        // Offset of each character in the inserted text, which lacks the dropped characters
+        let mut offsets = Vec::new();
+        for t in snippet.text.chars() {
+            offsets.push(self.buffer.cursor() - c);
+            if self.admit(&t) {
+                self.buffer.enter(t);
+            }
+        }
+        offsets.push(self.buffer.cursor() - c);
+        self.parser.buffer_replaced(c, 0, self.buffer.cursor() - c);
+        self.reparse(c);
+        let stops: Vec<usize> = snippet.stops.iter().map(|s| offsets[*s]).collect();
+        self.snippet_stops = SnippetStops::new(&stops, c);
+        if let Some(stop) = self.snippet_stops.cursor() {
+            self.buffer.set_cursor(stop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::char::CharMatcher;
+    use super::grammar::tests::define_grammar;
+
+    fn editor(text: &str, cursor: usize) -> SynchronousEditor<char, CharMatcher> {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let mut editor = SynchronousEditor::new(grammar);
+        editor.enter_iter(text.chars());
+        editor.set_cursor(cursor);
+        editor
+    }
+
+    /// Check if the parse tree contains an error node.
+    fn has_error(editor: &SynchronousEditor<char, CharMatcher>) -> bool {
+        editor
+            .cst_iter()
+            .any(|item| item.as_parsed().is_some_and(|n| n.lhs == ERROR_ID))
+    }
+
+    #[test]
+    fn with_start() {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let mut editor =
+            SynchronousEditor::with_start(grammar, "NP").expect("NP should be a non-terminal");
+        editor.enter_iter("mary from denver ".chars());
+        assert!(editor.is_accepted());
+        editor.enter_iter("called john ".chars());
+        assert!(!editor.is_accepted());
+
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        assert!(SynchronousEditor::with_start(grammar, "Adj").is_err());
+    }
+
+    #[test]
+    fn normalized_whitespace() {
+        let mut editor = editor("john\tcalled mary ", 0);
+        assert!(has_error(&editor));
+
+        editor.set_token_normalizer(char::fold_whitespace);
+        assert!(!has_error(&editor));
+        assert!(editor.is_accepted());
+        assert_eq!(editor.tokens()[4], '\t');
+        assert_eq!(editor.span_string(0, 11), "john\tcalled");
+
+        editor.clear_token_normalizer();
+        assert!(has_error(&editor));
+    }
+
+    #[test]
+    fn normalized_case() {
+        let mut editor = editor("", 0);
+        editor.set_token_normalizer(char::ascii_lowercase);
+        editor.enter_iter("John called MARY ".chars());
+        assert!(!has_error(&editor));
+        assert!(editor.is_accepted());
+        assert_eq!(editor.as_string(), "John called MARY ");
+    }
+
+    /// Editor with the benchmark TOML grammar
+    fn toml_editor(text: &str, cursor: usize) -> SynchronousEditor<char, CharMatcher> {
+        let mut editor = SynchronousEditor::new(bench::toml_grammar(bench::GrammarShape::Classes));
+        editor.enter_iter(text.chars());
+        editor.set_cursor(cursor);
+        editor
+    }
+
+    #[test]
+    fn swap_array_values() {
+        let mut editor = toml_editor("a = [1, 22, 333]\n", 9);
+        let symbols = editor.grammar().nt_ids(&["val", "keyval", "table"]);
+
+        assert!(editor.swap_with_prev_sibling(&symbols));
+        assert_eq!(editor.as_string(), "a = [22, 1, 333]\n");
+        assert_eq!(editor.cursor(), 6);
+        assert!(editor.is_accepted());
+        assert!(!has_error(&editor));
+
+        // Swapping back restores the original
+        assert!(editor.swap_with_next_sibling(&symbols));
+        assert_eq!(editor.as_string(), "a = [1, 22, 333]\n");
+        assert_eq!(editor.cursor(), 9);
+
+        // The first element has no previous sibling, the last no next one
+        editor.set_cursor(5);
+        assert!(!editor.swap_with_prev_sibling(&symbols));
+        editor.set_cursor(13);
+        assert!(!editor.swap_with_next_sibling(&symbols));
+        assert_eq!(editor.as_string(), "a = [1, 22, 333]\n");
+    }
+
+    #[test]
+    fn swap_keyvals() {
+        let text = "a = 1\nbb = [2]\n[t]\nc = 3\n";
+        let mut editor = toml_editor(text, 8);
+        let symbols = editor.grammar().nt_ids(&["val", "keyval", "table"]);
+
+        assert!(editor.swap_with_prev_sibling(&symbols));
+        assert_eq!(editor.as_string(), "bb = [2]\na = 1\n[t]\nc = 3\n");
+        assert_eq!(editor.cursor(), 2);
+
+        // The table header separates the key-value pairs. Values of different keys are no
+        // siblings.
+        editor.set_cursor(20);
+        assert!(!editor.swap_with_prev_sibling(&symbols));
+        editor.set_cursor(13);
+        assert!(!editor.swap_with_prev_sibling(&symbols));
+        assert!(!editor.swap_with_next_sibling(&symbols));
+    }
+
+    #[test]
+    fn external_edit_cursor_in_prefix() {
+        let mut editor = editor("john called  mary ", 2);
+        let report = editor.apply_external_edit("john called mary ".chars().collect());
+        assert!(report.changed);
+        assert!(!report.approximate);
+        assert_eq!(report.cursor, 2);
+        assert_eq!(editor.as_string(), "john called mary ");
+    }
+
+    #[test]
+    fn external_edit_cursor_in_suffix() {
+        let mut editor = editor("john called  mary ", 14);
+        let report = editor.apply_external_edit("john called mary ".chars().collect());
+        assert!(report.changed);
+        assert!(!report.approximate);
+        assert_eq!(report.cursor, 13);
+        assert_eq!(editor.cursor(), 13);
+    }
+
+    #[test]
+    fn external_edit_cursor_in_middle() {
+        let mut editor = editor("john XXXX mary ", 7);
+        let report = editor.apply_external_edit("john called mary ".chars().collect());
+        assert!(report.changed);
+        assert!(report.approximate);
+        assert_eq!(report.prefix, 5);
+        assert_eq!(report.suffix, 6);
+        // Two of four changed tokens before the cursor map to three of six
+        assert_eq!(report.cursor, 8);
+        assert_eq!(editor.as_string(), "john called mary ");
+    }
+
+    #[test]
+    fn single_line_submit() {
+        let mut editor = editor("john called mary", 16);
+        editor.set_submit_token(super::char::is_line_break);
+
+        // Multi-line mode is unchanged
+        editor.enter('\n');
+        assert_eq!(editor.as_string(), "john called mary\n");
+        assert!(!editor.take_submitted());
+
+        editor.move_backward(1);
+        editor.delete(1);
+        editor.set_single_line(true);
+        editor.enter('\n');
+        assert_eq!(editor.as_string(), "john called mary");
+        assert!(editor.take_submitted());
+        assert!(!editor.take_submitted());
+        assert!(!editor.is_accepted());
+
+        editor.enter_iter(" \n".chars());
+        assert_eq!(editor.as_string(), "john called mary ");
+        assert!(editor.take_submitted());
+        assert!(editor.is_accepted());
+    }
+
+    #[test]
+    fn set_grammar() {
+        let mut editor = editor("john called mary ", 5);
+        assert!(editor.is_accepted());
+
+        let previous = editor.set_grammar(bench::toml_grammar(bench::GrammarShape::Classes));
+        assert!(!editor.is_accepted());
+        assert_eq!(editor.as_string(), "john called mary ");
+        assert_eq!(editor.cursor(), 5);
+
+        editor.set_grammar(previous);
+        assert!(editor.is_accepted());
+        assert!(!has_error(&editor));
+    }
+
+    #[test]
+    fn token_filter() {
+        let mut editor = editor("john ", 5);
+        editor.set_token_filter(super::char::is_not_control);
+        let valid_entries = editor.parser.valid_entries();
+        editor.enter('\x1b');
+        editor.enter_iter("\x07\x08".chars());
+        assert_eq!(editor.as_string(), "john ");
+        assert_eq!(editor.parser.valid_entries(), valid_entries);
+    }
+
+    #[test]
+    fn external_edit_unchanged() {
+        let mut editor = editor("john called mary ", 3);
+        let report = editor.apply_external_edit("john called mary ".chars().collect());
+        assert!(!report.changed);
+        assert!(!report.approximate);
+        assert_eq!(report.cursor, 3);
+        assert_eq!(editor.cursor(), 3);
+    }
+
+    #[test]
+    fn snippet_insert_multi_byte() {
+        let mut editor = editor("jöhn ", 5);
+        editor.insert_snippet(&Snippet::parse("called $1 → $2"));
+        assert_eq!(editor.as_string(), "jöhn called  → ");
+        assert_eq!(editor.cursor(), 12);
+        assert!(editor.next_snippet_stop());
+        assert_eq!(editor.cursor(), 15);
+    }
+
+    #[test]
+    fn snippet_admit() {
+        let mut editor = editor("", 0);
+        editor.set_single_line(true);
+        editor.set_submit_token(super::char::is_line_break);
+        editor.set_token_filter(super::char::is_not_control);
+
+        // Dropped characters don't move the stops
+        editor.insert_snippet(&Snippet::parse("$2 cal\x07led $1\n"));
+        assert_eq!(editor.as_string(), " called ");
+        assert!(editor.take_submitted());
+        assert_eq!(editor.cursor(), 8);
+        assert!(editor.next_snippet_stop());
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn snippet_stops_shift() {
+        let mut editor = editor("", 0);
+        editor.insert_snippet(&Snippet::parse("$2 called $1 "));
+        assert_eq!(editor.cursor(), 8);
+
+        // Typing at the first stop moves the second one if it is behind.
+        editor.enter_iter("mary".chars());
+        assert_eq!(editor.cursor(), 12);
+        assert!(editor.next_snippet_stop());
+        assert_eq!(editor.cursor(), 0);
+
+        // Typing at the second stop moves the first one.
+        editor.enter_iter("john".chars());
+        assert_eq!(editor.as_string(), "john called mary ");
+        assert!(editor.is_accepted());
+        assert!(editor.prev_snippet_stop());
+        assert_eq!(editor.cursor(), 16);
+        assert!(!editor.prev_snippet_stop());
+
+        // Deleting inside the stop shrinks it.
+        editor.move_backward(2);
+        editor.delete(2);
+        assert!(editor.next_snippet_stop());
+        assert!(editor.prev_snippet_stop());
+        assert_eq!(editor.cursor(), 14);
+    }
+
+    #[test]
+    fn snippet_mode_exit() {
+        // Leave explicitly
+        let mut editor = editor("", 0);
+        editor.insert_snippet(&Snippet::parse("$1 called $2"));
+        assert!(editor.is_snippet_active());
+        editor.leave_snippet();
+        assert!(!editor.is_snippet_active());
+        assert!(!editor.next_snippet_stop());
+        assert_eq!(editor.cursor(), 0);
+
+        // Edit outside of all stops
+        editor.clear();
+        editor.insert_snippet(&Snippet::parse("$1 called $2"));
+        editor.set_cursor(editor.len());
+        editor.enter(' ');
+        assert!(editor.is_snippet_active());
+        editor.set_cursor(3);
+        editor.enter('x');
+        assert!(!editor.is_snippet_active());
+
+        // Backspace at the start of a stop
+        editor.clear();
+        editor.insert_snippet(&Snippet::parse("john $1"));
+        editor.move_backward(1);
+        editor.delete(1);
+        assert!(!editor.is_snippet_active());
+
+        // Moving past the last stop
+        editor.clear();
+        editor.insert_snippet(&Snippet::parse("$1 called $2"));
+        assert!(editor.next_snippet_stop());
+        assert!(!editor.next_snippet_stop());
+        assert!(!editor.is_snippet_active());
+        assert_eq!(editor.cursor(), 8);
+
+        // Snippets without stops don't activate snippet mode
+        editor.clear();
+        editor.insert_snippet(&Snippet::plain("john"));
+        assert!(!editor.is_snippet_active());
+        assert_eq!(editor.cursor(), 4);
+    }
+}
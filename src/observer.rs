@@ -0,0 +1,120 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Hooks into the steps of the parser
+//!
+//! An observer registered with [Parser::set_observer](../struct.Parser.html#method.set_observer)
+//! is told about the chart entries each token created, e.g. to show the Earley algorithm step by
+//! step. The events of a token are reported after it has been processed: first the error, if
+//! any, then the entries of the new column in chart order.
+//!
+//! Entries that existed before, e.g. when a reparse reuses the chart after an edit, are not
+//! reported again.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::grammar::{DottedRule, SymbolId};
+
+/// Receiver of the steps of the parser. All methods do nothing by default.
+pub trait ParserObserver {
+    /// A rule has been predicted at `position`.
+    fn on_predict(&mut self, _position: usize, _rule: usize) {}
+
+    /// The token at `position` matched the terminal before the dot of the rule that started at
+    /// `start`.
+    fn on_scan(&mut self, _position: usize, _dotted_rule: &DottedRule, _start: usize) {}
+
+    /// A non-terminal has been completed. It covers the tokens at positions *p* with `start` <=
+    /// *p* < `end`.
+    fn on_complete(&mut self, _symbol: SymbolId, _start: usize, _end: usize) {}
+
+    /// The token at `position` didn't match any expected terminal.
+    fn on_error(&mut self, _position: usize) {}
+}
+
+/// Step of the parser as recorded by [EventLog](struct.EventLog.html)
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// See [ParserObserver::on_predict](trait.ParserObserver.html#method.on_predict)
+    Predict { position: usize, rule: usize },
+    /// See [ParserObserver::on_scan](trait.ParserObserver.html#method.on_scan)
+    Scan {
+        position: usize,
+        dotted_rule: DottedRule,
+        start: usize,
+    },
+    /// See [ParserObserver::on_complete](trait.ParserObserver.html#method.on_complete)
+    Complete {
+        symbol: SymbolId,
+        start: usize,
+        end: usize,
+    },
+    /// See [ParserObserver::on_error](trait.ParserObserver.html#method.on_error)
+    Error { position: usize },
+}
+
+/// Observer that records the events. Clones share the same log, so one clone can be given to the
+/// parser while the other one reads the events, also on another thread.
+#[derive(Clone, Debug, Default)]
+pub struct EventLog(Arc<Mutex<Vec<Event>>>);
+
+impl EventLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove and return the recorded events.
+    pub fn take(&self) -> Vec<Event> {
+        std::mem::take(&mut self.events())
+    }
+
+    /// Lock the recorded events. A panic while the lock was held doesn't lose them.
+    fn events(&self) -> MutexGuard<'_, Vec<Event>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ParserObserver for EventLog {
+    fn on_predict(&mut self, position: usize, rule: usize) {
+        self.events().push(Event::Predict { position, rule });
+    }
+
+    fn on_scan(&mut self, position: usize, dotted_rule: &DottedRule, start: usize) {
+        self.events().push(Event::Scan {
+            position,
+            dotted_rule: dotted_rule.clone(),
+            start,
+        });
+    }
+
+    fn on_complete(&mut self, symbol: SymbolId, start: usize, end: usize) {
+        self.events().push(Event::Complete { symbol, start, end });
+    }
+
+    fn on_error(&mut self, position: usize) {
+        self.events().push(Event::Error { position });
+    }
+}
@@ -24,25 +24,127 @@
 
 //! Earley Parser
 
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io::Write;
+
 use itertools::Itertools;
 
-use super::grammar::{CompiledGrammar, CompiledSymbol, DottedRule, Matcher, SymbolId, ERROR_ID};
+use super::actions::{RuleActions, SubtreeMemo};
+use super::disambiguation::{Alternative, Disambiguation};
+use super::grammar::{
+    CompiledGrammar, CompiledSymbol, DottedRule, Error, Matcher, MatcherDisplay, SymbolId,
+    Synthesize, TerminalDispatch, ERROR_ID,
+};
+use super::observer::ParserObserver;
+use super::span::SpanTable;
+use super::sppf::{DerivationNode, Family, NodeId, NodeLabel, Sppf};
+use super::util::{checkpoint, CancelToken, Cancelled};
+
+/// Start position of a chart entry in the token buffer.
+///
+/// 32 bit by default to save memory, which limits the parsed part of the buffer to 4G tokens.
+/// The feature `positions-usize` removes the limit.
+#[cfg(not(feature = "positions-usize"))]
+type Position = u32;
+
+/// Start position of a chart entry in the token buffer. The feature `positions-usize` has been
+/// selected to support very long buffers.
+#[cfg(feature = "positions-usize")]
+type Position = usize;
+
+/// Convert a buffer position to a chart start position.
+///
+/// The parser stops before the buffer grows beyond the range of `Position`, see
+/// [Verdict::LimitExceeded](enum.Verdict.html#variant.LimitExceeded).
+#[allow(clippy::unnecessary_cast)]
+fn pos(position: usize) -> Position {
+    position as Position
+}
+
+/// Convert a chart start position to a buffer position.
+#[allow(clippy::unnecessary_cast)]
+fn idx(position: Position) -> usize {
+    position as usize
+}
 
 /// Entry in the parsing chart. Dotted rule indicate next symbol to be parsed
 /// (terminal/non-terminal). Second field is start position in the token buffer.
 ///
-/// Position is usize as to not limit the length of the input buffer.
-///
-/// TODO: Limit the size of the input buffer.
-type ChartEntry = (DottedRule, usize);
+/// The size of the chart can be limited with
+/// [set_chart_limit](struct.Parser.html#method.set_chart_limit).
+type ChartEntry = (DottedRule, Position);
 
 /// Ordered list of states for one position of the token buffer.
 type StateList = Vec<ChartEntry>;
 
+/// Temporary lists of [update](struct.Parser.html#method.update), kept to reuse their memory
+#[derive(Default)]
+struct Scratch {
+    states: StateList,
+    children: CstList,
+    siblings: CstList,
+}
+
+/// Columns of the chart after an edit that may be reused by the following reparse.
+///
+/// The edit replaced the tokens from `edit_start` to `old_end` (exclusive) by `inserted` tokens.
+/// Positions of the old parse at or after `old_end` are moved by the difference.
+struct Suffix {
+    edit_start: usize,
+    old_end: usize,
+    inserted: usize,
+
+    /// Old position of the first saved column
+    first: usize,
+    chart: Arena<ChartEntry>,
+    cst: Arena<CstEdge>,
+}
+
+impl Suffix {
+    /// Map a position of the old parse to the new one. Positions inside the edit have no
+    /// counterpart.
+    fn map(&self, old: usize) -> Option<usize> {
+        if old < self.edit_start {
+            Some(old)
+        } else if old >= self.old_end {
+            Some(old - self.old_end + self.edit_start + self.inserted)
+        } else {
+            None
+        }
+    }
+}
+
+/// Saved parse of a [Parser](struct.Parser.html).
+///
+/// Created by [save_state](struct.Parser.html#method.save_state) and applied by
+/// [restore_state](struct.Parser.html#method.restore_state). With the feature `serde`, the state
+/// can be serialized, e.g. to avoid parsing long documents again when they are reopened.
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParserState {
+    /// Fingerprint of the grammar the state was created with
+    grammar: u64,
+    chart: Arena<ChartEntry>,
+    cst: Arena<CstEdge>,
+    valid_entries: usize,
+}
+
+/// Reasons why a [ParserState](struct.ParserState.html) cannot be restored
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum StateError {
+    /// The state was created with a different grammar
+    GrammarMismatch,
+    /// The chart or the parse tree refer to entries that do not exist
+    Inconsistent,
+}
+
 /// Entry in the parse tree.
 ///
 /// The node of the tree are the parse state entries in the chart. The edges are stored separately.
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct CstEdge {
     /// Index into StateList at the buffer position where the edge originates.
     ///
@@ -59,14 +161,161 @@ struct CstEdge {
 /// List of edges at a given buffer position
 type CstList = Vec<CstEdge>;
 
+/// Per-position lists stored in one contiguous vector.
+///
+/// Column `p` occupies `entries[offsets[p]..offsets[p + 1]]`, the last column extends to the end
+/// of `entries`. Only the last column can grow, which matches how the chart is built.
+#[derive(PartialEq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Arena<E> {
+    entries: Vec<E>,
+    offsets: Vec<usize>,
+}
+
+impl<E> Arena<E> {
+    /// Create an arena with a single column.
+    fn new(column: Vec<E>) -> Self {
+        Self {
+            entries: column,
+            offsets: vec![0],
+        }
+    }
+
+    /// Number of columns
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Total number of entries in all columns
+    fn entry_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Range of `entries` that make up a column
+    fn range(&self, column: usize) -> std::ops::Range<usize> {
+        let end = match self.offsets.get(column + 1) {
+            Some(end) => *end,
+            None => self.entries.len(),
+        };
+        self.offsets[column]..end
+    }
+
+    /// Keep the first `columns` columns and drop the rest.
+    fn truncate(&mut self, columns: usize) {
+        if columns < self.offsets.len() {
+            self.entries.truncate(self.offsets[columns]);
+            self.offsets.truncate(columns);
+        }
+    }
+
+    /// Append a new column.
+    fn push_column<I: IntoIterator<Item = E>>(&mut self, column: I) {
+        self.offsets.push(self.entries.len());
+        self.entries.extend(column);
+    }
+
+    /// Keep the first `len` entries of the last column.
+    fn truncate_last(&mut self, len: usize) {
+        let start = self.offsets[self.offsets.len() - 1];
+        self.entries.truncate(start + len);
+    }
+
+    /// Append an entry to the last column.
+    fn push(&mut self, entry: E) {
+        self.entries.push(entry);
+    }
+
+    /// Iterate over the columns.
+    fn iter(&self) -> impl Iterator<Item = &[E]> {
+        (0..self.len()).map(move |column| &self[column])
+    }
+
+    /// Move the columns beginning with `column` into a new arena.
+    fn split_off(&mut self, column: usize) -> Self {
+        let start = self.offsets[column];
+        let entries = self.entries.split_off(start);
+        let offsets = self
+            .offsets
+            .split_off(column)
+            .into_iter()
+            .map(|offset| offset - start)
+            .collect();
+        Self { entries, offsets }
+    }
+
+    /// Check that the offsets describe valid columns, e.g. after deserialization.
+    fn is_consistent(&self) -> bool {
+        self.offsets.first() == Some(&0)
+            && self.offsets.windows(2).all(|w| w[0] <= w[1])
+            && self.offsets[self.offsets.len() - 1] <= self.entries.len()
+    }
+}
+
+impl<E: PartialEq> Arena<E> {
+    /// Add an entry to the last column if the entry does not already exist.
+    ///
+    /// Return the index into the last column.
+    fn add(&mut self, entry: E) -> SymbolId {
+        let start = self.offsets[self.offsets.len() - 1];
+        match self.entries[start..].iter().position(|e| *e == entry) {
+            Some(i) => i as SymbolId,
+            None => {
+                self.entries.push(entry);
+                state_id(self.entries.len() - 1 - start)
+            }
+        }
+    }
+}
+
+impl<E> std::ops::Index<usize> for Arena<E> {
+    type Output = [E];
+
+    fn index(&self, column: usize) -> &[E] {
+        &self.entries[self.range(column)]
+    }
+}
+
+impl<E> std::ops::IndexMut<usize> for Arena<E> {
+    fn index_mut(&mut self, column: usize) -> &mut [E] {
+        let range = self.range(column);
+        &mut self.entries[range]
+    }
+}
+
+/// Map a token to the value that is handed to the matchers
+pub type TokenNormalizer<T> = Box<dyn Fn(&T) -> T + Send>;
+
+/// Provide the token at a position of the buffer, or `None` if the buffer is shorter, see
+/// [Parser::set_token_source](struct.Parser.html#method.set_token_source)
+pub type TokenSource<T> = Box<dyn FnMut(usize) -> Option<T> + Send>;
+
+/// Destination of debug output
+pub type DebugSink = Box<dyn Write + Send>;
+
+/// Finder of the entries of a column that match a token, see
+/// [Parser::set_parallel_scan](struct.Parser.html#method.set_parallel_scan)
+#[cfg(feature = "parallel")]
+type ScanFinder<T, M> = fn(&Parser<T, M>, usize, usize, &T) -> Vec<usize>;
+
+/// Constructor of the terminal index of a grammar, see
+/// [Parser::set_terminal_dispatch](struct.Parser.html#method.set_terminal_dispatch)
+pub type DispatchBuilder<T, M> = fn(&CompiledGrammar<T, M>) -> Box<dyn TerminalDispatch<T> + Send>;
+
+/// Terminal index of a grammar and its constructor
+type TerminalIndex<T, M> = (Box<dyn TerminalDispatch<T> + Send>, DispatchBuilder<T, M>);
+
+/// Writer for dotted rules, see [CompiledGrammar::write_dotted_rule](struct.CompiledGrammar.html#method.write_dotted_rule)
+type DottedRuleWriter<T, M> =
+    fn(&CompiledGrammar<T, M>, &mut dyn Write, &DottedRule) -> std::io::Result<()>;
+
 /// Earley Parser on streams.
 ///
 /// Incrementally parse the input steam using the Earley Algorithm. Does not store any parsed
 /// tokens itself. If the parsed tokens cannot be reconstructed from a successful parse, they need
 /// to be stored separately.
 ///
-/// It is technically possible to change the grammar on the fly, but not implemented. File a
-/// feature request if you need that.
+/// The grammar can be changed on the fly with [grammar_mut](#method.grammar_mut) or replaced with
+/// [set_grammar](#method.set_grammar). This restarts the parse.
 pub struct Parser<T, M>
 where
     M: Matcher<T>,
@@ -74,6 +323,9 @@ where
     /// Compiled grammar to parse.
     grammar: CompiledGrammar<T, M>,
 
+    /// Non-terminals that derive the empty string. Index is the symbol ID.
+    nullable: Vec<bool>,
+
     /// Parsing chart.
     ///
     /// Outer dimension index corresponds to buffer position. Inner dimensions are the possible
@@ -81,16 +333,12 @@ where
     ///
     /// chart[0] contains the rules that derive directly or indirectly from the start symbol. In
     /// general, chart[i+1] contain the rules that apply after buffer[i] has been processed.
-    ///
-    /// TODO: Flatten this array
-    chart: Vec<StateList>,
+    chart: Arena<ChartEntry>,
 
     /// Nodes of the parse tree.
     ///
     /// Uses the same indexing as chart.
-    ///
-    /// TODO: Flatten this array
-    cst: Vec<CstList>,
+    cst: Arena<CstEdge>,
 
     /// Number of buffer entries (from the beginning) where the parse is valid.
     ///
@@ -100,10 +348,133 @@ where
     /// The value is to interpreted as the index into the chart from which the scanner reads to
     /// check if the current token matches.
     valid_entries: usize,
+
+    /// Applied to each token before it is matched against the terminals
+    normalizer: Option<TokenNormalizer<T>>,
+
+    /// Tokens to parse when an update skips ahead, see [set_token_source](#method.set_token_source)
+    token_source: Option<TokenSource<T>>,
+
+    /// Destination of debug output. Nothing is written if there is none.
+    debug_sink: std::cell::RefCell<Option<DebugSink>>,
+
+    /// If set, write the states of a column to the debug sink when its token is rejected
+    dump_on_reject: Option<DottedRuleWriter<T, M>>,
+
+    /// If set, the CST iterator follows only one derivation
+    disambiguation: Option<Box<dyn Disambiguation + Send>>,
+
+    /// Receiver of the steps of the parser, see [set_observer](#method.set_observer)
+    observer: Option<Box<dyn ParserObserver + Send>>,
+
+    /// Columns of the parse before the last edit, see
+    /// [buffer_replaced](#method.buffer_replaced)
+    suffix: Option<Suffix>,
+
+    /// Handling of tokens that don't match
+    recovery: RecoveryPolicy,
+
+    /// Maximal number of chart entries, see [set_chart_limit](#method.set_chart_limit)
+    chart_limit: Option<usize>,
+
+    /// Maximal number of rejected tokens in a row, see [set_error_limit](#method.set_error_limit)
+    error_limit: Option<usize>,
+
+    /// Number of rejected tokens in a row and the position after the last one
+    error_run: (usize, usize),
+
+    /// Counters of the work done, see [stats](#method.stats). The entry counts are empty.
+    counters: ParserStats,
+
+    /// Position of the last [commit](#method.commit)
+    committed: usize,
+
+    /// Terminals that begin each non-terminal if the predictions are pruned, see
+    /// [set_lookahead](#method.set_lookahead)
+    first_terminals: Option<Vec<Vec<usize>>>,
+
+    /// Index of the terminals that match a token and its constructor, see
+    /// [set_terminal_dispatch](#method.set_terminal_dispatch)
+    terminal_dispatch: Option<TerminalIndex<T, M>>,
+
+    /// Thread pool, minimal column width and finder of the parallel scan
+    #[cfg(feature = "parallel")]
+    parallel_scan: Option<(std::sync::Arc<rayon::ThreadPool>, usize, ScanFinder<T, M>)>,
+}
+
+/// Counters of the work done by a parser, see [Parser::stats](struct.Parser.html#method.stats)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParserStats {
+    /// Number of chart entries per position of the current parse
+    pub items_per_position: Vec<usize>,
+    /// Number of chart entries per rule of the current parse. Index is the rule index.
+    pub items_per_rule: Vec<usize>,
+    /// Number of entries that predicted a non-terminal
+    pub predictions: usize,
+    /// Number of completed entries that advanced their parents
+    pub completions: usize,
+    /// Number of tokens that didn't match any expected terminal
+    pub scan_failures: usize,
+    /// Maximal memory used by the chart and the parse tree, in bytes
+    pub peak_memory: usize,
+}
+
+/// What the parser does with a token that none of the expected terminals matches.
+///
+/// Each strategy marks the token with an entry of the error pseudo-rule
+/// [ERROR_ID](constant.ERROR_ID.html) in the parse tree, except `Reject`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub enum RecoveryPolicy {
+    /// Pretend that each expected terminal matched. This is the default.
+    #[default]
+    PretendMatch,
+    /// Ignore the token. The constructs in progress continue after it.
+    SkipToken,
+    /// Insert one of the expected terminals before the token. The inserted terminal doesn't cover
+    /// any tokens. If the token doesn't match after any insertion, fall back to `PretendMatch`.
+    InsertExpected,
+    /// Close the innermost unfinished construct of one of the given non-terminals as an error and
+    /// skip tokens until one can follow it. Fall back to `PretendMatch` if there is no such
+    /// construct.
+    Synchronize(Vec<SymbolId>),
+    /// Don't recover. The rest of the buffer is not parsed.
+    Reject,
+    /// Choose between inserting an expected terminal before the token, replacing the token by an
+    /// expected terminal, and deleting it, so that the following tokens parse with the fewest
+    /// edits. Up to the given number of edits are combined to parse the next few tokens. Only the
+    /// first edit is applied, later errors are repaired by a new search.
+    ///
+    /// The following tokens are only known to [update_slice](struct.Parser.html#method.update_slice).
+    /// Otherwise, insertion is preferred over replacement. If no combination of edits parses the
+    /// tokens, fall back to `PretendMatch`.
+    MinimalEdit(usize),
+}
+
+/// Number of tokens that need to parse after the edits of
+/// [RecoveryPolicy::MinimalEdit](enum.RecoveryPolicy.html#variant.MinimalEdit)
+const REPAIR_LOOKAHEAD: usize = 4;
+
+/// Edit of the input that the parser assumed to recover from an error, see
+/// [ParseError](struct.ParseError.html)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Repair {
+    /// An expected terminal has been inserted before the token
+    Insert,
+    /// The token has been taken for each of the expected terminals
+    Replace,
+    /// The token has been skipped
+    Delete,
+    /// An unfinished construct has been closed and tokens have been skipped
+    Synchronize,
+    /// The parser didn't recover
+    Reject,
 }
 
 /// Result of parser update.
 #[derive(PartialEq, Debug)]
+#[non_exhaustive]
 pub enum Verdict {
     /// Buffer position to continue parsing was incorrect.
     InvalidPosition,
@@ -116,6 +487,18 @@ pub enum Verdict {
 
     /// There are no terminals for the next update to match. Input has been rejected.
     Reject,
+
+    /// The chart is full, see [Parser::set_chart_limit](struct.Parser.html#method.set_chart_limit),
+    /// too many tokens in a row have been rejected, see
+    /// [Parser::set_error_limit](struct.Parser.html#method.set_error_limit), or the buffer is too
+    /// long for the position type (see feature `positions-usize`). The token
+    /// has not been parsed.
+    ///
+    /// The tokens before it stay parsed, including those that needed an error recovery, so
+    /// [Parser::valid_entries](struct.Parser.html#method.valid_entries) counts them. The parse
+    /// tree however ends where the start symbol was last completed. If the rejected tokens don't
+    /// complete it, the CST iterator reports them as unparsed along with the rest of the buffer.
+    LimitExceeded,
 }
 
 /// Identify a node in a CST path
@@ -140,6 +523,9 @@ pub struct CstIterItemNode {
     pub end: usize,
     /// The dotted rule that applied. The dot is right before the parser item.
     pub dotted_rule: DottedRule,
+    /// Left hand side of the rule, i.e. the parsed non-terminal if the rule is completed.
+    /// [ERROR_ID](constant.ERROR_ID.html) for error nodes.
+    pub lhs: SymbolId,
     /// Path from the root of the parse tree to this node.
     ///
     /// Only contains completed rules. Does not contain the path node to locate the current node.
@@ -150,6 +536,7 @@ pub struct CstIterItemNode {
 
 /// Returned by the `CstIter` for each parsed element.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum CstIterItem {
     /// A node of the parse tree
     Parsed(CstIterItemNode),
@@ -158,9 +545,158 @@ pub enum CstIterItem {
     Unparsed(usize),
 }
 
+impl CstIterItem {
+    /// Return the node if this item is a node of the parse tree
+    pub fn as_parsed(&self) -> Option<&CstIterItemNode> {
+        match self {
+            CstIterItem::Parsed(node) => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Return the start of the unparsed part of the buffer if this item marks it
+    pub fn unparsed_start(&self) -> Option<usize> {
+        match self {
+            CstIterItem::Unparsed(start) => Some(*start),
+            _ => None,
+        }
+    }
+}
+
+/// Derivation counts are capped at this value to avoid exponential blowup in highly ambiguous
+/// grammars.
+pub const MAX_DERIVATION_COUNT: usize = 1000;
+
+/// Ambiguously parsed part of the input
+#[derive(Debug, PartialEq)]
+pub struct Ambiguity {
+    /// Non-terminal that has been parsed ambiguously
+    pub symbol: SymbolId,
+    /// Start position of the ambiguous parse
+    pub start: usize,
+    /// End position of the ambiguous parse
+    pub end: usize,
+    /// Number of distinct derivations, at most `MAX_DERIVATION_COUNT`
+    pub derivations: usize,
+    /// Indices of the rules that derive the symbol
+    pub rules: Vec<usize>,
+    /// Positions where the derivations diverge, i.e. where alternative last children start
+    pub split_points: Vec<usize>,
+}
+
+/// Completed derivation of the start symbol, see
+/// [Parser::accepted_spans](struct.Parser.html#method.accepted_spans)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AcceptedSpan {
+    /// Index of the start rule
+    pub rule: usize,
+    /// Start position of the derivation
+    pub start: usize,
+    /// End position of the derivation
+    pub end: usize,
+}
+
+/// Entry of the parsing chart, see [Parser::chart_items](struct.Parser.html#method.chart_items)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChartItem {
+    /// Position of the column in the buffer
+    pub position: usize,
+    /// Rule and the number of symbols parsed so far
+    pub dotted_rule: DottedRule,
+    /// Position where the rule has been predicted
+    pub origin: usize,
+}
+
+/// Token that none of the expected terminals matched, see
+/// [Parser::errors](struct.Parser.html#method.errors)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError<T, M> {
+    /// Position of the token in the buffer
+    pub position: usize,
+    /// The token
+    pub found: T,
+    /// Terminals that would have matched, in the order of their declaration
+    pub expected: Vec<M>,
+    /// Non-terminal whose construct contains the error, or the construct that has been closed by
+    /// [RecoveryPolicy::Synchronize](enum.RecoveryPolicy.html#variant.Synchronize).
+    /// [ERROR_ID](constant.ERROR_ID.html) if the parser didn't recover.
+    pub recovered_as: SymbolId,
+    /// The edit that the parser assumed
+    pub repair: Repair,
+}
+
+/// List of ambiguities of a parse.
+///
+/// Only the nodes where derivations diverge are reported, not their parents.
+#[derive(Debug, Default)]
+pub struct AmbiguityReport {
+    /// Ambiguities in order of their end positions
+    pub ambiguities: Vec<Ambiguity>,
+}
+
+/// Options for [Parser::write_dot](struct.Parser.html#method.write_dot)
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    /// Name of the graph. Defaults to `cst` if empty.
+    pub name: String,
+    /// Draw the order in which the CST iterator visits the entries as numbered red edges.
+    pub preorder: bool,
+    /// Leave out the chart entries without CST edges, e.g. predictions that didn't match.
+    pub linked_only: bool,
+}
+
+/// Number of ambiguities listed by `AmbiguityReport::write_summary`
+const SUMMARY_LEN: usize = 10;
+
+/// Mutable access to the grammar of a parser, see [Parser::grammar_mut](struct.Parser.html#method.grammar_mut).
+///
+/// Dropping the guard resets the parse chart.
+pub struct GrammarMut<'a, T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    parser: &'a mut Parser<T, M>,
+}
+
+impl<'a, T, M> std::ops::Deref for GrammarMut<'a, T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    type Target = CompiledGrammar<T, M>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.parser.grammar
+    }
+}
+
+impl<'a, T, M> std::ops::DerefMut for GrammarMut<'a, T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.parser.grammar
+    }
+}
+
+impl<'a, T, M> Drop for GrammarMut<'a, T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    fn drop(&mut self) {
+        self.parser.reset_chart();
+    }
+}
+
 /// Iterator to access the parse tree in pre-order.
 ///
 /// Returns all parsed nodes, then the index of the first unparsed position of the buffer.
+///
+/// Subtrees are visited left to right. The order only depends on the chart and the
+/// disambiguation strategy, so iterating twice over the same parse yields the same sequence.
 pub struct CstIter<'a, T, M>
 where
     M: Matcher<T>,
@@ -177,6 +713,41 @@ where
 
     /// State: Has unparsed been returned
     done: bool,
+
+    /// Apply the disambiguation strategy of the parser
+    single_tree: bool,
+
+    /// If set, skip the nodes that don't overlap this buffer span
+    range: Option<(usize, usize)>,
+}
+
+/// Cursor to navigate the completed nodes of the parse tree.
+///
+/// The children of a node are computed when the cursor moves down to them. Terminals are not
+/// nodes of their own.
+pub struct CstCursor<'a, T, M>
+where
+    M: Matcher<T>,
+{
+    /// The parser
+    parser: &'a Parser<T, M>,
+
+    /// Nodes of each level from the root to the current node, with the index of the current one
+    levels: Vec<(Vec<CstPathNode>, usize)>,
+}
+
+/// Convert the index of a new entry in a column to a state id.
+///
+/// Panics if the column has more entries than state ids, instead of silently wrapping around. The
+/// number of states can be raised with the feature `symbols-u32` and the chart can be limited with
+/// [Parser::set_chart_limit](struct.Parser.html#method.set_chart_limit).
+fn state_id(index: usize) -> SymbolId {
+    SymbolId::try_from(index).unwrap_or_else(|_| {
+        panic!(
+            "Chart column has more than {} entries. Enable the feature symbols-u32.",
+            SymbolId::MAX
+        )
+    })
 }
 
 /// Add an entry to a state list if the entry does not already exist.
@@ -188,9 +759,32 @@ fn add_to_state_list(state_list: &mut StateList, entry: ChartEntry) -> SymbolId
             return i as SymbolId;
         }
     }
-    let res = state_list.len();
+    let res = state_id(state_list.len());
     state_list.push(entry);
-    res as SymbolId
+    res
+}
+
+/// Indices of the entries whose terminal matches the token.
+///
+/// Each terminal is matched at most once, however many entries expect it.
+fn matching_entries<'a, T, M>(
+    grammar: &'a CompiledGrammar<T, M>,
+    states: &'a [ChartEntry],
+    token: &'a T,
+) -> impl Iterator<Item = usize> + 'a
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    let nt_count = grammar.nt_count();
+    // Result by terminal index
+    let mut matched: Vec<Option<bool>> = vec![None; grammar.terminals().len()];
+    states.iter().enumerate().filter_map(move |(i, (dr, _))| {
+        let t = (*grammar.rhs(dr.rule as usize).get(dr.dot())? as usize).checked_sub(nt_count)?;
+        matched[t]
+            .get_or_insert_with(|| grammar.terminals()[t].matches(token.clone()))
+            .then_some(i)
+    })
 }
 
 /// Add an entry to the CST edge list if the entry does not already exist.
@@ -204,243 +798,1119 @@ fn add_to_cst_list(cst_list: &mut CstList, entry: CstEdge) {
 }
 
 /// Predict function of the Earley Algorithm.
+///
+/// The predictions are added to the last column of the chart.
 fn predict<T, M>(
-    state_list: &mut StateList,
+    chart: &mut Arena<ChartEntry>,
     symbol: SymbolId,
     dot_buffer: usize,
     grammar: &CompiledGrammar<T, M>,
 ) where
     M: Matcher<T> + Clone,
 {
-    for i in 0..grammar.rule_count() {
-        if grammar.lhs_is(i, symbol) {
-            let new_entry = (DottedRule::new(i), dot_buffer);
-            add_to_state_list(state_list, new_entry);
-        }
+    for i in grammar.rules_for_lhs(symbol) {
+        let new_entry = (DottedRule::new(*i), pos(dot_buffer));
+        chart.add(new_entry);
     }
 }
 
-impl<T, M> Parser<T, M>
+/// Predict and complete the state list at `position` of the chart, which must be the last one.
+///
+/// The predictor moves the dot over nullable non-terminals right away (Aycock and Horspool).
+/// Otherwise, an entry added after a nullable non-terminal has been completed at the same position
+/// would never see that completion.
+///
+/// This will usually grow the state list. Thus, indexed access is required. Child edges of the CST
+/// are added to `cst_child_list`, sibling edges to `cst_sibling_list`. The caller concatenates
+/// them in this order.
+///
+/// Return whether a start rule has been completed.
+fn predict_and_complete<T, M>(
+    grammar: &CompiledGrammar<T, M>,
+    nullable: &[bool],
+    chart: &mut Arena<ChartEntry>,
+    position: usize,
+    cst_child_list: &mut CstList,
+    cst_sibling_list: &mut CstList,
+    prediction: Prediction,
+) -> bool
 where
-    T: Clone,
     M: Matcher<T> + Clone,
 {
-    /// Create a new parser, given a grammar.
-    pub fn new(grammar: CompiledGrammar<T, M>) -> Self {
-        // Index 0 is special: It contains all the predictions of the start symbol. As the chart is
-        // only extended while parsing, chart entries before the current one aren't changed. Thus,
-        // the fully predicted chart[0] only needs to be generated once.
-        let mut start_set = Vec::new();
-        // Fill in the rules that have the start symbol as lhs.
-        for i in 0..grammar.rule_count() {
-            if grammar.is_start_rule(i) {
-                let new_entry = (DottedRule::new(i), 0);
-                add_to_state_list(&mut start_set, new_entry);
-            }
-        }
-
-        // The predictor for the start state is also special. As empty rules are allowed,
-        // *complete* needs to run. However, it is restricted to start == 0.  Since the state list
-        // will grow during this operation, the index needs to be checked every time.
-        let mut new_cst_list = Vec::new();
-        let mut i = 0;
-        while i < start_set.len() {
-            match grammar.dotted_symbol(&start_set[i].0) {
-                CompiledSymbol::NonTerminal(nt) => {
-                    predict(&mut start_set, nt, 0, &grammar);
-                    if grammar.nt_with_empty_rule(nt) {
-                        let new_entry = (start_set[i].0.advance_dot(), start_set[i].1);
-                        add_to_state_list(&mut start_set, new_entry);
-                    }
+    debug_assert_eq!(position + 1, chart.len());
+    let mut start_rule_completed = false;
+    let mut i = 0;
+    while i < chart[position].len() {
+        // Entries that have been completed without predictions are only predicted.
+        let completed = match prediction {
+            Prediction::Lookahead { first, .. } => i < first,
+            _ => false,
+        };
+        match grammar.dotted_symbol(&chart[position][i].0) {
+            CompiledSymbol::NonTerminal(nt) => {
+                let predicted = match prediction {
+                    Prediction::All => true,
+                    Prediction::Deferred => false,
+                    Prediction::Lookahead { allowed, .. } => allowed[nt as usize],
+                };
+                if predicted {
+                    predict(chart, nt, position, grammar);
                 }
-                CompiledSymbol::Terminal(_) => {
-                    // Can't do anything as we don't know the first token.
+                if nullable[nt as usize] && !completed {
+                    let new_entry = (chart[position][i].0.advance_dot(), chart[position][i].1);
+                    let new_state = chart.add(new_entry);
+                    // Add a CST sibling link to the same position as not to break the tree.
+                    add_to_cst_list(
+                        cst_sibling_list,
+                        CstEdge {
+                            from_state: new_state,
+                            to_state: i as SymbolId,
+                            to_position: position,
+                        },
+                    );
                 }
-                CompiledSymbol::Completed(completed) => {
-                    // Complete
-                    let start = start_set[i].1;
-                    // Check all the rules at *start* if the dot is at the completed symbol. Start
-                    // must be 0. Thus a double-borrow would occur of this done with an iterator.
-                    let mut rule_index = 0;
-                    while rule_index < start_set.len() {
-                        if let CompiledSymbol::NonTerminal(maybe_completed) =
-                            grammar.dotted_symbol(&start_set[rule_index].0)
-                        {
-                            if maybe_completed == completed {
-                                // Update the Earley chart
-                                let new_entry = (
-                                    start_set[rule_index].0.advance_dot(),
-                                    start_set[rule_index].1,
-                                );
-                                let new_state = add_to_state_list(&mut start_set, new_entry);
-                                // Create the CST edge from the completed rule to the rule that
-                                // started it, i.e. the parent/child link. Keep in mind that the
-                                // links have to go towards the older entries to keep them
-                                // consistent with the siblings edges.
+            }
+            CompiledSymbol::Terminal(_) => {
+                // Can't do anything as we don't know the next token.
+            }
+            CompiledSymbol::Completed(_) if completed => {}
+            CompiledSymbol::Completed(completed) => {
+                // Complete
+                start_rule_completed |= grammar.is_start_symbol(completed);
+                let start = idx(chart[position][i].1);
+                // Check all the rules at *start* if the dot is at the completed symbol. Start
+                // may be position, thus the state list may grow.
+                let mut rule_index = 0;
+                while rule_index < chart[start].len() {
+                    if let CompiledSymbol::NonTerminal(maybe_completed) =
+                        grammar.dotted_symbol(&chart[start][rule_index].0)
+                    {
+                        if maybe_completed == completed {
+                            // Update the Earley chart
+                            let new_entry = (
+                                chart[start][rule_index].0.advance_dot(),
+                                chart[start][rule_index].1,
+                            );
+                            let new_state = chart.add(new_entry);
+                            // Create the CST edge from the completed rule to the rule that
+                            // started it, i.e. the parent/child link. Keep in mind that the
+                            // links have to go towards the older entries to keep them
+                            // consistent with the siblings edges.
+                            add_to_cst_list(
+                                cst_child_list,
+                                CstEdge {
+                                    from_state: new_state,
+                                    to_state: i as SymbolId,
+                                    to_position: position,
+                                },
+                            );
+                            // Create the CST edge how the dot moved, i.e. the sibling link. Omit
+                            // links to the beginning of rules as they can't link to further
+                            // completions.
+                            if !chart[start][rule_index].0.is_first() {
                                 add_to_cst_list(
-                                    &mut new_cst_list,
+                                    cst_sibling_list,
                                     CstEdge {
                                         from_state: new_state,
-                                        to_state: i as SymbolId,
-                                        to_position: 0,
+                                        to_state: rule_index as SymbolId,
+                                        to_position: start,
                                     },
                                 );
-                                // Create the CST edge how the dot moved, i.e. the sibling link. Omit
-                                // links to the beginning of rules as they can't link to further
-                                // completions.
-                                if !start_set[rule_index].0.is_first() {
-                                    add_to_cst_list(
-                                        &mut new_cst_list,
-                                        CstEdge {
-                                            from_state: new_state,
-                                            to_state: rule_index as SymbolId,
-                                            to_position: start,
-                                        },
-                                    );
-                                }
                             }
                         }
-                        rule_index += 1;
                     }
+                    rule_index += 1;
                 }
             }
-            i += 1;
         }
+        i += 1;
+    }
+    start_rule_completed
+}
+
+/// Non-terminals predicted by `predict_and_complete`
+#[derive(Clone, Copy)]
+enum Prediction<'a> {
+    /// All of them
+    All,
+    /// None. The column is predicted when the next token is known, see
+    /// [Parser::set_lookahead](struct.Parser.html#method.set_lookahead).
+    Deferred,
+    /// The non-terminals for which `allowed` is true. Index is the symbol ID. The entries before
+    /// `first` have already been completed.
+    Lookahead { allowed: &'a [bool], first: usize },
+}
 
-        let mut chart = Vec::new();
-        chart.push(start_set);
-        let mut cst = Vec::new();
-        cst.push(new_cst_list);
+/// Build chart[0] and its CST edges.
+///
+/// Index 0 is special: It contains all the predictions of the start symbol. As the chart is only
+/// extended while parsing, chart entries before the current one aren't changed. Thus, the fully
+/// predicted chart[0] only needs to be generated once per grammar.
+fn start_chart<T, M>(
+    grammar: &CompiledGrammar<T, M>,
+    nullable: &[bool],
+) -> (Arena<ChartEntry>, Arena<CstEdge>)
+where
+    M: Matcher<T> + Clone,
+{
+    let mut start_set = Vec::new();
+    // Fill in the rules that have the start symbol as lhs.
+    for i in grammar.rules_for_lhs(grammar.start()) {
+        let new_entry = (DottedRule::new(*i), 0);
+        add_to_state_list(&mut start_set, new_entry);
+    }
+
+    // As empty rules are allowed, *complete* needs to run, too. It is restricted to start == 0.
+    let mut chart = Arena::new(start_set);
+    let mut cst_child_list = Vec::new();
+    let mut cst_sibling_list = Vec::new();
+    predict_and_complete(
+        grammar,
+        nullable,
+        &mut chart,
+        0,
+        &mut cst_child_list,
+        &mut cst_sibling_list,
+        Prediction::All,
+    );
+    cst_child_list.append(&mut cst_sibling_list);
+    (chart, Arena::new(cst_child_list))
+}
+
+impl<T, M> Parser<T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    /// Create a new parser, given a grammar.
+    pub fn new(grammar: CompiledGrammar<T, M>) -> Self {
+        let nullable = grammar.nullable_symbols();
+        let (chart, cst) = start_chart(&grammar, &nullable);
         Self {
             grammar,
+            nullable,
             chart,
             cst,
             valid_entries: 0,
+            normalizer: None,
+            token_source: None,
+            debug_sink: std::cell::RefCell::new(None),
+            dump_on_reject: None,
+            disambiguation: None,
+            observer: None,
+            suffix: None,
+            recovery: RecoveryPolicy::default(),
+            chart_limit: None,
+            error_limit: None,
+            error_run: (0, 0),
+            counters: ParserStats::default(),
+            committed: 0,
+            first_terminals: None,
+            terminal_dispatch: None,
+            #[cfg(feature = "parallel")]
+            parallel_scan: None,
         }
     }
 
-    /// Borrow the grammar
-    pub fn grammar<'a>(&'a self) -> &'a CompiledGrammar<T, M> {
-        &self.grammar
-    }
-
-    /// Get the dotted rule from a CST path node.
-    pub fn dotted_rule(&self, node: &CstPathNode) -> DottedRule {
-        self.chart[node.position][node.state as usize].0.clone()
+    /// Create a new parser that accepts the derivations of the non-terminal `start` instead of
+    /// the start symbol of the grammar, e.g. to check a fragment of a document.
+    pub fn with_start(mut grammar: CompiledGrammar<T, M>, start: &str) -> Result<Self, Error> {
+        grammar.set_start_by_name(start)?;
+        Ok(Self::new(grammar))
     }
 
-    /// The buffer has changed at `position`. All parse entries are invalid beginning with the given
-    /// position.
+    /// Create a new parser like [new](#method.new), but log a warning for each non-terminal that
+    /// cannot be derived from the start symbol or can never be completed.
     ///
-    /// The chart will not be changed to keep the function small and fast.
-    pub fn buffer_changed(&mut self, position: usize) {
-        if position < self.valid_entries {
-            self.valid_entries = position;
+    /// Rules using these non-terminals are dead and only cost time while parsing.
+    pub fn new_checked(grammar: CompiledGrammar<T, M>) -> Self {
+        let reachable = grammar.reachable_symbols();
+        let productive = grammar.productive_symbols();
+        for sym in 0..grammar.nt_count() {
+            let name = grammar.nt_name(sym as SymbolId);
+            if !reachable[sym] {
+                warn!(
+                    "non-terminal {} is not reachable from the start symbol",
+                    name
+                );
+            }
+            if !productive[sym] {
+                warn!("non-terminal {} never derives a string of terminals", name);
+            }
         }
+        Self::new(grammar)
     }
 
-    /// Process one entry in the buffer. To support lexers/character class mappers, this function
-    /// does not take the buffer directly, but just one token. The caller is respondible to ensure
-    /// the token extraction is deterministc.
+    /// Borrow the grammar mutably.
     ///
-    /// If the position is inside the already-parsed section, the valid part will be reset.
+    /// When the returned guard is dropped, the chart is rebuilt from the changed grammar and the
+    /// parse restarts at position 0.
+    pub fn grammar_mut(&mut self) -> GrammarMut<'_, T, M> {
+        GrammarMut { parser: self }
+    }
+
+    /// Replace the grammar and return the previous one.
     ///
-    /// If the position is inside the unparsed section, an error will be returned.
+    /// The chart is rebuilt from the new grammar and the parse restarts at position 0. Saved
+    /// states of the previous grammar can no longer be restored.
+    pub fn set_grammar(&mut self, grammar: CompiledGrammar<T, M>) -> CompiledGrammar<T, M> {
+        let previous = std::mem::replace(&mut self.grammar, grammar);
+        self.reset_chart();
+        previous
+    }
+
+    /// Save the chart and the parse tree to be restored later.
     ///
-    /// If the position is at the first unparsed position, the token will be processed.
+    /// The tokens are not part of the state. They need to be saved by the caller. The state
+    /// records the [fingerprint](struct.CompiledGrammar.html#method.fingerprint)
+    /// of the grammar including the matchers of the terminals.
+    pub fn save_state(&self) -> ParserState
+    where
+        M: std::hash::Hash,
+    {
+        ParserState {
+            grammar: self.grammar.fingerprint(),
+            chart: self.chart.clone(),
+            cst: self.cst.clone(),
+            valid_entries: self.valid_entries,
+        }
+    }
+
+    /// Replace the parse by a saved state.
     ///
-    /// When the terminal has been processed, the next entry is fully predicted. This allows *ruby
-    /// slippers* parsing when the user requests the acceptable tokens and inserts it into the
-    /// buffer before updating the parser.
+    /// The state must have been saved by a parser with the same grammar. The parser is unchanged
+    /// if the state is rejected. Settings like the token normalizer are not part of the state, so
+    /// restoring into a parser with different settings leads to a parse those settings would not
+    /// have produced.
+    pub fn restore_state(&mut self, state: ParserState) -> Result<(), StateError>
+    where
+        M: std::hash::Hash,
+    {
+        if state.grammar != self.grammar.fingerprint() {
+            return Err(StateError::GrammarMismatch);
+        }
+        if !self.is_consistent(&state) {
+            return Err(StateError::Inconsistent);
+        }
+        self.chart = state.chart;
+        self.cst = state.cst;
+        self.valid_entries = state.valid_entries;
+        self.suffix = None;
+        self.committed = 0;
+        Ok(())
+    }
+
+    /// Check that all indices of the state are in range for this grammar.
+    fn is_consistent(&self, state: &ParserState) -> bool {
+        let chart = &state.chart;
+        if !chart.is_consistent()
+            || !state.cst.is_consistent()
+            || chart.len() != state.cst.len()
+            || state.valid_entries >= chart.len()
+        {
+            return false;
+        }
+        let valid_entry = |position: usize, (dr, start): &ChartEntry| {
+            (dr.rule as usize) < self.grammar.rule_count()
+                && !self.grammar.is_removed(dr.rule as usize)
+                && dr.dot() <= self.grammar.rhs(dr.rule as usize).len()
+                && idx(*start) <= position
+        };
+        let valid_edge = |position: usize, edge: &CstEdge| {
+            (edge.from_state as usize) < chart[position].len()
+                && edge.to_position <= position
+                && (edge.to_state as usize) < chart[edge.to_position].len()
+        };
+        (0..chart.len()).all(|p| {
+            chart[p].iter().all(|e| valid_entry(p, e))
+                && state.cst[p].iter().all(|e| valid_edge(p, e))
+        })
+    }
+
+    /// Discard the parse and rebuild the start set.
+    fn reset_chart(&mut self) {
+        self.nullable = self.grammar.nullable_symbols();
+        let (chart, cst) = start_chart(&self.grammar, &self.nullable);
+        self.chart = chart;
+        self.cst = cst;
+        self.valid_entries = 0;
+        self.suffix = None;
+        self.committed = 0;
+        if self.first_terminals.is_some() {
+            self.first_terminals = Some(self.grammar.first_terminals());
+        }
+        if let Some((_, build)) = self.terminal_dispatch {
+            self.terminal_dispatch = Some((build(&self.grammar), build));
+        }
+    }
+
+    /// Look up the terminals that match a token in an index instead of calling the matcher of each
+    /// expected terminal, e.g. with [CharDispatch](char/struct.CharDispatch.html).
+    ///
+    /// The index is built from the grammar and again whenever the grammar changes. None switches
+    /// back to the matchers. The index takes precedence over the parallel scan.
+    pub fn set_terminal_dispatch(&mut self, build: Option<DispatchBuilder<T, M>>) {
+        self.terminal_dispatch = build.map(|build| (build(&self.grammar), build));
+    }
+
+    /// Set the destination of debug output or remove it.
+    ///
+    /// The parser never writes to stdout or stderr by itself. This keeps it usable in programs
+    /// that own the terminal.
+    pub fn set_debug_sink(&mut self, sink: Option<DebugSink>) {
+        self.debug_sink.replace(sink);
+    }
+
+    /// Write the states of a column to the debug sink. Errors are ignored.
+    fn dump_column(&self, position: usize, write_dotted_rule: DottedRuleWriter<T, M>) {
+        if let Some(sink) = self.debug_sink.borrow_mut().as_mut() {
+            let _ = writeln!(sink, "reject at {}:", position);
+            for e in self.chart[position].iter() {
+                let _ = write!(sink, "  ");
+                let _ = write_dotted_rule(&self.grammar, sink, &e.0);
+                let _ = writeln!(sink, "[{}]", e.1);
+            }
+        }
+    }
+
+    /// Normalize each token before it is matched, e.g. to treat tabs and spaces the same.
+    ///
+    /// The normalizer only changes what the matchers see. The caller keeps the original tokens.
+    /// As earlier matches might differ now, the whole input needs to be parsed again.
+    pub fn set_token_normalizer(&mut self, normalizer: TokenNormalizer<T>) {
+        self.normalizer = Some(normalizer);
+        self.buffer_changed(0);
+    }
+
+    /// Match the tokens as they are. The whole input needs to be parsed again.
+    pub fn clear_token_normalizer(&mut self) {
+        self.normalizer = None;
+        self.buffer_changed(0);
+    }
+
+    /// Set the source of the tokens between the parsed part of the buffer and the position of an
+    /// update that skips ahead.
+    ///
+    /// Without a source, [update](#method.update) and [update_slice](#method.update_slice) return
+    /// `InvalidPosition` in that case. With a source, the missing tokens are parsed first. The
+    /// source must return the same tokens the caller would pass to `update`.
+    pub fn set_token_source(&mut self, source: Option<TokenSource<T>>) {
+        self.token_source = source;
+    }
+
+    /// Parse the tokens from the token source until `position` is the first unparsed one.
+    ///
+    /// Return whether any token has been rejected, or the verdict if the parser cannot reach
+    /// `position`.
+    fn catch_up(&mut self, position: usize, scratch: &mut Scratch) -> Result<bool, Verdict> {
+        let mut rejected = false;
+        while self.valid_entries < position {
+            let valid_entries = self.valid_entries;
+            let token = match self.token_source.as_mut().and_then(|f| f(valid_entries)) {
+                Some(token) => token,
+                None => return Err(Verdict::InvalidPosition),
+            };
+            if self.is_limit_reached() {
+                return Err(Verdict::LimitExceeded);
+            }
+            rejected |= self.step_repaired(valid_entries, token, &[], scratch) == Verdict::Reject;
+        }
+        Ok(rejected)
+    }
+
+    /// Select how the parser continues after a token that doesn't match. The whole input needs to
+    /// be parsed again.
+    pub fn set_recovery_policy(&mut self, policy: RecoveryPolicy) {
+        self.recovery = policy;
+        self.buffer_changed(0);
+    }
+
+    /// Use the next token to predict only the non-terminals that can begin with it or derive the
+    /// empty string. The whole input needs to be parsed again.
+    ///
+    /// This shrinks the columns of grammars that predict many non-terminals at each position,
+    /// while the CST stays the same. As the column after the last token has not been predicted
+    /// yet, queries like [expected_terminals](#method.expected_terminals) only see the entries
+    /// in progress there, and the parser doesn't reuse the columns after an edit.
+    pub fn set_lookahead(&mut self, on: bool) {
+        self.first_terminals = if on {
+            Some(self.grammar.first_terminals())
+        } else {
+            None
+        };
+        self.buffer_changed(0);
+    }
+
+    /// Predict chart[position], which must be the last column and has only been completed. With
+    /// a token, only the non-terminals that can derive it are predicted. Predictions for an earlier
+    /// token are removed first.
+    fn predict_last(&mut self, position: usize, token: Option<&T>) {
+        // Entries of other columns start before position, so the predictions come last.
+        let first = self.chart[position]
+            .iter()
+            .position(|(_, start)| idx(*start) == position)
+            .unwrap_or(self.chart[position].len());
+        self.chart.truncate_last(first);
+        let kept: Vec<CstEdge> = self.cst[position]
+            .iter()
+            .filter(|e| {
+                (e.from_state as usize) < first
+                    && !(e.to_position == position && e.to_state as usize >= first)
+            })
+            .cloned()
+            .collect();
+        self.cst.truncate_last(0);
+        kept.into_iter().for_each(|e| self.cst.push(e));
+
+        let nt_count = self.grammar.nt_count();
+        let allowed: Vec<bool> = match (token, &self.first_terminals) {
+            (Some(token), Some(first_terminals)) => {
+                let matching: Vec<bool> = self
+                    .grammar
+                    .terminals()
+                    .iter()
+                    .map(|t| t.matches(token.clone()))
+                    .collect();
+                (0..nt_count)
+                    .map(|nt| self.nullable[nt] || first_terminals[nt].iter().any(|t| matching[*t]))
+                    .collect()
+            }
+            _ => vec![true; nt_count],
+        };
+        let mut children = Vec::new();
+        let mut siblings = Vec::new();
+        predict_and_complete(
+            &self.grammar,
+            &self.nullable,
+            &mut self.chart,
+            position,
+            &mut children,
+            &mut siblings,
+            Prediction::Lookahead {
+                allowed: &allowed,
+                first,
+            },
+        );
+        for edge in children.into_iter().chain(siblings) {
+            if !self.cst[position].contains(&edge) {
+                self.cst.push(edge);
+            }
+        }
+    }
+
+    /// Limit the number of chart entries of all columns, or remove the limit with `None`.
+    ///
+    /// Once the chart has reached the limit, [update](#method.update) doesn't parse any more
+    /// tokens and returns `LimitExceeded`. The CST iterator reports the rest of the buffer as
+    /// unparsed. The parse is kept if the limit is lowered.
+    pub fn set_chart_limit(&mut self, limit: Option<usize>) {
+        self.chart_limit = limit;
+    }
+
+    /// Report the work done since the parser has been created or the counters have been reset, and
+    /// the size of the current parse.
+    pub fn stats(&self) -> ParserStats {
+        let mut items_per_rule = vec![0; self.grammar.rule_count()];
+        for column in 0..=self.valid_entries {
+            for (dr, _) in self.chart[column].iter() {
+                items_per_rule[dr.rule as usize] += 1;
+            }
+        }
+        ParserStats {
+            items_per_position: (0..=self.valid_entries)
+                .map(|column| self.chart[column].len())
+                .collect(),
+            items_per_rule,
+            ..self.counters.clone()
+        }
+    }
+
+    /// Reset the counters of [stats](#method.stats)
+    pub fn reset_stats(&mut self) {
+        self.counters = ParserStats::default();
+    }
+
+    /// Size of the chart and the parse tree in bytes
+    fn memory(&self) -> usize {
+        self.chart.entry_count() * std::mem::size_of::<ChartEntry>()
+            + self.cst.entry_count() * std::mem::size_of::<CstEdge>()
+    }
+
+    /// Limit the number of consecutive tokens that need an error recovery, or remove the limit
+    /// with `None`.
+    ///
+    /// Garbage input makes every expected terminal produce an error entry for each token. Once the
+    /// limit has been reached, [update](#method.update) doesn't parse any more tokens and returns
+    /// `LimitExceeded`, like the [chart limit](#method.set_chart_limit) does. The rest of the buffer
+    /// is reported as unparsed. Changing the buffer before the errors resets the count.
+    pub fn set_error_limit(&mut self, limit: Option<usize>) {
+        self.error_limit = limit;
+    }
+
+    /// Check if the chart has reached its limit, the last tokens have been rejected too often, or
+    /// the next column couldn't be addressed by a chart entry.
+    fn is_limit_reached(&self) -> bool {
+        self.valid_entries >= idx(Position::MAX)
+            || self
+                .chart_limit
+                .is_some_and(|limit| self.chart.entry_count() >= limit)
+            || self.error_limit.is_some_and(|limit| {
+                self.error_run.1 == self.valid_entries && self.error_run.0 >= limit
+            })
+    }
+
+    /// Select a single derivation in the CST iterator if the input has been parsed ambiguously.
+    ///
+    /// Without a strategy, the iterator visits all derivations. The chart is not affected.
+    pub fn set_disambiguation(&mut self, strategy: Option<Box<dyn Disambiguation + Send>>) {
+        self.disambiguation = strategy;
+    }
+
+    /// Register an observer of the steps of the parser or remove it. Return the previous one.
+    ///
+    /// See [observer](observer/index.html) for the events.
+    pub fn set_observer(
+        &mut self,
+        observer: Option<Box<dyn ParserObserver + Send>>,
+    ) -> Option<Box<dyn ParserObserver + Send>> {
+        std::mem::replace(&mut self.observer, observer)
+    }
+
+    /// Report the entries of chart[position + 1] and the error of the token at `position` to the
+    /// observer.
+    fn notify_observer(&mut self, position: usize, rejected: bool) {
+        let observer = match self.observer.as_mut() {
+            Some(observer) => observer,
+            None => return,
+        };
+        if rejected {
+            observer.on_error(position);
+        }
+        let new_position = position + 1;
+        let nt_count = self.grammar.nt_count();
+        for (dr, start) in self.chart[new_position].iter() {
+            let rule = dr.rule as usize;
+            if rule == ERROR_ID as usize {
+                continue;
+            }
+            if dr.is_first() {
+                observer.on_predict(new_position, rule);
+            } else if self.grammar.rhs(rule)[dr.dot() - 1] as usize >= nt_count {
+                observer.on_scan(position, dr, idx(*start));
+            }
+            if self.grammar.dotted_is_completed(dr) {
+                observer.on_complete(self.grammar.lhs(rule), idx(*start), new_position);
+            }
+        }
+    }
+
+    /// Completed children of a completed node, including error entries, strictly left to right.
+    ///
+    /// The children are sorted by their start position. Empty children come before a child that
+    /// starts at the same position, several of them keep the order of the rule. Terminals are
+    /// not nodes of their own. Follows the first derivation if there is no disambiguation
+    /// strategy, see [set_disambiguation](#method.set_disambiguation).
+    pub fn children(&self, node: &CstPathNode) -> Vec<CstPathNode> {
+        let mut children = Vec::new();
+        let mut current = node.clone();
+        loop {
+            let edges: Vec<&CstEdge> = self.cst[current.position]
+                .iter()
+                .filter(|edge| edge.from_state == current.state)
+                .collect();
+            let edges = self.disambiguate(edges);
+            let to_node = |edge: &CstEdge| CstPathNode {
+                position: edge.to_position,
+                state: edge.to_state,
+            };
+            let is_error = |edge: &CstEdge| {
+                self.chart[edge.to_position][edge.to_state as usize].0.rule as usize
+                    == ERROR_ID as usize
+            };
+            let child = edges.iter().find(|edge| self.is_child(edge));
+            let split = child.map(|c| idx(self.chart[c.to_position][c.to_state as usize].1));
+            children.extend(child.map(|c| to_node(c)));
+            children.extend(edges.iter().filter(|e| is_error(e)).map(|e| to_node(e)));
+            let prefix = edges
+                .iter()
+                .find(|e| {
+                    !self.is_child(e) && !is_error(e) && split.is_none_or(|s| s == e.to_position)
+                })
+                .map(|e| to_node(e));
+            match prefix {
+                Some(p) => current = p,
+                None => break,
+            }
+        }
+        children.reverse();
+        children.sort_by_key(|child| self.span(child));
+        children
+    }
+
+    /// Borrow the grammar
+    pub fn grammar<'a>(&'a self) -> &'a CompiledGrammar<T, M> {
+        &self.grammar
+    }
+
+    /// Get the dotted rule from a CST path node.
+    pub fn dotted_rule(&self, node: &CstPathNode) -> DottedRule {
+        self.chart[node.position][node.state as usize].0.clone()
+    }
+
+    /// Get the start and end position of the buffer covered by a CST path node.
+    pub fn span(&self, node: &CstPathNode) -> (usize, usize) {
+        (
+            idx(self.chart[node.position][node.state as usize].1),
+            node.position,
+        )
+    }
+
+    /// The buffer has changed at `position`. All parse entries are invalid beginning with the given
+    /// position.
+    ///
+    /// The chart will not be changed to keep the function small and fast.
+    pub fn buffer_changed(&mut self, position: usize) {
+        if position < self.valid_entries {
+            self.valid_entries = position;
+        }
+        // Only the reparse of the last edit may use the saved columns
+        if self
+            .suffix
+            .as_ref()
+            .is_some_and(|suffix| suffix.edit_start != position)
+        {
+            self.suffix = None;
+        }
+    }
+
+    /// The `removed` tokens beginning at `position` have been replaced by `inserted` tokens.
+    ///
+    /// Like [buffer_changed](#method.buffer_changed), but the columns after the edit are kept.
+    /// When reparsing the new tokens produces a column identical to a kept one, the remaining
+    /// columns are moved back into the chart and [valid_entries](#method.valid_entries) jumps
+    /// ahead. Callers must therefore continue at `valid_entries` instead of the next token.
+    pub fn buffer_replaced(&mut self, position: usize, removed: usize, inserted: usize) {
+        let valid_entries = self.valid_entries;
+        self.suffix = None;
+        self.buffer_changed(position);
+        if self.first_terminals.is_some() {
+            return;
+        }
+        // The column at position is still needed to parse the first new token
+        let first = std::cmp::max(position + removed, position + 1);
+        if first > valid_entries {
+            return;
+        }
+        self.chart.truncate(valid_entries + 1);
+        self.cst.truncate(valid_entries + 1);
+        self.suffix = Some(Suffix {
+            edit_start: position,
+            old_end: position + removed,
+            inserted,
+            first,
+            chart: self.chart.split_off(first),
+            cst: self.cst.split_off(first),
+        });
+    }
+
+    /// If the column at `position` equals the saved column at the same place, move the saved
+    /// columns after it back into the chart.
+    ///
+    /// The later columns depend only on the tokens, which are unchanged, on the in-progress entries
+    /// of the column, and on the columns their start positions refer to. Thus these entries must
+    /// not refer to positions that have been parsed again, except the column itself. Completed
+    /// entries only need to match in their rules, as later columns refer to them by index.
+    fn reuse_suffix(&mut self, position: usize) {
+        let suffix = match &self.suffix {
+            Some(suffix) => suffix,
+            None => return,
+        };
+        let column = match suffix.map(suffix.first) {
+            Some(first) if position >= first => position - first,
+            _ => return,
+        };
+        if column >= suffix.chart.len() {
+            self.suffix = None;
+            return;
+        }
+        let old_position = suffix.first + column;
+        let new = &self.chart[position];
+        let old = &suffix.chart[column];
+        let same = new.len() == old.len()
+            && new.iter().zip(old.iter()).all(|(n, o)| {
+                n.0 == o.0
+                    && (self.grammar.dotted_is_completed(&n.0)
+                        || (idx(o.1) < suffix.edit_start && n.1 == o.1)
+                        || (idx(o.1) == old_position && idx(n.1) == position))
+            });
+        if !same {
+            if column + 1 == suffix.chart.len() {
+                self.suffix = None;
+            }
+            return;
+        }
+
+        let suffix = self.suffix.take().unwrap();
+        let map = |old: usize| {
+            let new = suffix.map(old);
+            debug_assert!(new.is_some(), "saved column refers to edited tokens");
+            new.unwrap_or(old)
+        };
+        for c in (column + 1)..suffix.chart.len() {
+            self.chart.push_column(
+                suffix.chart[c]
+                    .iter()
+                    .map(|(dr, start)| (dr.clone(), pos(map(idx(*start))))),
+            );
+            self.cst
+                .push_column(suffix.cst[c].iter().map(|edge| CstEdge {
+                    from_state: edge.from_state,
+                    to_state: edge.to_state,
+                    to_position: map(edge.to_position),
+                }));
+        }
+        self.valid_entries = self.chart.len() - 1;
+    }
+
+    /// Process one entry in the buffer. To support lexers/character class mappers, this function
+    /// does not take the buffer directly, but just one token. The caller is respondible to ensure
+    /// the token extraction is deterministc.
+    ///
+    /// If the position is inside the already-parsed section, the valid part will be reset.
+    ///
+    /// If the position is inside the unparsed section, an error will be returned, unless the
+    /// missing tokens can be taken from the [token source](#method.set_token_source). The verdict
+    /// is `Reject` if any of them has been rejected.
+    ///
+    /// If the position is at the first unparsed position, the token will be processed.
+    ///
+    /// When the terminal has been processed, the next entry is fully predicted. This allows *ruby
+    /// slippers* parsing when the user requests the acceptable tokens (see
+    /// [suggest_insertion](#method.suggest_insertion)) and inserts it into the buffer before
+    /// updating the parser.
     ///
     /// The function returns whether the input is accepted, rejected or still undecided.
     pub fn update(&mut self, position: usize, token: T) -> Verdict {
-        self.buffer_changed(position);
-        if position > self.valid_entries {
-            return Verdict::InvalidPosition;
+        if position < self.valid_entries {
+            self.buffer_changed(position);
+        }
+        let mut scratch = Scratch::default();
+        let rejected = match self.catch_up(position, &mut scratch) {
+            Ok(rejected) => rejected,
+            Err(verdict) => return verdict,
+        };
+        if position < self.valid_entries {
+            // The token source reached a saved part of the chart
+            return if rejected {
+                Verdict::Reject
+            } else if self.accepted_at(position + 1) {
+                Verdict::Accept
+            } else {
+                Verdict::More
+            };
+        }
+        if self.is_limit_reached() {
+            return Verdict::LimitExceeded;
         }
+        match self.step_repaired(position, token, &[], &mut scratch) {
+            Verdict::More | Verdict::Accept if rejected => Verdict::Reject,
+            verdict => verdict,
+        }
+    }
 
-        // position is valid.
-        //
-        // The chart must have at least one entry more than the buffer. That means chart[position+1]
-        // needs to exist. If everything is correct so far and we're parsing the first time,
-        // `position + 1 == chart.len()`. If we're not parsing the first time, the chart may be
-        // longer.
-        debug_assert!(position + 1 <= self.chart.len());
-        // Check if room for position+1 needs to be made.
-        if (position + 1) == self.chart.len() {
-            // Should only need to add one state list
-            self.chart.push(Vec::new());
-            debug_assert!(position + 1 < self.chart.len());
-            self.cst.push(Vec::new());
-            debug_assert_eq!(self.cst.len(), self.chart.len());
-        }
-        // Get the state list to write to in the scanner. We work on a new vector to simplify the
-        // access. This will change anyway when the chart is flattened.
-        let mut new_state_list = Vec::new();
-        self.chart[position + 1].clear();
-
-        // Get the state list to read from
-        let state_list = &self.chart[position];
+    /// Process one token like [update](#method.update) and return the non-terminals it completed,
+    /// innermost first.
+    ///
+    /// The tuples consist of symbol, start and end of the span, see
+    /// [completions_at](#method.completions_at). The list is empty if the token hasn't been
+    /// processed.
+    pub fn update_detailed(
+        &mut self,
+        position: usize,
+        token: T,
+    ) -> (Verdict, Vec<(SymbolId, usize, usize)>) {
+        let verdict = self.update(position, token);
+        let completed = match verdict {
+            Verdict::InvalidPosition | Verdict::LimitExceeded => Vec::new(),
+            _ => self
+                .completions_at(position + 1)
+                .into_iter()
+                .map(|(sym, start)| (sym, start, position + 1))
+                .collect(),
+        };
+        (verdict, completed)
+    }
+
+    /// Process a run of tokens beginning at `position`, like calling
+    /// [update](#method.update) for each of them.
+    ///
+    /// The temporary lists are shared by all tokens and the verdict is computed once at the end:
+    /// `Reject` if any token has been rejected, otherwise whether the start symbol has been
+    /// completed after the last token. Tokens that the parser skipped by reusing the chart after
+    /// an edit (see [buffer_replaced](#method.buffer_replaced)) are not processed again.
+    pub fn update_slice(&mut self, position: usize, tokens: &[T]) -> Verdict {
+        if position < self.valid_entries {
+            self.buffer_changed(position);
+        }
+        let mut scratch = Scratch::default();
+        let mut rejected = match self.catch_up(position, &mut scratch) {
+            Ok(rejected) => rejected,
+            Err(verdict) => return verdict,
+        };
+        for (i, token) in tokens.iter().enumerate() {
+            if position + i == self.valid_entries {
+                if self.is_limit_reached() {
+                    return Verdict::LimitExceeded;
+                }
+                let following = &tokens[i + 1..];
+                rejected |=
+                    self.step_repaired(position + i, token.clone(), following, &mut scratch)
+                        == Verdict::Reject;
+            }
+        }
+        if rejected {
+            Verdict::Reject
+        } else if self.accepted_at(position + tokens.len()) {
+            Verdict::Accept
+        } else {
+            Verdict::More
+        }
+    }
+
+    /// Process the token at `position` like [step](#method.step). With
+    /// [RecoveryPolicy::MinimalEdit](enum.RecoveryPolicy.html#variant.MinimalEdit), the
+    /// recovery is chosen by trying the edits on the following tokens.
+    ///
+    /// Counts the consecutive rejected tokens for the [error limit](#method.set_error_limit).
+    fn step_repaired(
+        &mut self,
+        position: usize,
+        token: T,
+        following: &[T],
+        scratch: &mut Scratch,
+    ) -> Verdict {
+        let edits = match self.recovery {
+            RecoveryPolicy::MinimalEdit(edits) => Some(edits),
+            _ => None,
+        };
+        let verdict = match edits {
+            Some(edits) if !self.token_matches(position, &token) => {
+                self.step_minimal_edit(position, token, following, edits, scratch)
+            }
+            _ => self.step(position, token, scratch),
+        };
+        if verdict != Verdict::Reject {
+            self.error_run.0 = 0;
+        } else if self.error_run.1 == position {
+            self.error_run.0 += 1;
+        } else {
+            self.error_run.0 = 1;
+        }
+        self.error_run.1 = position + 1;
+        verdict
+    }
+
+    /// Process the token at `position`, which doesn't match, with the cheapest combination of at
+    /// most `edits` edits.
+    fn step_minimal_edit(
+        &mut self,
+        position: usize,
+        token: T,
+        following: &[T],
+        edits: usize,
+        scratch: &mut Scratch,
+    ) -> Verdict {
+        let window: Vec<T> = std::iter::once(token.clone())
+            .chain(following.iter().take(edits + REPAIR_LOOKAHEAD).cloned())
+            .collect();
+
+        // The trials must neither reuse the old parse nor leave traces.
+        let suffix = self.suffix.take();
+        let dump_on_reject = self.dump_on_reject.take();
+        let observer = self.observer.take();
+        let counters = self.counters.clone();
+        let repair = self.cheapest_repair(position, &window, edits);
+        self.suffix = suffix;
+        self.dump_on_reject = dump_on_reject;
+        self.observer = observer;
+        self.counters = counters;
+
+        self.recovery = repair.map_or(RecoveryPolicy::PretendMatch, |(_, policy)| policy);
+        let verdict = self.step(position, token, scratch);
+        self.recovery = RecoveryPolicy::MinimalEdit(edits);
+        verdict
+    }
+
+    /// Check if the token at `position`, which must be the first unparsed one, matches one of the
+    /// expected terminals.
+    ///
+    /// Prepares chart[position] like [step](#method.step) does.
+    fn token_matches(&mut self, position: usize, token: &T) -> bool {
+        self.chart.truncate(position + 1);
+        self.cst.truncate(position + 1);
+        self.remove_insertion(position);
+        let token = match &self.normalizer {
+            Some(normalize) => normalize(token),
+            None => token.clone(),
+        };
+        if self.first_terminals.is_some() && position > 0 {
+            self.predict_last(position, Some(&token));
+        }
+        !self.matching_states(position, 0, &token).is_empty()
+    }
+
+    /// Find the repair of `tokens[0]`, which doesn't match at `position`, that parses all tokens
+    /// with the fewest edits, but at most `edits`.
+    ///
+    /// Return the number of edits and the recovery policy that makes the first one. The chart is
+    /// unchanged afterwards. Of repairs with the same number of edits, insertion is preferred
+    /// over replacement and replacement over deletion.
+    fn cheapest_repair(
+        &mut self,
+        position: usize,
+        tokens: &[T],
+        edits: usize,
+    ) -> Option<(usize, RecoveryPolicy)> {
+        if edits == 0 {
+            return None;
+        }
+        let mut best: Option<(usize, RecoveryPolicy)> = None;
+        for policy in [
+            RecoveryPolicy::InsertExpected,
+            RecoveryPolicy::PretendMatch,
+            RecoveryPolicy::SkipToken,
+        ] {
+            self.recovery = policy.clone();
+            self.step(position, tokens[0].clone(), &mut Scratch::default());
+            // A failed insertion falls back to the replacement.
+            let applied = policy != RecoveryPolicy::InsertExpected
+                || self.original_len(position) < self.chart[position].len();
+            let budget = best.as_ref().map_or(edits, |(cost, _)| cost - 1) - 1;
+            if applied {
+                if let Some(cost) = self.repair_cost(position + 1, &tokens[1..], budget) {
+                    best = Some((cost + 1, policy));
+                }
+            }
+            self.undo_steps(position);
+            if best.as_ref().is_some_and(|(cost, _)| *cost == 1) {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Number of edits, at most `edits`, needed to parse the tokens from `position` on.
+    fn repair_cost(&mut self, position: usize, tokens: &[T], edits: usize) -> Option<usize> {
+        for (i, token) in tokens.iter().enumerate() {
+            if !self.token_matches(position + i, token) {
+                return self
+                    .cheapest_repair(position + i, &tokens[i..], edits)
+                    .map(|(cost, _)| cost);
+            }
+            self.step(position + i, token.clone(), &mut Scratch::default());
+        }
+        Some(0)
+    }
+
+    /// Drop the columns after `position` and the insertion at `position`, so the token there can
+    /// be processed again.
+    fn undo_steps(&mut self, position: usize) {
+        self.chart.truncate(position + 1);
+        self.cst.truncate(position + 1);
+        self.remove_insertion(position);
+        self.valid_entries = position;
+    }
+
+    /// Process the token at `position`, which must be the first unparsed one.
+    fn step(&mut self, position: usize, token: T, scratch: &mut Scratch) -> Verdict {
+        // The chart must have at least one entry more than the buffer. That means chart[position]
+        // needs to exist. If we're not parsing the first time, the chart may be longer. Only the
+        // last column can grow, so the columns after position are dropped and chart[position+1]
+        // is rebuilt at the end.
+        debug_assert!(position < self.chart.len());
+        self.chart.truncate(position + 1);
+        self.cst.truncate(position + 1);
+        self.remove_insertion(position);
+        // Get the state list to write to in the scanner. The scanner reads from chart[position],
+        // so the new column is collected separately.
+        let new_state_list = &mut scratch.states;
 
         // New entries for cst edge. Child edges need to come first for iterator to work. In case
         // of errors, the error links need to come first.
-        let mut cst_child_list = Vec::new();
-        let mut cst_sibling_list = Vec::new();
+        let cst_child_list = &mut scratch.children;
+        let cst_sibling_list = &mut scratch.siblings;
 
         // Perform *scan*.
         //
         // The invariant of chart is that chart[i] has been fully predicted and completed before
         // update(i) is called. Thus, only *scan* remains to be done. The order of operations
         // doesn't matter as *scan* will not change the chart[i].
-        let mut scanned = false;
-        for (state_index, state) in state_list.iter().enumerate() {
-            let dr = &state.0;
-            if let CompiledSymbol::Terminal(t) = self.grammar.dotted_symbol(&dr) {
-                if t.matches(token.clone()) {
-                    // Successful, advance the dot and store in new_state
-                    let new_entry = (dr.advance_dot(), state.1);
-                    let new_state = add_to_state_list(&mut new_state_list, new_entry);
-
-                    // Add a sibling link if this isn't the first symbol in the rule.
-                    if !dr.is_first() {
-                        add_to_cst_list(
-                            &mut cst_sibling_list,
-                            CstEdge {
-                                from_state: new_state,
-                                to_state: state_index as SymbolId,
-                                to_position: position,
-                            },
-                        );
-                    }
-
-                    scanned = true;
-                }
-            }
+        let token = match &self.normalizer {
+            Some(normalize) => normalize(&token),
+            None => token,
+        };
+        let lookahead = self.first_terminals.is_some() && position > 0;
+        if lookahead {
+            self.predict_last(position, Some(&token));
+        }
+        let mut scanned = self.scan(position, 0, &token, new_state_list, cst_sibling_list);
+        if !scanned && lookahead {
+            // Recover from the error with all predictions
+            self.predict_last(position, None);
         }
 
         let mut verdict = None;
+        if !scanned && self.recovery == RecoveryPolicy::InsertExpected {
+            scanned = self.insert_expected(position, &token, new_state_list, cst_sibling_list);
+            if scanned {
+                verdict = Some(Verdict::Reject);
+            }
+        }
 
         // In order to handle empty rules, the chart must be used, not a separate copy.
         let new_position = position + 1;
-        self.chart[new_position] = new_state_list;
+        self.chart.push_column(new_state_list.drain(..));
 
         if !scanned {
+            self.counters.scan_failures += 1;
+            if let Some(write_dotted_rule) = self.dump_on_reject {
+                self.dump_column(position, write_dotted_rule);
+            }
+
             // None of the predicted symbols matched.
+            let recovered = match &self.recovery {
+                RecoveryPolicy::SkipToken => {
+                    self.skip_token(position, cst_child_list, cst_sibling_list);
+                    true
+                }
+                RecoveryPolicy::Synchronize(symbols) => {
+                    let symbols = symbols.clone();
+                    self.synchronize(position, &symbols, cst_child_list, cst_sibling_list)
+                }
+                RecoveryPolicy::Reject => true,
+                _ => false,
+            };
+
             // Remedy: Accept all terminals and insert one error pseudo-rule per terminal into the
             //         parse tree. Then, predict as usual, but link the
             //         predictions to the error rules.
 
             // Only process the existing entries.
-            for i in 0..self.chart[position].len() {
+            let pretend = if recovered {
+                0
+            } else {
+                self.chart[position].len()
+            };
+            for i in 0..pretend {
                 let dr = &self.chart[position][i].0;
                 if let CompiledSymbol::Terminal(_t) = self.grammar.dotted_symbol(&dr) {
                     // Pretend to be successful, advance the dot and store in new_state
                     let new_entry = (dr.advance_dot(), self.chart[position][i].1);
-                    let new_state = add_to_state_list(&mut self.chart[new_position], new_entry);
+                    let new_state = self.chart.add(new_entry);
                     // Mark as error by adding the error pseudo-rule
-                    let error_state = self.chart[new_position].len() as SymbolId;
-                    self.chart[new_position].push((DottedRule::new(ERROR_ID as usize), position));
+                    let error_state = state_id(self.chart[new_position].len());
+                    self.chart
+                        .push((DottedRule::new(ERROR_ID as usize), pos(position)));
 
                     // Link pretended match to error entry. Must not be de-duplicated if multiple
                     // errors occur.
@@ -455,97 +1925,35 @@ where
             verdict = Some(Verdict::Reject);
         }
 
-        // Predict and complete the new state. This will usually grow the state list. Thus, indexed
-        // access is required.
-        let mut start_rule_completed = false;
-        let mut i = 0;
-        while i < self.chart[new_position].len() {
-            match self.grammar.dotted_symbol(&self.chart[new_position][i].0) {
-                CompiledSymbol::NonTerminal(nt) => {
-                    predict(
-                        &mut self.chart[new_position],
-                        nt,
-                        new_position,
-                        &self.grammar,
-                    );
-                    if self.grammar.nt_with_empty_rule(nt) {
-                        let new_entry = (
-                            self.chart[new_position][i].0.advance_dot(),
-                            self.chart[new_position][i].1,
-                        );
-                        let new_state = add_to_state_list(&mut self.chart[new_position], new_entry);
-                        // Add a CST sibling link to the previous position as not to break the
-                        // tree.
-                        add_to_cst_list(
-                            &mut cst_sibling_list,
-                            CstEdge {
-                                from_state: new_state,
-                                to_state: i as SymbolId,
-                                to_position: new_position,
-                            },
-                        );
-                    }
-                }
-                CompiledSymbol::Terminal(_) => {
-                    // Can't do anything as we don't know the new token.
-                }
-                CompiledSymbol::Completed(completed) => {
-                    // Complete
-                    start_rule_completed =
-                        start_rule_completed | self.grammar.is_start_symbol(completed);
-                    let start = self.chart[new_position][i].1;
-                    // Check all the rules at *start* if the dot is at the completed symbol
-                    let mut rule_index = 0;
-                    while rule_index < self.chart[start].len() {
-                        if let CompiledSymbol::NonTerminal(maybe_completed) =
-                            self.grammar.dotted_symbol(&self.chart[start][rule_index].0)
-                        {
-                            if maybe_completed == completed {
-                                // Update the Earley chart
-                                let new_entry = (
-                                    self.chart[start][rule_index].0.advance_dot(),
-                                    self.chart[start][rule_index].1,
-                                );
-                                let new_state =
-                                    add_to_state_list(&mut self.chart[new_position], new_entry);
-                                // Create the CST edge from the completed rule to the rule that
-                                // started it, i.e. the parent/child link. Keep in mind that the
-                                // links have to go towards the older entries to keep them
-                                // consistent with the siblings edges.
-                                add_to_cst_list(
-                                    &mut cst_child_list,
-                                    CstEdge {
-                                        from_state: new_state,
-                                        to_state: i as SymbolId,
-                                        to_position: new_position,
-                                    },
-                                );
-                                // Create the CST edge how the dot moved, i.e. the sibling link. Omit
-                                // links to the beginning of rules as they can't link to further
-                                // completions.
-                                if !self.chart[start][rule_index].0.is_first() {
-                                    add_to_cst_list(
-                                        &mut cst_sibling_list,
-                                        CstEdge {
-                                            from_state: new_state,
-                                            to_state: rule_index as SymbolId,
-                                            to_position: start,
-                                        },
-                                    );
-                                }
-                            }
-                        }
-                        rule_index += 1;
-                    }
-                }
+        let start_rule_completed = predict_and_complete(
+            &self.grammar,
+            &self.nullable,
+            &mut self.chart,
+            new_position,
+            cst_child_list,
+            cst_sibling_list,
+            if self.first_terminals.is_some() {
+                Prediction::Deferred
+            } else {
+                Prediction::All
+            },
+        );
+
+        self.cst
+            .push_column(cst_child_list.drain(..).chain(cst_sibling_list.drain(..)));
+
+        for (dr, _) in self.chart[new_position].iter() {
+            match self.grammar.dotted_symbol(dr) {
+                CompiledSymbol::NonTerminal(_) => self.counters.predictions += 1,
+                CompiledSymbol::Completed(_) => self.counters.completions += 1,
+                CompiledSymbol::Terminal(_) => {}
             }
-            i += 1;
         }
-
-        self.cst[new_position] = cst_child_list;
-        self.cst[new_position].append(&mut cst_sibling_list);
+        self.counters.peak_memory = std::cmp::max(self.counters.peak_memory, self.memory());
+        self.notify_observer(position, verdict == Some(Verdict::Reject));
 
         self.valid_entries = new_position;
+        self.reuse_suffix(new_position);
 
         verdict = verdict.or_else(|| {
             Some(if start_rule_completed {
@@ -558,25 +1966,602 @@ where
         verdict.unwrap()
     }
 
-    /// Return a pre-order CST iterator, starting at the last position that accepted the input.
-    pub fn cst_iter(&self) -> CstIter<T, M> {
-        // Collect all the entries that complete a start symbol. Search backwards from the last
-        // entry.
-        let mut stack = Vec::new();
+    /// Scan the token with the entries of chart[position], beginning at index `first`.
+    ///
+    /// Return true if at least one terminal matched.
+    fn scan(
+        &self,
+        position: usize,
+        first: usize,
+        token: &T,
+        new_state_list: &mut StateList,
+        cst_sibling_list: &mut CstList,
+    ) -> bool {
+        let mut scanned = false;
+        for state_index in self.matching_states(position, first, token) {
+            let (dr, start) = &self.chart[position][state_index];
+            // Successful, advance the dot and store in new_state
+            let new_entry = (dr.advance_dot(), *start);
+            let new_state = add_to_state_list(new_state_list, new_entry);
 
-        debug_assert!(self.valid_entries < self.chart.len());
-        debug_assert!(self.valid_entries < self.cst.len());
-        debug_assert!(self.chart.len() == self.cst.len());
-        let mut position = self.valid_entries;
-        let mut unparsed = position;
-        loop {
-            for (rule_index, rule) in self.chart[position].iter().enumerate() {
-                // If the rule indicates a completed start symbol, push it to the stack.
-                if self.grammar.dotted_is_completed_start(&rule.0) {
-                    stack.push((
-                        CstPathNode {
-                            position,
-                            state: rule_index as SymbolId,
+            // Add a sibling link if this isn't the first symbol in the rule.
+            if !dr.is_first() {
+                add_to_cst_list(
+                    cst_sibling_list,
+                    CstEdge {
+                        from_state: new_state,
+                        to_state: state_index as SymbolId,
+                        to_position: position,
+                    },
+                );
+            }
+
+            scanned = true;
+        }
+        scanned
+    }
+
+    /// Indices of the entries of chart[position], beginning at `first`, whose terminal matches
+    /// the token.
+    fn matching_states(&self, position: usize, first: usize, token: &T) -> Vec<usize> {
+        if let Some((dispatch, _)) = &self.terminal_dispatch {
+            let matching = dispatch.matching_terminals(token);
+            let nt_count = self.grammar.nt_count();
+            return self.chart[position][first..]
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (dr, _))| {
+                    let sym = *self.grammar.rhs(dr.rule as usize).get(dr.dot())? as usize;
+                    (sym >= nt_count && matching[sym - nt_count]).then_some(first + i)
+                })
+                .collect();
+        }
+        #[cfg(feature = "parallel")]
+        {
+            if let Some((_, min_states, find)) = &self.parallel_scan {
+                if self.chart[position].len() - first >= *min_states {
+                    return find(self, position, first, token);
+                }
+            }
+        }
+        matching_entries(&self.grammar, &self.chart[position][first..], token)
+            .map(|i| first + i)
+            .collect()
+    }
+
+    /// Pretend that each expected terminal has been inserted before the token at `position`,
+    /// then scan the token again.
+    ///
+    /// The inserted terminals are added to chart[position], which must be the last column. They
+    /// are marked by an error entry that starts and ends at `position`. If the token still
+    /// doesn't match, the column is restored.
+    fn insert_expected(
+        &mut self,
+        position: usize,
+        token: &T,
+        new_state_list: &mut StateList,
+        cst_sibling_list: &mut CstList,
+    ) -> bool {
+        let first = self.chart[position].len();
+        let error_state = self
+            .chart
+            .add((DottedRule::new(ERROR_ID as usize), pos(position)));
+        let mut children = Vec::new();
+        let mut siblings = Vec::new();
+        for i in 0..first {
+            let (dr, start) = self.chart[position][i].clone();
+            if let CompiledSymbol::Terminal(_) = self.grammar.dotted_symbol(&dr) {
+                let new_state = self.chart.add((dr.advance_dot(), start));
+                children.push(CstEdge {
+                    from_state: new_state,
+                    to_state: error_state,
+                    to_position: position,
+                });
+                if !dr.is_first() {
+                    siblings.push(CstEdge {
+                        from_state: new_state,
+                        to_state: i as SymbolId,
+                        to_position: position,
+                    });
+                }
+            }
+        }
+        predict_and_complete(
+            &self.grammar,
+            &self.nullable,
+            &mut self.chart,
+            position,
+            &mut children,
+            &mut siblings,
+            Prediction::All,
+        );
+        // The existing entries have been processed again. Only keep the new edges.
+        for edge in children.into_iter().chain(siblings) {
+            if !self.cst[position].contains(&edge) {
+                self.cst.push(edge);
+            }
+        }
+
+        let scanned = self.scan(position, first, token, new_state_list, cst_sibling_list);
+        if !scanned {
+            self.remove_insertion(position);
+        }
+        scanned
+    }
+
+    /// Remove the entries and edges added by [insert_expected](#method.insert_expected) from
+    /// chart[position], which must be the last column.
+    ///
+    /// They have been appended after the error entry that marks them. The first edge that was
+    /// appended starts at or leads to one of them.
+    fn remove_insertion(&mut self, position: usize) {
+        let first = self.original_len(position);
+        if first < self.chart[position].len() {
+            let edges = self.cst[position]
+                .iter()
+                .position(|e| {
+                    e.from_state as usize >= first
+                        || (e.to_position == position && e.to_state as usize >= first)
+                })
+                .unwrap_or(self.cst[position].len());
+            self.chart.truncate_last(first);
+            self.cst.truncate_last(edges);
+        }
+    }
+
+    /// Continue the constructs in progress at `position` after the token there, which becomes an
+    /// error.
+    ///
+    /// Predictions are made again after the token. Completed entries have already been processed.
+    fn skip_token(
+        &mut self,
+        position: usize,
+        cst_child_list: &mut CstList,
+        cst_sibling_list: &mut CstList,
+    ) {
+        let new_position = position + 1;
+        let error_state = self
+            .chart
+            .add((DottedRule::new(ERROR_ID as usize), pos(position)));
+        for i in 0..self.chart[position].len() {
+            let (dr, start) = self.chart[position][i].clone();
+            if self.grammar.dotted_is_completed(&dr) || (dr.is_first() && idx(start) == position) {
+                continue;
+            }
+            let new_state = self.chart.add((dr, start));
+            cst_child_list.push(CstEdge {
+                from_state: new_state,
+                to_state: error_state,
+                to_position: new_position,
+            });
+            cst_sibling_list.push(CstEdge {
+                from_state: new_state,
+                to_state: i as SymbolId,
+                to_position: position,
+            });
+        }
+    }
+
+    /// Close the innermost unfinished constructs of the synchronizing symbols as errors that end
+    /// after the token at `position`. If constructs have been closed by earlier errors, these
+    /// errors are extended over the token instead.
+    ///
+    /// Return false if there was nothing to close or extend.
+    fn synchronize(
+        &mut self,
+        position: usize,
+        symbols: &[SymbolId],
+        cst_child_list: &mut CstList,
+        cst_sibling_list: &mut CstList,
+    ) -> bool {
+        let new_position = position + 1;
+        let column = &self.chart[position];
+        let is_error = |e: &CstEdge| {
+            self.chart[e.to_position][e.to_state as usize].0.rule as usize == ERROR_ID as usize
+        };
+        // Entry after the closed construct, start of the error, predecessor of the entry
+        type Target = (ChartEntry, usize, Option<(usize, SymbolId)>);
+        let mut targets: Vec<Target> = Vec::new();
+
+        // Entries after a construct that has been closed by an earlier error
+        for (i, (dr, start)) in column.iter().enumerate() {
+            if dr.is_first() || !symbols.contains(&self.grammar.rhs(dr.rule as usize)[dr.dot() - 1])
+            {
+                continue;
+            }
+            let edges = || {
+                self.cst[position]
+                    .iter()
+                    .filter(move |e| e.from_state as usize == i)
+            };
+            let error_start = edges()
+                .find(|e| e.to_position == position && is_error(e))
+                .map(|e| idx(self.chart[position][e.to_state as usize].1));
+            if let Some(error_start) = error_start {
+                let predecessor = edges()
+                    .find(|e| !is_error(e))
+                    .map(|e| (e.to_position, e.to_state));
+                targets.push(((dr.clone(), *start), error_start, predecessor));
+            }
+        }
+
+        // Innermost unfinished constructs
+        let unfinished = |(dr, _): &&ChartEntry| {
+            symbols.contains(&self.grammar.lhs(dr.rule as usize))
+                && !self.grammar.dotted_is_completed(dr)
+        };
+        let innermost = if targets.is_empty() {
+            column.iter().filter(unfinished).map(|e| idx(e.1)).max()
+        } else {
+            None
+        };
+        if let Some(innermost) = innermost {
+            let closed: Vec<SymbolId> = column
+                .iter()
+                .filter(unfinished)
+                .filter(|e| idx(e.1) == innermost)
+                .map(|e| self.grammar.lhs(e.0.rule as usize))
+                .collect();
+            for (w, (dr, start)) in self.chart[innermost].iter().enumerate() {
+                if let CompiledSymbol::NonTerminal(nt) = self.grammar.dotted_symbol(dr) {
+                    if closed.contains(&nt) {
+                        let predecessor = if dr.is_first() {
+                            None
+                        } else {
+                            Some((innermost, w as SymbolId))
+                        };
+                        targets.push(((dr.advance_dot(), *start), innermost, predecessor));
+                    }
+                }
+            }
+        }
+
+        for (entry, error_start, predecessor) in targets.iter() {
+            let error_state = self
+                .chart
+                .add((DottedRule::new(ERROR_ID as usize), pos(*error_start)));
+            let new_state = self.chart.add(entry.clone());
+            add_to_cst_list(
+                cst_child_list,
+                CstEdge {
+                    from_state: new_state,
+                    to_state: error_state,
+                    to_position: new_position,
+                },
+            );
+            if let Some((to_position, to_state)) = predecessor {
+                add_to_cst_list(
+                    cst_sibling_list,
+                    CstEdge {
+                        from_state: new_state,
+                        to_state: *to_state,
+                        to_position: *to_position,
+                    },
+                );
+            }
+        }
+        !targets.is_empty()
+    }
+
+    /// Number of tokens from the beginning of the buffer that have been parsed.
+    pub fn valid_entries(&self) -> usize {
+        self.valid_entries
+    }
+
+    /// Parse the tokens from the start of the buffer and return the verdict of the last one.
+    ///
+    /// Parsing stops at the first rejected token. The cancel token is checked once per input
+    /// token. After cancellation, the parse is valid up to the last processed token.
+    pub fn validate<I>(
+        &mut self,
+        tokens: I,
+        cancel: Option<&CancelToken>,
+    ) -> Result<Verdict, Cancelled>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        self.buffer_changed(0);
+        let mut verdict = if self.accepted_at(0) {
+            Verdict::Accept
+        } else {
+            Verdict::More
+        };
+        for (i, t) in tokens.into_iter().enumerate() {
+            checkpoint(cancel)?;
+            verdict = self.update(i, t);
+            if verdict == Verdict::Reject || verdict == Verdict::LimitExceeded {
+                break;
+            }
+        }
+        Ok(verdict)
+    }
+
+    /// Check if the start symbol has been completed at the given position.
+    ///
+    /// The derivation may contain errors the parser recovered from, see
+    /// [accepted_at_without_errors](#method.accepted_at_without_errors). Return false if the
+    /// position has not been parsed yet.
+    pub fn accepted_at(&self, position: usize) -> bool {
+        position <= self.valid_entries
+            && self.chart[position]
+                .iter()
+                .any(|e| self.grammar.dotted_is_completed_start(&e.0))
+    }
+
+    /// Check if the start symbol has been completed at the given position without any error
+    /// recovery.
+    ///
+    /// Errors are only recovered from if a token doesn't match any terminal, so every derivation
+    /// that covers such a token contains an error. Error entries that start at `position` belong
+    /// to the token after it. Return false if the position has not been parsed yet.
+    pub fn accepted_at_without_errors(&self, position: usize) -> bool {
+        self.accepted_at(position) && !self.has_errors_up_to(position)
+    }
+
+    /// Check if any token before `position` has been recovered from.
+    ///
+    /// Error entries that start at `position` belong to the token after it.
+    fn has_errors_up_to(&self, position: usize) -> bool {
+        (0..=position).any(|p| {
+            self.chart[p].iter().any(|(dr, start)| {
+                dr.rule as usize == ERROR_ID as usize && (p < position || idx(*start) < p)
+            })
+        })
+    }
+
+    /// Check if the tokens up to `position` can be continued to an accepted input without any
+    /// error recovery.
+    ///
+    /// Return false if the position has not been parsed yet.
+    pub fn is_viable_prefix(&self, position: usize) -> bool {
+        position <= self.valid_entries
+            && !self.has_errors_up_to(position)
+            && (self.accepted_at(position)
+                || self.chart[position].iter().any(|(dr, _)| {
+                    matches!(self.grammar.dotted_symbol(dr), CompiledSymbol::Terminal(_))
+                }))
+    }
+
+    /// Return the largest completed subtrees that cover the tokens after the last accepted
+    /// position, from left to right, e.g. to style the text after an error.
+    ///
+    /// `tokens` are the tokens the parser has been fed with, including those it didn't process.
+    /// The subtrees are found by parsing the tokens again without context, so that any
+    /// non-terminal may begin where the previous subtree ended. Tokens that don't begin a
+    /// completed non-terminal are skipped. The tuples consist of symbol, start and end. Of
+    /// several symbols with the same span, the outermost one is returned.
+    pub fn tail_subtrees(&self, tokens: &[T]) -> Vec<(SymbolId, usize, usize)> {
+        let mut position = (0..=self.valid_entries)
+            .rev()
+            .find(|p| self.accepted_at(*p))
+            .unwrap_or(0);
+        let mut subtrees = Vec::new();
+        while position < tokens.len() {
+            match self.longest_subtree(&tokens[position..]) {
+                Some((symbol, len)) => {
+                    subtrees.push((symbol, position, position + len));
+                    position += len;
+                }
+                None => position += 1,
+            }
+        }
+        subtrees
+    }
+
+    /// Longest non-empty derivation of any non-terminal at the beginning of the tokens. Return
+    /// the symbol completed last and the number of tokens.
+    fn longest_subtree(&self, tokens: &[T]) -> Option<(SymbolId, usize)> {
+        let start_set = (0..self.grammar.rule_count())
+            .filter(|r| *r != ERROR_ID as usize && !self.grammar.is_removed(*r))
+            .map(|r| (DottedRule::new(r), 0))
+            .collect();
+        let mut chart = Arena::new(start_set);
+        let mut children = Vec::new();
+        let mut siblings = Vec::new();
+        predict_and_complete(
+            &self.grammar,
+            &self.nullable,
+            &mut chart,
+            0,
+            &mut children,
+            &mut siblings,
+            Prediction::All,
+        );
+        let mut longest = None;
+        for (position, token) in tokens.iter().enumerate() {
+            let token = match &self.normalizer {
+                Some(normalize) => normalize(token),
+                None => token.clone(),
+            };
+            let scanned: Vec<ChartEntry> =
+                matching_entries(&self.grammar, &chart[position], &token)
+                    .map(|i| {
+                        let (dr, start) = &chart[position][i];
+                        (dr.advance_dot(), *start)
+                    })
+                    .collect();
+            if scanned.is_empty() {
+                break;
+            }
+            chart.push_column(scanned);
+            // The tree is not needed, only the entries.
+            children.clear();
+            siblings.clear();
+            predict_and_complete(
+                &self.grammar,
+                &self.nullable,
+                &mut chart,
+                position + 1,
+                &mut children,
+                &mut siblings,
+                Prediction::All,
+            );
+            if let Some((dr, _)) = chart[position + 1].iter().rev().find(|(dr, start)| {
+                *start == 0
+                    && dr.rule as usize != ERROR_ID as usize
+                    && self.grammar.dotted_is_completed(dr)
+            }) {
+                longest = Some((self.grammar.lhs(dr.rule as usize), position + 1));
+            }
+        }
+        longest
+    }
+
+    /// List the completed start rules of the parsed part of the buffer, ordered by end position
+    /// and rule.
+    ///
+    /// A buffer is accepted if a span ends at its length. Several spans with the same end
+    /// position indicate an ambiguity of the start symbol.
+    pub fn accepted_spans(&self) -> Vec<AcceptedSpan> {
+        (0..=self.valid_entries)
+            .flat_map(|end| {
+                self.chart[end]
+                    .iter()
+                    .filter(|(dr, _)| self.grammar.dotted_is_completed_start(dr))
+                    .map(move |(dr, start)| AcceptedSpan {
+                        rule: dr.rule as usize,
+                        start: idx(*start),
+                        end,
+                    })
+                    .sorted_by_key(|span| span.rule)
+            })
+            .collect()
+    }
+
+    /// Iterate over the entries of the parsed part of the chart, column by column in the order
+    /// they have been added.
+    ///
+    /// Meant for tools and tests that inspect the parser state. Columns before the last
+    /// [compact](#method.compact) only keep the entries that are still referenced.
+    pub fn chart_items(&self) -> impl Iterator<Item = ChartItem> + '_ {
+        (0..=self.valid_entries).flat_map(move |position| self.chart_column(position))
+    }
+
+    /// Iterate over the entries of the chart column at `position`. The column is empty if it
+    /// hasn't been parsed.
+    pub fn chart_column(&self, position: usize) -> impl Iterator<Item = ChartItem> + '_ {
+        let column: &[ChartEntry] = if position <= self.valid_entries {
+            &self.chart[position]
+        } else {
+            &[]
+        };
+        column.iter().map(move |(dr, origin)| ChartItem {
+            position,
+            dotted_rule: dr.clone(),
+            origin: idx(*origin),
+        })
+    }
+
+    /// Return a CST iterator that only yields the nodes overlapping the buffer span from `start`
+    /// to `end`, e.g. the visible part of a document.
+    ///
+    /// Nodes that don't cover any token overlap if they are placed strictly inside the span. Thus,
+    /// a node is only skipped together with its parent. The unparsed part of the buffer is reported
+    /// as by [cst_iter](#method.cst_iter).
+    pub fn cst_iter_range(&self, start: usize, end: usize) -> CstIter<'_, T, M> {
+        let mut iter = self.cst_iter();
+        iter.range = Some((start, end));
+        let stack = std::mem::take(&mut iter.stack);
+        iter.stack = stack
+            .into_iter()
+            .filter(|(node, _)| iter.overlaps(node))
+            .collect();
+        iter
+    }
+
+    /// Return the innermost completed node of the parse tree that contains the token at
+    /// `position`, together with its path.
+    ///
+    /// Only the nodes on the way from the root to the result are visited. Return `None` if the
+    /// token hasn't been parsed.
+    pub fn node_at(&self, position: usize) -> Option<CstIterItemNode> {
+        let contains = |node: &CstPathNode| {
+            let (start, end) = self.span(node);
+            start <= position && position < end
+        };
+        let mut current = self
+            .cst_iter()
+            .stack
+            .into_iter()
+            .map(|(node, _)| node)
+            .find(|node| contains(node))?;
+        let mut visited = HashSet::new();
+        let mut path = Vec::new();
+        let mut found: Option<CstPathNode> = None;
+        while visited.insert((current.position, current.state)) {
+            if self
+                .grammar
+                .dotted_is_completed(&self.dotted_rule(&current))
+            {
+                path.extend(found.take());
+                found = Some(current.clone());
+            }
+            let edges = self.cst[current.position]
+                .iter()
+                .filter(|edge| edge.from_state == current.state)
+                .collect();
+            let next = self
+                .disambiguate(edges)
+                .into_iter()
+                .map(|edge| CstPathNode {
+                    position: edge.to_position,
+                    state: edge.to_state,
+                })
+                .find(|node| contains(node));
+            match next {
+                Some(node) => current = node,
+                None => break,
+            }
+        }
+        let current = found?;
+        let (start, end) = self.span(&current);
+        let dotted_rule = self.dotted_rule(&current);
+        Some(CstIterItemNode {
+            start,
+            end,
+            lhs: self.grammar.lhs(dotted_rule.rule as usize),
+            dotted_rule,
+            path: CstPath(path),
+            current,
+        })
+    }
+
+    /// Return a cursor at the root of the parse tree, i.e. the start symbol that has been completed
+    /// at the last position that accepted the input.
+    ///
+    /// Return `None` if no part of the input has been accepted.
+    pub fn cst_cursor(&self) -> Option<CstCursor<'_, T, M>> {
+        let roots: Vec<CstPathNode> = self.cst_iter().stack.into_iter().map(|(n, _)| n).collect();
+        let root = roots
+            .iter()
+            .find(|node| self.span(node).0 == 0)
+            .or_else(|| roots.first())?
+            .clone();
+        Some(CstCursor {
+            parser: self,
+            levels: vec![(vec![root], 0)],
+        })
+    }
+
+    /// Return a pre-order CST iterator, starting at the last position that accepted the input.
+    pub fn cst_iter(&self) -> CstIter<T, M> {
+        // Collect all the entries that complete a start symbol. Search backwards from the last
+        // entry.
+        let mut stack = Vec::new();
+
+        debug_assert!(self.valid_entries < self.chart.len());
+        debug_assert!(self.valid_entries < self.cst.len());
+        debug_assert!(self.chart.len() == self.cst.len());
+        let mut position = self.valid_entries;
+        let mut unparsed = position;
+        loop {
+            for (rule_index, rule) in self.chart[position].iter().enumerate() {
+                // If the rule indicates a completed start symbol, push it to the stack.
+                if self.grammar.dotted_is_completed_start(&rule.0) {
+                    stack.push((
+                        CstPathNode {
+                            position,
+                            state: rule_index as SymbolId,
                         },
                         false,
                     ));
@@ -592,12 +2577,57 @@ where
             unparsed = position;
         }
 
+        // With a strategy, only one complete parse is visited. Completed start symbols that don't
+        // start at 0 are nested in that parse.
+        if let Some(strategy) = &self.disambiguation {
+            if stack.iter().any(|(node, _)| self.span(node).0 == 0) {
+                stack.retain(|(node, _)| self.span(node).0 == 0);
+            }
+            if stack.len() > 1 {
+                let alternatives: Vec<Alternative> = stack
+                    .iter()
+                    .map(|(node, _)| Alternative {
+                        rule: self.dotted_rule(node).rule as usize,
+                        start: self.span(node).0,
+                        end: node.position,
+                    })
+                    .collect();
+                let chosen = stack.swap_remove(strategy.choose(&alternatives));
+                stack = vec![chosen];
+            }
+        }
+
         CstIter {
             parser: &self,
             stack,
             unparsed,
             done: false,
+            single_tree: true,
+            range: None,
+        }
+    }
+
+    /// Return the non-terminals that have been completed with a non-empty span ending at the given
+    /// position, innermost first.
+    ///
+    /// Returned tuples consist of symbol and start position. As all completed entries of the chart
+    /// are considered, some of them might not be part of the final parse tree. Return an empty
+    /// vector if the position was invalid.
+    pub fn completions_at(&self, position: usize) -> Vec<(SymbolId, usize)> {
+        if position > self.valid_entries {
+            return Vec::new();
         }
+        self.chart[position]
+            .iter()
+            .filter(|(dr, start)| {
+                idx(*start) < position
+                    && dr.rule as usize != ERROR_ID as usize
+                    && self.grammar.dotted_is_completed(dr)
+            })
+            .map(|(dr, start)| (self.grammar.lhs(dr.rule as usize), idx(*start)))
+            .unique()
+            .sorted_by_key(|(_, start)| std::cmp::Reverse(*start))
+            .collect()
     }
 
     /// Return the full set of symbols that could be parsed from the given position, including the
@@ -605,48 +2635,21 @@ where
     ///
     /// Return an empty vector if the position was invalid.
     ///
-    /// Returned tuples consist of possible symbol and start position.
+    /// Returned tuples consist of possible symbol and start position. They are taken from the
+    /// chart entries at the position in reverse order, so the cost depends only on the size of
+    /// that column and not on the size of the parse tree.
     pub fn full_predictions(&self, position: usize) -> Vec<(SymbolId, usize)> {
         if position > self.valid_entries {
             return Vec::new();
         }
-
-        // Collect all the entries at the position
-        let mut stack = Vec::new();
-
-        for rule_index in 0..self.chart[position].len() {
-            stack.push((
-                CstPathNode {
-                    position,
-                    state: rule_index as SymbolId,
-                },
-                false,
-            ));
-        }
-
-        CstIter {
-            parser: &self,
-            stack,
-            unparsed: position,
-            done: false,
-        }
-        .filter_map(|n| match n {
-            CstIterItem::Parsed(n) => {
-                if n.start != position
-                    && n.end == position
-                    && !self.grammar.dotted_is_completed(&n.dotted_rule)
-                {
-                    let lhs = self.grammar.lhs(n.dotted_rule.rule as usize);
-                    Some((lhs, n.start))
-                } else {
-                    None
-                }
-            }
-
-            CstIterItem::Unparsed(_unparsed) => None,
-        })
-        .unique()
-        .collect()
+        // Every node of the tree that ends at the position is an entry of its column.
+        self.chart[position]
+            .iter()
+            .rev()
+            .filter(|(dr, start)| idx(*start) != position && !self.grammar.dotted_is_completed(dr))
+            .map(|(dr, start)| (self.grammar.lhs(dr.rule as usize), idx(*start)))
+            .unique()
+            .collect()
     }
 
     /// Iterate through the predictions in the same order that the cst would generate them.
@@ -671,64 +2674,624 @@ where
             .unique()
             .collect()
     }
-}
-
-impl<'a, T, M> Iterator for CstIter<'a, T, M>
-where
-    M: Matcher<T> + Clone,
-{
-    type Item = CstIterItem;
 
-    fn next(&mut self) -> Option<CstIterItem> {
-        // Traverse the tree
-        // Algo
-        // - If the stack is empty, switch to end sequence (return unparsed, then none)
-        // - Get the top-of-stack (TOS) item, but leave it on the stack. There is at least one entry.
-        // - If the TOS is marked as completed, return it. In that case, all outgoing nodes
-        //   have been processed in previous calls.
-        // - Mark the TOS as completed. If there are outgoing edges, the will be processed before
-        //   the TOS. If we return to this entry later, we know, it has been processed and can be
-        //   returned.
-        // - Process the ooutgoing edges in order. This will process the parent/child links (i.e. downward
-        //   links) first. That way, thwy will be put on the stack first, i.e. processed later.
-        // - Put the node the edge points to on the stack, mark as incomplete.
-        // - Continue with the new TOS item.
-        loop {
-            if let Some(tos) = self.stack.last_mut() {
-                if tos.1 {
-                    // TOS is complete
-                    let tos = self.stack.pop().unwrap();
-                    let state = &self.parser.chart[tos.0.position][tos.0.state as usize];
-                    let start = state.1;
-                    let end = tos.0.position;
-                    // The path is the list of completed, processed entries on the stack.
-                    let path = CstPath(
-                        self.stack
-                            .iter()
-                            .filter_map(|(node, processed)| {
-                                let is_result = if *processed {
-                                    let dr =
-                                        &self.parser.chart[node.position][node.state as usize].0;
-                                    self.parser.grammar.dotted_symbol(dr).is_complete()
-                                } else {
-                                    false
-                                };
-                                if is_result {
-                                    Some(node.clone())
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect(),
-                    );
+    /// Return the symbols of `stop` that could begin at the given position, skipping helper
+    /// symbols in between.
+    ///
+    /// Starting with the symbols the entries in progress wait for, the rules of all symbols not in
+    /// `stop` are expanded through their leading non-terminals up to and including the first one
+    /// that doesn't derive the empty string. The symbols are returned in the order they have been
+    /// found.
+    pub fn expanded_predictions(&self, position: usize, stop: &[SymbolId]) -> Vec<SymbolId> {
+        if position > self.valid_entries {
+            return Vec::new();
+        }
+        let mut todo: Vec<SymbolId> = self.chart[position]
+            .iter()
+            .rev()
+            .filter(|(dr, start)| !(dr.is_first() && idx(*start) == position))
+            .filter_map(|(dr, _)| match self.grammar.dotted_symbol(dr) {
+                CompiledSymbol::NonTerminal(nt) => Some(nt),
+                _ => None,
+            })
+            .collect();
+        if position == 0 {
+            todo.push(self.grammar.start());
+        }
+        let nt_count = self.grammar.nt_count();
+        let mut visited = vec![false; nt_count];
+        let mut found = Vec::new();
+        while let Some(sym) = todo.pop() {
+            if visited[sym as usize] {
+                continue;
+            }
+            visited[sym as usize] = true;
+            if stop.contains(&sym) {
+                found.push(sym);
+                continue;
+            }
+            // Push in reverse to visit the rules in order
+            let mut expanded = Vec::new();
+            for rule in self.grammar.rules_for_lhs(sym) {
+                for s in self.grammar.rhs(*rule) {
+                    if *s as usize >= nt_count {
+                        break;
+                    }
+                    expanded.push(*s);
+                    if !self.nullable[*s as usize] {
+                        break;
+                    }
+                }
+            }
+            todo.extend(expanded.into_iter().rev());
+        }
+        found
+    }
 
-                    let node = CstIterItemNode {
-                        start,
-                        end,
-                        dotted_rule: state.0.clone(),
-                        path,
-                        current: tos.0.clone(),
-                    };
+    /// Return the terminals that could be scanned at the given position, sorted and without
+    /// duplicates.
+    ///
+    /// Return an empty vector if the position was invalid.
+    pub fn expected_terminals(&self, position: usize) -> Vec<M>
+    where
+        M: Ord,
+    {
+        if position > self.valid_entries {
+            return Vec::new();
+        }
+        self.chart[position][..self.original_len(position)]
+            .iter()
+            .filter_map(|state| match self.grammar.dotted_symbol(&state.0) {
+                CompiledSymbol::Terminal(t) => Some(t),
+                _ => None,
+            })
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Return tokens that could be inserted at the given position, synthesized from the expected
+    /// terminals, e.g. to offer a missing closing bracket.
+    ///
+    /// Tokens that continue the constructs in progress come before those that begin new ones.
+    /// Terminals that cannot be synthesized are left out. Return an empty vector if the position
+    /// was invalid.
+    pub fn suggest_insertion(&self, position: usize) -> Vec<T>
+    where
+        T: PartialEq,
+        M: Synthesize<T>,
+    {
+        if position > self.valid_entries {
+            return Vec::new();
+        }
+        let mut suggestions = Vec::new();
+        for (dr, _) in self.chart[position][..self.original_len(position)].iter() {
+            if let CompiledSymbol::Terminal(t) = self.grammar.dotted_symbol(dr) {
+                if let Some(token) = t.synthesize() {
+                    if !suggestions.contains(&token) {
+                        suggestions.push(token);
+                    }
+                }
+            }
+        }
+        suggestions
+    }
+
+    /// Number of entries of chart[position] without the terminals inserted by
+    /// [RecoveryPolicy::InsertExpected](enum.RecoveryPolicy.html#variant.InsertExpected).
+    fn original_len(&self, position: usize) -> usize {
+        self.chart[position]
+            .iter()
+            .position(|(dr, start)| {
+                dr.rule as usize == ERROR_ID as usize && idx(*start) == position
+            })
+            .unwrap_or(self.chart[position].len())
+    }
+
+    /// List the tokens that didn't match, in buffer order.
+    ///
+    /// `tokens` are the tokens the parser has been fed with. If a construct has been closed by
+    /// [RecoveryPolicy::Synchronize](enum.RecoveryPolicy.html#variant.Synchronize), only the first
+    /// of the skipped tokens is listed. After a hard reject, only the rejected token is listed.
+    pub fn errors(&self, tokens: &[T]) -> Vec<ParseError<T, M>>
+    where
+        T: Clone,
+    {
+        let is_error = |(dr, _): &ChartEntry| dr.rule as usize == ERROR_ID as usize;
+        let mut errors = Vec::new();
+        for (position, token) in tokens.iter().enumerate().take(self.valid_entries) {
+            let next = position + 1;
+            let inserted = self.original_len(position) < self.chart[position].len();
+            // Errors that end after the token. Those of a closed construct that was already
+            // skipping tokens continue an earlier error.
+            let error_states: Vec<usize> = (0..self.chart[next].len())
+                .filter(|i| {
+                    let entry = &self.chart[next][*i];
+                    is_error(entry)
+                        && idx(entry.1) <= position
+                        && !(idx(entry.1) < position
+                            && self.chart[position]
+                                .iter()
+                                .any(|e| is_error(e) && e.1 == entry.1))
+                })
+                .collect();
+            let rejected = self.chart[next].is_empty() && !self.chart[position].is_empty();
+            if !inserted && error_states.is_empty() && !rejected {
+                continue;
+            }
+
+            // The innermost entry that links to the error
+            let (column, targets) = if inserted {
+                (position, vec![self.original_len(position)])
+            } else {
+                (next, error_states)
+            };
+            let parent_state = self.cst[column]
+                .iter()
+                .filter(|e| e.to_position == column && targets.contains(&(e.to_state as usize)))
+                .map(|e| e.from_state as usize)
+                .max_by_key(|state| self.chart[column][*state].1);
+            let parent = parent_state.map(|state| &self.chart[column][state]);
+            let closed = match (parent, &self.recovery) {
+                (Some((dr, _)), RecoveryPolicy::Synchronize(_)) => !inserted && !dr.is_first(),
+                _ => false,
+            };
+            let recovered_as = match parent {
+                Some((dr, _)) if closed => self.grammar.rhs(dr.rule as usize)[dr.dot() - 1],
+                Some((dr, _)) => self.grammar.lhs(dr.rule as usize),
+                None => ERROR_ID,
+            };
+            // A skipped token leaves the entries in progress unchanged.
+            let deleted = |state: usize| {
+                self.cst[next].iter().any(|e| {
+                    e.from_state as usize == state
+                        && e.to_position == position
+                        && self.chart[position][e.to_state as usize] == self.chart[next][state]
+                })
+            };
+            let repair = match parent_state {
+                _ if inserted => Repair::Insert,
+                _ if closed => Repair::Synchronize,
+                Some(state) if deleted(state) => Repair::Delete,
+                Some(_) => Repair::Replace,
+                None => Repair::Reject,
+            };
+
+            let nt_count = self.grammar.nt_count();
+            let expected = self.chart[position][..self.original_len(position)]
+                .iter()
+                .filter_map(|(dr, _)| {
+                    let rhs = self.grammar.rhs(dr.rule as usize);
+                    rhs.get(dr.dot())
+                        .filter(|sym| **sym as usize >= nt_count)
+                        .map(|sym| *sym as usize - nt_count)
+                })
+                .sorted()
+                .dedup()
+                .map(|terminal| self.grammar.terminals()[terminal].clone())
+                .collect();
+            errors.push(ParseError {
+                position,
+                found: token.clone(),
+                expected,
+                recovered_as,
+                repair,
+            });
+        }
+        errors
+    }
+
+    /// Remove the chart entries at positions up to and including `up_to` that are neither part of
+    /// the CST nor needed to continue parsing.
+    ///
+    /// Kept are all entries reachable via CST edges from later positions, from the predicted and
+    /// in-progress entries at `up_to` and from the last accepting position up to `up_to`, as well
+    /// as the entries that in-progress entries might complete in the future. The CST edges are
+    /// rewritten to the new entry indices, so `cst_iter` returns the same items before and after.
+    /// Columns beyond the valid part of the chart are dropped, too, as they would be recomputed
+    /// anyway.
+    ///
+    /// Afterwards, the buffer must not be changed before `up_to`. Predictions and acceptance at
+    /// positions before `up_to` are no longer reliable.
+    ///
+    /// Return the number of removed entries. Nothing is removed if `up_to` is beyond the valid part
+    /// of the chart.
+    pub fn compact(&mut self, up_to: usize) -> usize {
+        if up_to > self.valid_entries {
+            return 0;
+        }
+        let columns = self.valid_entries + 1;
+        self.chart.truncate(columns);
+        self.cst.truncate(columns);
+
+        // Mark the entries to keep.
+        let mut todo = Vec::new();
+        for position in (up_to + 1)..columns {
+            for state in 0..self.chart[position].len() {
+                todo.push((position, state));
+            }
+        }
+        for (state, entry) in self.chart[up_to].iter().enumerate() {
+            if entry.0.is_first() || !self.grammar.dotted_is_completed(&entry.0) {
+                todo.push((up_to, state));
+            }
+        }
+        if let Some(position) = (0..=up_to).rev().find(|p| self.accepted_at(*p)) {
+            for (state, entry) in self.chart[position].iter().enumerate() {
+                if self.grammar.dotted_is_completed_start(&entry.0) {
+                    todo.push((position, state));
+                }
+            }
+        }
+        self.compact_reachable(up_to, todo, true, true)
+    }
+
+    /// Remove all chart entries that are not part of the CST of the accepted input, i.e. the
+    /// predictions and in-progress entries that would only be needed to continue parsing.
+    ///
+    /// Only the first column is kept completely. Afterwards, the parse can only be restarted at
+    /// the beginning of the buffer, while `cst_iter` returns the same items as before.
+    ///
+    /// Return the number of removed entries. Nothing is removed if the parsed part of the buffer
+    /// has not been accepted.
+    pub fn compact_accepted(&mut self) -> usize {
+        let up_to = self.valid_entries;
+        if !self.accepted_at(up_to) {
+            return 0;
+        }
+        self.chart.truncate(up_to + 1);
+        self.cst.truncate(up_to + 1);
+        let mut todo: Vec<(usize, usize)> = (0..self.chart[0].len()).map(|s| (0, s)).collect();
+        for (state, entry) in self.chart[up_to].iter().enumerate() {
+            if self.grammar.dotted_is_completed_start(&entry.0) {
+                todo.push((up_to, state));
+            }
+        }
+        self.compact_reachable(up_to, todo, false, true)
+    }
+
+    /// Finalize the parse before `position` for stream parsing: pass the nodes of the CST that end
+    /// after the previous commit and not after `position` to `sink`, children first, then remove
+    /// all entries before `position` that are not needed to continue parsing.
+    ///
+    /// Afterwards, the CST only contains the nodes that end after `position` and their ancestors.
+    /// Their children before `position` are omitted. The buffer must not be changed before
+    /// `position`. Each removed column still occupies one offset in the chart and the CST.
+    ///
+    /// Return the number of removed entries. Nothing is done if `position` is beyond the valid part
+    /// of the chart or before the previous commit.
+    pub fn commit(&mut self, position: usize, sink: &mut dyn FnMut(&CstIterItemNode)) -> usize {
+        if position > self.valid_entries || position < self.committed {
+            return 0;
+        }
+        for item in self.cst_iter() {
+            if let CstIterItem::Parsed(node) = item {
+                if self.committed < node.end && node.end <= position {
+                    sink(&node);
+                }
+            }
+        }
+        self.committed = position;
+
+        let columns = self.valid_entries + 1;
+        self.chart.truncate(columns);
+        self.cst.truncate(columns);
+        let todo = (position..columns)
+            .flat_map(|column| (0..self.chart[column].len()).map(move |state| (column, state)))
+            .collect();
+        self.compact_reachable(position, todo, true, false)
+    }
+
+    /// Keep the entries of the columns after `up_to` and those in `todo`. If `follow_edges` is set,
+    /// keep the entries reachable via CST edges, too. Otherwise, the edges to removed entries are
+    /// dropped. If `follow_waiting` is set, keep the entries that in-progress entries might
+    /// complete in the future.
+    ///
+    /// Return the number of removed entries.
+    fn compact_reachable(
+        &mut self,
+        up_to: usize,
+        mut todo: Vec<(usize, usize)>,
+        follow_waiting: bool,
+        follow_edges: bool,
+    ) -> usize {
+        let columns = self.valid_entries + 1;
+        let mut keep: Vec<Vec<bool>> = (0..=up_to)
+            .map(|position| vec![false; self.chart[position].len()])
+            .collect();
+        while let Some((position, state)) = todo.pop() {
+            if position <= up_to {
+                if keep[position][state] {
+                    continue;
+                }
+                keep[position][state] = true;
+            }
+            if follow_edges {
+                for edge in self.cst[position].iter() {
+                    if edge.from_state as usize == state && edge.to_position <= up_to {
+                        todo.push((edge.to_position, edge.to_state as usize));
+                    }
+                }
+            }
+            // When an in-progress entry completes, the entries at its start that wait for its lhs
+            // will be advanced.
+            let (dr, start) = &self.chart[position][state];
+            let start = idx(*start);
+            if follow_waiting && start <= up_to && !self.grammar.dotted_is_completed(dr) {
+                let lhs = self.grammar.lhs(dr.rule as usize);
+                for (waiting, entry) in self.chart[start].iter().enumerate() {
+                    if let CompiledSymbol::NonTerminal(nt) = self.grammar.dotted_symbol(&entry.0) {
+                        if nt == lhs {
+                            todo.push((start, waiting));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Map the old indices to the new ones
+        let new_index: Vec<Vec<Option<SymbolId>>> = keep
+            .iter()
+            .map(|kept| {
+                let mut next = 0;
+                kept.iter()
+                    .map(|k| {
+                        if *k {
+                            next += 1;
+                            Some(next - 1)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Rebuild both arenas without the removed entries.
+        let old_count = self.chart.entry_count();
+        let mut chart = Arena::new(Vec::new());
+        let mut cst = Arena::new(Vec::new());
+        for position in 0..columns {
+            let kept = |state: usize| position > up_to || keep[position][state];
+            let state_list = self.chart[position]
+                .iter()
+                .enumerate()
+                .filter(|(state, _)| kept(*state))
+                .map(|(_, entry)| entry.clone());
+            let cst_list = self.cst[position]
+                .iter()
+                .filter(|edge| kept(edge.from_state as usize))
+                .filter_map(|edge| {
+                    Some(CstEdge {
+                        from_state: if position <= up_to {
+                            new_index[position][edge.from_state as usize]
+                                .expect("origin of edge should have been kept")
+                        } else {
+                            edge.from_state
+                        },
+                        to_state: if edge.to_position <= up_to {
+                            new_index[edge.to_position][edge.to_state as usize]?
+                        } else {
+                            edge.to_state
+                        },
+                        to_position: edge.to_position,
+                    })
+                });
+            if position == 0 {
+                chart = Arena::new(state_list.collect());
+                cst = Arena::new(cst_list.collect());
+            } else {
+                chart.push_column(state_list);
+                cst.push_column(cst_list);
+            }
+        }
+        let reclaimed = old_count - chart.entry_count();
+        self.chart = chart;
+        self.cst = cst;
+        self.suffix = None;
+        reclaimed
+    }
+}
+
+impl<T, M> Parser<T, M>
+where
+    M: Matcher<T> + Clone,
+{
+    /// Check if the edge links to a completed child. Error entries are handled like terminals.
+    fn is_child(&self, e: &CstEdge) -> bool {
+        let dr = &self.chart[e.to_position][e.to_state as usize].0;
+        dr.rule as usize != ERROR_ID as usize && self.grammar.dotted_is_completed(dr)
+    }
+
+    /// Keep the edges of one derivation if a disambiguation strategy is set.
+    ///
+    /// The strategy selects one of the child edges. The sibling edges to the start of that child
+    /// are kept.
+    fn disambiguate<'e>(&self, edges: Vec<&'e CstEdge>) -> Vec<&'e CstEdge> {
+        let strategy = match &self.disambiguation {
+            Some(strategy) => strategy,
+            None => return edges,
+        };
+        let children: Vec<&CstEdge> = edges.iter().copied().filter(|e| self.is_child(e)).collect();
+        if children.len() < 2 {
+            return edges;
+        }
+        let alternatives: Vec<Alternative> = children
+            .iter()
+            .map(|e| {
+                let (dr, start) = &self.chart[e.to_position][e.to_state as usize];
+                Alternative {
+                    rule: dr.rule as usize,
+                    start: idx(*start),
+                    end: e.to_position,
+                }
+            })
+            .collect();
+        let chosen = strategy.choose(&alternatives);
+        let split = alternatives[chosen].start;
+        edges
+            .into_iter()
+            .filter(|e| {
+                std::ptr::eq(*e, children[chosen]) || (!self.is_child(e) && e.to_position == split)
+            })
+            .collect()
+    }
+}
+
+impl<'a, T, M> CstCursor<'a, T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    /// Current node
+    pub fn node(&self) -> &CstPathNode {
+        let (nodes, index) = self.levels.last().expect("cursor should have a root level");
+        &nodes[*index]
+    }
+
+    /// Dotted rule of the current node. The dot is at the end.
+    pub fn dotted_rule(&self) -> DottedRule {
+        self.parser.dotted_rule(self.node())
+    }
+
+    /// Start and end position of the current node
+    pub fn span(&self) -> (usize, usize) {
+        self.parser.span(self.node())
+    }
+
+    /// Number of levels above the current node
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// Move to the parent node. Return false if the cursor is at the root.
+    pub fn goto_parent(&mut self) -> bool {
+        if self.levels.len() > 1 {
+            self.levels.pop();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move to the first child node. Return false if the current node has no children.
+    pub fn goto_first_child(&mut self) -> bool {
+        let children = self.parser.children(self.node());
+        if children.is_empty() {
+            false
+        } else {
+            self.levels.push((children, 0));
+            true
+        }
+    }
+
+    /// Move to the next sibling node. Return false if the current node is the last child.
+    pub fn goto_next_sibling(&mut self) -> bool {
+        let (nodes, index) = self
+            .levels
+            .last_mut()
+            .expect("cursor should have a root level");
+        if *index + 1 < nodes.len() {
+            *index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Move to the previous sibling node. Return false if the current node is the first child.
+    pub fn goto_previous_sibling(&mut self) -> bool {
+        let (_, index) = self
+            .levels
+            .last_mut()
+            .expect("cursor should have a root level");
+        if *index > 0 {
+            *index -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<'a, T, M> CstIter<'a, T, M>
+where
+    M: Matcher<T>,
+{
+    /// Check if the node overlaps the range of the iterator, if any.
+    fn overlaps(&self, node: &CstPathNode) -> bool {
+        match self.range {
+            Some((start, end)) => {
+                let node_start = idx(self.parser.chart[node.position][node.state as usize].1);
+                let node_end = node.position;
+                if node_start == node_end {
+                    start < node_start && node_start < end
+                } else {
+                    node_start < end && start < node_end
+                }
+            }
+            None => true,
+        }
+    }
+}
+
+impl<'a, T, M> Iterator for CstIter<'a, T, M>
+where
+    M: Matcher<T> + Clone,
+{
+    type Item = CstIterItem;
+
+    fn next(&mut self) -> Option<CstIterItem> {
+        // Traverse the tree
+        // Algo
+        // - If the stack is empty, switch to end sequence (return unparsed, then none)
+        // - Get the top-of-stack (TOS) item, but leave it on the stack. There is at least one entry.
+        // - If the TOS is marked as completed, return it. In that case, all outgoing nodes
+        //   have been processed in previous calls.
+        // - Mark the TOS as completed. If there are outgoing edges, the will be processed before
+        //   the TOS. If we return to this entry later, we know, it has been processed and can be
+        //   returned.
+        // - Process the ooutgoing edges in order. This will process the parent/child links (i.e. downward
+        //   links) first. That way, thwy will be put on the stack first, i.e. processed later.
+        // - Put the node the edge points to on the stack, mark as incomplete.
+        // - Continue with the new TOS item.
+        loop {
+            if let Some(tos) = self.stack.last_mut() {
+                if tos.1 {
+                    // TOS is complete
+                    let tos = self.stack.pop().unwrap();
+                    let state = &self.parser.chart[tos.0.position][tos.0.state as usize];
+                    let start = idx(state.1);
+                    let end = tos.0.position;
+                    // The path is the list of completed, processed entries on the stack.
+                    let path = CstPath(
+                        self.stack
+                            .iter()
+                            .filter_map(|(node, processed)| {
+                                let is_result = if *processed {
+                                    let dr =
+                                        &self.parser.chart[node.position][node.state as usize].0;
+                                    self.parser.grammar.dotted_symbol(dr).is_complete()
+                                } else {
+                                    false
+                                };
+                                if is_result {
+                                    Some(node.clone())
+                                } else {
+                                    None
+                                }
+                            })
+                            .collect(),
+                    );
+
+                    let node = CstIterItemNode {
+                        start,
+                        end,
+                        dotted_rule: state.0.clone(),
+                        lhs: self.parser.grammar.lhs(state.0.rule as usize),
+                        path,
+                        current: tos.0.clone(),
+                    };
                     return Some(CstIterItem::Parsed(node));
                 } else {
                     // TOS is no processed yet, mark it and process.
@@ -736,12 +3299,19 @@ where
                     // Find the edges and put the node they point to on the stack.
                     let from_state = tos.0.state;
                     let from_position = tos.0.position;
-                    for edge in self.parser.cst[from_position].iter() {
-                        if edge.from_state == from_state {
-                            let node = CstPathNode {
-                                position: edge.to_position,
-                                state: edge.to_state,
-                            };
+                    let mut edges: Vec<&CstEdge> = self.parser.cst[from_position]
+                        .iter()
+                        .filter(|edge| edge.from_state == from_state)
+                        .collect();
+                    if self.single_tree {
+                        edges = self.parser.disambiguate(edges);
+                    }
+                    for edge in edges {
+                        let node = CstPathNode {
+                            position: edge.to_position,
+                            state: edge.to_state,
+                        };
+                        if self.overlaps(&node) {
                             self.stack.push((node, false));
                         }
                     }
@@ -758,21 +3328,101 @@ where
     }
 }
 
+#[cfg(feature = "parallel")]
 impl<T, M> Parser<T, M>
 where
-    M: Matcher<T> + Clone + PartialEq + std::fmt::Debug,
+    T: Clone + Sync,
+    M: Matcher<T> + Clone + Sync,
 {
-    pub fn print_chart(&self) {
+    /// Match the tokens against the terminals of columns with at least `min_states` entries on
+    /// a pool of `threads` threads. Disable the parallel scan with less than two threads.
+    ///
+    /// The pool is created once and kept by the parser. Still, handing a column to the pool costs
+    /// more than scanning a few hundred entries sequentially, so `min_states` should be in the
+    /// thousands. The benchmarks `scan/sequential` and `scan/parallel` compare both on the
+    /// machine at hand.
+    ///
+    /// The results of the threads are merged in the order of the entries, so the chart is the
+    /// same as with the sequential scan. Prediction and completion are not parallelized. If the
+    /// pool cannot be created, a warning is logged and the scan stays sequential.
+    pub fn set_parallel_scan(&mut self, threads: usize, min_states: usize) {
+        self.parallel_scan = None;
+        if threads > 1 {
+            match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+                Ok(pool) => {
+                    self.parallel_scan =
+                        Some((std::sync::Arc::new(pool), min_states, Self::scan_parallel))
+                }
+                Err(e) => warn!("parallel scan disabled: {}", e),
+            }
+        }
+    }
+
+    /// Find the matching entries of chart[position] beginning at `first` with one slice of the
+    /// column per thread of the pool.
+    fn scan_parallel(&self, position: usize, first: usize, token: &T) -> Vec<usize> {
+        use rayon::prelude::*;
+
+        let states = &self.chart[position][first..];
+        let pool = match &self.parallel_scan {
+            Some((pool, _, _)) => pool,
+            None => {
+                return matching_entries(&self.grammar, states, token)
+                    .map(|i| first + i)
+                    .collect()
+            }
+        };
+        let chunk_size = std::cmp::max(1, states.len().div_ceil(pool.current_num_threads()));
+        let grammar = &self.grammar;
+        pool.install(|| {
+            states
+                .par_chunks(chunk_size)
+                .enumerate()
+                .flat_map_iter(|(chunk, chunk_states)| {
+                    matching_entries(grammar, chunk_states, token)
+                        .map(move |i| first + chunk * chunk_size + i)
+                })
+                .collect()
+        })
+    }
+}
+
+impl<T, M> Parser<T, M>
+where
+    M: Matcher<T> + Clone + MatcherDisplay,
+{
+    /// Write the parsed part of the chart.
+    ///
+    /// Debug function. Creates unicode characters that might not display correctly on old
+    /// terminals.
+    pub fn write_chart(&self, writer: &mut dyn Write) -> std::io::Result<()> {
         for i in 0..=self.valid_entries {
-            println!("chart[{}]:", i);
+            writeln!(writer, "chart[{}]:", i)?;
             for e in self.chart[i].iter() {
-                println!(
-                    "  {}, [{}]",
-                    self.grammar.dotted_rule_to_string(&e.0).unwrap(),
-                    e.1
-                );
+                write!(writer, "  ")?;
+                self.grammar.write_dotted_rule(writer, &e.0)?;
+                writeln!(writer, ", [{}]", e.1)?;
             }
         }
+        Ok(())
+    }
+
+    /// Write the parsed part of the chart to the debug sink, or to stdout if there is none.
+    #[deprecated(note = "use write_chart or set_debug_sink")]
+    pub fn print_chart(&self) {
+        let _ = match self.debug_sink.borrow_mut().as_mut() {
+            Some(sink) => self.write_chart(sink),
+            None => self.write_chart(&mut std::io::stdout()),
+        };
+    }
+
+    /// Write the states of the rejected column to the debug sink whenever a token is rejected.
+    pub fn set_debug_dump_on_reject(&mut self, on: bool) {
+        self.dump_on_reject = if on {
+            Some(CompiledGrammar::write_dotted_rule)
+        } else {
+            None
+        };
     }
 
     pub fn trace_chart(&self) {
@@ -790,85 +3440,718 @@ where
     }
 }
 
-impl CstIterItemNode {
-    pub fn path_iter(&self) -> impl Iterator<Item = &CstPathNode> {
-        self.path.0.iter()
+impl<T, M> Parser<T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone + MatcherDisplay,
+{
+    /// Write the nodes of the parse tree in the order of the [CST iterator](#method.cst_iter),
+    /// indented by their depth, followed by the start of the unparsed part.
+    ///
+    /// Debug function. Creates unicode characters that might not display correctly on old
+    /// terminals.
+    pub fn write_cst(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+        for item in self.cst_iter() {
+            match item {
+                CstIterItem::Parsed(node) => {
+                    write!(writer, "{:1$}", "", 2 * node.path.0.len())?;
+                    self.grammar.write_dotted_rule(writer, &node.dotted_rule)?;
+                    writeln!(writer, ", [{}, {}]", node.start, node.end)?;
+                }
+                CstIterItem::Unparsed(position) => writeln!(writer, "unparsed: {}", position)?,
+            }
+        }
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    use super::super::char::CharMatcher;
-    use super::super::grammar::tests::define_grammar;
-    use super::super::grammar::{Grammar, Symbol};
 
-    /// Define the grammar from: https://www.cs.unm.edu/~luger/ai-final2/CH9_Dynamic%20Programming%20and%20the%20Earley%20Parser.pdf
+    /// Write the parsed part of the chart and the CST edges as a Graphviz graph.
     ///
-    /// These are the alrady tokenized words
-    #[derive(Hash, PartialOrd, PartialEq, Clone, Debug, Eq, Ord)]
-    pub enum Token {
-        John,
-        Called,
-        Mary,
-        From,
-        Denver,
+    /// The nodes are the chart entries, labeled with the dotted rule and the span. The edges point
+    /// from an entry to its children and to its predecessor in the same rule.
+    pub fn write_dot(&self, writer: &mut dyn Write, options: &DotOptions) -> std::io::Result<()> {
+        let name = if options.name.is_empty() {
+            "cst"
+        } else {
+            options.name.as_str()
+        };
+        writeln!(writer, "digraph \"{}\" {{", escape_dot(name))?;
+        let linked = |position: usize, state: usize| {
+            (0..=self.valid_entries).any(|p| {
+                self.cst[p].iter().any(|e| {
+                    (p == position && e.from_state as usize == state)
+                        || (e.to_position == position && e.to_state as usize == state)
+                })
+            })
+        };
+        for position in 0..=self.valid_entries {
+            for (state, (dr, start)) in self.chart[position].iter().enumerate() {
+                if options.linked_only && !linked(position, state) {
+                    continue;
+                }
+                writeln!(
+                    writer,
+                    "  c_{}_{} [label=\"{} [{},{}]\"]",
+                    position,
+                    state,
+                    escape_dot(self.grammar.dotted_rule_to_string(dr)?.trim_end()),
+                    start,
+                    position
+                )?;
+            }
+        }
+        for position in 0..=self.valid_entries {
+            for e in self.cst[position].iter() {
+                writeln!(
+                    writer,
+                    "  c_{}_{} -> c_{}_{}",
+                    position, e.from_state, e.to_position, e.to_state
+                )?;
+            }
+        }
+        if options.preorder {
+            let mut last: Option<CstPathNode> = None;
+            for (i, item) in self.cst_iter().enumerate() {
+                if let CstIterItem::Parsed(node) = item {
+                    if let Some(last) = last {
+                        writeln!(
+                            writer,
+                            "  c_{}_{} -> c_{}_{} [label=\"{}\",color=red]",
+                            last.position, last.state, node.current.position, node.current.state, i
+                        )?;
+                    }
+                    last = Some(node.current);
+                }
+            }
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+/// Escape a string for a quoted Graphviz ID
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl CstIterItemNode {
+    pub fn path_iter(&self) -> impl Iterator<Item = &CstPathNode> {
+        self.path.0.iter()
+    }
+
+    /// Byte range of the node in the source text, see [SpanTable](span/struct.SpanTable.html).
+    ///
+    /// Return None if the table doesn't cover the node.
+    pub fn byte_span(&self, spans: &SpanTable) -> Option<std::ops::Range<usize>> {
+        spans.byte_range(self.start, self.end)
+    }
+}
+
+/// State of the derivation count of a chart entry
+#[derive(Clone, Copy, PartialEq)]
+enum Count {
+    Unknown,
+    Visiting,
+    Done(usize),
+}
+
+/// Helper to count the derivations of all chart entries
+struct DerivationCounter<'a, T, M>
+where
+    M: Matcher<T>,
+{
+    /// The parser
+    parser: &'a Parser<T, M>,
+
+    /// CST edges by position and originating state
+    edges: Vec<Vec<Vec<&'a CstEdge>>>,
+
+    /// Derivation count by position and state
+    counts: Vec<Vec<Count>>,
+}
+
+impl<'a, T, M> DerivationCounter<'a, T, M>
+where
+    M: Matcher<T> + Clone,
+{
+    fn new(parser: &'a Parser<T, M>) -> Self {
+        let positions = parser.valid_entries + 1;
+        let edges = (0..positions)
+            .map(|p| {
+                let mut by_state = vec![Vec::new(); parser.chart[p].len()];
+                for e in parser.cst[p].iter() {
+                    by_state[e.from_state as usize].push(e);
+                }
+                by_state
+            })
+            .collect();
+        let counts = parser
+            .chart
+            .iter()
+            .take(positions)
+            .map(|state_list| vec![Count::Unknown; state_list.len()])
+            .collect();
+        let mut counter = Self {
+            parser,
+            edges,
+            counts,
+        };
+        counter.count_all();
+        counter
+    }
+
+    /// Check if the edge links to a completed child. Error entries are handled like terminals.
+    fn is_child(&self, e: &CstEdge) -> bool {
+        self.parser.is_child(e)
+    }
+
+    /// Compute the derivation counts of all entries.
+    ///
+    /// Uses an explicit stack as the nesting of completions can be as deep as the input is long.
+    /// Entries that depend on themselves (cyclic grammars) count as having no derivation.
+    fn count_all(&mut self) {
+        for p in 0..self.counts.len() {
+            for s in 0..self.counts[p].len() {
+                let mut stack = vec![(p, s)];
+                while let Some(&(p, s)) = stack.last() {
+                    if let Count::Done(_) = self.counts[p][s] {
+                        stack.pop();
+                        continue;
+                    }
+                    self.counts[p][s] = Count::Visiting;
+                    let len = stack.len();
+                    for e in self.edges[p][s].iter() {
+                        let (q, u) = (e.to_position, e.to_state as usize);
+                        if self.counts[q][u] == Count::Unknown {
+                            stack.push((q, u));
+                        }
+                    }
+                    if stack.len() == len {
+                        self.counts[p][s] = Count::Done(self.compute(p, s));
+                        stack.pop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Derivation count of an entry or 0 if it hasn't been counted yet
+    fn count(&self, p: usize, s: usize) -> usize {
+        match self.counts[p][s] {
+            Count::Done(n) => n,
+            _ => 0,
+        }
+    }
+
+    /// Number of derivations of the entry's prefix before position `q`
+    fn prefix_count(&self, p: usize, s: usize, q: usize) -> usize {
+        let (dr, start) = &self.parser.chart[p][s];
+        if dr.dot() == 1 {
+            return if q == idx(*start) { 1 } else { 0 };
+        }
+        self.edges[p][s]
+            .iter()
+            .filter(|e| !self.is_child(e) && e.to_position == q)
+            .fold(0, |n, e| {
+                n.saturating_add(self.count(e.to_position, e.to_state as usize))
+            })
+    }
+
+    /// Alternative last children of an entry as (start, derivation count) pairs
+    fn alternatives(&self, p: usize, s: usize) -> Vec<(usize, usize)> {
+        let mut alternatives: Vec<(usize, usize)> = Vec::new();
+        for e in self.edges[p][s].iter().filter(|e| self.is_child(e)) {
+            let q = idx(self.parser.chart[p][e.to_state as usize].1);
+            let n = self
+                .count(p, e.to_state as usize)
+                .saturating_mul(self.prefix_count(p, s, q));
+            if n == 0 {
+                continue;
+            }
+            match alternatives.iter_mut().find(|a| a.0 == q) {
+                Some(a) => a.1 = a.1.saturating_add(n),
+                None => alternatives.push((q, n)),
+            }
+        }
+        alternatives
+    }
+
+    /// Compute the derivation count of an entry from the counts of the entries it links to.
+    fn compute(&self, p: usize, s: usize) -> usize {
+        if self.parser.chart[p][s].0.is_first() {
+            return 1;
+        }
+        let n = if self.edges[p][s].iter().any(|e| self.is_child(e)) {
+            self.alternatives(p, s)
+                .iter()
+                .fold(0, |n: usize, a| n.saturating_add(a.1))
+        } else if self.edges[p][s].is_empty() {
+            // First symbol was a terminal or an error
+            1
+        } else {
+            // Terminal or nullable symbol before the dot
+            self.edges[p][s].iter().fold(0, |n: usize, e| {
+                n.saturating_add(self.count(e.to_position, e.to_state as usize))
+            })
+        };
+        n.min(MAX_DERIVATION_COUNT)
+    }
+
+    /// Collect the positions where the derivations of the entry and its prefixes diverge.
+    fn split_points(&self, p: usize, s: usize, split_points: &mut Vec<usize>) {
+        let mut todo = vec![(p, s)];
+        let mut visited = Vec::new();
+        while let Some((p, s)) = todo.pop() {
+            if visited.contains(&(p, s)) || self.parser.chart[p][s].0.dot() < 2 {
+                continue;
+            }
+            visited.push((p, s));
+            let alternatives = self.alternatives(p, s);
+            if alternatives.len() > 1 {
+                split_points.extend(alternatives.iter().map(|a| a.0));
+            }
+            for e in self.edges[p][s].iter() {
+                if !self.is_child(e) && self.count(e.to_position, e.to_state as usize) != 0 {
+                    todo.push((e.to_position, e.to_state as usize));
+                }
+            }
+        }
+    }
+}
+
+impl<T, M> Parser<T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    /// Report where the input has been parsed ambiguously.
+    ///
+    /// Each reported (symbol, start, end) triple is a node of the parse forest with more than one
+    /// derivation, where the derivations diverge in the node itself, not only in its children.
+    pub fn ambiguity_report(&self) -> AmbiguityReport {
+        let counter = DerivationCounter::new(self);
+        let mut ambiguities = Vec::new();
+        for end in 0..=self.valid_entries {
+            // Group the completed entries at this position by symbol and start.
+            let mut groups: Vec<(SymbolId, usize, Vec<usize>)> = Vec::new();
+            for (s, (dr, start)) in self.chart[end].iter().enumerate() {
+                let symbol = self.grammar.lhs(dr.rule as usize);
+                if symbol == ERROR_ID
+                    || !self.grammar.dotted_is_completed(dr)
+                    || counter.count(end, s) == 0
+                {
+                    continue;
+                }
+                let start = idx(*start);
+                match groups.iter_mut().find(|g| g.0 == symbol && g.1 == start) {
+                    Some(g) => g.2.push(s),
+                    None => groups.push((symbol, start, vec![s])),
+                }
+            }
+
+            for (symbol, start, states) in groups {
+                let mut split_points = Vec::new();
+                for s in states.iter() {
+                    counter.split_points(end, *s, &mut split_points);
+                }
+                if states.len() < 2 && split_points.is_empty() {
+                    continue;
+                }
+                split_points.sort();
+                split_points.dedup();
+                let mut rules: Vec<usize> = states
+                    .iter()
+                    .map(|s| self.chart[end][*s].0.rule as usize)
+                    .collect();
+                rules.sort();
+                rules.dedup();
+                let derivations = states
+                    .iter()
+                    .fold(0, |n: usize, s| n.saturating_add(counter.count(end, *s)))
+                    .min(MAX_DERIVATION_COUNT);
+                ambiguities.push(Ambiguity {
+                    symbol,
+                    start,
+                    end,
+                    derivations,
+                    rules,
+                    split_points,
+                });
+            }
+        }
+        AmbiguityReport { ambiguities }
+    }
+
+    /// List the non-terminals and spans in the accepted part of the input that have more than one
+    /// derivation.
+    ///
+    /// Unlike [ambiguity_report](#method.ambiguity_report), nodes that are only ambiguous because
+    /// of their children are listed, too. Rules and split points are taken from the alternatives of
+    /// the node itself. The list is ordered by end and start position.
+    pub fn ambiguities(&self) -> Vec<Ambiguity> {
+        let forest = self.forest();
+        forest
+            .ambiguous_nodes()
+            .map(|id| {
+                let node = forest.node(id);
+                let symbol = match node.label {
+                    NodeLabel::Symbol(symbol) => symbol,
+                    _ => unreachable!("ambiguous nodes should be symbols"),
+                };
+                let families = &node.families;
+                let rules = families.iter().map(|f| f.rule).sorted().dedup().collect();
+                let split_points = if families.len() > 1 {
+                    families
+                        .iter()
+                        .filter_map(|f| f.right.map(|r| forest.node(r).start))
+                        .sorted()
+                        .dedup()
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                Ambiguity {
+                    symbol,
+                    start: node.start,
+                    end: node.end,
+                    derivations: forest.derivation_count(id),
+                    rules,
+                    split_points,
+                }
+            })
+            .sorted_by_key(|a| (a.end, a.start))
+            .collect()
+    }
+
+    /// Iterate over the distinct parse trees of the input up to the last position that accepted
+    /// it, at most `limit` and `MAX_DERIVATION_COUNT`.
+    ///
+    /// Each tree is a derivation of the [forest](#method.forest) with the nodes in pre-order.
+    pub fn derivations(&self, limit: usize) -> impl Iterator<Item = Vec<DerivationNode>> {
+        let forest = self.forest();
+        let count = forest
+            .root()
+            .map_or(0, |root| forest.derivation_count(root));
+        (0..std::cmp::min(limit, count)).filter_map(move |n| forest.derivation(forest.root()?, n))
+    }
+
+    /// Compute a value from the parse of `tokens` with the given actions, e.g. to build an AST.
+    ///
+    /// The first derivation of the [forest](#method.forest) is evaluated. Return `None` if the
+    /// input hasn't been accepted or a rule without action has no value to pass on.
+    pub fn evaluate<V>(&self, actions: &RuleActions<T, V>, tokens: &[T]) -> Option<V> {
+        let forest = self.forest();
+        let derivation = forest.derivation(forest.root()?, 0)?;
+        actions.evaluate(&derivation, tokens)
     }
 
-    fn print_cst_as_dot<T, M>(parser: &Parser<T, M>, prefix: &str, preorder: bool)
+    /// Like [evaluate](#method.evaluate), but reuse the values of subtrees with the same symbol and
+    /// tokens from earlier evaluations, e.g. after a reparse.
+    pub fn evaluate_memoized<V>(
+        &self,
+        actions: &RuleActions<T, V>,
+        tokens: &[T],
+        memo: &mut SubtreeMemo<V>,
+    ) -> Option<V>
     where
-        M: Matcher<T> + Clone + std::fmt::Debug,
-        T: Clone,
+        T: std::hash::Hash,
+        V: Clone,
     {
-        // Print the parse tree for dot
-        println!("\n{}:\tdigraph {{", prefix);
-        // Print the nodes, using their position as an id
-        for (chart_index, state_list) in parser.chart.iter().enumerate() {
-            for (state_index, state) in state_list.iter().enumerate() {
-                println!(
-                    "{}:\tc_{}_{} [label=\"{} [{},{}]\"]",
-                    prefix,
-                    chart_index,
-                    state_index,
-                    parser.grammar.dotted_rule_to_string(&state.0).unwrap(),
-                    state.1,
-                    chart_index
-                );
+        let forest = self.forest();
+        let derivation = forest.derivation(forest.root()?, 0)?;
+        actions.evaluate_memoized(&derivation, tokens, memo)
+    }
+
+    /// Build the shared packed parse forest of the input up to the last position that accepted it.
+    ///
+    /// The forest is empty if the input hasn't been accepted at any position.
+    pub fn forest(&self) -> Sppf {
+        let mut forest = Sppf::default();
+        let end = match (0..=self.valid_entries)
+            .rev()
+            .find(|p| self.accepted_at(*p))
+        {
+            Some(end) => end,
+            None => {
+                forest.finish(None);
+                return forest;
             }
-        }
-        // Print the edges
-        for (from_position, es) in parser.cst.iter().enumerate() {
-            for e in es.iter() {
-                println!(
-                    "{}:\tc_{}_{}  -> c_{}_{}",
-                    prefix, from_position, e.from_state, e.to_position, e.to_state
-                );
+        };
+
+        // Chart entries as (rule, dot, start) by position and completed rules as (rule, start) by
+        // position and lhs.
+        let mut entries = HashSet::new();
+        let mut completed: HashMap<(usize, SymbolId), Vec<(usize, usize)>> = HashMap::new();
+        for p in 0..=end {
+            for (dr, start) in self.chart[p].iter() {
+                let rule = dr.rule as usize;
+                let start = idx(*start);
+                entries.insert((p, rule, dr.dot(), start));
+                if rule != ERROR_ID as usize && self.grammar.dotted_is_completed(dr) {
+                    completed
+                        .entry((p, self.grammar.lhs(rule)))
+                        .or_default()
+                        .push((rule, start));
+                }
             }
         }
+        // Positions whose tokens have been skipped by the error recovery
+        let is_error = |p: usize| {
+            p > 0
+                && self.chart[p].iter().any(|(dr, start)| {
+                    dr.rule as usize == ERROR_ID as usize && idx(*start) == p - 1
+                })
+        };
 
-        if preorder {
-            // Print the CST in pre-order
-            let mut last_cst_node: Option<CstPathNode> = None;
-            for (i, cst_item) in parser.cst_iter().enumerate() {
-                if let CstIterItem::Parsed(cst_node) = cst_item {
-                    if let Some(last_cst_node) = last_cst_node {
-                        println!(
-                            "{}:\tc_{}_{}  -> c_{}_{} [label=\"{}\",color=red]",
-                            prefix,
-                            last_cst_node.position,
-                            last_cst_node.state,
-                            cst_node.current.position,
-                            cst_node.current.state,
-                            i,
-                        );
+        let (root, _) = forest.add_node(NodeLabel::Symbol(self.grammar.start()), 0, end);
+        let mut todo = vec![root];
+        while let Some(id) = todo.pop() {
+            let node = forest.node(id);
+            let (label, start, end) = (node.label, node.start, node.end);
+            // Rule entries that derive the node as (rule, dot, start)
+            let derived = match label {
+                NodeLabel::Symbol(symbol) => completed
+                    .get(&(end, symbol))
+                    .map(|c| {
+                        c.iter()
+                            .filter(|(_, s)| *s == start)
+                            .map(|(rule, s)| (*rule, self.grammar.rhs(*rule).len(), *s))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                NodeLabel::Intermediate { rule, dot } => vec![(rule, dot, start)],
+                _ => Vec::new(),
+            };
+            for (rule, dot, start) in derived {
+                if dot == 0 {
+                    forest.add_family(
+                        id,
+                        Family {
+                            rule,
+                            left: None,
+                            right: None,
+                        },
+                    );
+                    continue;
+                }
+                // Node of the symbols before the last one, if the split position is possible
+                let left = |forest: &mut Sppf, todo: &mut Vec<NodeId>, split: usize| {
+                    if dot == 1 {
+                        return if split == start { Some(None) } else { None };
+                    }
+                    if !entries.contains(&(split, rule, dot - 1, start)) {
+                        return None;
+                    }
+                    let label = NodeLabel::Intermediate { rule, dot: dot - 1 };
+                    let (left, new) = forest.add_node(label, start, split);
+                    if new {
+                        todo.push(left);
+                    }
+                    Some(Some(left))
+                };
+                let symbol = self.grammar.rhs(rule)[dot - 1];
+                if (symbol as usize) >= self.grammar.nt_count() {
+                    let label = if is_error(end) {
+                        NodeLabel::Error
+                    } else {
+                        NodeLabel::Token
+                    };
+                    if let Some(left) = left(&mut forest, &mut todo, end - 1) {
+                        let (right, _) = forest.add_node(label, end - 1, end);
+                        let right = Some(right);
+                        forest.add_family(id, Family { rule, left, right });
+                    }
+                } else {
+                    let splits: Vec<usize> = completed
+                        .get(&(end, symbol))
+                        .map(|c| c.iter().map(|(_, s)| *s).unique().collect())
+                        .unwrap_or_default();
+                    for split in splits {
+                        if let Some(left) = left(&mut forest, &mut todo, split) {
+                            let (right, new) =
+                                forest.add_node(NodeLabel::Symbol(symbol), split, end);
+                            if new {
+                                todo.push(right);
+                            }
+                            let right = Some(right);
+                            forest.add_family(id, Family { rule, left, right });
+                        }
                     }
-
-                    last_cst_node = Some(cst_node.current.clone());
                 }
             }
         }
-        println!("{}:\t}}", prefix);
+        forest.finish(Some(root));
+        forest
+    }
+}
+
+impl AmbiguityReport {
+    /// Check if the parse was unambiguous
+    pub fn is_empty(&self) -> bool {
+        self.ambiguities.is_empty()
+    }
+
+    /// Write the ambiguities with the most derivations, including the rules involved.
+    pub fn write_summary<T, M>(
+        &self,
+        w: &mut dyn std::io::Write,
+        grammar: &CompiledGrammar<T, M>,
+    ) -> std::io::Result<()>
+    where
+        M: Matcher<T> + Clone + MatcherDisplay,
+    {
+        let top = self
+            .ambiguities
+            .iter()
+            .sorted_by(|a, b| b.derivations.cmp(&a.derivations))
+            .take(SUMMARY_LEN);
+        for a in top {
+            let more = if a.derivations == MAX_DERIVATION_COUNT {
+                " or more"
+            } else {
+                ""
+            };
+            writeln!(
+                w,
+                "{} [{}, {}): {}{} derivations, split at {:?}",
+                grammar.nt_name(a.symbol),
+                a.start,
+                a.end,
+                a.derivations,
+                more,
+                a.split_points
+            )?;
+            for r in a.rules.iter() {
+                write!(w, "  ")?;
+                grammar.write_dotted_rule(w, &DottedRule::new(*r))?;
+                writeln!(w)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::char::CharMatcher;
+    use super::super::grammar::tests::define_grammar;
+    use super::super::grammar::{Grammar, Rule, Symbol};
+    use super::super::sppf::DerivationNode;
+
+    /// Define the grammar from: https://www.cs.unm.edu/~luger/ai-final2/CH9_Dynamic%20Programming%20and%20the%20Earley%20Parser.pdf
+    ///
+    /// These are the alrady tokenized words
+    #[derive(Hash, PartialOrd, PartialEq, Clone, Debug, Eq, Ord)]
+    pub enum Token {
+        John,
+        Called,
+        Mary,
+        From,
+        Denver,
+    }
+
+    impl MatcherDisplay for Token {}
+
+    /// Parsed part of the chart as text
+    fn chart_string<T, M>(parser: &Parser<T, M>) -> String
+    where
+        M: Matcher<T> + Clone + MatcherDisplay,
+    {
+        let mut out = Vec::new();
+        parser
+            .write_chart(&mut out)
+            .expect("writing to a vector should work");
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    #[test]
+    fn chart_items() {
+        let mut parser = Parser::new(list_grammar());
+        parser.update_slice(0, &['a', '(', 'a']);
+        let items: Vec<ChartItem> = parser.chart_items().collect();
+        assert_eq!(
+            items.len(),
+            chart_string(&parser)
+                .lines()
+                .filter(|l| !l.starts_with("chart"))
+                .count()
+        );
+        assert!(items.windows(2).all(|w| w[0].position <= w[1].position));
+        assert!(items.iter().all(|i| i.origin <= i.position));
+        assert!(parser
+            .chart_column(0)
+            .all(|i| i.dotted_rule.dot() == 0 && i.origin == 0));
+        assert!(parser
+            .chart_column(3)
+            .any(|i| i.origin == 2 && parser.grammar().dotted_is_completed(&i.dotted_rule)));
+        assert_eq!(parser.chart_column(4).count(), 0);
+    }
+
+    #[test]
+    fn tail_subtrees() {
+        let mut parser = Parser::new(list_grammar());
+        parser.set_recovery_policy(RecoveryPolicy::Reject);
+        let s = parser.grammar().nt_id("S");
+        let tokens: Vec<char> = "a)a(a(a)".chars().collect();
+        assert_eq!(parser.update_slice(0, &tokens), Verdict::Reject);
+        assert_eq!(parser.tail_subtrees(&tokens), vec![(s, 2, 3), (s, 4, 8)]);
+
+        // Nothing to do after a complete parse
+        let tokens: Vec<char> = "a(a)".chars().collect();
+        parser.update_slice(0, &tokens);
+        assert_eq!(parser.tail_subtrees(&tokens), vec![]);
+
+        // The tail begins after the last accepted position, not at the error.
+        let mut parser = Parser::new(statement_grammar());
+        parser.set_recovery_policy(RecoveryPolicy::Reject);
+        let stmt = parser.grammar().nt_id("Stmt");
+        let s = parser.grammar().nt_id("S");
+        let tokens: Vec<char> = "x=a;x?x=a;".chars().collect();
+        parser.update_slice(0, &tokens);
+        assert_eq!(parser.tail_subtrees(&tokens), vec![(s, 6, 10)]);
+        assert!(parser.tail_subtrees(&tokens).iter().all(|t| t.0 != stmt));
+    }
+
+    #[test]
+    fn write_cst() {
+        let mut parser = Parser::new(list_grammar());
+        parser.update_slice(0, &['(', 'a', ')']);
+        let mut out = Vec::new();
+        parser
+            .write_cst(&mut out)
+            .expect("writing to a vector should work");
+        assert_eq!(
+            String::from_utf8_lossy(&out),
+            "      Item → '(' • List ')' , [0, 1]
+        Item → 'a' • , [1, 2]
+      List → Item • , [1, 2]
+      Item → '(' List • ')' , [0, 2]
+    Item → '(' List ')' • , [0, 3]
+  List → Item • , [0, 3]
+S → List • , [0, 3]
+unparsed: 3
+"
+        );
+    }
+
+    fn cst_as_dot<T, M>(parser: &Parser<T, M>, prefix: &str, preorder: bool) -> String
+    where
+        M: Matcher<T> + Clone + MatcherDisplay,
+        T: Clone,
+    {
+        let options = DotOptions {
+            name: prefix.to_string(),
+            preorder,
+            linked_only: false,
+        };
+        let mut dot = Vec::new();
+        parser
+            .write_dot(&mut dot, &options)
+            .expect("writing to a vector should work");
+        // Prefix the lines to find them in the test output
+        let dot = String::from_utf8(dot).unwrap();
+        dot.lines()
+            .fold(String::from("\n"), |out, l| out + prefix + ":\t" + l + "\n")
     }
 
     /// Define the grammar from: https://www.cs.unm.edu/~luger/ai-final2/CH9_Dynamic%20Programming%20and%20the%20Earley%20Parser.pdf
@@ -951,10 +4234,10 @@ mod tests {
             position = i;
         }
         let res = parser.update(position + 1, Token::Denver);
-        parser.print_chart();
+        print!("{}", chart_string(&parser));
         assert_eq!(res, Verdict::Accept);
 
-        print_cst_as_dot(&parser, "john", false);
+        print!("{}", cst_as_dot(&parser, "john", false));
 
         let cst_iter = parser.cst_iter();
         for i in cst_iter {
@@ -1044,6 +4327,33 @@ mod tests {
         assert!(item.is_none());
     }
 
+    #[test]
+    fn item_accessors() {
+        let grammar = define_grammar();
+        let compiled_grammar = grammar.compile().expect("compilation should have worked");
+
+        let mut parser = Parser::<char, CharMatcher>::new(compiled_grammar);
+        for (i, c) in "john w".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let items: Vec<CstIterItem> = parser.cst_iter().collect();
+        assert_eq!(items.len(), 1);
+        assert!(items[0].as_parsed().is_none());
+        assert_eq!(items[0].unparsed_start(), Some(0));
+
+        let grammar = define_grammar();
+        let compiled_grammar = grammar.compile().expect("compilation should have worked");
+        let mut parser = Parser::<char, CharMatcher>::new(compiled_grammar);
+        for (i, c) in "john called mary ".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let root = parser
+            .cst_iter()
+            .filter_map(|item| item.as_parsed().map(|node| (node.start, node.end)))
+            .last();
+        assert_eq!(root, Some((0, 17)));
+    }
+
     #[test]
     fn reset() {
         let grammar = define_grammar();
@@ -1120,7 +4430,7 @@ mod tests {
         }
         {
             let res = parser.update(1, 'c');
-            parser.print_chart();
+            print!("{}", chart_string(&parser));
             assert_eq!(res, Verdict::Accept);
         }
         // "abb" should fail
@@ -1197,8 +4507,8 @@ mod tests {
             assert_eq!(res, *v);
         }
 
-        parser.print_chart();
-        print_cst_as_dot(&parser, "error", true);
+        print!("{}", chart_string(&parser));
+        print!("{}", cst_as_dot(&parser, "error", true));
 
         // Go through the parse tree
         for (cst_node, gt) in parser.cst_iter().zip(
@@ -1293,8 +4603,8 @@ mod tests {
             assert_eq!(res, *v);
         }
 
-        parser.print_chart();
-        print_cst_as_dot(&parser, "mid_term_ok", true);
+        print!("{}", chart_string(&parser));
+        print!("{}", cst_as_dot(&parser, "mid_term_ok", true));
 
         // Go through the parse tree
         for (cst_node, gt) in parser.cst_iter().zip(
@@ -1352,8 +4662,8 @@ mod tests {
         }
 
         // Print chart and graph
-        parser.print_chart();
-        print_cst_as_dot(&parser, "mid_term", true);
+        print!("{}", chart_string(&parser));
+        print!("{}", cst_as_dot(&parser, "mid_term", true));
 
         // Go through the parse tree
         for (cst_node, gt) in parser.cst_iter().zip(
@@ -1394,4 +4704,1738 @@ mod tests {
             }
         }
     }
+
+    /// E ::= E '+' E | 'a'
+    fn ambiguous_grammar() -> CompiledGrammar<char, CharMatcher> {
+        use CharMatcher::*;
+        let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+        grammar.set_start("E".to_string());
+        grammar.add(Rule::new("E").nt("E").t(Exact('+')).nt("E"));
+        grammar.add(Rule::new("E").t(Exact('a')));
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    #[test]
+    fn ambiguity_report() {
+        let mut parser = Parser::<char, CharMatcher>::new(ambiguous_grammar());
+        for (i, c) in "a+a+a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let report = parser.ambiguity_report();
+        assert_eq!(report.ambiguities.len(), 1);
+        let a = &report.ambiguities[0];
+        assert_eq!(parser.grammar.nt_name(a.symbol), "E");
+        assert_eq!((a.start, a.end), (0, 5));
+        assert_eq!(a.derivations, 2);
+        assert_eq!(a.rules, vec![1]);
+        assert_eq!(a.split_points, vec![2, 4]);
+
+        let mut summary = Vec::new();
+        report
+            .write_summary(&mut summary, &parser.grammar)
+            .expect("writing to a vector should work");
+        let summary = String::from_utf8(summary).unwrap();
+        assert!(summary.starts_with("E [0, 5): 2 derivations, split at [2, 4]\n  E → "));
+
+        // One more operand: a+a+a+a has 5 derivations
+        parser.update(5, '+');
+        parser.update(6, 'a');
+        let report = parser.ambiguity_report();
+        assert_eq!(report.ambiguities.last().unwrap().derivations, 5);
+    }
+
+    /// Symbol names and spans of a derivation from the forest
+    fn derivation_dump(
+        parser: &Parser<char, CharMatcher>,
+        derivation: &[DerivationNode],
+    ) -> Vec<(String, usize, usize)> {
+        derivation
+            .iter()
+            .map(|node| match node.label {
+                NodeLabel::Symbol(s) => {
+                    (parser.grammar.nt_name(s).to_string(), node.start, node.end)
+                }
+                NodeLabel::Error => ("error".to_string(), node.start, node.end),
+                _ => ("token".to_string(), node.start, node.end),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn forest() {
+        let mut parser = Parser::<char, CharMatcher>::new(ambiguous_grammar());
+        for (i, c) in "a+a+a+a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let forest = parser.forest();
+        let root = forest.root().expect("input should have been accepted");
+        assert_eq!(forest.derivation_count(root), 5);
+
+        // Each span of E is stored once.
+        let symbols = (0..forest.len())
+            .filter(|id| matches!(forest.node(*id).label, NodeLabel::Symbol(_)))
+            .count();
+        assert_eq!(symbols, 10);
+        assert_eq!(forest.ambiguous_nodes().count(), 3);
+
+        let derivations: Vec<Vec<DerivationNode>> = forest.derivations(root).collect();
+        assert_eq!(derivations.len(), 5);
+        for (i, d) in derivations.iter().enumerate() {
+            assert_eq!(d.len(), 4 + 3 + 7);
+            assert_eq!(d[0].parent, None);
+            assert!(d[1..].iter().all(|n| n.parent.is_some()));
+            assert!(derivations[..i].iter().all(|e| e != d));
+        }
+        assert_eq!(forest.derivation(root, 5), None);
+
+        // Same count as the ambiguity report
+        let report = parser.ambiguity_report();
+        assert_eq!(report.ambiguities.last().unwrap().derivations, 5);
+
+        assert_eq!(parser.derivations(3).collect::<Vec<_>>(), derivations[..3]);
+        assert_eq!(parser.derivations(10).collect::<Vec<_>>(), derivations);
+        parser.update(7, '+');
+        assert_eq!(parser.derivations(10).count(), 5);
+        parser.buffer_changed(1);
+        assert_eq!(parser.derivations(10).count(), 1);
+    }
+
+    #[test]
+    fn forest_nullable_and_errors() {
+        let mut parser = Parser::<char, CharMatcher>::new(nullable_grammar());
+        for (i, c) in "a;ba".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let forest = parser.forest();
+        let root = forest.root().expect("input should have been accepted");
+        let derivation = forest.derivation(root, 0).unwrap();
+        assert_eq!(
+            derivation_dump(&parser, &derivation),
+            vec![
+                ("S".to_string(), 0, 4),
+                ("Item".to_string(), 0, 1),
+                ("Opt".to_string(), 0, 0),
+                ("token".to_string(), 0, 1),
+                ("token".to_string(), 1, 2),
+                ("Item".to_string(), 2, 4),
+                ("Opt".to_string(), 2, 3),
+                ("token".to_string(), 2, 3),
+                ("token".to_string(), 3, 4),
+            ]
+        );
+        assert_eq!(forest.derivations(root).count(), 1);
+
+        let mut parser = Parser::<char, CharMatcher>::new(error_grammar());
+        for (i, c) in "axb".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let forest = parser.forest();
+        let root = forest.root().expect("input should have been accepted");
+        let derivation = forest.derivation(root, 0).unwrap();
+        assert!(derivation_dump(&parser, &derivation).contains(&("error".to_string(), 1, 2)));
+
+        let parser = Parser::<char, CharMatcher>::new(error_grammar());
+        assert!(parser.forest().is_empty());
+    }
+
+    #[test]
+    fn forest_cyclic() {
+        use CharMatcher::*;
+        let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+        grammar.set_start("E".to_string());
+        grammar.add(Rule::new("E").nt("E"));
+        grammar.add(Rule::new("E").t(Exact('a')));
+        let mut parser = Parser::<char, CharMatcher>::new(grammar.compile().unwrap());
+        parser.update(0, 'a');
+        let forest = parser.forest();
+        let root = forest.root().expect("input should have been accepted");
+        assert_eq!(forest.derivation_count(root), 1);
+        assert_eq!(forest.derivation(root, 0).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn ambiguities() {
+        let mut parser = Parser::<char, CharMatcher>::new(ambiguous_grammar());
+        for (i, c) in "a+a+a+a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let spans: Vec<(usize, usize, usize)> = parser
+            .ambiguities()
+            .iter()
+            .map(|a| (a.start, a.end, a.derivations))
+            .collect();
+        assert_eq!(spans, vec![(0, 5, 2), (0, 7, 5), (2, 7, 2)]);
+        let a = &parser.ambiguities()[0];
+        assert_eq!(parser.grammar.nt_name(a.symbol), "E");
+        assert_eq!(a.rules, vec![1]);
+        assert_eq!(a.split_points, vec![2, 4]);
+
+        let mut parser = Parser::new(crate::bench::toml_grammar(
+            crate::bench::GrammarShape::Classes,
+        ));
+        for (i, c) in crate::bench::generate_toml(1).chars().enumerate() {
+            parser.update(i, c);
+        }
+        assert!(parser.ambiguities().is_empty());
+    }
+
+    /// Spans of the completed entries visited by the CST iterator
+    fn completed_spans(parser: &Parser<char, CharMatcher>) -> Vec<(String, usize, usize)> {
+        parser
+            .cst_iter()
+            .filter_map(|item| {
+                let node = item.as_parsed()?;
+                if !parser.grammar.dotted_is_completed(&node.dotted_rule) {
+                    return None;
+                }
+                let rule = node.dotted_rule.rule as usize;
+                let name = parser.grammar.nt_name(parser.grammar.lhs(rule)).to_string();
+                Some((name, node.start, node.end))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disambiguation() {
+        use crate::disambiguation::{FirstDeclared, LongestMatch, RulePriority};
+
+        let mut parser = Parser::<char, CharMatcher>::new(ambiguous_grammar());
+        for (i, c) in "a+a+a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        // All derivations and partial parses are merged without a strategy.
+        assert_eq!(completed_spans(&parser).len(), 13);
+
+        parser.set_disambiguation(Some(Box::new(LongestMatch)));
+        let spans = completed_spans(&parser);
+        assert_eq!(spans.len(), 5);
+        assert!(spans.contains(&("E".to_string(), 2, 5)));
+        assert!(!spans.contains(&("E".to_string(), 0, 3)));
+
+        // S = A | B, A = x, B = x
+        use CharMatcher::*;
+        let mut grammar: Grammar<char, CharMatcher> = Grammar::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("A"));
+        grammar.add(Rule::new("S").nt("B"));
+        grammar.add(Rule::new("A").t(Exact('x')));
+        grammar.add(Rule::new("B").t(Exact('x')));
+        let mut parser = Parser::<char, CharMatcher>::new(grammar.compile().unwrap());
+        parser.update(0, 'x');
+        assert_eq!(completed_spans(&parser).len(), 4);
+
+        parser.set_disambiguation(Some(Box::new(FirstDeclared)));
+        assert_eq!(
+            completed_spans(&parser),
+            vec![("A".to_string(), 0, 1), ("S".to_string(), 0, 1)]
+        );
+
+        parser.set_disambiguation(Some(Box::new(RulePriority(vec![0, 0, 1]))));
+        assert_eq!(
+            completed_spans(&parser),
+            vec![("B".to_string(), 0, 1), ("S".to_string(), 0, 1)]
+        );
+
+        parser.set_disambiguation(None);
+        assert_eq!(completed_spans(&parser).len(), 4);
+    }
+
+    #[test]
+    fn cst_iter_range() {
+        let grammar = define_grammar();
+        let mut parser = Parser::<char, CharMatcher>::new(grammar.compile().unwrap());
+        for (i, c) in "john called mary from denver ".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let spans = |iter: CstIter<char, CharMatcher>| -> Vec<(usize, usize)> {
+            iter.filter_map(|item| item.as_parsed().map(|n| (n.start, n.end)))
+                .collect()
+        };
+        let all = spans(parser.cst_iter());
+        for (start, end) in [(0, 29), (5, 11), (12, 13), (20, 29), (29, 30)] {
+            let expected: Vec<(usize, usize)> = all
+                .iter()
+                .copied()
+                .filter(|(s, e)| {
+                    if s == e {
+                        start < *s && *s < end
+                    } else {
+                        *s < end && start < *e
+                    }
+                })
+                .collect();
+            assert_eq!(spans(parser.cst_iter_range(start, end)), expected);
+        }
+        assert!(spans(parser.cst_iter_range(12, 13)).len() < all.len());
+        assert!(parser
+            .cst_iter_range(29, 30)
+            .last()
+            .unwrap()
+            .unparsed_start()
+            .is_some());
+    }
+
+    #[test]
+    fn node_at() {
+        let grammar = define_grammar();
+        let mut parser = Parser::<char, CharMatcher>::new(grammar.compile().unwrap());
+        let input = "john called mary ";
+        for (i, c) in input.chars().enumerate() {
+            parser.update(i, c);
+        }
+        let name = |dr: &DottedRule| parser.grammar.nt_name(parser.grammar.lhs(dr.rule as usize));
+        for position in 0..input.len() {
+            let node = parser
+                .node_at(position)
+                .expect("position should have been parsed");
+            // Same as the innermost completed node of the iterator
+            let expected = parser
+                .cst_iter()
+                .filter_map(|item| match item {
+                    CstIterItem::Parsed(n)
+                        if parser.grammar.dotted_is_completed(&n.dotted_rule)
+                            && n.start <= position
+                            && position < n.end =>
+                    {
+                        Some(n)
+                    }
+                    _ => None,
+                })
+                .min_by_key(|n| n.end - n.start)
+                .unwrap();
+            assert_eq!((node.start, node.end), (expected.start, expected.end));
+            assert_eq!(node.dotted_rule, expected.dotted_rule);
+            assert_eq!(node.path.0.len(), expected.path.0.len());
+        }
+        let node = parser.node_at(6).unwrap();
+        assert_eq!(name(&node.dotted_rule), "Verb");
+        let path: Vec<&str> = node
+            .path
+            .0
+            .iter()
+            .map(|n| name(&parser.dotted_rule(n)))
+            .collect();
+        assert_eq!(path, vec!["S", "VP"]);
+        assert!(parser.node_at(input.len()).is_none());
+    }
+
+    #[test]
+    fn cst_cursor() {
+        let grammar = define_grammar();
+        let mut parser = Parser::<char, CharMatcher>::new(grammar.compile().unwrap());
+        assert!(parser.cst_cursor().is_none());
+        for (i, c) in "john called mary ".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let name = |c: &CstCursor<char, CharMatcher>| {
+            let rule = c.dotted_rule().rule as usize;
+            parser.grammar.nt_name(parser.grammar.lhs(rule)).to_string()
+        };
+
+        // Walk the whole tree in pre-order and compare with the iterator.
+        let mut cursor = parser.cst_cursor().unwrap();
+        assert!(!cursor.goto_parent());
+        let mut visited = Vec::new();
+        loop {
+            visited.push((name(&cursor), cursor.span(), cursor.depth()));
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break;
+                }
+            }
+            if cursor.depth() == 0 {
+                break;
+            }
+        }
+        let mut expected: Vec<(String, (usize, usize), usize)> = parser
+            .cst_iter()
+            .filter_map(|item| match item {
+                CstIterItem::Parsed(n) if parser.grammar.dotted_is_completed(&n.dotted_rule) => {
+                    let rule = n.dotted_rule.rule as usize;
+                    let name = parser.grammar.nt_name(parser.grammar.lhs(rule)).to_string();
+                    Some((name, (n.start, n.end), n.path.0.len()))
+                }
+                _ => None,
+            })
+            .collect();
+        // The iterator returns the children before their parents
+        expected.sort_by_key(|(_, (start, end), depth)| (*start, std::cmp::Reverse(*end), *depth));
+        visited.sort_by_key(|(_, (start, end), depth)| (*start, std::cmp::Reverse(*end), *depth));
+        assert_eq!(visited, expected);
+
+        let mut cursor = parser.cst_cursor().unwrap();
+        assert_eq!(name(&cursor), "S");
+        assert!(cursor.goto_first_child());
+        assert!(!cursor.goto_previous_sibling());
+        assert!(cursor.goto_next_sibling());
+        assert_eq!(name(&cursor), "VP");
+        assert!(cursor.goto_previous_sibling());
+        assert!(cursor.goto_parent());
+        assert_eq!(cursor.depth(), 0);
+    }
+
+    #[test]
+    fn children() {
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        let input: Vec<char> = "a(aa)a".chars().collect();
+        assert_eq!(parser.update_slice(0, &input), Verdict::Accept);
+
+        let spans = |nodes: &[CstPathNode]| -> Vec<(usize, usize)> {
+            nodes.iter().map(|n| parser.span(n)).collect()
+        };
+        let completed: Vec<CstIterItemNode> = parser
+            .cst_iter()
+            .filter_map(|item| match item {
+                CstIterItem::Parsed(n) if parser.grammar.dotted_is_completed(&n.dotted_rule) => {
+                    Some(n)
+                }
+                _ => None,
+            })
+            .collect();
+        for node in completed.iter() {
+            let children = parser.children(&node.current);
+            let child_spans = spans(&children);
+            // Strictly left to right
+            assert!(child_spans.windows(2).all(|w| w[0].1 <= w[1].0));
+            // Same order as the iterator
+            let depth = node.path.0.len() + 1;
+            let in_iter: Vec<(usize, usize)> = completed
+                .iter()
+                .filter(|n| {
+                    n.path.0.len() == depth
+                        && spans(&n.path.0[depth - 1..]) == vec![(node.start, node.end)]
+                })
+                .map(|n| (n.start, n.end))
+                .collect();
+            assert_eq!(child_spans, in_iter);
+        }
+        let root = &completed.last().unwrap().current;
+        assert_eq!(spans(&parser.children(root)), vec![(0, 6)]);
+        let list = &parser.children(root)[0];
+        assert_eq!(spans(&parser.children(list)), vec![(0, 5), (5, 6)]);
+    }
+
+    #[test]
+    fn write_dot() {
+        let mut parser = Parser::<char, CharMatcher>::new(ambiguous_grammar());
+        for (i, c) in "a+a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let dot = |options: &DotOptions| {
+            let mut out = Vec::new();
+            parser.write_dot(&mut out, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let all = dot(&DotOptions {
+            name: "a \"quoted\" name".to_string(),
+            ..Default::default()
+        });
+        assert!(all.starts_with("digraph \"a \\\"quoted\\\" name\" {\n"));
+        assert!(all.contains("  c_0_0 [label=\"E → • E '+' E [0,0]\"]\n"));
+        assert!(all.contains(" -> "));
+        assert!(!all.contains("color=red"));
+        assert!(all.ends_with("}\n"));
+
+        let linked = dot(&DotOptions {
+            preorder: true,
+            linked_only: true,
+            ..Default::default()
+        });
+        assert!(linked.starts_with("digraph \"cst\" {"));
+        assert!(linked.contains("color=red"));
+        let entries = |dot: &str| dot.lines().filter(|l| !l.contains(" -> ")).count();
+        assert!(entries(&linked) < entries(&all));
+    }
+
+    #[test]
+    fn no_ambiguity() {
+        let grammar = define_grammar();
+        let compiled_grammar = grammar.compile().expect("compilation should have worked");
+        let mut parser = Parser::<char, CharMatcher>::new(compiled_grammar);
+        for (i, c) in "john called mary ".chars().enumerate() {
+            parser.update(i, c);
+        }
+        assert!(parser.ambiguity_report().is_empty());
+    }
+
+    /// Grammar of the error test
+    ///
+    /// S = A B
+    /// A = a A
+    /// A = a
+    /// B = b
+    /// B = c
+    fn error_grammar() -> CompiledGrammar<char, CharMatcher> {
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("A").nt("B"));
+        grammar.add(Rule::new("A").t(Exact('a')).nt("A"));
+        grammar.add(Rule::new("A").t(Exact('a')));
+        grammar.add(Rule::new("B").t(Exact('b')));
+        grammar.add(Rule::new("B").t(Exact('c')));
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    /// Symbol names and spans of the parse tree in pre-order
+    fn cst_dump(parser: &Parser<char, CharMatcher>) -> Vec<(String, usize, usize)> {
+        parser
+            .cst_iter()
+            .map(|item| match item {
+                CstIterItem::Parsed(node) => (
+                    parser.grammar.nt_name(node.lhs).to_string(),
+                    node.start,
+                    node.end,
+                ),
+                CstIterItem::Unparsed(p) => ("unparsed".to_string(), p, p),
+            })
+            .collect()
+    }
+
+    /// Grammar with a nullable symbol at the start of a construct
+    ///
+    /// S = Item ; Item
+    /// Item = Opt a
+    /// Opt =
+    /// Opt = b
+    fn nullable_grammar() -> CompiledGrammar<char, CharMatcher> {
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("Item").t(Exact(';')).nt("Item"));
+        grammar.add(Rule::new("Item").nt("Opt").t(Exact('a')));
+        grammar.add(Rule::new("Opt"));
+        grammar.add(Rule::new("Opt").t(Exact('b')));
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    /// Nodes of the parse tree in iteration order with their dot, relative to a buffer range
+    fn cst_dump_in(
+        parser: &Parser<char, CharMatcher>,
+        start: usize,
+        end: usize,
+    ) -> Vec<(String, usize, usize, usize)> {
+        parser
+            .cst_iter()
+            .filter_map(|item| {
+                item.as_parsed()
+                    .map(|n| (n.dotted_rule.clone(), n.start, n.end))
+            })
+            .filter(|(dr, s, e)| {
+                start <= *s && *e <= end && !parser.grammar.is_start_rule(dr.rule as usize)
+            })
+            .map(|(dr, s, e)| {
+                let name = parser.grammar.nt_name(parser.grammar.lhs(dr.rule as usize));
+                (name.to_string(), dr.dot(), s - start, e - start)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_rule_order() {
+        let mut parser = Parser::<char, CharMatcher>::new(nullable_grammar());
+        for (i, c) in "a;a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        assert!(parser.accepted_at(3));
+        let first = cst_dump_in(&parser, 0, 1);
+        assert!(first.iter().any(|n| n.0 == "Opt"));
+        assert_eq!(first, cst_dump_in(&parser, 2, 3));
+    }
+
+    /// Debug sink that can be inspected after the parser took ownership
+    #[derive(Clone, Default)]
+    struct SharedSink(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedSink {
+        fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+        }
+    }
+
+    #[test]
+    fn dump_on_reject() {
+        let sink = SharedSink::default();
+        let mut parser = Parser::<char, CharMatcher>::new(error_grammar());
+        parser.set_debug_sink(Some(Box::new(sink.clone())));
+        parser.set_debug_dump_on_reject(true);
+        for (i, c) in "ad".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let text = sink.text();
+        assert!(text.starts_with("reject at 1:\n"), "{}", text);
+        assert!(text.contains("  B → • 'b' [1]\n"), "{}", text);
+        assert!(text.contains("  A → 'a' • A [0]\n"), "{}", text);
+    }
+
+    #[test]
+    fn no_dump_by_default() {
+        let sink = SharedSink::default();
+        let mut parser = Parser::<char, CharMatcher>::new(error_grammar());
+        parser.set_debug_sink(Some(Box::new(sink.clone())));
+        for (i, c) in "ad".chars().enumerate() {
+            parser.update(i, c);
+        }
+        parser.set_debug_dump_on_reject(true);
+        parser.set_debug_dump_on_reject(false);
+        parser.update(1, 'x');
+        assert_eq!(sink.text(), "");
+    }
+
+    #[test]
+    fn compact_error() {
+        let mut parser = Parser::<char, CharMatcher>::new(error_grammar());
+        for (i, c) in "aadeaab".chars().enumerate() {
+            parser.update(i, c);
+        }
+        let before = cst_dump(&parser);
+        let reclaimed = parser.compact(parser.valid_entries());
+        assert!(reclaimed > 0);
+        assert_eq!(cst_dump(&parser), before);
+
+        // Nothing left to reclaim
+        assert_eq!(parser.compact(parser.valid_entries()), 0);
+        assert_eq!(parser.compact(parser.valid_entries() + 1), 0);
+    }
+
+    /// Character matcher that counts its calls
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    struct CountingMatcher(char);
+
+    thread_local! {
+        static MATCH_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    impl Matcher<char> for CountingMatcher {
+        fn matches(&self, t: char) -> bool {
+            MATCH_CALLS.with(|calls| calls.set(calls.get() + 1));
+            self.0 == t
+        }
+    }
+
+    #[test]
+    fn match_each_terminal_once() {
+        let mut grammar = Grammar::<char, CountingMatcher>::new();
+        grammar.set_start("S".to_string());
+        for (lhs, last) in [("A", 'a'), ("B", 'b'), ("C", 'c')].iter() {
+            grammar.add(Rule::new("S").nt(lhs));
+            grammar.add(
+                Rule::new(lhs)
+                    .t(CountingMatcher('x'))
+                    .t(CountingMatcher(*last)),
+            );
+        }
+        let mut parser = Parser::new(grammar.compile().unwrap());
+        MATCH_CALLS.with(|calls| calls.set(0));
+        assert_eq!(parser.update(0, 'x'), Verdict::More);
+        assert_eq!(MATCH_CALLS.with(|calls| calls.get()), 1);
+        assert_eq!(parser.update(1, 'b'), Verdict::Accept);
+        assert_eq!(MATCH_CALLS.with(|calls| calls.get()), 4);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_scan() {
+        let tokens: Vec<char> = "a(a(aa)a)a((a))".chars().collect();
+        let mut sequential = Parser::<char, CharMatcher>::new(list_grammar());
+        sequential.update_slice(0, &tokens);
+        let mut parallel = Parser::<char, CharMatcher>::new(list_grammar());
+        parallel.set_parallel_scan(3, 0);
+        parallel.update_slice(0, &tokens);
+        assert_eq!(chart_string(&parallel), chart_string(&sequential));
+        assert_eq!(cst_dump(&parallel), cst_dump(&sequential));
+    }
+
+    #[test]
+    fn observer() {
+        use crate::observer::{Event, EventLog};
+        let mut parser = Parser::new(list_grammar());
+        let log = EventLog::new();
+        assert!(parser.set_observer(Some(Box::new(log.clone()))).is_none());
+        let list = parser.grammar().nt_id("List");
+        let item = parser.grammar().nt_id("Item");
+
+        parser.update(0, '(');
+        let events = log.take();
+        assert!(
+            matches!(&events[0], Event::Scan { position: 0, dotted_rule, start: 0 }
+            if parser.grammar().lhs(dotted_rule.rule as usize) == item)
+        );
+        let predicted: Vec<SymbolId> = events[1..]
+            .iter()
+            .map(|e| match e {
+                Event::Predict { position: 1, rule } => parser.grammar().lhs(*rule),
+                _ => panic!("unexpected event {:?}", e),
+            })
+            .collect();
+        assert_eq!(predicted, vec![list, list, item, item]);
+
+        parser.update(1, 'a');
+        let completed: Vec<Event> = log
+            .take()
+            .into_iter()
+            .filter(|e| matches!(e, Event::Complete { .. }))
+            .collect();
+        assert_eq!(
+            completed,
+            vec![
+                Event::Complete {
+                    symbol: item,
+                    start: 1,
+                    end: 2
+                },
+                Event::Complete {
+                    symbol: list,
+                    start: 1,
+                    end: 2
+                }
+            ]
+        );
+
+        parser.update(2, '?');
+        assert_eq!(log.take()[0], Event::Error { position: 2 });
+
+        assert!(parser.set_observer(None).is_some());
+        parser.update(3, ')');
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn update_detailed() {
+        let mut parser = Parser::new(list_grammar());
+        let s = parser.grammar().nt_id("S");
+        let list = parser.grammar().nt_id("List");
+        let item = parser.grammar().nt_id("Item");
+        assert_eq!(
+            parser.update_detailed(0, 'a'),
+            (Verdict::Accept, vec![(item, 0, 1), (list, 0, 1), (s, 0, 1)])
+        );
+        assert_eq!(parser.update_detailed(1, '('), (Verdict::More, vec![]));
+        assert_eq!(
+            parser.update_detailed(2, 'a'),
+            (Verdict::More, vec![(item, 2, 3), (list, 2, 3)])
+        );
+        assert_eq!(
+            parser.update_detailed(3, ')'),
+            (Verdict::Accept, vec![(item, 1, 4), (list, 0, 4), (s, 0, 4)])
+        );
+        assert_eq!(
+            parser.update_detailed(5, 'a'),
+            (Verdict::InvalidPosition, vec![])
+        );
+    }
+
+    #[test]
+    fn suggest_insertion() {
+        let mut parser = Parser::new(list_grammar());
+        parser.update_slice(0, &['a', '(', 'a']);
+        assert_eq!(parser.suggest_insertion(0), vec!['a', '(']);
+        assert_eq!(parser.suggest_insertion(3), vec![')', 'a', '(']);
+        assert_eq!(parser.suggest_insertion(4), vec![]);
+
+        // Ruby slippers: inserting the suggestion closes the list.
+        parser.update(3, ')');
+        assert!(parser.accepted_at(4));
+
+        // Terminals without an obvious token are left out.
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").t(NoneOf(vec!['x'])));
+        grammar.add(Rule::new("S").t(Range('0', '9')));
+        let parser = Parser::new(grammar.compile().expect("compilation should have worked"));
+        assert_eq!(parser.suggest_insertion(0), vec!['0']);
+    }
+
+    #[test]
+    fn completions_at() {
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        parser.update_slice(0, &['a', '(', 'a', 'a', ')']);
+        let id = |name| parser.grammar().nt_id(name);
+        let (s, list, item) = (id("S"), id("List"), id("Item"));
+        assert_eq!(parser.completions_at(0), vec![]);
+        assert_eq!(parser.completions_at(1), vec![(item, 0), (list, 0), (s, 0)]);
+        assert_eq!(parser.completions_at(4), vec![(item, 3), (list, 2)]);
+        assert_eq!(parser.completions_at(5), vec![(item, 1), (list, 0), (s, 0)]);
+        assert_eq!(parser.completions_at(6), vec![]);
+    }
+
+    #[test]
+    fn full_predictions() {
+        let mut parser = Parser::new(list_grammar());
+        let list = parser.grammar().nt_id("List");
+        let item = parser.grammar().nt_id("Item");
+        for (i, c) in "a(a".chars().enumerate() {
+            parser.update(i, c);
+        }
+        assert_eq!(parser.full_predictions(0), vec![]);
+        assert_eq!(parser.full_predictions(1), vec![(list, 0)]);
+        assert_eq!(parser.full_predictions(2), vec![(item, 1)]);
+        assert_eq!(parser.full_predictions(3), vec![(list, 2), (item, 1)]);
+        assert_eq!(parser.full_predictions(4), vec![]);
+    }
+
+    #[test]
+    fn expanded_predictions() {
+        use CharMatcher::*;
+        // S = Stmt ; Stmt = Ws Assign ';' | Ws Print ';' ; Ws = | Ws ' '
+        // Assign = 'x' '=' Value ; Print = 'p' Value ; Value = Ws Number ; Number = '1'
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("Stmt"));
+        grammar.add(Rule::new("Stmt").nt("Ws").nt("Assign").t(Exact(';')));
+        grammar.add(Rule::new("Stmt").nt("Ws").nt("Print").t(Exact(';')));
+        grammar.add(Rule::new("Ws"));
+        grammar.add(Rule::new("Ws").nt("Ws").t(Exact(' ')));
+        grammar.add(Rule::new("Assign").t(Exact('x')).t(Exact('=')).nt("Value"));
+        grammar.add(Rule::new("Print").t(Exact('p')).nt("Value"));
+        grammar.add(Rule::new("Value").nt("Ws").nt("Number"));
+        grammar.add(Rule::new("Number").t(Exact('1')));
+        let grammar = grammar.compile().unwrap();
+        let names: Vec<String> = (0..grammar.nt_count() as SymbolId)
+            .map(|sym| grammar.nt_name(sym).to_string())
+            .collect();
+        let id = |name: &str| names.iter().position(|n| n == name).unwrap() as SymbolId;
+        let mut parser = Parser::<char, CharMatcher>::new(grammar);
+        let stop = [id("Assign"), id("Print"), id("Number")];
+
+        assert_eq!(
+            parser.expanded_predictions(0, &stop),
+            vec![id("Assign"), id("Print")]
+        );
+        assert!(parser.predictions(0).contains(&id("Ws")));
+        parser.update_slice(0, &[' ', 'x', '=', ' ']);
+        assert_eq!(parser.expanded_predictions(4, &stop), vec![id("Number")]);
+        assert_eq!(
+            parser.expanded_predictions(3, &[id("Value")]),
+            vec![id("Value")]
+        );
+        assert_eq!(
+            parser.expanded_predictions(5, &stop),
+            Vec::<SymbolId>::new()
+        );
+    }
+
+    #[test]
+    fn lookahead() {
+        let check = |grammar: fn() -> CompiledGrammar<char, CharMatcher>, text: &str| {
+            let tokens: Vec<char> = text.chars().collect();
+            let mut full = Parser::<char, CharMatcher>::new(grammar());
+            let mut pruned = Parser::<char, CharMatcher>::new(grammar());
+            pruned.set_lookahead(true);
+            for (i, t) in tokens.iter().enumerate() {
+                assert_eq!(pruned.update(i, *t), full.update(i, *t), "{:?} {}", text, i);
+            }
+            assert_eq!(cst_dump(&pruned), cst_dump(&full), "{:?}", text);
+            assert!(pruned.chart.entry_count() <= full.chart.entry_count());
+            pruned.chart.entry_count() < full.chart.entry_count()
+        };
+        assert!(check(list_grammar, "a(a(aa)a)a"));
+        assert!(check(statement_grammar, "x=a;x=a;"));
+        check(statement_grammar, "x=a;x=?;x=a;");
+        check(list_grammar, "a)a((a");
+
+        // Changing a token undoes the pruned predictions of the old one
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        parser.set_lookahead(true);
+        parser.update_slice(0, &['a', 'a', 'a']);
+        parser.update_slice(1, &['(', 'a', ')']);
+        let mut full = Parser::<char, CharMatcher>::new(list_grammar());
+        full.update_slice(0, &['a', '(', 'a', ')']);
+        assert_eq!(cst_dump(&parser), cst_dump(&full));
+        assert!(parser.accepted_at(4));
+    }
+
+    #[test]
+    fn commit() {
+        let tokens: Vec<char> = "a(a)a(aa)a(a)".chars().collect();
+        let node_key = |node: &CstIterItemNode| (node.start, node.end, node.dotted_rule.clone());
+        let mut fresh = Parser::<char, CharMatcher>::new(list_grammar());
+        fresh.update_slice(0, &tokens);
+        let nodes = |parser: &Parser<char, CharMatcher>, from: usize| -> Vec<_> {
+            parser
+                .cst_iter()
+                .filter_map(|item| item.as_parsed().map(node_key))
+                .filter(|(_, end, _)| *end > from)
+                .collect()
+        };
+
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        let mut emitted = Vec::new();
+        parser.update_slice(0, &tokens[..6]);
+        assert!(parser.commit(4, &mut |node| emitted.push(node_key(node))) > 0);
+        parser.update_slice(6, &tokens[6..10]);
+        assert!(parser.commit(9, &mut |node| emitted.push(node_key(node))) > 0);
+        // Repeated and earlier commits emit nothing
+        assert_eq!(
+            parser.commit(9, &mut |node| emitted.push(node_key(node))),
+            0
+        );
+        assert_eq!(
+            parser.commit(3, &mut |node| emitted.push(node_key(node))),
+            0
+        );
+        parser.update_slice(10, &tokens[10..]);
+
+        // The committed nodes and the remaining ones make up the whole tree
+        let mut all = emitted.clone();
+        all.extend(nodes(&parser, 9));
+        all.sort_by_key(|(start, end, dr)| (*start, *end, dr.rule));
+        let mut expected = nodes(&fresh, 0);
+        expected.sort_by_key(|(start, end, dr)| (*start, *end, dr.rule));
+        assert_eq!(all, expected);
+        assert!(parser.accepted_at(tokens.len()));
+        assert!(parser.chart.entry_count() < fresh.chart.entry_count());
+    }
+
+    /// Parsers can be moved to another thread, also with callbacks installed
+    #[test]
+    fn parser_is_send() {
+        fn assert_send<S: Send>(_: &S) {}
+        let mut parser = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+        parser.set_token_normalizer(Box::new(crate::char::ascii_lowercase));
+        parser.set_observer(Some(Box::new(crate::observer::EventLog::new())));
+        assert_send(&parser);
+    }
+
+    #[test]
+    fn state_id_range() {
+        assert_eq!(state_id(SymbolId::MAX as usize), SymbolId::MAX);
+        assert!(std::panic::catch_unwind(|| state_id(SymbolId::MAX as usize + 1)).is_err());
+    }
+
+    #[test]
+    fn compact_accepted() {
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        let tokens: Vec<char> = "a(a(aa)a)a".chars().collect();
+        parser.update_slice(0, &tokens[..3]);
+        // Incomplete input is not compacted
+        assert_eq!(parser.compact_accepted(), 0);
+
+        parser.update_slice(3, &tokens[3..]);
+        let before = cst_dump(&parser);
+        let count = parser.chart.entry_count();
+        let mut compacted = Parser::<char, CharMatcher>::new(list_grammar());
+        compacted.update_slice(0, &tokens);
+        compacted.compact(compacted.valid_entries());
+        let reclaimed = parser.compact_accepted();
+        assert!(reclaimed > count - compacted.chart.entry_count());
+        assert_eq!(cst_dump(&parser), before);
+        assert!(parser.is_consistent(&parser.save_state()));
+
+        // Restart from the beginning
+        parser.update_slice(0, &tokens);
+        assert_eq!(cst_dump(&parser), before);
+    }
+
+    #[test]
+    fn save_restore_state() {
+        let mut parser = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+        let input = "john called mary ";
+        for (i, c) in input.chars().enumerate() {
+            parser.update(i, c);
+        }
+        let state = parser.save_state();
+
+        // A fresh parser continues where the saved one stopped
+        let mut restored = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+        assert_eq!(restored.restore_state(state.clone()), Ok(()));
+        assert_eq!(chart_string(&restored), chart_string(&parser));
+        assert_eq!(cst_dump(&restored), cst_dump(&parser));
+        for (i, c) in "from john ".chars().enumerate() {
+            parser.update(input.len() + i, c);
+            restored.update(input.len() + i, c);
+        }
+        assert_eq!(cst_dump(&restored), cst_dump(&parser));
+
+        // Broken states leave the parser untouched
+        let mut broken = state.clone();
+        broken.valid_entries = broken.chart.len();
+        assert_eq!(
+            restored.restore_state(broken),
+            Err(StateError::Inconsistent)
+        );
+        let mut broken = state.clone();
+        broken.cst.entries[0].to_position = 100;
+        assert_eq!(
+            restored.restore_state(broken),
+            Err(StateError::Inconsistent)
+        );
+        let mut broken = state.clone();
+        broken.chart.offsets[1] = broken.chart.entries.len() + 1;
+        assert_eq!(
+            restored.restore_state(broken),
+            Err(StateError::Inconsistent)
+        );
+        assert_eq!(cst_dump(&restored), cst_dump(&parser));
+
+        // A changed grammar rejects the state
+        let mut other = define_grammar();
+        other.set_start("NP".to_string());
+        let mut other = Parser::<char, CharMatcher>::new(other.compile().unwrap());
+        assert_eq!(other.restore_state(state), Err(StateError::GrammarMismatch));
+    }
+
+    #[test]
+    fn restore_state_changed_terminal() {
+        let build = |t: char| {
+            let mut grammar = Grammar::<char, CharMatcher>::new();
+            grammar.set_start("S".to_string());
+            grammar.add(Rule::new("S").t(CharMatcher::Exact('x')).nt("A"));
+            grammar.add(Rule::new("A").t(CharMatcher::Exact(t)));
+            Parser::new(grammar.compile().expect("compilation should have worked"))
+        };
+        let mut parser = build('a');
+        assert_eq!(parser.update_slice(0, &['x', 'a']), Verdict::Accept);
+        let state = parser.save_state();
+
+        assert_eq!(build('a').restore_state(state.clone()), Ok(()));
+        assert_eq!(
+            build('b').restore_state(state),
+            Err(StateError::GrammarMismatch)
+        );
+    }
+
+    /// Feed the tokens from the first invalid position and return the number of updates.
+    fn reparse(parser: &mut Parser<char, CharMatcher>, buffer: &[char]) -> usize {
+        let mut updates = 0;
+        while parser.valid_entries() < buffer.len() {
+            let i = parser.valid_entries();
+            parser.update(i, buffer[i]);
+            updates += 1;
+        }
+        updates
+    }
+
+    #[test]
+    fn update_slice() {
+        for text in ["john called mary ", "john called", "john kissed mary ", ""].iter() {
+            let tokens: Vec<char> = text.chars().collect();
+            let mut single = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+            let mut verdict = Verdict::More;
+            let mut rejected = false;
+            for (i, c) in tokens.iter().enumerate() {
+                verdict = single.update(i, *c);
+                rejected |= verdict == Verdict::Reject;
+            }
+            if rejected {
+                verdict = Verdict::Reject;
+            }
+
+            let mut bulk = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+            assert_eq!(bulk.update_slice(0, &tokens), verdict, "{:?}", text);
+            assert_eq!(bulk.save_state(), single.save_state(), "{:?}", text);
+        }
+
+        // Continue after the parsed part, or restart inside it
+        let mut parser = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+        let tokens: Vec<char> = "john called mary from john ".chars().collect();
+        assert_eq!(parser.update_slice(0, &tokens[..5]), Verdict::More);
+        assert_eq!(
+            parser.update_slice(7, &tokens[7..]),
+            Verdict::InvalidPosition
+        );
+        assert_eq!(parser.update_slice(5, &tokens[5..17]), Verdict::Accept);
+        assert_eq!(parser.update_slice(12, &tokens[12..]), Verdict::Accept);
+        assert_eq!(parser.valid_entries(), tokens.len());
+    }
+
+    #[test]
+    fn token_source() {
+        let tokens: Vec<char> = "john called mary ".chars().collect();
+        let mut reference = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+        reference.update_slice(0, &tokens);
+
+        let mut parser = Parser::<char, CharMatcher>::new(define_grammar().compile().unwrap());
+        assert_eq!(parser.update(5, tokens[5]), Verdict::InvalidPosition);
+        let source = tokens[..12].to_vec();
+        parser.set_token_source(Some(Box::new(move |p| source.get(p).cloned())));
+        assert_eq!(parser.update(5, tokens[5]), Verdict::More);
+        assert_eq!(parser.valid_entries(), 6);
+        assert_eq!(
+            parser.update_slice(16, &tokens[16..]),
+            Verdict::InvalidPosition
+        );
+        assert_eq!(parser.update_slice(10, &tokens[10..]), Verdict::Accept);
+        assert_eq!(parser.save_state(), reference.save_state());
+    }
+
+    /// Statements: S = Program ; Program = Program Stmt | Stmt ; Stmt = 'x' '=' 'a' ';'
+    fn statement_grammar() -> CompiledGrammar<char, CharMatcher> {
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("Program"));
+        grammar.add(Rule::new("Program").nt("Program").nt("Stmt"));
+        grammar.add(Rule::new("Program").nt("Stmt"));
+        grammar.add(
+            Rule::new("Stmt")
+                .t(Exact('x'))
+                .t(Exact('='))
+                .t(Exact('a'))
+                .t(Exact(';')),
+        );
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    #[test]
+    fn recovery_policy() {
+        // Verdict, acceptance and spans of the errors
+        let run = |parser: &mut Parser<char, CharMatcher>, policy: RecoveryPolicy, text: &str| {
+            parser.set_recovery_policy(policy);
+            let tokens: Vec<char> = text.chars().collect();
+            let verdict = parser.update_slice(0, &tokens);
+            let errors: Vec<(usize, usize)> = parser
+                .cst_iter()
+                .filter_map(|item| match item {
+                    CstIterItem::Parsed(n) if n.dotted_rule.rule as usize == ERROR_ID as usize => {
+                        Some((n.start, n.end))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let accepted = parser.accepted_at(tokens.len());
+            assert_eq!(
+                parser.accepted_at_without_errors(tokens.len()),
+                accepted && errors.is_empty()
+            );
+            (verdict, accepted, errors)
+        };
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        let stmt = parser.grammar().nt_id("Stmt");
+        let mut check = |policy, text, expected| {
+            assert_eq!(run(&mut parser, policy, text), expected, "{:?}", text);
+        };
+        use RecoveryPolicy::*;
+        check(
+            PretendMatch,
+            "x=a;x=?;",
+            (Verdict::Reject, true, vec![(6, 7)]),
+        );
+        check(
+            SkipToken,
+            "x=a;x=?a;",
+            (Verdict::Reject, true, vec![(6, 7)]),
+        );
+        check(
+            InsertExpected,
+            "x=a;x=;",
+            (Verdict::Reject, true, vec![(6, 6)]),
+        );
+        // Falls back to PretendMatch
+        check(
+            InsertExpected,
+            "x=a;x=?;",
+            (Verdict::Reject, true, vec![(6, 7)]),
+        );
+        check(
+            Synchronize(vec![stmt]),
+            "x=a;x?=a;x=a;",
+            (Verdict::Reject, true, vec![(4, 9)]),
+        );
+        check(
+            Synchronize(vec![stmt]),
+            "x=a;?x=a;",
+            (Verdict::Reject, true, vec![(4, 5)]),
+        );
+        check(Reject, "x=a;?x=a;", (Verdict::Reject, false, vec![]));
+        check(Reject, "x=a;x=a;", (Verdict::Accept, true, vec![]));
+
+        // The insertion is removed when the token is parsed again
+        check(
+            InsertExpected,
+            "x=a;x=;",
+            (Verdict::Reject, true, vec![(6, 6)]),
+        );
+        assert_eq!(parser.update_slice(6, &['a', ';']), Verdict::Accept);
+        let mut fresh = Parser::<char, CharMatcher>::new(statement_grammar());
+        fresh.update_slice(0, &"x=a;x=a;".chars().collect::<Vec<char>>());
+        assert_eq!(chart_string(&parser), chart_string(&fresh));
+        assert_eq!(cst_dump(&parser), cst_dump(&fresh));
+    }
+
+    #[test]
+    fn stats() {
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        parser.update_slice(0, &['x', '=', 'a', ';', 'x', '?']);
+        let stats = parser.stats();
+        assert_eq!(
+            stats.items_per_position,
+            (0..=6).map(|p| parser.chart[p].len()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            stats.items_per_rule.iter().sum::<usize>(),
+            stats.items_per_position.iter().sum::<usize>()
+        );
+        assert_eq!(stats.scan_failures, 1);
+        assert!(stats.predictions > 0 && stats.completions > 0 && stats.peak_memory > 0);
+
+        // Counters accumulate over re-parses, entry counts don't
+        parser.update_slice(4, &['x', '=', 'a', ';']);
+        let again = parser.stats();
+        assert_eq!(again.scan_failures, 1);
+        assert!(again.predictions > stats.predictions);
+        assert!(again.completions > stats.completions);
+        assert_eq!(again.items_per_position.len(), 9);
+
+        parser.reset_stats();
+        assert_eq!(parser.stats().predictions, 0);
+        assert_eq!(parser.stats().items_per_position.len(), 9);
+    }
+
+    #[test]
+    fn position_width() {
+        #[cfg(not(feature = "positions-usize"))]
+        assert_eq!(std::mem::size_of::<Position>(), 4);
+        #[cfg(feature = "positions-usize")]
+        assert_eq!(
+            std::mem::size_of::<Position>(),
+            std::mem::size_of::<usize>()
+        );
+    }
+
+    #[test]
+    fn chart_limit() {
+        let tokens: Vec<char> = "x=a;x=a;x=a;".chars().collect();
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        parser.update_slice(0, &tokens);
+        let full = chart_string(&parser);
+
+        let mut limited = Parser::<char, CharMatcher>::new(statement_grammar());
+        limited.set_chart_limit(Some(10));
+        assert_eq!(limited.update_slice(0, &tokens), Verdict::LimitExceeded);
+        let parsed = limited.valid_entries();
+        assert!(parsed > 0 && parsed < tokens.len());
+        assert_eq!(
+            limited.update(parsed, tokens[parsed]),
+            Verdict::LimitExceeded
+        );
+        assert!(matches!(
+            limited.cst_iter().last(),
+            Some(CstIterItem::Unparsed(start)) if start == parsed
+        ));
+
+        // Raising the limit continues the parse
+        limited.set_chart_limit(None);
+        assert_eq!(
+            limited.update_slice(parsed, &tokens[parsed..]),
+            Verdict::Accept
+        );
+        assert_eq!(chart_string(&limited), full);
+    }
+
+    #[test]
+    fn error_limit() {
+        let tokens: Vec<char> = "x=a;#####x=a;".chars().collect();
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        parser.set_error_limit(Some(3));
+        assert_eq!(parser.update_slice(0, &tokens), Verdict::LimitExceeded);
+        assert_eq!(parser.valid_entries(), 7);
+        assert_eq!(parser.update(7, '#'), Verdict::LimitExceeded);
+        // The skipped tokens do not belong to a complete statement
+        assert!(matches!(
+            parser.cst_iter().last(),
+            Some(CstIterItem::Unparsed(4))
+        ));
+
+        // Fewer errors in a row are recovered from
+        let tokens: Vec<char> = "x=a;##a;x=a;".chars().collect();
+        assert_eq!(parser.update_slice(0, &tokens), Verdict::Reject);
+        assert_eq!(parser.valid_entries(), tokens.len());
+    }
+
+    #[test]
+    fn viable_prefix() {
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        assert!(parser.is_viable_prefix(0));
+        parser.update_slice(0, &['x', '=', 'a', ';', 'x', '=']);
+        assert!((0..=6).all(|p| parser.is_viable_prefix(p)));
+        assert!(!parser.is_viable_prefix(7));
+
+        // Recovered errors are not viable
+        parser.update_slice(6, &['?', ';', 'x']);
+        assert!(parser.is_viable_prefix(6));
+        assert!(!parser.is_viable_prefix(7));
+        assert!(!parser.is_viable_prefix(9));
+
+        parser.set_recovery_policy(RecoveryPolicy::Reject);
+        parser.update_slice(0, &['x', '=', 'a', ';', 'x', '=', '?']);
+        assert!(parser.is_viable_prefix(6));
+        assert!(!parser.is_viable_prefix(7));
+
+        // Recovering from a later token doesn't change the answer for earlier prefixes
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").t(Exact('a')).t(Exact('b')).t(Exact('c')));
+        let mut parser = Parser::<char, CharMatcher>::new(
+            grammar.compile().expect("compilation should have worked"),
+        );
+        parser.set_recovery_policy(RecoveryPolicy::InsertExpected);
+        parser.update(0, 'a');
+        assert!(parser.is_viable_prefix(1));
+        assert_eq!(parser.update(1, 'c'), Verdict::Reject);
+        assert!(parser.is_viable_prefix(1));
+        assert!(!parser.is_viable_prefix(2));
+    }
+
+    #[test]
+    fn accepted_spans() {
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        assert_eq!(parser.accepted_spans(), vec![]);
+        parser.update_slice(0, &['a', '(', 'a', ')']);
+        let grammar = parser.grammar();
+        let rule = grammar.rules_for_lhs(grammar.nt_id("S"))[0];
+        let span = |start, end| AcceptedSpan { rule, start, end };
+        assert_eq!(parser.accepted_spans(), vec![span(0, 1), span(0, 4)]);
+        parser.update_slice(4, &['(']);
+        assert_eq!(parser.accepted_spans(), vec![span(0, 1), span(0, 4)]);
+    }
+
+    #[test]
+    fn expected_terminals() {
+        use CharMatcher::*;
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        assert_eq!(parser.expected_terminals(0), vec![Exact('('), Exact('a')]);
+        parser.update_slice(0, &['(', 'a']);
+        assert_eq!(
+            parser.expected_terminals(2),
+            vec![Exact('('), Exact(')'), Exact('a')]
+        );
+        assert_eq!(parser.expected_terminals(3), vec![]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        use CharMatcher::*;
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        let stmt = parser.grammar().nt_id("Stmt");
+        let mut check =
+            |policy,
+             text: &str,
+             expected: Vec<(usize, char, Vec<CharMatcher>, SymbolId, Repair)>| {
+                parser.set_recovery_policy(policy);
+                let tokens: Vec<char> = text.chars().collect();
+                parser.update_slice(0, &tokens);
+                let errors: Vec<_> = parser
+                    .errors(&tokens)
+                    .into_iter()
+                    .map(|e| (e.position, e.found, e.expected, e.recovered_as, e.repair))
+                    .collect();
+                assert_eq!(errors, expected, "{:?}", text);
+            };
+        use RecoveryPolicy::*;
+        check(PretendMatch, "x=a;x=a;", vec![]);
+        check(
+            PretendMatch,
+            "x=a;x=?;",
+            vec![(6, '?', vec![Exact('a')], stmt, Repair::Replace)],
+        );
+        check(
+            SkipToken,
+            "x=a;x=?a;",
+            vec![(6, '?', vec![Exact('a')], stmt, Repair::Delete)],
+        );
+        check(
+            InsertExpected,
+            "x=a;x=;",
+            vec![(6, ';', vec![Exact('a')], stmt, Repair::Insert)],
+        );
+        check(
+            Synchronize(vec![stmt]),
+            "x=a;x?=a;x=a;",
+            vec![(5, '?', vec![Exact('=')], stmt, Repair::Synchronize)],
+        );
+        check(
+            Reject,
+            "x=a;?x=a;",
+            vec![(4, '?', vec![Exact('x')], ERROR_ID, Repair::Reject)],
+        );
+    }
+
+    #[test]
+    fn minimal_edit() {
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        let mut check = |edits, text: &str, expected: Vec<(usize, Repair)>| {
+            parser.set_recovery_policy(RecoveryPolicy::MinimalEdit(edits));
+            let tokens: Vec<char> = text.chars().collect();
+            assert_eq!(
+                parser.update_slice(0, &tokens) == Verdict::Reject,
+                !expected.is_empty()
+            );
+            let errors: Vec<_> = parser
+                .errors(&tokens)
+                .into_iter()
+                .map(|e| (e.position, e.repair))
+                .collect();
+            assert_eq!(errors, expected, "{:?}", text);
+            assert!(parser.accepted_at(tokens.len()), "{:?}", text);
+            assert_eq!(
+                parser.accepted_at_without_errors(tokens.len()),
+                expected.is_empty()
+            );
+        };
+        check(2, "x=a;x=a;", vec![]);
+        check(2, "x=a;x=;x=a;", vec![(6, Repair::Insert)]);
+        check(2, "x=a;=a;", vec![(4, Repair::Insert)]);
+        check(2, "x=a;x=b;x=a;", vec![(6, Repair::Replace)]);
+        // Replacing the token would need a second edit
+        check(2, "x=a;x=?a;x=a;", vec![(6, Repair::Delete)]);
+        check(
+            2,
+            "x=a;x=a??;x=a;",
+            vec![(7, Repair::Delete), (8, Repair::Delete)],
+        );
+
+        // The policy and the old parse are restored after the search
+        assert_eq!(parser.recovery, RecoveryPolicy::MinimalEdit(2));
+        // Only the applied replacements and deletions count, not the trials.
+        assert_eq!(parser.stats().scan_failures, 4);
+        let mut fresh = Parser::<char, CharMatcher>::new(statement_grammar());
+        fresh.set_recovery_policy(RecoveryPolicy::SkipToken);
+        fresh.update_slice(0, &"x=a;x=a??;x=a;".chars().collect::<Vec<char>>());
+        assert_eq!(chart_string(&parser), chart_string(&fresh));
+
+        // Without the following tokens, the insertion or replacement is taken.
+        let mut single = Parser::<char, CharMatcher>::new(statement_grammar());
+        single.set_recovery_policy(RecoveryPolicy::MinimalEdit(2));
+        let tokens: Vec<char> = "x=a;x=?a;".chars().collect();
+        for (i, c) in tokens.iter().enumerate() {
+            single.update(i, *c);
+        }
+        assert_eq!(single.errors(&tokens)[0].repair, Repair::Replace);
+    }
+
+    /// Nested lists: S = List ; List = List Item | Item ; Item = 'a' | '(' List ')'
+    fn list_grammar() -> CompiledGrammar<char, CharMatcher> {
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("List"));
+        grammar.add(Rule::new("List").nt("List").nt("Item"));
+        grammar.add(Rule::new("List").nt("Item"));
+        grammar.add(Rule::new("Item").t(Exact('a')));
+        grammar.add(Rule::new("Item").t(Exact('(')).nt("List").t(Exact(')')));
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    #[test]
+    fn reuse_suffix() {
+        // Adding an item to a list only parses the item and the next token
+        let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+        let mut buffer: Vec<char> = "a(aa)a".repeat(10).chars().collect();
+        reparse(&mut parser, &buffer);
+        buffer.insert(3, 'a');
+        parser.buffer_replaced(3, 0, 1);
+        assert_eq!(reparse(&mut parser, &buffer), 2);
+        assert_eq!(parser.valid_entries(), buffer.len());
+
+        // Replacing an item by a different one parses up to the end of the next item
+        buffer.splice(7..8, "(a)".chars());
+        parser.buffer_replaced(7, 1, 3);
+        assert_eq!(reparse(&mut parser, &buffer), 7);
+
+        let mut seed: u32 = 0x2516;
+        let mut random = move |n: usize| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) as usize) % n
+        };
+        for _run in 0..20 {
+            let mut parser = Parser::<char, CharMatcher>::new(list_grammar());
+            let mut buffer: Vec<char> = Vec::new();
+            for _step in 0..40 {
+                let start = random(buffer.len() + 1);
+                let end = start + random(std::cmp::min(buffer.len() - start, 3) + 1);
+                let inserted: Vec<char> = (0..random(4))
+                    .map(|_| ['a', 'a', '(', ')', 'b'][random(5)])
+                    .collect();
+                parser.buffer_replaced(start, end - start, inserted.len());
+                buffer.splice(start..end, inserted);
+                reparse(&mut parser, &buffer);
+
+                let mut fresh = Parser::<char, CharMatcher>::new(list_grammar());
+                reparse(&mut fresh, &buffer);
+                assert_eq!(chart_string(&parser), chart_string(&fresh));
+                assert_eq!(cst_dump(&parser), cst_dump(&fresh));
+            }
+        }
+    }
+
+    /// Apply random edits and compactions to one parser and only the edits to a twin.
+    #[test]
+    fn compact_twin() {
+        let mut seed: u32 = 0x2464;
+        let mut random = move |n: usize| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) as usize) % n
+        };
+
+        for _run in 0..20 {
+            let mut compacted = Parser::<char, CharMatcher>::new(error_grammar());
+            let mut twin = Parser::<char, CharMatcher>::new(error_grammar());
+            let mut buffer: Vec<char> = Vec::new();
+            // Positions before frozen must not change after compaction.
+            let mut frozen = 0;
+            for _step in 0..40 {
+                match random(4) {
+                    0 => {
+                        let up_to = frozen + random(compacted.valid_entries() - frozen + 1);
+                        compacted.compact(up_to);
+                        frozen = up_to;
+                    }
+                    1 if buffer.len() > frozen => {
+                        let position = frozen + random(buffer.len() - frozen);
+                        buffer[position] = ['a', 'b', 'c', 'd'][random(4)];
+                        compacted.buffer_changed(position);
+                        twin.buffer_changed(position);
+                    }
+                    _ => buffer.push(['a', 'a', 'b', 'c', 'd'][random(5)]),
+                }
+                for (i, token) in buffer.iter().enumerate().skip(compacted.valid_entries()) {
+                    assert_eq!(compacted.update(i, *token), twin.update(i, *token));
+                }
+                assert_eq!(cst_dump(&compacted), cst_dump(&twin));
+                assert_eq!(
+                    compacted.predictions(compacted.valid_entries()),
+                    twin.predictions(twin.valid_entries())
+                );
+            }
+        }
+    }
+
+    /// Feed `text` from the start and check that the whole input is accepted.
+    fn accepts(parser: &mut Parser<char, CharMatcher>, text: &str) -> bool {
+        parser.buffer_changed(0);
+        let mut res = Verdict::More;
+        for (i, c) in text.chars().enumerate() {
+            res = parser.update(i, c);
+            if res == Verdict::Reject {
+                return false;
+            }
+        }
+        res == Verdict::Accept
+    }
+
+    #[test]
+    fn grammar_mut() {
+        let grammar = define_grammar();
+        let compiled_grammar = grammar.compile().expect("compilation should have worked");
+        let mut parser = Parser::<char, CharMatcher>::new(compiled_grammar);
+
+        assert!(accepts(&mut parser, "john called mary "));
+        assert!(!accepts(&mut parser, "sue called mary "));
+
+        let rules_before: Vec<SymbolId> = (0..parser.grammar().rule_count())
+            .map(|i| parser.grammar().lhs(i))
+            .collect();
+
+        // Add a new non-terminal with new terminals and make it a noun.
+        let (name, noun) = {
+            let mut grammar = parser.grammar_mut();
+            let name = grammar
+                .add_rule_dyn(
+                    "Name",
+                    "sue "
+                        .chars()
+                        .map(|c| Symbol::Terminal(CharMatcher::Exact(c)))
+                        .collect(),
+                )
+                .expect("rule should be added");
+            let noun = grammar
+                .add_rule_dyn("Noun", vec![Symbol::NonTerminal("Name".to_string())])
+                .expect("rule should be added");
+            (name, noun)
+        };
+        assert_eq!(name, rules_before.len());
+        assert_eq!(noun, rules_before.len() + 1);
+        assert!(accepts(&mut parser, "sue called mary "));
+        assert!(accepts(&mut parser, "john called sue "));
+
+        // Remove the noun rule again. The indices of the other rules don't change.
+        parser
+            .grammar_mut()
+            .remove_rule_dyn(noun)
+            .expect("rule should be removed");
+        assert!(!accepts(&mut parser, "sue called mary "));
+        assert!(accepts(&mut parser, "john called mary "));
+        assert_eq!(parser.grammar().rule_count(), rules_before.len() + 2);
+        assert!(parser.grammar().is_removed(noun));
+        assert!(!parser.grammar().is_removed(name));
+        for (i, lhs) in rules_before.iter().enumerate() {
+            assert_eq!(parser.grammar().lhs(i), *lhs);
+        }
+        assert!(parser.grammar_mut().remove_rule_dyn(noun).is_err());
+    }
+
+    #[test]
+    fn terminal_dispatch() {
+        let text = "[server]\nhost = \"example.org\" # main\nports = [ 80, 443 ]\n";
+        let chart = |dispatch: bool| {
+            let mut parser = Parser::new(crate::bench::toml_grammar(
+                crate::bench::GrammarShape::Classes,
+            ));
+            if dispatch {
+                parser.set_terminal_dispatch(Some(crate::char::CharDispatch::build));
+            }
+            let mut verdict = Verdict::More;
+            for (i, c) in text.chars().enumerate() {
+                verdict = parser.update(i, c);
+            }
+            (verdict == Verdict::Accept, chart_string(&parser))
+        };
+        let (accepted, without) = chart(false);
+        assert!(accepted);
+        assert_eq!(chart(true), (true, without));
+
+        // The index follows changes of the grammar.
+        let mut parser = Parser::new(statement_grammar());
+        parser.set_terminal_dispatch(Some(crate::char::CharDispatch::build));
+        assert!(accepts(&mut parser, "x=a;"));
+        parser.set_grammar(list_grammar());
+        assert!(accepts(&mut parser, "a(a)"));
+        assert!(!accepts(&mut parser, "a(b)"));
+    }
+
+    #[test]
+    fn set_grammar() {
+        let mut parser = Parser::<char, CharMatcher>::new(statement_grammar());
+        assert!(accepts(&mut parser, "x=a;"));
+        let state = parser.save_state();
+
+        let previous = parser.set_grammar(list_grammar());
+        assert_eq!(parser.valid_entries(), 0);
+        assert!(!accepts(&mut parser, "x=a;"));
+        assert!(accepts(&mut parser, "a(a)"));
+        assert_eq!(
+            parser.restore_state(state),
+            Err(StateError::GrammarMismatch)
+        );
+
+        parser.set_grammar(previous);
+        assert!(accepts(&mut parser, "x=a;"));
+    }
+
+    #[test]
+    fn validate() {
+        let mut parser = Parser::new(
+            define_grammar()
+                .compile()
+                .expect("compilation should have worked"),
+        );
+        assert_eq!(
+            parser.validate("john called mary ".chars(), None),
+            Ok(Verdict::Accept)
+        );
+        assert_eq!(
+            parser.validate("john calls".chars(), None),
+            Ok(Verdict::Reject)
+        );
+        assert_eq!(parser.validate("john ".chars(), None), Ok(Verdict::More));
+    }
+
+    #[test]
+    fn validate_cancelled() {
+        let mut parser = Parser::new(
+            define_grammar()
+                .compile()
+                .expect("compilation should have worked"),
+        );
+
+        // Cancel while the input is being read. Only the current token is processed afterwards.
+        let cancel = CancelToken::new();
+        let mut after_cancel = 0;
+        let input = "john called mary "
+            .chars()
+            .chain("from denver ".chars().cycle())
+            .take(10_000)
+            .enumerate()
+            .map(|(i, c)| {
+                if i == 100 {
+                    cancel.cancel();
+                }
+                if cancel.is_cancelled() {
+                    after_cancel += 1;
+                }
+                c
+            });
+        assert_eq!(parser.validate(input, Some(&cancel)), Err(Cancelled));
+        assert_eq!(after_cancel, 1);
+        assert_eq!(parser.valid_entries(), 100);
+
+        // Cancel from another thread. The input never ends.
+        let cancel = CancelToken::new();
+        let other = cancel.clone();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            other.cancel();
+        });
+        let input = "john called mary "
+            .chars()
+            .chain("from denver ".chars().cycle());
+        assert_eq!(parser.validate(input, Some(&cancel)), Err(Cancelled));
+        handle.join().expect("thread should finish");
+
+        // The parser is still usable.
+        assert_eq!(
+            parser.validate("mary called john ".chars(), Some(&CancelToken::new())),
+            Ok(Verdict::Accept)
+        );
+    }
+
+    #[test]
+    fn arena() {
+        let mut arena = Arena::new(vec![1, 2]);
+        arena.push_column(vec![3]);
+        assert_eq!(arena.add(4), 1);
+        assert_eq!(arena.add(3), 0);
+        assert_eq!(arena.len(), 2);
+        assert_eq!(&arena[0], &[1, 2]);
+        assert_eq!(&arena[1], &[3, 4]);
+
+        arena.truncate(1);
+        arena.push_column(Vec::new());
+        assert_eq!(&arena[1], &[] as &[i32]);
+        assert_eq!(arena.add(1), 0);
+        assert_eq!(arena.entry_count(), 3);
+        assert_eq!(
+            arena.iter().map(<[i32]>::len).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn indirectly_nullable() {
+        use CharMatcher::*;
+        // A is nullable only through B. A is completed at position 0 before C predicts it again.
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("A").nt("C"));
+        grammar.add(Rule::new("C").nt("A").t(Exact('x')));
+        grammar.add(Rule::new("A").nt("B"));
+        grammar.add(Rule::new("B"));
+        grammar.add(Rule::new("B").t(Exact('b')));
+        let mut parser = Parser::new(grammar.compile().expect("compilation should have worked"));
+
+        for text in ["x", "bx", "bbx"].iter() {
+            assert_eq!(
+                parser.validate(text.chars(), None),
+                Ok(Verdict::Accept),
+                "{}",
+                text
+            );
+        }
+    }
 }
@@ -0,0 +1,317 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Pluggable sources of predictions
+//!
+//! A prediction provider suggests input for the cursor position of an editor. The
+//! [PredictionEngine](struct.PredictionEngine.html) asks all providers in the order they have been
+//! added and removes suggestions with the same text.
+
+use super::snippet::Snippet;
+use super::{CompiledGrammar, Matcher, SymbolId, SynchronousEditor};
+
+/// Suggested input, possibly with tab stops
+pub type Prediction = Snippet;
+
+/// Completed node of the parse tree that contains the cursor
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnclosingNode {
+    /// Symbol of the node
+    pub symbol: SymbolId,
+    /// Start position of the node
+    pub start: usize,
+    /// End position of the node
+    pub end: usize,
+}
+
+/// Information about the cursor position passed to the providers
+pub struct PredictionContext<'a, T, M>
+where
+    M: Matcher<T>,
+{
+    /// Grammar of the editor
+    grammar: &'a CompiledGrammar<T, M>,
+    /// All tokens of the buffer
+    tokens: &'a [T],
+    /// Cursor position
+    cursor: usize,
+    /// Symbols predicted at the cursor
+    predicted: Vec<SymbolId>,
+    /// Chain of completed nodes containing the cursor, outermost first
+    enclosing: Vec<EnclosingNode>,
+}
+
+/// Source of predictions
+pub trait PredictionProvider<T, M>
+where
+    M: Matcher<T>,
+{
+    /// Suggest input for the context. The order of the result is kept.
+    fn predict(&self, ctx: &PredictionContext<T, M>) -> Vec<Prediction>;
+}
+
+/// Ordered list of prediction providers
+pub struct PredictionEngine<T, M>
+where
+    M: Matcher<T>,
+{
+    providers: Vec<Box<dyn PredictionProvider<T, M>>>,
+}
+
+impl<'a, T, M> PredictionContext<'a, T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    /// Describe the cursor position of the editor.
+    ///
+    /// A node contains the cursor if it starts before the cursor and ends at or after it, i.e.
+    /// the cursor is inside the node or directly behind it.
+    pub fn new(editor: &'a SynchronousEditor<T, M>) -> Self {
+        let parser = editor.parser();
+        let grammar = editor.grammar();
+        let cursor = editor.cursor();
+
+        // The innermost node that contains the token before the cursor. Its path holds the others.
+        let mut enclosing = Vec::new();
+        if let Some(node) = cursor.checked_sub(1).and_then(|p| editor.node_at(p)) {
+            enclosing = node
+                .path_iter()
+                .map(|n| {
+                    let (start, end) = parser.span(n);
+                    EnclosingNode {
+                        symbol: grammar.lhs(parser.dotted_rule(n).rule as usize),
+                        start,
+                        end,
+                    }
+                })
+                .collect();
+            enclosing.push(EnclosingNode {
+                symbol: node.lhs,
+                start: node.start,
+                end: node.end,
+            });
+        }
+
+        Self {
+            grammar,
+            tokens: editor.tokens(),
+            cursor,
+            predicted: editor.predictions_at_cursor(),
+            enclosing,
+        }
+    }
+}
+
+impl<'a, T, M> PredictionContext<'a, T, M>
+where
+    M: Matcher<T>,
+{
+    /// Grammar of the editor
+    pub fn grammar(&self) -> &CompiledGrammar<T, M> {
+        self.grammar
+    }
+
+    /// Cursor position
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Symbols predicted at the cursor
+    pub fn predicted(&self) -> &[SymbolId] {
+        &self.predicted
+    }
+
+    /// Chain of completed nodes containing the cursor, outermost first
+    pub fn enclosing(&self) -> &[EnclosingNode] {
+        &self.enclosing
+    }
+
+    /// Borrow the tokens from `start` to before `end`.
+    pub fn tokens(&self, start: usize, end: usize) -> &[T] {
+        &self.tokens[start..end]
+    }
+}
+
+impl<'a, M> PredictionContext<'a, char, M>
+where
+    M: Matcher<char>,
+{
+    /// Return the text from `start` to before `end`.
+    pub fn text(&self, start: usize, end: usize) -> String {
+        self.tokens[start..end].iter().collect()
+    }
+}
+
+impl<T, M> PredictionEngine<T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    /// Create an engine without providers
+    pub fn new() -> Self {
+        Self {
+            providers: Vec::new(),
+        }
+    }
+
+    /// Append a provider. It will be asked after the providers added before.
+    pub fn add<P>(&mut self, provider: P)
+    where
+        P: PredictionProvider<T, M> + 'static,
+    {
+        self.providers.push(Box::new(provider));
+    }
+
+    /// Collect the predictions of all providers for the cursor position of the editor.
+    ///
+    /// Predictions with the same text as an earlier one are dropped.
+    pub fn predict(&self, editor: &SynchronousEditor<T, M>) -> Vec<Prediction> {
+        let ctx = PredictionContext::new(editor);
+        let mut predictions: Vec<Prediction> = Vec::new();
+        for provider in self.providers.iter() {
+            for p in provider.predict(&ctx) {
+                if !predictions.iter().any(|q| q.text == p.text) {
+                    predictions.push(p);
+                }
+            }
+        }
+        predictions
+    }
+}
+
+impl<T, M> Default for PredictionEngine<T, M>
+where
+    T: Clone,
+    M: Matcher<T> + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, M, P> PredictionProvider<T, M> for std::rc::Rc<P>
+where
+    M: Matcher<T>,
+    P: PredictionProvider<T, M>,
+{
+    fn predict(&self, ctx: &PredictionContext<T, M>) -> Vec<Prediction> {
+        (**self).predict(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::char::CharMatcher;
+    use super::super::grammar::tests::define_grammar;
+
+    type Context<'a> = PredictionContext<'a, char, CharMatcher>;
+
+    /// Suggest fixed texts
+    struct Fixed(&'static [&'static str]);
+
+    impl PredictionProvider<char, CharMatcher> for Fixed {
+        fn predict(&self, _ctx: &Context) -> Vec<Prediction> {
+            self.0.iter().map(|t| Snippet::plain(t)).collect()
+        }
+    }
+
+    /// Suggest the names of the enclosing nodes
+    struct Enclosing;
+
+    impl PredictionProvider<char, CharMatcher> for Enclosing {
+        fn predict(&self, ctx: &Context) -> Vec<Prediction> {
+            ctx.enclosing()
+                .iter()
+                .map(|n| Snippet::plain(ctx.grammar().nt_name(n.symbol)))
+                .collect()
+        }
+    }
+
+    fn editor(text: &str, cursor: usize) -> SynchronousEditor<char, CharMatcher> {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let mut editor = SynchronousEditor::new(grammar);
+        editor.enter_iter(text.chars());
+        editor.set_cursor(cursor);
+        editor
+    }
+
+    #[test]
+    fn order_and_dedup() {
+        let mut engine = PredictionEngine::new();
+        engine.add(Fixed(&["mary", "john", "mary"]));
+        engine.add(Fixed(&["denver", "john"]));
+        let editor = editor("john ", 5);
+        let texts: Vec<String> = engine
+            .predict(&editor)
+            .into_iter()
+            .map(|p| p.text)
+            .collect();
+        assert_eq!(texts, vec!["mary", "john", "denver"]);
+    }
+
+    #[test]
+    fn enclosing_chain() {
+        let editor = editor("john called mary ", 8);
+        let ctx = PredictionContext::new(&editor);
+        assert_eq!(ctx.cursor(), 8);
+        assert_eq!(ctx.text(5, 11), "called");
+
+        // Compare with the completed CST nodes that contain the cursor, root first.
+        let expected: Vec<EnclosingNode> = editor
+            .cst_iter()
+            .filter_map(|item| {
+                item.as_parsed()
+                    .filter(|n| {
+                        n.start < 8
+                            && 8 <= n.end
+                            && editor.grammar().dotted_is_completed(&n.dotted_rule)
+                    })
+                    .map(|n| EnclosingNode {
+                        symbol: n.lhs,
+                        start: n.start,
+                        end: n.end,
+                    })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        assert!(expected.len() > 1);
+        assert_eq!(ctx.enclosing(), &expected[..]);
+
+        let mut engine = PredictionEngine::new();
+        engine.add(Enclosing);
+        let names: Vec<String> = engine
+            .predict(&editor)
+            .into_iter()
+            .map(|p| p.text)
+            .collect();
+        assert_eq!(names, vec!["S", "VP", "Verb"]);
+    }
+}
@@ -0,0 +1,284 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Line-based protocol to drive an editor from another process
+//!
+//! The other process owns the text and sends its changes. The editor keeps the parse up to date
+//! and answers queries about it. Positions and counts are in characters unless noted otherwise.
+//!
+//! # Messages
+//!
+//! * `EDIT <pos> <del_count> <inserted_len>`, followed by a line break and exactly
+//!   `inserted_len` bytes of UTF-8 text: Delete `del_count` characters at `pos`, then insert the
+//!   text there. The bytes are not terminated by a line break.
+//! * `QUERY predictions <pos>`: List the symbols predicted at `pos`.
+//! * `QUERY status`: Report the length of the buffer and whether it has been accepted without
+//!   errors.
+//! * `QUERY spans <symbol_name>`: List the completed nodes of the symbol in the parse tree.
+//!
+//! # Responses
+//!
+//! Each message is answered by a single line.
+//!
+//! * `EDIT`: `OK <len>` with the new length of the buffer.
+//! * `QUERY predictions`: `OK` followed by the names of the predicted symbols, separated by
+//!   spaces.
+//! * `QUERY status`: `OK <len> accepted` or `OK <len> incomplete`.
+//! * `QUERY spans`: `OK` followed by `<start>-<end>` for each node, separated by spaces. The end
+//!   is exclusive.
+//! * Any malformed or invalid message: `ERR <reason>`.
+
+use std::io::{BufRead, Read, Write};
+
+use super::{CstIterItem, Matcher, SymbolId, SynchronousEditor};
+
+/// Result of a single message. Err contains the reason for the ERR response.
+type Response = Result<String, String>;
+
+/// Parse a number from a message
+fn number(word: Option<&str>, what: &str) -> Result<usize, String> {
+    word.ok_or_else(|| format!("missing {}", what))?
+        .parse()
+        .map_err(|_| format!("invalid {}", what))
+}
+
+/// Process messages from `r` and write the responses to `w` until `r` reaches its end.
+pub fn serve<M, R, W>(
+    editor: &mut SynchronousEditor<char, M>,
+    mut r: R,
+    mut w: W,
+) -> std::io::Result<()>
+where
+    M: Matcher<char> + Clone,
+    R: BufRead,
+    W: Write,
+{
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if r.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let response = match std::str::from_utf8(&line) {
+            Ok(message) => {
+                let mut words = message.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some("EDIT"), pos) => edit(editor, &mut r, pos, words.next(), words.next()),
+                    (Some("QUERY"), Some("predictions")) => predictions(editor, words.next()),
+                    (Some("QUERY"), Some("status")) => Ok(status(editor)),
+                    (Some("QUERY"), Some("spans")) => spans(editor, words.next()),
+                    (Some("QUERY"), _) => Err("unknown query".to_string()),
+                    (None, _) => Err("empty message".to_string()),
+                    _ => Err("unknown message".to_string()),
+                }
+            }
+            Err(_) => Err("message is not UTF-8".to_string()),
+        };
+        match response {
+            Ok(text) => writeln!(w, "OK{}", text)?,
+            Err(reason) => writeln!(w, "ERR {}", reason)?,
+        }
+        w.flush()?;
+    }
+}
+
+/// Apply an EDIT message. The inserted text is read from `r`, even if the positions are invalid.
+fn edit<M, R>(
+    editor: &mut SynchronousEditor<char, M>,
+    r: &mut R,
+    pos: Option<&str>,
+    del_count: Option<&str>,
+    inserted_len: Option<&str>,
+) -> Response
+where
+    M: Matcher<char> + Clone,
+    R: BufRead,
+{
+    let inserted_len = number(inserted_len, "inserted length")?;
+    let mut bytes = Vec::new();
+    r.take(inserted_len as u64)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    if bytes.len() != inserted_len {
+        return Err("inserted text is too short".to_string());
+    }
+    let pos = number(pos, "position")?;
+    let del_count = number(del_count, "delete count")?;
+    let text = String::from_utf8(bytes).map_err(|_| "inserted text is not UTF-8".to_string())?;
+    let end = pos
+        .checked_add(del_count)
+        .filter(|end| *end <= editor.len())
+        .ok_or_else(|| "range outside of buffer".to_string())?;
+    editor.replace(pos, end, text.chars());
+    Ok(format!(" {}", editor.len()))
+}
+
+/// Answer a QUERY predictions message
+fn predictions<M>(editor: &SynchronousEditor<char, M>, pos: Option<&str>) -> Response
+where
+    M: Matcher<char> + Clone,
+{
+    let pos = number(pos, "position")?;
+    if pos > editor.len() {
+        return Err("position outside of buffer".to_string());
+    }
+    let grammar = editor.grammar();
+    Ok(editor
+        .parser()
+        .predictions(pos)
+        .iter()
+        .map(|sym| format!(" {}", grammar.nt_name(*sym)))
+        .collect())
+}
+
+/// Answer a QUERY status message
+fn status<M>(editor: &SynchronousEditor<char, M>) -> String
+where
+    M: Matcher<char> + Clone,
+{
+    let state = if editor.is_accepted() {
+        "accepted"
+    } else {
+        "incomplete"
+    };
+    format!(" {} {}", editor.len(), state)
+}
+
+/// Answer a QUERY spans message
+fn spans<M>(editor: &SynchronousEditor<char, M>, name: Option<&str>) -> Response
+where
+    M: Matcher<char> + Clone,
+{
+    let name = name.ok_or_else(|| "missing symbol name".to_string())?;
+    let grammar = editor.grammar();
+    let sym = grammar.nt_id(name);
+    if sym == SymbolId::MAX {
+        return Err(format!("unknown symbol {}", name));
+    }
+    Ok(editor
+        .cst_iter()
+        .filter_map(|item| match item {
+            CstIterItem::Parsed(node)
+                if grammar.dotted_is_completed(&node.dotted_rule) && node.lhs == sym =>
+            {
+                Some(format!(" {}-{}", node.start, node.end))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::char::CharMatcher;
+    use super::super::grammar::tests::define_grammar;
+
+    fn serve_script(script: &[u8]) -> (SynchronousEditor<char, CharMatcher>, Vec<String>) {
+        let grammar = define_grammar()
+            .compile()
+            .expect("compilation should have worked");
+        let mut editor = SynchronousEditor::new(grammar);
+        let mut output = Vec::new();
+        serve(&mut editor, std::io::Cursor::new(script), &mut output)
+            .expect("serving from memory should work");
+        let responses = String::from_utf8(output)
+            .expect("responses should be UTF-8")
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+        (editor, responses)
+    }
+
+    #[test]
+    fn session() {
+        let (editor, responses) = serve_script(
+            b"EDIT 0 0 12\njohn called QUERY status\n\
+              EDIT 12 0 4\nmaryQUERY predictions 12\n\
+              EDIT 16 0 1\n QUERY status\n\
+              QUERY spans Noun\n\
+              EDIT 0 4 4\nmaryQUERY spans Noun\n",
+        );
+        assert_eq!(
+            responses,
+            vec![
+                "OK 12",
+                "OK 12 incomplete",
+                "OK 16",
+                "OK Noun NP",
+                "OK 17",
+                "OK 17 accepted",
+                "OK 0-5 12-17",
+                "OK 17",
+                "OK 0-5 12-17",
+            ]
+        );
+        assert_eq!(editor.as_string(), "mary called mary ");
+    }
+
+    #[test]
+    fn erroneous() {
+        let (editor, responses) = serve_script(b"EDIT 0 0 17\njohn cxlled mary QUERY status\n");
+        assert_eq!(responses, vec!["OK 17", "OK 17 incomplete"]);
+        // The parser recovered from the error
+        assert!(editor.parser().accepted_at(17));
+    }
+
+    #[test]
+    fn multi_byte() {
+        let (editor, responses) =
+            serve_script("EDIT 0 0 4\n→aEDIT 1 1 0\nEDIT 1 0 2\näQUERY status".as_bytes());
+        assert_eq!(responses, vec!["OK 2", "OK 1", "OK 2", "OK 2 incomplete"]);
+        assert_eq!(editor.as_string(), "→ä");
+    }
+
+    #[test]
+    fn malformed() {
+        let (editor, responses) = serve_script(
+            b"HELLO\n\nQUERY\nQUERY spans\nQUERY spans Nothing\nQUERY predictions x\n\
+              QUERY predictions 5\nEDIT 0 0\nEDIT 0 0 x\nEDIT 3 0 1\nj\
+              EDIT 0 0 2\n\xff\xfe\xffQUERY status\nEDIT 0 0 10\njohn",
+        );
+        assert_eq!(
+            responses,
+            vec![
+                "ERR unknown message",
+                "ERR empty message",
+                "ERR unknown query",
+                "ERR missing symbol name",
+                "ERR unknown symbol Nothing",
+                "ERR invalid position",
+                "ERR position outside of buffer",
+                "ERR missing inserted length",
+                "ERR invalid inserted length",
+                "ERR range outside of buffer",
+                "ERR inserted text is not UTF-8",
+                "ERR message is not UTF-8",
+                "ERR inserted text is too short",
+            ]
+        );
+        assert_eq!(editor.as_string(), "");
+    }
+}
@@ -0,0 +1,158 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Shrink rejected inputs to small test cases
+//!
+//! When a large document is rejected unexpectedly, [shrink_rejection](fn.shrink_rejection.html)
+//! removes chunks of tokens as long as the input is still rejected in the same way. A rejection is
+//! considered the same if the parser expected the same terminals where it failed.
+//!
+//! The algorithm is a variant of delta debugging (ddmin): It tries to remove halves, then quarters
+//! and so on, down to single tokens. It is deterministic.
+
+use super::util::{checkpoint, CancelToken, Cancelled};
+use super::{Matcher, Parser, Verdict};
+
+/// Default number of candidates to parse
+pub const DEFAULT_BUDGET: usize = 10_000;
+
+/// Parse the tokens from the start and return the expected terminals at the first rejection.
+///
+/// Return None if the tokens are not rejected.
+fn rejection<T, M>(parser: &mut Parser<T, M>, tokens: &[T]) -> Option<Vec<M>>
+where
+    T: Clone,
+    M: Matcher<T> + Ord + Clone,
+{
+    parser.buffer_changed(0);
+    for (i, t) in tokens.iter().enumerate() {
+        if parser.update(i, t.clone()) == Verdict::Reject {
+            return Some(parser.expected_terminals(i));
+        }
+    }
+    None
+}
+
+/// Shrink a rejected input to a locally minimal one that is rejected with the same expected
+/// terminals.
+///
+/// At most `budget` candidates are parsed. If the budget runs out, the smallest input found so far
+/// is returned. Inputs that are not rejected are returned unchanged.
+///
+/// The parser is reset and left in the state of the last parsed candidate. The cancel token is
+/// checked once per candidate.
+pub fn shrink_rejection<T, M>(
+    parser: &mut Parser<T, M>,
+    tokens: &[T],
+    budget: usize,
+    cancel: Option<&CancelToken>,
+) -> Result<Vec<T>, Cancelled>
+where
+    T: Clone,
+    M: Matcher<T> + Ord + Clone,
+{
+    let mut current = tokens.to_vec();
+    let expected = match rejection(parser, &current) {
+        Some(expected) => expected,
+        None => return Ok(current),
+    };
+
+    let mut budget = budget;
+    let mut chunks = 2;
+    while current.len() >= 2 && budget > 0 {
+        let chunk_len = current.len().div_ceil(chunks);
+        let mut removed = false;
+        let mut start = 0;
+        while start < current.len() && budget > 0 {
+            let end = (start + chunk_len).min(current.len());
+            let candidate: Vec<T> = current[..start]
+                .iter()
+                .chain(current[end..].iter())
+                .cloned()
+                .collect();
+            checkpoint(cancel)?;
+            budget -= 1;
+            if rejection(parser, &candidate).as_ref() == Some(&expected) {
+                current = candidate;
+                removed = true;
+                break;
+            }
+            start = end;
+        }
+
+        if removed {
+            chunks = (chunks - 1).max(2);
+        } else if chunks >= current.len() {
+            // Single tokens could not be removed: Locally minimal.
+            break;
+        } else {
+            chunks = (chunks * 2).min(current.len());
+        }
+    }
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bench::{generate_toml, toml_grammar, GrammarShape};
+
+    #[test]
+    fn shrink_toml() {
+        let mut parser = Parser::new(toml_grammar(GrammarShape::Classes));
+        let doc = generate_toml(2);
+        let middle = doc[..doc.len() / 2]
+            .rfind('\n')
+            .expect("document has lines")
+            + 1;
+        let broken = format!("{}key = ]\n{}", &doc[..middle], &doc[middle..]);
+        let tokens: Vec<char> = broken.chars().collect();
+
+        let expected = rejection(&mut parser, &tokens).expect("input should be rejected");
+        let shrunk = shrink_rejection(&mut parser, &tokens, DEFAULT_BUDGET, None)
+            .expect("shrinking without a token should not be cancelled");
+        assert!(shrunk.len() <= 10, "shrunk to {:?}", shrunk);
+        assert_eq!(rejection(&mut parser, &shrunk), Some(expected));
+    }
+
+    #[test]
+    fn accepted_is_unchanged() {
+        let mut parser = Parser::new(toml_grammar(GrammarShape::Classes));
+        let tokens: Vec<char> = generate_toml(1).chars().collect();
+        assert_eq!(rejection(&mut parser, &tokens), None);
+        assert_eq!(
+            shrink_rejection(&mut parser, &tokens, DEFAULT_BUDGET, None),
+            Ok(tokens)
+        );
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let tokens: Vec<char> = "key = ]\n".chars().collect();
+        assert_eq!(
+            shrink_rejection(&mut parser, &tokens, DEFAULT_BUDGET, Some(&cancel)),
+            Err(Cancelled)
+        );
+    }
+}
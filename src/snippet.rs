@@ -0,0 +1,226 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Snippets with multiple tab stops
+//!
+//! A snippet is defined by a template text with markers `$1`, `$2`, ... for the tab stops. The
+//! stops are visited in the order of their numbers. `$$` inserts a literal `$`. A `$` not
+//! followed by a digit or another `$` is kept as is.
+
+/// Text to insert with the positions of its tab stops
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snippet {
+    /// Text without the markers
+    pub text: String,
+    /// Offsets of the tab stops in characters, in the order they are visited
+    pub stops: Vec<usize>,
+}
+
+impl Snippet {
+    /// Parse a template with `$n` markers.
+    pub fn parse(template: &str) -> Self {
+        let mut text = String::new();
+        let mut numbered = Vec::new();
+        let mut offset = 0;
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '$' {
+                if chars.peek() == Some(&'$') {
+                    chars.next();
+                } else if chars.peek().is_some_and(|d| d.is_ascii_digit()) {
+                    let mut number = 0usize;
+                    while let Some(d) = chars.peek().and_then(|d| d.to_digit(10)) {
+                        number = number.saturating_mul(10).saturating_add(d as usize);
+                        chars.next();
+                    }
+                    numbered.push((number, offset));
+                    continue;
+                }
+            }
+            text.push(c);
+            offset += 1;
+        }
+        // Stable sort keeps repeated numbers in text order.
+        numbered.sort_by_key(|(number, _)| *number);
+        Self {
+            text,
+            stops: numbered.into_iter().map(|(_, offset)| offset).collect(),
+        }
+    }
+
+    /// Snippet without tab stops
+    pub fn plain(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            stops: Vec::new(),
+        }
+    }
+}
+
+/// Tab stops of an inserted snippet
+///
+/// Each stop is a range of buffer positions that grows when the user types into it and moves when
+/// text before it changes.
+#[derive(Debug, Default)]
+pub(crate) struct SnippetStops {
+    /// Start and end of each stop in the order they are visited
+    ranges: Vec<(usize, usize)>,
+    /// Index of the current stop
+    current: usize,
+}
+
+impl SnippetStops {
+    /// Stops of a snippet inserted at the given position. The offsets of the stops are relative
+    /// to the position, in the order they are visited.
+    pub fn new(stops: &[usize], position: usize) -> Self {
+        Self {
+            ranges: stops.iter().map(|s| (position + s, position + s)).collect(),
+            current: 0,
+        }
+    }
+
+    /// Check if there are any stops left
+    pub fn is_active(&self) -> bool {
+        !self.ranges.is_empty()
+    }
+
+    /// Forget all stops
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+        self.current = 0;
+    }
+
+    /// Position of the cursor at the current stop
+    pub fn cursor(&self) -> Option<usize> {
+        self.ranges.get(self.current).map(|r| r.1)
+    }
+
+    /// Go to the next stop. Return false if there is none.
+    pub fn next(&mut self) -> bool {
+        if self.current + 1 < self.ranges.len() {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Go to the previous stop. Return false if there is none.
+    pub fn prev(&mut self) -> bool {
+        if self.current > 0 {
+            self.current -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Find the stop containing the range, preferring the current one.
+    fn containing(&self, start: usize, end: usize) -> Option<usize> {
+        let contains = |i: &usize| {
+            let (s, e) = self.ranges[*i];
+            s <= start && end <= e
+        };
+        Some(self.current)
+            .filter(|i| *i < self.ranges.len())
+            .filter(contains)
+            .or_else(|| (0..self.ranges.len()).find(contains))
+    }
+
+    /// Tokens have been inserted. Grow the stop they have been inserted into and move the stops
+    /// behind. Clear all stops if the insertion was outside of all stops.
+    pub fn inserted(&mut self, position: usize, n: usize) {
+        match self.containing(position, position) {
+            Some(inside) => {
+                for (i, r) in self.ranges.iter_mut().enumerate() {
+                    if i == inside {
+                        r.1 += n;
+                    } else if r.0 >= position {
+                        r.0 += n;
+                        r.1 += n;
+                    } else if r.1 > position {
+                        r.1 += n;
+                    }
+                }
+            }
+            None => self.clear(),
+        }
+    }
+
+    /// Tokens in the range [start, end) have been deleted. Shrink the stop they have been deleted
+    /// from and move the stops behind. Clear all stops if the deletion was not inside a stop.
+    pub fn deleted(&mut self, start: usize, end: usize) {
+        if self.containing(start, end).is_none() {
+            self.clear();
+            return;
+        }
+        let map = |p: usize| {
+            if p >= end {
+                p - (end - start)
+            } else if p > start {
+                start
+            } else {
+                p
+            }
+        };
+        for r in self.ranges.iter_mut() {
+            *r = (map(r.0), map(r.1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_markers() {
+        let snippet = Snippet::parse("name = \"$1\" # $2");
+        assert_eq!(snippet.text, "name = \"\" # ");
+        assert_eq!(snippet.stops, vec![8, 12]);
+
+        // Stops are ordered by number, not by position.
+        let snippet = Snippet::parse("$2-$1-$10");
+        assert_eq!(snippet.text, "--");
+        assert_eq!(snippet.stops, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn parse_escape() {
+        let snippet = Snippet::parse("cost = \"$$$1\" $x $");
+        assert_eq!(snippet.text, "cost = \"$\" $x $");
+        assert_eq!(snippet.stops, vec![9]);
+
+        let snippet = Snippet::plain("$1");
+        assert_eq!(snippet.text, "$1");
+        assert!(snippet.stops.is_empty());
+    }
+
+    #[test]
+    fn parse_multi_byte() {
+        let snippet = Snippet::parse("größe = $1 → $2");
+        assert_eq!(snippet.text, "größe =  → ");
+        assert_eq!(snippet.stops, vec![8, 11]);
+    }
+}
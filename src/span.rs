@@ -0,0 +1,179 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Byte ranges of tokens in their source text
+//!
+//! The parser addresses tokens by their index in the buffer. If the tokens are produced by a lexer
+//! over multi-byte text, a [SpanTable](struct.SpanTable.html) maps the indices back to byte ranges
+//! of the source, e.g. to report the location of a node with
+//! [CstIterItemNode::byte_span](../struct.CstIterItemNode.html#method.byte_span).
+
+use std::ops::Range;
+
+/// Source byte range of each token, indexed like the buffer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpanTable {
+    /// Byte range of each token. The ranges are ordered and don't overlap, but there might be
+    /// gaps, e.g. for whitespace dropped by the lexer.
+    ranges: Vec<Range<usize>>,
+}
+
+impl SpanTable {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the table for a buffer that holds the characters of the text.
+    pub fn from_chars(text: &str) -> Self {
+        Self {
+            ranges: text
+                .char_indices()
+                .map(|(offset, c)| offset..offset + c.len_utf8())
+                .collect(),
+        }
+    }
+
+    /// Append the byte range of the next token.
+    ///
+    /// The range must not begin before the end of the previous one.
+    pub fn push(&mut self, range: Range<usize>) {
+        debug_assert!(range.start <= range.end);
+        debug_assert!(self
+            .ranges
+            .last()
+            .is_none_or(|last| last.end <= range.start));
+        self.ranges.push(range);
+    }
+
+    /// Drop the ranges of the tokens from `len` on, e.g. before lexing the changed text again.
+    pub fn truncate(&mut self, len: usize) {
+        self.ranges.truncate(len);
+    }
+
+    /// Number of tokens in the table
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Check if the table has no tokens
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Byte range of the token at the given index
+    pub fn token(&self, index: usize) -> Option<Range<usize>> {
+        self.ranges.get(index).cloned()
+    }
+
+    /// Byte range of the tokens at positions *p* with `start` <= *p* < `end`.
+    ///
+    /// An empty token range maps to an empty byte range at the start of the next token, or at the
+    /// end of the last one. Return None if the range is not covered by the table.
+    pub fn byte_range(&self, start: usize, end: usize) -> Option<Range<usize>> {
+        if start > end || end > self.ranges.len() {
+            return None;
+        }
+        if start == end {
+            let offset = match self.ranges.get(start) {
+                Some(range) => range.start,
+                None => self.ranges.last().map_or(0, |range| range.end),
+            };
+            return Some(offset..offset);
+        }
+        Some(self.ranges[start].start..self.ranges[end - 1].end)
+    }
+
+    /// Index of the token that contains the byte offset or follows it.
+    ///
+    /// Return the number of tokens if the offset is behind the last token.
+    pub fn token_at(&self, offset: usize) -> usize {
+        self.ranges.partition_point(|range| range.end <= offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::char::CharMatcher;
+    use crate::{Grammar, Parser, Rule, SymbolId};
+
+    #[test]
+    fn multi_byte_chars() {
+        let spans = SpanTable::from_chars("aä€b");
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans.token(1), Some(1..3));
+        assert_eq!(spans.token(2), Some(3..6));
+        assert_eq!(spans.byte_range(1, 3), Some(1..6));
+        assert_eq!(spans.byte_range(2, 2), Some(3..3));
+        assert_eq!(spans.byte_range(4, 4), Some(7..7));
+        assert_eq!(spans.byte_range(3, 5), None);
+
+        assert_eq!(spans.token_at(0), 0);
+        assert_eq!(spans.token_at(2), 1);
+        assert_eq!(spans.token_at(5), 2);
+        assert_eq!(spans.token_at(7), 4);
+    }
+
+    #[test]
+    fn gaps() {
+        let mut spans = SpanTable::new();
+        spans.push(0..2);
+        spans.push(4..5);
+        spans.push(8..10);
+        assert_eq!(spans.byte_range(0, 3), Some(0..10));
+        assert_eq!(spans.byte_range(1, 1), Some(4..4));
+        assert_eq!(spans.token_at(3), 1);
+
+        spans.truncate(1);
+        assert_eq!(spans.byte_range(1, 1), Some(2..2));
+        assert_eq!(spans.token(1), None);
+    }
+
+    #[test]
+    fn node_spans() {
+        use CharMatcher::*;
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("S".to_string());
+        grammar.add(Rule::new("S").nt("Word").t(Exact('!')));
+        grammar.add(Rule::new("Word").t(Exact('ä')).t(Exact('ö')));
+        let mut parser = Parser::new(grammar.compile().expect("compilation should have worked"));
+
+        let text = "äö!";
+        for (i, c) in text.chars().enumerate() {
+            parser.update(i, c);
+        }
+        let spans = SpanTable::from_chars(text);
+        let nodes: Vec<(SymbolId, Option<std::ops::Range<usize>>)> = parser
+            .cst_iter()
+            .filter_map(|item| item.as_parsed().map(|n| (n.lhs, n.byte_span(&spans))))
+            .filter(|(lhs, _)| (*lhs as usize) < parser.grammar().nt_count())
+            .collect();
+        let word = parser.grammar().nt_id("Word");
+        let s = parser.grammar().nt_id("S");
+        assert!(nodes.contains(&(word, Some(0..4))));
+        assert!(nodes.contains(&(s, Some(0..5))));
+    }
+}
@@ -0,0 +1,302 @@
+/*
+    MIT License
+
+    Copyright (c) 2020 Lars Krueger <lars_e_krueger@gmx.de>
+
+    Permission is hereby granted, free of charge, to any person obtaining a copy
+    of this software and associated documentation files (the "Software"), to deal
+    in the Software without restriction, including without limitation the rights
+    to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    copies of the Software, and to permit persons to whom the Software is
+    furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in all
+    copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    SOFTWARE.
+*/
+
+//! Shared packed parse forest
+//!
+//! The forest contains each (symbol, start, end) node of all derivations exactly once. Alternative
+//! derivations of a node are stored as its families (packed nodes). Rules with more than two
+//! symbols are binarized by intermediate nodes, so the size of the forest stays polynomial in the
+//! length of the input, even if the number of derivations explodes.
+//!
+//! Build the forest with [Parser::forest](../struct.Parser.html#method.forest).
+
+use std::collections::HashMap;
+
+use super::{SymbolId, MAX_DERIVATION_COUNT};
+
+/// Index of a node in the forest
+pub type NodeId = usize;
+
+/// What a node of the forest has derived
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NodeLabel {
+    /// Non-terminal
+    Symbol(SymbolId),
+    /// First `dot` symbols of the right hand side of a rule
+    Intermediate { rule: usize, dot: usize },
+    /// Token that matched a terminal
+    Token,
+    /// Token that has been skipped by the error recovery
+    Error,
+}
+
+/// One way to derive a node (packed node).
+///
+/// `right` is the node of the last symbol, `left` the intermediate node of the symbols before it.
+/// Both are `None` for an empty rule, `left` is `None` if the rule has only one symbol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Family {
+    /// Index of the rule that applied
+    pub rule: usize,
+    /// Node of all but the last symbol
+    pub left: Option<NodeId>,
+    /// Node of the last symbol
+    pub right: Option<NodeId>,
+}
+
+/// Node of the forest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    /// What has been derived
+    pub label: NodeLabel,
+    /// Position of the first token
+    pub start: usize,
+    /// Position after the last token
+    pub end: usize,
+    /// Alternative derivations. Empty for tokens and errors.
+    pub families: Vec<Family>,
+}
+
+/// Node of a single derivation, see [Sppf::derivation](struct.Sppf.html#method.derivation)
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivationNode {
+    /// Symbol, token or error. Intermediate nodes are resolved.
+    pub label: NodeLabel,
+    /// Rule that derived a symbol
+    pub rule: Option<usize>,
+    /// Position of the first token
+    pub start: usize,
+    /// Position after the last token
+    pub end: usize,
+    /// Index of the parent in the derivation
+    pub parent: Option<usize>,
+}
+
+/// Shared packed parse forest
+#[derive(Debug, Default)]
+pub struct Sppf {
+    /// All nodes
+    nodes: Vec<Node>,
+    /// Node by label, start and end
+    index: HashMap<(NodeLabel, usize, usize), NodeId>,
+    /// Node of the start symbol
+    root: Option<NodeId>,
+    /// Derivation count by node, at most `MAX_DERIVATION_COUNT`
+    counts: Vec<usize>,
+}
+
+impl Sppf {
+    /// Node of the start symbol, if the input has been accepted
+    pub fn root(&self) -> Option<NodeId> {
+        self.root
+    }
+
+    /// Access a node
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check if the forest has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Find a node by label and span
+    pub fn find(&self, label: NodeLabel, start: usize, end: usize) -> Option<NodeId> {
+        self.index.get(&(label, start, end)).copied()
+    }
+
+    /// Number of derivations of a node, at most `MAX_DERIVATION_COUNT`.
+    ///
+    /// Derivations that contain themselves (cyclic grammars) are not counted.
+    pub fn derivation_count(&self, id: NodeId) -> usize {
+        self.counts[id]
+    }
+
+    /// Symbol nodes that have more than one derivation
+    pub fn ambiguous_nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        (0..self.nodes.len()).filter(move |id| {
+            matches!(self.nodes[*id].label, NodeLabel::Symbol(_)) && self.counts[*id] > 1
+        })
+    }
+
+    /// Extract the derivation with index `n` of a node, `n < derivation_count(id)`.
+    ///
+    /// The nodes are returned in pre-order. Return `None` if `n` is out of range.
+    pub fn derivation(&self, id: NodeId, n: usize) -> Option<Vec<DerivationNode>> {
+        if n >= self.counts[id] {
+            return None;
+        }
+        let mut derivation = Vec::new();
+        let mut todo = vec![(id, n, None)];
+        while let Some((id, n, parent)) = todo.pop() {
+            let node = &self.nodes[id];
+            let index = derivation.len();
+            let mut item = DerivationNode {
+                label: node.label,
+                rule: None,
+                start: node.start,
+                end: node.end,
+                parent,
+            };
+            if !node.families.is_empty() {
+                let (family, n) = self.choose(id, n);
+                item.rule = Some(family.rule);
+                // Children from last to first, i.e. in the order they need to be pushed
+                let mut children = Vec::new();
+                let mut family = family;
+                let mut n = n;
+                loop {
+                    let right = family.right.map_or(1, |r| self.counts[r]);
+                    if let Some(right_id) = family.right {
+                        children.push((right_id, n % right, Some(index)));
+                    }
+                    match family.left {
+                        Some(left) => {
+                            let (f, m) = self.choose(left, n / right);
+                            family = f;
+                            n = m;
+                        }
+                        None => break,
+                    }
+                }
+                todo.extend(children);
+            }
+            derivation.push(item);
+        }
+        Some(derivation)
+    }
+
+    /// Iterate over all derivations of a node, at most `MAX_DERIVATION_COUNT`.
+    pub fn derivations(&self, id: NodeId) -> impl Iterator<Item = Vec<DerivationNode>> + '_ {
+        (0..self.counts[id]).filter_map(move |n| self.derivation(id, n))
+    }
+
+    /// Select the family that contains the derivation with index `n` and the index within it.
+    fn choose(&self, id: NodeId, mut n: usize) -> (&Family, usize) {
+        for family in self.nodes[id].families.iter() {
+            let count = self.family_count(family);
+            if n < count {
+                return (family, n);
+            }
+            n -= count;
+        }
+        unreachable!("derivation index should be smaller than the derivation count");
+    }
+
+    /// Number of derivations of a family, computed from the counts of its children
+    fn family_count(&self, family: &Family) -> usize {
+        let count = |id: Option<NodeId>| id.map_or(1, |id| self.counts[id]);
+        count(family.left).saturating_mul(count(family.right))
+    }
+
+    /// Get the node for a label and span, create it if it doesn't exist.
+    ///
+    /// Return the ID and whether the node is new.
+    pub(crate) fn add_node(
+        &mut self,
+        label: NodeLabel,
+        start: usize,
+        end: usize,
+    ) -> (NodeId, bool) {
+        if let Some(id) = self.find(label, start, end) {
+            return (id, false);
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            label,
+            start,
+            end,
+            families: Vec::new(),
+        });
+        self.index.insert((label, start, end), id);
+        (id, true)
+    }
+
+    /// Add a family to a node if it doesn't exist yet.
+    pub(crate) fn add_family(&mut self, id: NodeId, family: Family) {
+        if !self.nodes[id].families.contains(&family) {
+            self.nodes[id].families.push(family);
+        }
+    }
+
+    /// Set the root node and compute the derivation counts.
+    ///
+    /// Uses an explicit stack as the nesting of nodes can be as deep as the input is long. Families
+    /// that lead back to a node that is being counted count as having no derivation. They are
+    /// removed, so that the counts stay consistent when extracting derivations.
+    pub(crate) fn finish(&mut self, root: Option<NodeId>) {
+        self.root = root;
+        const UNKNOWN: usize = usize::MAX;
+        const VISITING: usize = usize::MAX - 1;
+        self.counts = vec![UNKNOWN; self.nodes.len()];
+        for id in 0..self.nodes.len() {
+            let mut stack = vec![id];
+            while let Some(&id) = stack.last() {
+                if self.counts[id] < VISITING {
+                    stack.pop();
+                    continue;
+                }
+                self.counts[id] = VISITING;
+                let len = stack.len();
+                for family in self.nodes[id].families.iter() {
+                    for child in family.left.iter().chain(family.right.iter()) {
+                        if self.counts[*child] == UNKNOWN {
+                            stack.push(*child);
+                        }
+                    }
+                }
+                if stack.len() == len {
+                    let counts = &self.counts;
+                    self.nodes[id].families.retain(|family| {
+                        family
+                            .left
+                            .iter()
+                            .chain(family.right.iter())
+                            .all(|child| counts[*child] != VISITING)
+                    });
+                    let count = if self.nodes[id].families.is_empty() {
+                        match self.nodes[id].label {
+                            NodeLabel::Token | NodeLabel::Error => 1,
+                            _ => 0,
+                        }
+                    } else {
+                        self.nodes[id]
+                            .families
+                            .iter()
+                            .fold(0, |n: usize, f| n.saturating_add(self.family_count(f)))
+                    };
+                    self.counts[id] = count.min(MAX_DERIVATION_COUNT);
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
@@ -24,11 +24,52 @@
 
 //! Style sheet with arbitrary styles
 
-use super::SymbolId;
+use std::sync::Mutex;
+
+use super::char::CharMatcher;
+use super::util::{checkpoint, CancelToken, Cancelled};
+use super::{CompiledGrammar, CstIterItem, Matcher, Parser, SymbolId, Verdict};
 
 pub struct StyleSheet<Style> {
     /// All style matchers
     styles: Vec<StyleMatcher<Style>>,
+
+    /// Embedded languages
+    sub_languages: Vec<SubLanguage<Style>>,
+}
+
+/// Embedded language inside the text of a non-terminal, e.g. version requirements inside strings.
+///
+/// When a completed node of the trigger symbol is classified, its text is parsed with the
+/// sub-grammar. If the sub-grammar accepts the text, the spans of the sub-language's style sheet
+/// replace the style of the node. Spans without a style in the sub-language keep the style of the
+/// node. If the text is rejected, the node is styled as if there was no sub-language.
+///
+/// Only nodes that are styled at or above the trigger node are affected.
+pub struct SubLanguage<Style> {
+    /// Non-terminal whose text is parsed with the sub-grammar
+    trigger_symbol: SymbolId,
+
+    /// Parser for the sub-grammar. Reset before each use. Locked so that style sheets can be
+    /// shared between threads.
+    parser: Mutex<Parser<char, CharMatcher>>,
+
+    /// Styles of the sub-language
+    sheet: StyleSheet<Style>,
+
+    /// Nodes with more tokens than this are not parsed
+    max_len: usize,
+}
+
+/// Section of the buffer to be rendered with the same style
+#[derive(Debug)]
+pub struct StyledSpan<'a, Style> {
+    /// Index of the first token
+    pub start: usize,
+    /// Index after the last token
+    pub end: usize,
+    /// Style of the tokens. None, if the style sheet had no match.
+    pub style: Option<&'a Style>,
 }
 
 /// Simple matcher for parse tree paths
@@ -64,13 +105,21 @@ pub enum LookedUp<'a, Style> {
 
 impl<Style> StyleSheet<Style> {
     pub fn new() -> Self {
-        Self { styles: Vec::new() }
+        Self {
+            styles: Vec::new(),
+            sub_languages: Vec::new(),
+        }
     }
 
     pub fn add(&mut self, m: StyleMatcher<Style>) {
         self.styles.push(m);
     }
 
+    /// Register an embedded language.
+    pub fn add_sub_language(&mut self, sub: SubLanguage<Style>) {
+        self.sub_languages.push(sub);
+    }
+
     /// Lookup a path in the style sheet.
     pub fn lookup(&self, path: &[SymbolId]) -> LookedUp<Style> {
         // Keep track of the still-possible matchers and respective position in the match list.
@@ -138,6 +187,200 @@ impl<Style> StyleSheet<Style> {
     }
 }
 
+impl<Style> StyleSheet<Style> {
+    /// Split the parsed buffer into styled spans.
+    ///
+    /// The parse tree is traversed in the order of the CST iterator. If the style sheet has a
+    /// style for a node, the tokens up to the end of the node are assigned that style. If it only
+    /// has styles for the parents of the node, the node is skipped and styled with the parent.
+    /// Nodes without any style are assigned `None`. The unparsed rest of the buffer is assigned
+    /// `None` too.
+    ///
+    /// Spans are returned in buffer order and don't overlap.
+    pub fn classify<M>(
+        &self,
+        parser: &Parser<char, M>,
+        tokens: &[char],
+    ) -> Vec<StyledSpan<'_, Style>>
+    where
+        M: Matcher<char> + Clone,
+    {
+        self.classify_cancellable(parser, tokens, None)
+            .unwrap_or_default()
+    }
+
+    /// Split the parsed buffer into styled spans like [classify](#method.classify). The token is
+    /// checked once per node of the parse tree.
+    pub fn classify_cancellable<M>(
+        &self,
+        parser: &Parser<char, M>,
+        tokens: &[char],
+        cancel: Option<&CancelToken>,
+    ) -> Result<Vec<StyledSpan<'_, Style>>, Cancelled>
+    where
+        M: Matcher<char> + Clone,
+    {
+        let grammar = parser.grammar();
+        let mut spans = Vec::new();
+        // Spans of sub-languages that have not been merged into the result yet
+        let mut sub_spans: Vec<StyledSpan<Style>> = Vec::new();
+        let mut rendered_until = 0;
+        for cst_node in parser.cst_iter() {
+            checkpoint(cancel)?;
+            match cst_node {
+                CstIterItem::Parsed(cst_node) => {
+                    let lhs = cst_node.lhs;
+                    if grammar.dotted_is_completed(&cst_node.dotted_rule) {
+                        for sub in self.sub_languages.iter() {
+                            if sub.trigger_symbol == lhs
+                                && cst_node.end - cst_node.start <= sub.max_len
+                                && cst_node.start >= rendered_until
+                            {
+                                sub.classify(
+                                    &tokens[cst_node.start..cst_node.end],
+                                    cst_node.start,
+                                    &mut sub_spans,
+                                );
+                            }
+                        }
+                    }
+
+                    if cst_node.end != cst_node.start && cst_node.end > rendered_until {
+                        let mut path: Vec<SymbolId> = cst_node
+                            .path
+                            .0
+                            .iter()
+                            .map(|n| grammar.lhs(parser.dotted_rule(n).rule as usize))
+                            .collect();
+                        path.push(lhs);
+
+                        let style = match self.lookup(&path) {
+                            LookedUp::Parent => continue,
+                            LookedUp::Found(style) => Some(style),
+                            LookedUp::Nothing => None,
+                        };
+                        splice(
+                            &mut spans,
+                            &mut sub_spans,
+                            rendered_until,
+                            cst_node.end,
+                            style,
+                        );
+                        rendered_until = cst_node.end;
+                    }
+                }
+                CstIterItem::Unparsed(_unparsed) => {
+                    if tokens.len() > rendered_until {
+                        spans.push(StyledSpan {
+                            start: rendered_until,
+                            end: tokens.len(),
+                            style: None,
+                        });
+                        rendered_until = tokens.len();
+                    }
+                }
+            }
+        }
+        Ok(spans)
+    }
+}
+
+/// Add the span from `start` to `end` to the result. Sub-language spans inside that range
+/// replace the given style.
+fn splice<'a, Style>(
+    spans: &mut Vec<StyledSpan<'a, Style>>,
+    sub_spans: &mut Vec<StyledSpan<'a, Style>>,
+    start: usize,
+    end: usize,
+    style: Option<&'a Style>,
+) {
+    let mut from = start;
+    let mut later = Vec::new();
+    for sub in sub_spans.drain(..) {
+        if sub.start >= end {
+            // Belongs to a later span.
+            later.push(sub);
+            continue;
+        }
+        if sub.start < from || sub.end > end {
+            // Already covered or overlapping the end. Forget it.
+            continue;
+        }
+        if from < sub.start {
+            spans.push(StyledSpan {
+                start: from,
+                end: sub.start,
+                style,
+            });
+        }
+        spans.push(StyledSpan {
+            start: sub.start,
+            end: sub.end,
+            style: sub.style.or(style),
+        });
+        from = sub.end;
+    }
+    if from < end {
+        spans.push(StyledSpan {
+            start: from,
+            end,
+            style,
+        });
+    }
+    *sub_spans = later;
+}
+
+impl<Style> SubLanguage<Style> {
+    /// Create an embedded language for nodes of `trigger_symbol` with at most `max_len` tokens.
+    pub fn new(
+        trigger_symbol: SymbolId,
+        grammar: CompiledGrammar<char, CharMatcher>,
+        sheet: StyleSheet<Style>,
+        max_len: usize,
+    ) -> Self {
+        Self {
+            trigger_symbol,
+            parser: Mutex::new(Parser::new(grammar)),
+            sheet,
+            max_len,
+        }
+    }
+
+    /// Parse the text of a node and add the styled spans to `sub_spans` if the sub-grammar
+    /// accepts it. Positions are translated by `offset`.
+    ///
+    /// Spans of nested trigger nodes have been added before, as their nodes are visited first.
+    /// They are replaced.
+    fn classify<'a>(
+        &'a self,
+        text: &[char],
+        offset: usize,
+        sub_spans: &mut Vec<StyledSpan<'a, Style>>,
+    ) {
+        let mut parser = self.parser.lock().unwrap_or_else(|e| e.into_inner());
+        parser.buffer_changed(0);
+        let mut verdict = Verdict::More;
+        for (i, c) in text.iter().enumerate() {
+            verdict = parser.update(i, *c);
+            if verdict == Verdict::Reject {
+                return;
+            }
+        }
+        if verdict != Verdict::Accept {
+            return;
+        }
+        let end = offset + text.len();
+        sub_spans.retain(|span| span.end <= offset || span.start >= end);
+        for span in self.sheet.classify(&parser, text) {
+            sub_spans.push(StyledSpan {
+                start: span.start + offset,
+                end: span.end + offset,
+                style: span.style,
+            });
+        }
+    }
+}
+
 impl<Style> StyleMatcher<Style> {
     pub fn new(style: Style) -> Self {
         Self {
@@ -161,3 +404,126 @@ impl<Style> StyleMatcher<Style> {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Grammar, Rule};
+    use CharMatcher::*;
+
+    /// Names and quoted strings
+    fn entry_grammar() -> CompiledGrammar<char, CharMatcher> {
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("Doc".to_string());
+        grammar.add(Rule::new("Doc").nt("Doc").nt("Entry"));
+        grammar.add(Rule::new("Doc").nt("Entry"));
+        grammar.add(Rule::new("Entry").nt("Name"));
+        grammar.add(Rule::new("Entry").nt("Quoted"));
+        grammar.add(Rule::new("Name").t(Range('a', 'z')));
+        grammar.add(Rule::new("Quoted").t(Exact('"')).nt("Text").t(Exact('"')));
+        grammar.add(Rule::new("Text").nt("Char").nt("Text"));
+        grammar.add(Rule::new("Text"));
+        grammar.add(Rule::new("Char").t(NoneOf(vec!['"'])));
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    /// Dotted version numbers
+    fn version_grammar() -> CompiledGrammar<char, CharMatcher> {
+        let mut grammar = Grammar::<char, CharMatcher>::new();
+        grammar.set_start("Version".to_string());
+        grammar.add(Rule::new("Version").nt("Digit"));
+        grammar.add(Rule::new("Version").nt("Digit").t(Exact('.')).nt("Version"));
+        grammar.add(Rule::new("Digit").t(Range('0', '9')));
+        grammar.compile().expect("compilation should have worked")
+    }
+
+    /// Style sheet with a version sub-language for the text of strings
+    fn sheet(
+        grammar: &CompiledGrammar<char, CharMatcher>,
+        max_len: usize,
+    ) -> StyleSheet<&'static str> {
+        let versions = version_grammar();
+        let mut sub_sheet = StyleSheet::new();
+        sub_sheet.add(StyleMatcher::new("digit").skip_to(versions.nt_id("Digit")));
+
+        let mut sheet = StyleSheet::new();
+        sheet.add(StyleMatcher::new("name").skip_to(grammar.nt_id("Name")));
+        sheet.add(StyleMatcher::new("string").skip_to(grammar.nt_id("Quoted")));
+        sheet.add_sub_language(SubLanguage::new(
+            grammar.nt_id("Text"),
+            versions,
+            sub_sheet,
+            max_len,
+        ));
+        sheet
+    }
+
+    /// Parse the text and return the spans as tuples
+    fn classify(max_len: usize, text: &str) -> Vec<(usize, usize, Option<&'static str>)> {
+        let grammar = entry_grammar();
+        let sheet = sheet(&grammar, max_len);
+        let mut parser = Parser::<char, CharMatcher>::new(grammar);
+        let tokens: Vec<char> = text.chars().collect();
+        parser.update_slice(0, &tokens);
+        sheet
+            .classify(&parser, &tokens)
+            .iter()
+            .map(|span| (span.start, span.end, span.style.copied()))
+            .collect()
+    }
+
+    #[test]
+    fn sub_language() {
+        assert_eq!(
+            classify(10, "a\"1.2\"x\"b\""),
+            vec![
+                (0, 1, Some("name")),
+                (1, 2, Some("string")),
+                (2, 3, Some("digit")),
+                (3, 4, Some("string")),
+                (4, 5, Some("digit")),
+                (5, 6, Some("string")),
+                (6, 7, Some("name")),
+                (7, 10, Some("string")),
+            ]
+        );
+
+        // Only the shorter text of the nested node is parsed
+        assert_eq!(
+            classify(2, "\"1.2\""),
+            vec![
+                (0, 3, Some("string")),
+                (3, 4, Some("digit")),
+                (4, 5, Some("string")),
+            ]
+        );
+    }
+
+    #[test]
+    fn splice_sub_spans() {
+        let (outer, inner) = ("outer", "inner");
+        let sub = |start, end, style| StyledSpan { start, end, style };
+        let mut spans = Vec::new();
+        let mut sub_spans = vec![sub(1, 2, Some(&inner)), sub(3, 5, None), sub(6, 7, None)];
+        splice(&mut spans, &mut sub_spans, 0, 4, Some(&outer));
+        let tuples: Vec<(usize, usize, Option<&&str>)> =
+            spans.iter().map(|s| (s.start, s.end, s.style)).collect();
+        assert_eq!(
+            tuples,
+            vec![
+                (0, 1, Some(&outer)),
+                (1, 2, Some(&inner)),
+                (2, 4, Some(&outer))
+            ]
+        );
+        // Spans after the end are kept for later
+        assert_eq!(sub_spans.len(), 1);
+        assert_eq!(sub_spans[0].start, 6);
+    }
+
+    #[test]
+    fn style_sheet_is_sync() {
+        fn assert_sync<S: Sync>(_: &S) {}
+        assert_sync(&sheet(&entry_grammar(), 10));
+    }
+}